@@ -46,7 +46,9 @@ pub use crate::sys::types::{
 };
 pub use crate::sys::types::{Val as Value, ValType as Type};
 pub use crate::sys::utils::is_wasm;
-pub use target_lexicon::{Architecture, CallingConvention, OperatingSystem, Triple, HOST};
+pub use target_lexicon::{
+    Architecture, CallingConvention, Environment, OperatingSystem, Triple, HOST,
+};
 #[cfg(feature = "compiler")]
 pub use wasmer_compiler::{
     wasmparser, CompilerConfig, FunctionMiddleware, MiddlewareError, MiddlewareReaderState,
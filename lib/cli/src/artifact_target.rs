@@ -0,0 +1,83 @@
+//! The target an artifact was compiled for, recorded by `wasmer compile`
+//! and read by `wasmer inspect --artifact-target`.
+//!
+//! The serialized artifacts don't record the target or the compiler, so
+//! they're appended to Universal artifacts as a trailer: the target as
+//! JSON, its length as a little-endian `u64`, and the magic bytes. The
+//! engine ignores the bytes after the serialized module, and the trailer
+//! can be read from the end of the file without reading the artifact.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The magic bytes at the end of the trailer.
+const MAGIC_FOOTER: &[u8; 8] = b"WASMTGT\0";
+
+/// The length of the footer following the target.
+const FOOTER_LENGTH: usize = 8 + MAGIC_FOOTER.len();
+
+/// The target an artifact was compiled for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactTarget {
+    /// The target triple.
+    pub triple: String,
+    /// The compiler, like `cranelift`.
+    pub compiler: String,
+    /// The CPU features the code may use.
+    pub cpu_features: Vec<String>,
+}
+
+impl ArtifactTarget {
+    /// Encode the target as a trailer to append to an artifact.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut encoded = serde_json::to_vec(self)?;
+        encoded.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+        encoded.extend_from_slice(MAGIC_FOOTER);
+        Ok(encoded)
+    }
+
+    /// Read the target from the trailer of the artifact at `path`, if it
+    /// has one.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let mut file = File::open(path)?;
+        let file_length = file.metadata()?.len();
+        if file_length < FOOTER_LENGTH as u64 {
+            return Ok(None);
+        }
+        let mut footer = [0; FOOTER_LENGTH];
+        file.seek(SeekFrom::End(-(FOOTER_LENGTH as i64)))?;
+        file.read_exact(&mut footer)?;
+        if &footer[8..] != MAGIC_FOOTER {
+            return Ok(None);
+        }
+        let length = u64::from_le_bytes(footer[..8].try_into()?);
+        if length > file_length - FOOTER_LENGTH as u64 {
+            bail!("the target recorded in the artifact is truncated");
+        }
+        let mut encoded = vec![0; length as usize];
+        file.seek(SeekFrom::End(-(FOOTER_LENGTH as i64) - length as i64))?;
+        file.read_exact(&mut encoded)?;
+        let target = serde_json::from_slice(&encoded)
+            .context("the target recorded in the artifact is corrupted")?;
+        Ok(Some(target))
+    }
+
+    /// The target on one line: the triple, the compiler, and the CPU
+    /// features separated by commas (`-` if none).
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.triple,
+            self.compiler,
+            if self.cpu_features.is_empty() {
+                "-".to_string()
+            } else {
+                self.cpu_features.join(",")
+            }
+        )
+    }
+}
@@ -14,6 +14,8 @@ wasm_byte_vec_t generate_serialized_data() {
 
         char* byte_ptr = (char*)&WASMER_METADATA[0];
 
+        resolve_function_pointers();
+
         size_t num_function_pointers
                 = sizeof(function_pointers) / sizeof(void*);
         size_t num_function_trampolines
@@ -68,11 +70,73 @@ wasm_module_t* wasmer_staticlib_engine_new(wasm_store_t* store, const char* wasm
 }
 "#;
 
+/// A constructor instantiating the module when the program starts, which
+/// runs its start function, registered in `.init_array` (or in
+/// `__mod_init_func` on Apple platforms).
+///
+/// It only supports modules without imports.
+pub const INIT_ARRAY_CONSTRUCTOR: &str = r#"
+#include <stdio.h>
+
+// The module instantiated by `wasmer_init_array_constructor`.
+wasm_engine_t* wasmer_init_engine = NULL;
+wasm_store_t* wasmer_init_store = NULL;
+wasm_module_t* wasmer_init_module = NULL;
+wasm_instance_t* wasmer_init_instance = NULL;
+
+static void wasmer_init_array_constructor(void) {
+        wasm_config_t* config = wasm_config_new();
+        wasm_config_set_engine(config, STATICLIB);
+        wasmer_init_engine = wasm_engine_new_with_config(config);
+        wasmer_init_store = wasm_store_new(wasmer_init_engine);
+        wasmer_init_module = wasmer_staticlib_engine_new(wasmer_init_store, "");
+        if (!wasmer_init_module) {
+                fprintf(stderr, "Failed to create the module in its constructor\n");
+                abort();
+        }
+
+        // Instantiating the module runs its start function
+        wasm_extern_vec_t imports = WASM_EMPTY_VEC;
+        wasm_trap_t* trap = NULL;
+        wasmer_init_instance = wasm_instance_new(wasmer_init_store, wasmer_init_module, &imports, &trap);
+        if (!wasmer_init_instance) {
+                if (trap) {
+                        wasm_message_t message;
+                        wasm_trap_message(trap, &message);
+                        fprintf(stderr, "The start function of the module trapped in its constructor: %.*s\n", (int)message.size, message.data);
+                } else {
+                        fprintf(stderr, "Failed to instantiate the module in its constructor\n");
+                }
+                abort();
+        }
+}
+
+#ifdef __APPLE__
+__attribute__((used, section("__DATA,__mod_init_func")))
+#else
+__attribute__((used, section(".init_array")))
+#endif
+static void (*const wasmer_init_array_entry)(void) = wasmer_init_array_constructor;
+"#;
+
+/// The symbols of the compiled functions, when the object emits them in
+/// a single symbol.
+pub struct SingleSymbol {
+    /// The symbol of the functions.
+    pub code: String,
+    /// The symbol of the `u64` offsets of the functions in `code`.
+    pub offsets: String,
+}
+
 /// Generate the header file that goes with the generated object file.
+///
+/// With `single_symbol`, the function pointers are resolved from the
+/// offsets table of the object when the module is loaded.
 pub fn generate_header_file(
     module_info: &ModuleInfo,
     symbol_registry: &dyn SymbolRegistry,
     metadata_length: usize,
+    single_symbol: Option<&SingleSymbol>,
 ) -> String {
     let mut c_statements = vec![];
     c_statements.push(CStatement::LiteralConstant {
@@ -99,69 +163,101 @@ pub fn generate_header_file(
         },
         definition: None,
     });
-    let function_declarations = module_info
-        .functions
-        .iter()
-        .filter_map(|(f_index, sig_index)| {
-            Some((module_info.local_func_index(f_index)?, sig_index))
-        })
-        .map(|(function_local_index, _sig_index)| {
-            let function_name =
-                symbol_registry.symbol_to_name(Symbol::LocalFunction(function_local_index));
-            // TODO: figure out the signature here too
-            CStatement::Declaration {
-                name: function_name,
-                is_extern: false,
-                is_const: false,
-                ctype: CType::Function {
-                    arguments: vec![CType::Void],
-                    return_value: None,
-                },
-                definition: None,
-            }
-        });
-    c_statements.push(CStatement::LiteralConstant {
-        value: r#"
-// Compiled Wasm function pointers ordered by function index: the order they
-// appeared in in the Wasm module.
-"#
-        .to_string(),
-    });
-    c_statements.extend(function_declarations);
+    match single_symbol {
+        Some(single_symbol) => {
+            let num_functions = module_info.functions.len() - module_info.num_imported_functions;
+            c_statements.push(CStatement::LiteralConstant {
+                value: format!(
+                    r#"
+// Compiled Wasm functions, in a single symbol, and their offsets in it ordered
+// by function index: the order they appeared in in the Wasm module.
+extern const unsigned char {code}[];
+extern const unsigned long long {offsets}[];
 
-    // function pointer array
-    {
-        let function_pointer_array_statements = module_info
-            .functions
-            .iter()
-            .filter_map(|(f_index, sig_index)| {
-                Some((module_info.local_func_index(f_index)?, sig_index))
-            })
-            .map(|(function_local_index, _sig_index)| {
-                let function_name =
-                    symbol_registry.symbol_to_name(Symbol::LocalFunction(function_local_index));
-                // TODO: figure out the signature here too
-
-                CStatement::Cast {
-                    target_type: CType::void_ptr(),
-                    expression: Box::new(CStatement::LiteralConstant {
-                        value: function_name,
-                    }),
-                }
-            })
-            .collect::<Vec<_>>();
+void* function_pointers[{num_functions}];
 
-        c_statements.push(CStatement::Declaration {
-            name: "function_pointers".to_string(),
-            is_extern: false,
-            is_const: true,
-            ctype: CType::Array {
-                inner: Box::new(CType::void_ptr()),
-            },
-            definition: Some(Box::new(CStatement::LiteralArray {
-                items: function_pointer_array_statements,
-            })),
-        });
+static void resolve_function_pointers(void) {{
+        for (size_t i = 0; i < {num_functions}; ++i) {{
+                function_pointers[i] = (void*)({code} + {offsets}[i]);
+        }}
+}}
+"#,
+                    code = single_symbol.code,
+                    offsets = single_symbol.offsets,
+                    num_functions = num_functions,
+                ),
+            });
+        }
+        None => {
+            let function_declarations = module_info
+                .functions
+                .iter()
+                .filter_map(|(f_index, sig_index)| {
+                    Some((module_info.local_func_index(f_index)?, sig_index))
+                })
+                .map(|(function_local_index, _sig_index)| {
+                    let function_name =
+                        symbol_registry.symbol_to_name(Symbol::LocalFunction(function_local_index));
+                    // TODO: figure out the signature here too
+                    CStatement::Declaration {
+                        name: function_name,
+                        is_extern: false,
+                        is_const: false,
+                        ctype: CType::Function {
+                            arguments: vec![CType::Void],
+                            return_value: None,
+                        },
+                        definition: None,
+                    }
+                });
+            c_statements.push(CStatement::LiteralConstant {
+                value: r#"
+    // Compiled Wasm function pointers ordered by function index: the order they
+    // appeared in in the Wasm module.
+    "#
+                .to_string(),
+            });
+            c_statements.extend(function_declarations);
+
+            // function pointer array
+            {
+                let function_pointer_array_statements = module_info
+                    .functions
+                    .iter()
+                    .filter_map(|(f_index, sig_index)| {
+                        Some((module_info.local_func_index(f_index)?, sig_index))
+                    })
+                    .map(|(function_local_index, _sig_index)| {
+                        let function_name = symbol_registry
+                            .symbol_to_name(Symbol::LocalFunction(function_local_index));
+                        // TODO: figure out the signature here too
+
+                        CStatement::Cast {
+                            target_type: CType::void_ptr(),
+                            expression: Box::new(CStatement::LiteralConstant {
+                                value: function_name,
+                            }),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                c_statements.push(CStatement::Declaration {
+                    name: "function_pointers".to_string(),
+                    is_extern: false,
+                    is_const: true,
+                    ctype: CType::Array {
+                        inner: Box::new(CType::void_ptr()),
+                    },
+                    definition: Some(Box::new(CStatement::LiteralArray {
+                        items: function_pointer_array_statements,
+                    })),
+                });
+            }
+
+            c_statements.push(CStatement::LiteralConstant {
+                value: "\nstatic void resolve_function_pointers(void) {}\n".to_string(),
+            });
+        }
     }
 
     let func_trampoline_declarations =
@@ -2,11 +2,11 @@
 
 #[cfg(feature = "compiler")]
 use crate::commands::Compile;
-#[cfg(all(feature = "staticlib", feature = "compiler"))]
-use crate::commands::CreateExe;
 #[cfg(feature = "wast")]
 use crate::commands::Wast;
 use crate::commands::{Cache, Config, Inspect, Run, SelfUpdate, Validate};
+#[cfg(all(feature = "staticlib", feature = "compiler"))]
+use crate::commands::{CreateExe, CreateObj, Targets};
 use crate::error::PrettyError;
 use anyhow::Result;
 
@@ -49,6 +49,16 @@ enum WasmerCLIOptions {
     #[structopt(name = "create-exe")]
     CreateExe(CreateExe),
 
+    /// Compile a WebAssembly binary into a native object file
+    #[cfg(all(feature = "staticlib", feature = "compiler"))]
+    #[structopt(name = "create-obj")]
+    CreateObj(CreateObj),
+
+    /// List the targets native executables and objects can be built for
+    #[cfg(all(feature = "staticlib", feature = "compiler"))]
+    #[structopt(name = "targets")]
+    Targets(Targets),
+
     /// Get various configuration information needed
     /// to compile programs which use Wasmer
     #[structopt(name = "config")]
@@ -79,6 +89,10 @@ impl WasmerCLIOptions {
             Self::Compile(compile) => compile.execute(),
             #[cfg(all(feature = "staticlib", feature = "compiler"))]
             Self::CreateExe(create_exe) => create_exe.execute(),
+            #[cfg(all(feature = "staticlib", feature = "compiler"))]
+            Self::CreateObj(create_obj) => create_obj.execute(),
+            #[cfg(all(feature = "staticlib", feature = "compiler"))]
+            Self::Targets(targets) => targets.execute(),
             Self::Config(config) => config.execute(),
             Self::Inspect(inspect) => inspect.execute(),
             #[cfg(feature = "wast")]
@@ -100,8 +114,8 @@ pub fn wasmer_main() {
     let args = std::env::args().collect::<Vec<_>>();
     let command = args.get(1);
     let options = match command.unwrap_or(&"".to_string()).as_ref() {
-        "cache" | "compile" | "config" | "create-exe" | "help" | "inspect" | "run"
-        | "self-update" | "validate" | "wast" => WasmerCLIOptions::from_args(),
+        "cache" | "compile" | "config" | "create-exe" | "create-obj" | "help" | "inspect"
+        | "run" | "self-update" | "targets" | "validate" | "wast" => WasmerCLIOptions::from_args(),
         _ => {
             WasmerCLIOptions::from_iter_safe(args.iter()).unwrap_or_else(|e| {
                 match e.kind {
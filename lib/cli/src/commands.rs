@@ -5,9 +5,13 @@ mod compile;
 mod config;
 #[cfg(all(feature = "staticlib", feature = "compiler"))]
 mod create_exe;
+#[cfg(all(feature = "staticlib", feature = "compiler"))]
+mod create_obj;
 mod inspect;
 mod run;
 mod self_update;
+#[cfg(all(feature = "staticlib", feature = "compiler"))]
+mod targets;
 mod validate;
 #[cfg(feature = "wast")]
 mod wast;
@@ -16,6 +20,10 @@ mod wast;
 pub use compile::*;
 #[cfg(all(feature = "staticlib", feature = "compiler"))]
 pub use create_exe::*;
+#[cfg(all(feature = "staticlib", feature = "compiler"))]
+pub use create_obj::*;
+#[cfg(all(feature = "staticlib", feature = "compiler"))]
+pub use targets::*;
 #[cfg(feature = "wast")]
 pub use wast::*;
 pub use {cache::*, config::*, inspect::*, run::*, self_update::*, validate::*};
@@ -1,10 +1,19 @@
+use crate::artifact_target::ArtifactTarget;
 use crate::store::{EngineType, StoreOptions};
+use crate::utils::check_target_endianness;
 use crate::warning;
 use anyhow::{Context, Result};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 use structopt::StructOpt;
 use wasmer::*;
 
+mod determinism;
+mod slow_patterns;
+
+use slow_patterns::SlowPatterns;
+
 #[derive(Debug, StructOpt)]
 /// The options for the `wasmer compile` subcommand
 pub struct Compile {
@@ -20,10 +29,45 @@ pub struct Compile {
     #[structopt(name = "HEADER PATH", long = "header", parse(from_os_str))]
     header_path: Option<PathBuf>,
 
+    /// Write the debug information (function names and source offsets
+    /// used in backtraces) to this file instead of the output artifact.
+    ///
+    /// Pass it to `wasmer run --debug-file` to get symbolicated backtraces.
+    /// Only supported with the Universal engine.
+    #[structopt(name = "DEBUG PATH", long = "split-debug", parse(from_os_str))]
+    split_debug: Option<PathBuf>,
+
     /// Compilation Target triple
     #[structopt(long = "target")]
     target_triple: Option<Triple>,
 
+    /// Override the ABI of the `--target` triple: `eabi`, `eabihf`, `gnu`
+    /// or `musl`.
+    ///
+    /// On ARM, the floating-point ABI (`eabi` or `eabihf`) and the C
+    /// library (`gnu` or `musl`) are set independently, so
+    /// `--target armv7-unknown-linux-gnueabihf --target-abi musl` compiles
+    /// for `armv7-unknown-linux-musleabihf`. Other architectures only
+    /// support `gnu` and `musl`.
+    #[structopt(long = "target-abi", requires = "target-triple")]
+    target_abi: Option<TargetAbi>,
+
+    /// Warn about code patterns that compile poorly or run slowly with the
+    /// selected compiler, like very large functions, many `call_indirect`
+    /// or memory accesses with less than their natural alignment.
+    ///
+    /// The warnings are advisory only: the compiled code doesn't change.
+    #[structopt(long = "warn-slow-patterns")]
+    warn_slow_patterns: bool,
+
+    /// Compile the module this many times, with a new engine each time,
+    /// and fail if the artifacts aren't all byte-identical.
+    ///
+    /// This is a diagnostic for nondeterminism in the compilers. The
+    /// first differing section (or byte offset) is reported.
+    #[structopt(long = "check-determinism", name = "N")]
+    check_determinism: Option<usize>,
+
     #[structopt(flatten)]
     store: StoreOptions,
 
@@ -31,6 +75,102 @@ pub struct Compile {
     cpu_features: Vec<CpuFeature>,
 }
 
+/// The ABIs supported by `--target-abi`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TargetAbi {
+    /// The ARM EABI, passing floats in integer registers.
+    Eabi,
+    /// The ARM EABI, passing floats in floating-point registers.
+    Eabihf,
+    /// The GNU C library.
+    Gnu,
+    /// The musl C library.
+    Musl,
+}
+
+impl FromStr for TargetAbi {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "eabi" => Ok(Self::Eabi),
+            "eabihf" => Ok(Self::Eabihf),
+            "gnu" => Ok(Self::Gnu),
+            "musl" => Ok(Self::Musl),
+            _ => bail!(
+                "unknown target ABI `{}`, expected `eabi`, `eabihf`, `gnu` or `musl`",
+                s
+            ),
+        }
+    }
+}
+
+impl ToString for TargetAbi {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Eabi => "eabi",
+            Self::Eabihf => "eabihf",
+            Self::Gnu => "gnu",
+            Self::Musl => "musl",
+        }
+        .to_string()
+    }
+}
+
+impl TargetAbi {
+    /// The triple with its environment set to this ABI, checking that the
+    /// architecture and operating system support it.
+    fn apply(self, triple: &Triple) -> Result<Triple> {
+        let is_c_library = matches!(self, Self::Gnu | Self::Musl);
+        if is_c_library && triple.operating_system != OperatingSystem::Linux {
+            bail!(
+                "`--target-abi {}` is only supported for Linux targets, not `{}`",
+                self.to_string(),
+                triple
+            );
+        }
+        let environment = if let Architecture::Arm(_) = triple.architecture {
+            // The C library and the floating-point ABI of the triple, where
+            // an unspecified floating-point ABI defaults to soft-float
+            let (c_library, float_abi) = match triple.environment {
+                Environment::Gnu | Environment::Gnueabi => (Some(Self::Gnu), Self::Eabi),
+                Environment::Gnueabihf => (Some(Self::Gnu), Self::Eabihf),
+                Environment::Musl | Environment::Musleabi => (Some(Self::Musl), Self::Eabi),
+                Environment::Musleabihf => (Some(Self::Musl), Self::Eabihf),
+                Environment::Eabihf => (None, Self::Eabihf),
+                _ => (None, Self::Eabi),
+            };
+            let (c_library, float_abi) = if is_c_library {
+                (Some(self), float_abi)
+            } else {
+                (c_library, self)
+            };
+            match (c_library, float_abi) {
+                (Some(Self::Gnu), Self::Eabihf) => Environment::Gnueabihf,
+                (Some(Self::Gnu), _) => Environment::Gnueabi,
+                (Some(_), Self::Eabihf) => Environment::Musleabihf,
+                (Some(_), _) => Environment::Musleabi,
+                (None, Self::Eabihf) => Environment::Eabihf,
+                (None, _) => Environment::Eabi,
+            }
+        } else {
+            match self {
+                Self::Gnu => Environment::Gnu,
+                Self::Musl => Environment::Musl,
+                _ => bail!(
+                    "`--target-abi {}` isn't supported for `{}` targets, the supported ABIs are `gnu` and `musl`",
+                    self.to_string(),
+                    triple.architecture
+                ),
+            }
+        };
+        Ok(Triple {
+            environment,
+            ..triple.clone()
+        })
+    }
+}
+
 impl Compile {
     /// Runs logic for the `compile` subcommand
     pub fn execute(&self) -> Result<()> {
@@ -60,9 +200,38 @@ impl Compile {
         })
     }
 
+    /// Compile the module `count - 1` more times and compare the
+    /// artifacts with the one of `module`.
+    fn verify_determinism(&self, module: &Module, target: Target, count: usize) -> Result<()> {
+        if count < 2 {
+            bail!("`--check-determinism` needs at least 2 compilations to compare");
+        }
+        let expected = module.serialize()?;
+        for compilation in 2..=count {
+            let (store, _engine_type, _compiler_type) =
+                self.store.get_store_for_target(target.clone())?;
+            let actual = Module::from_file(&store, &self.path)?.serialize()?;
+            if let Some(difference) = determinism::first_difference(&expected, &actual) {
+                bail!(
+                    "compilation {} produced a different artifact than the first one: {}",
+                    compilation,
+                    difference
+                );
+            }
+        }
+        eprintln!("✔ {} compilations produced identical artifacts.", count);
+        Ok(())
+    }
+
     fn inner_execute(&self) -> Result<()> {
-        let target = self
-            .target_triple
+        let target_triple = match (&self.target_triple, self.target_abi) {
+            (Some(target_triple), Some(target_abi)) => Some(target_abi.apply(target_triple)?),
+            (target_triple, _) => target_triple.clone(),
+        };
+        if let Some(target_triple) = &target_triple {
+            check_target_endianness(target_triple)?;
+        }
+        let target = target_triple
             .as_ref()
             .map(|target_triple| {
                 let mut features = self
@@ -76,8 +245,15 @@ impl Compile {
                 Target::new(target_triple.clone(), features)
             })
             .unwrap_or_default();
-        let (store, engine_type, compiler_type) =
-            self.store.get_store_for_target(target.clone())?;
+        let slow_patterns = Arc::new(SlowPatterns::new());
+        let middlewares: Vec<Arc<dyn ModuleMiddleware>> = if self.warn_slow_patterns {
+            vec![slow_patterns.clone() as Arc<dyn ModuleMiddleware>]
+        } else {
+            vec![]
+        };
+        let (store, engine_type, compiler_type) = self
+            .store
+            .get_store_for_target_with_middlewares(target.clone(), middlewares)?;
         let output_filename = self
             .output
             .file_stem()
@@ -98,12 +274,59 @@ impl Compile {
         println!("Compiler: {}", compiler_type.to_string());
         println!("Target: {}", target.triple());
 
+        if self.split_debug.is_some() && engine_type != EngineType::Universal {
+            bail!("`--split-debug` is only supported with the Universal engine");
+        }
+
         let module = Module::from_file(&store, &self.path)?;
-        let _ = module.serialize_to_file(&self.output)?;
+        if let Some(count) = self.check_determinism {
+            self.verify_determinism(&module, target.clone(), count)?;
+        }
+        if self.warn_slow_patterns {
+            let warnings = slow_patterns.report(module.info(), &compiler_type);
+            if warnings == 0 {
+                eprintln!("No slow patterns found.");
+            }
+        }
+        // Recorded for `wasmer inspect --artifact-target`
+        let artifact_target = ArtifactTarget {
+            triple: target.triple().to_string(),
+            compiler: compiler_type.to_string(),
+            cpu_features: target
+                .cpu_features()
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+        };
+        match &self.split_debug {
+            #[cfg(feature = "universal")]
+            Some(debug_path) => {
+                let (mut artifact, debug) = unsafe {
+                    wasmer_engine_universal::UniversalArtifact::split_debug_info(
+                        &module.serialize()?,
+                    )?
+                };
+                artifact.extend(artifact_target.encode()?);
+                std::fs::write(&self.output, artifact)?;
+                std::fs::write(debug_path, debug)?;
+            }
+            _ if engine_type == EngineType::Universal => {
+                let mut artifact = module.serialize()?;
+                artifact.extend(artifact_target.encode()?);
+                std::fs::write(&self.output, artifact)?;
+            }
+            _ => module.serialize_to_file(&self.output)?,
+        }
         eprintln!(
             "✔ File compiled successfully to `{}`.",
             self.output.display(),
         );
+        if let Some(debug_path) = &self.split_debug {
+            eprintln!(
+                "✔ Debug information written successfully to `{}`.",
+                debug_path.display(),
+            );
+        }
 
         #[cfg(feature = "staticlib")]
         if engine_type == EngineType::Staticlib {
@@ -116,6 +339,7 @@ impl Compile {
                 module_info,
                 symbol_registry,
                 metadata_length,
+                None,
             );
 
             let header_path = self.header_path.as_ref().cloned().unwrap_or_else(|| {
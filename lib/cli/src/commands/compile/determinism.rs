@@ -0,0 +1,61 @@
+//! Compare the artifacts of several compilations of a module, used by
+//! `wasmer compile --check-determinism`.
+
+use object::read::{Object, ObjectSection};
+
+/// The number of bytes shown around the first difference.
+const CONTEXT_LENGTH: usize = 16;
+
+/// Describe the first difference between two artifacts, or return `None`
+/// if they are identical.
+///
+/// Artifacts that are native objects (from the Dylib and Staticlib
+/// engines) are compared section by section, so the description names
+/// the first section that differs.
+pub fn first_difference(expected: &[u8], actual: &[u8]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    if let (Ok(expected_file), Ok(actual_file)) =
+        (object::File::parse(expected), object::File::parse(actual))
+    {
+        for expected_section in expected_file.sections() {
+            let name = expected_section.name().unwrap_or("<unnamed>");
+            let expected_data = expected_section.data().unwrap_or_default();
+            let actual_data = match actual_file.section_by_name(name) {
+                Some(actual_section) => actual_section.data().unwrap_or_default(),
+                None => return Some(format!("section `{}` is missing", name)),
+            };
+            if let Some(description) = describe_bytes(expected_data, actual_data) {
+                return Some(format!("section `{}` differs: {}", name, description));
+            }
+        }
+    }
+    describe_bytes(expected, actual)
+}
+
+/// Describe the first differing byte of two buffers, with the bytes that
+/// follow it.
+fn describe_bytes(expected: &[u8], actual: &[u8]) -> Option<String> {
+    let offset = match expected.iter().zip(actual).position(|(a, b)| a != b) {
+        Some(offset) => offset,
+        None if expected.len() == actual.len() => return None,
+        None => expected.len().min(actual.len()),
+    };
+    let hex = |bytes: &[u8]| {
+        let end = (offset + CONTEXT_LENGTH).min(bytes.len());
+        bytes[offset..end]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    Some(format!(
+        "at offset {:#x} (sizes {} and {} bytes)\n  first:   {}\n  current: {}",
+        offset,
+        expected.len(),
+        actual.len(),
+        hex(expected),
+        hex(actual)
+    ))
+}
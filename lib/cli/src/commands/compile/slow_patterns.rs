@@ -0,0 +1,178 @@
+//! Heuristics to detect code patterns that compile poorly or run slowly,
+//! used by `wasmer compile --warn-slow-patterns`.
+//!
+//! The checks run as a middleware, so they see the operators of every
+//! function while the module is compiled. They are advisory only and
+//! don't change the generated code.
+
+use crate::store::CompilerType;
+use crate::warning;
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::{MemoryImmediate, Operator};
+use wasmer::{
+    FunctionMiddleware, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState,
+    ModuleMiddleware,
+};
+use wasmer_types::ModuleInfo;
+
+/// Functions with at least this many operators are considered very large.
+const LARGE_FUNCTION_OPERATORS: usize = 50_000;
+
+/// Functions with at least this many `call_indirect` are reported.
+const MANY_INDIRECT_CALLS: usize = 100;
+
+/// What was seen in a single function.
+#[derive(Debug, Default, Clone)]
+struct FunctionStats {
+    operators: usize,
+    indirect_calls: usize,
+    unaligned_accesses: usize,
+}
+
+/// The module-level middleware collecting the stats of every function.
+#[derive(Debug, Default)]
+pub struct SlowPatterns {
+    stats: Arc<Mutex<Vec<(LocalFunctionIndex, FunctionStats)>>>,
+}
+
+impl SlowPatterns {
+    /// Creates a `SlowPatterns` middleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Print a warning for every slow pattern found in the compiled module.
+    ///
+    /// Returns the number of warnings printed.
+    pub fn report(&self, module_info: &ModuleInfo, compiler_type: &CompilerType) -> usize {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.sort_by_key(|(index, _)| *index);
+
+        let mut warnings = 0;
+        for (local_index, stats) in stats {
+            let index = module_info.func_index(local_index);
+            let function = match module_info.function_names.get(&index) {
+                Some(name) => format!("function {} (`{}`)", index.as_u32(), name),
+                None => format!("function {}", index.as_u32()),
+            };
+            if stats.operators >= LARGE_FUNCTION_OPERATORS {
+                warnings += 1;
+                match compiler_type {
+                    CompilerType::Singlepass => warning!(
+                        "{} is very large ({} operators): Singlepass doesn't optimize across operators, so splitting it or using Cranelift may run faster",
+                        function, stats.operators
+                    ),
+                    _ => warning!(
+                        "{} is very large ({} operators): it stresses the register allocator of {}; consider splitting it or compiling with Singlepass",
+                        function, stats.operators, compiler_type.to_string()
+                    ),
+                }
+            }
+            if stats.indirect_calls >= MANY_INDIRECT_CALLS {
+                warnings += 1;
+                warning!(
+                    "{} has {} `call_indirect`: each one checks the signature at runtime and can't be inlined; prefer direct calls on hot paths",
+                    function, stats.indirect_calls
+                );
+            }
+            if stats.unaligned_accesses > 0 {
+                warnings += 1;
+                warning!(
+                    "{} has {} memory accesses declaring less than their natural alignment, which may be slower on some targets; check the alignment of the data layout",
+                    function, stats.unaligned_accesses
+                );
+            }
+        }
+        warnings
+    }
+}
+
+impl MemoryUsage for SlowPatterns {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+impl ModuleMiddleware for SlowPatterns {
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionSlowPatterns {
+            local_function_index,
+            stats: FunctionStats::default(),
+            module_stats: self.stats.clone(),
+        })
+    }
+}
+
+/// The function-level middleware, which hands its stats over to the
+/// module-level one when the function is done.
+#[derive(Debug)]
+struct FunctionSlowPatterns {
+    local_function_index: LocalFunctionIndex,
+    stats: FunctionStats,
+    module_stats: Arc<Mutex<Vec<(LocalFunctionIndex, FunctionStats)>>>,
+}
+
+impl FunctionMiddleware for FunctionSlowPatterns {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        self.stats.operators += 1;
+        if let Operator::CallIndirect { .. } = operator {
+            self.stats.indirect_calls += 1;
+        }
+        if let Some((memarg, natural_alignment)) = memory_access(&operator) {
+            if memarg.align < natural_alignment {
+                self.stats.unaligned_accesses += 1;
+            }
+        }
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+impl Drop for FunctionSlowPatterns {
+    fn drop(&mut self) {
+        if let Ok(mut module_stats) = self.module_stats.lock() {
+            module_stats.push((self.local_function_index, mem::take(&mut self.stats)));
+        }
+    }
+}
+
+/// The memory argument of a plain load or store, with the log2 of its
+/// natural alignment.
+fn memory_access(operator: &Operator) -> Option<(MemoryImmediate, u8)> {
+    use Operator::*;
+    match *operator {
+        I32Load8S { memarg }
+        | I32Load8U { memarg }
+        | I64Load8S { memarg }
+        | I64Load8U { memarg }
+        | I32Store8 { memarg }
+        | I64Store8 { memarg } => Some((memarg, 0)),
+        I32Load16S { memarg }
+        | I32Load16U { memarg }
+        | I64Load16S { memarg }
+        | I64Load16U { memarg }
+        | I32Store16 { memarg }
+        | I64Store16 { memarg } => Some((memarg, 1)),
+        I32Load { memarg }
+        | F32Load { memarg }
+        | I64Load32S { memarg }
+        | I64Load32U { memarg }
+        | I32Store { memarg }
+        | F32Store { memarg }
+        | I64Store32 { memarg } => Some((memarg, 2)),
+        I64Load { memarg } | F64Load { memarg } | I64Store { memarg } | F64Store { memarg } => {
+            Some((memarg, 3))
+        }
+        V128Load { memarg } | V128Store { memarg } => Some((memarg, 4)),
+        _ => None,
+    }
+}
@@ -1,26 +1,73 @@
 //! Create a standalone native executable for a given Wasm file.
 
-use crate::store::{CompilerOptions, EngineType};
+use crate::embedded_source::SourceCompression;
+use crate::store::{CompilerOptions, CompilerType, EngineType};
+use crate::utils::check_target_endianness;
+use crate::warning;
+use crate::wasm_features::enabled_wasm_features;
 use anyhow::{Context, Result};
+use bytesize::ByteSize;
+use object::write::{StandardSection, StandardSegment, Symbol, SymbolSection};
+use object::{SectionKind, SymbolFlags, SymbolKind, SymbolScope};
+use serde::{Deserialize, Serialize};
 use std::env;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
 use structopt::StructOpt;
 use wasmer::*;
+use wasmer_engine_staticlib::MetadataCompression;
+
+pub(crate) mod manifest;
+mod stage;
+mod strip_exports;
+mod sysroot;
+
+use manifest::{BuildManifest, ManifestFile, ManifestTool};
+use stage::Stage;
+use sysroot::Sysroot;
 
 const WASMER_MAIN_C_SOURCE: &[u8] = include_bytes!("wasmer_create_exe_main.c");
+const WASMER_DYLIB_C_SOURCE: &[u8] = include_bytes!("wasmer_create_exe_dylib.c");
+
+#[cfg(not(windows))]
+const C_COMPILER: &str = "cc";
+// We must use a C++ compiler on Windows because wasm.h uses `static_assert`
+// which isn't available in `clang` on Windows.
+#[cfg(windows)]
+const C_COMPILER: &str = "clang++";
+
+/// The executable packer used by `--self-extract`.
+const PACKER: &str = "upx";
+
+/// The header of the C glue, describing the module object.
+const HEADER_FILE: &str = "my_wasm.h";
 
-#[derive(Debug, StructOpt)]
+/// The object copier used by `--objcopy-redefine` and
+/// `--weak-runtime-symbols`.
+const OBJCOPY: &str = "objcopy";
+
+/// The tool merging the slices of `--universal`.
+const LIPO: &str = "lipo";
+
+#[derive(Debug, Clone, StructOpt)]
 /// The options for the `wasmer create-exe` subcommand
 pub struct CreateExe {
     /// Input file
-    #[structopt(name = "FILE", parse(from_os_str))]
-    path: PathBuf,
+    #[structopt(name = "FILE", parse(from_os_str), required_unless = "link-from")]
+    path: Option<PathBuf>,
 
     /// Output file
-    #[structopt(name = "OUTPUT PATH", short = "o", parse(from_os_str))]
-    output: PathBuf,
+    #[structopt(
+        name = "OUTPUT PATH",
+        short = "o",
+        parse(from_os_str),
+        required_unless = "objects-only"
+    )]
+    output: Option<PathBuf>,
 
     /// Compilation Target triple
     #[structopt(long = "target")]
@@ -32,88 +79,1712 @@ pub struct CreateExe {
     #[structopt(short = "m", multiple = true)]
     cpu_features: Vec<CpuFeature>,
 
+    /// Target a x86_64 microarchitecture level: `baseline`, `v2`, `v3`,
+    /// `v4` or `native`.
+    ///
+    /// `v2` adds SSE4.2 and POPCNT, `v3` adds AVX2, BMI and LZCNT, `v4`
+    /// adds AVX-512, and `native` uses the features of the host. The CPU
+    /// features passed with `-m` are added to the profile's.
+    #[structopt(long = "target-feature-profile")]
+    target_feature_profile: Option<FeatureProfile>,
+
     /// Additional libraries to link against.
     /// This is useful for fixing linker errors that may occur on some systems.
     #[structopt(short = "l", multiple = true)]
     libraries: Vec<String>,
+
+    /// Runtime search path for the shared libraries linked with `-l`.
+    ///
+    /// Write `$ORIGIN` for the directory of the executable: it is translated
+    /// to `@loader_path` on macOS. Ignored on Windows.
+    #[structopt(long = "rpath", name = "RPATH", multiple = true)]
+    rpaths: Vec<String>,
+
+    /// The linker family to use: `gnu`, `lld`, `msvc` or `ld64`.
+    ///
+    /// Defaults to linking through the system C compiler (`gnu`, or
+    /// `ld64` on macOS).
+    #[structopt(long = "linker-flavor")]
+    linker_flavor: Option<LinkerFlavor>,
+
+    /// Path to the linker binary, overriding the one of `--linker-flavor`.
+    #[structopt(long = "linker", parse(from_os_str))]
+    linker: Option<PathBuf>,
+
+    /// Look for the C compiler and the linker in this directory (and its
+    /// `bin` subdirectory) instead of the `PATH`, for hermetic builds.
+    ///
+    /// A `--linker` given as a path rather than a bare name is used as is.
+    #[structopt(long = "toolchain-root", parse(from_os_str))]
+    toolchain_root: Option<PathBuf>,
+
+    /// Compile and link against the headers and libraries of the target
+    /// in this directory, like `/opt/aarch64-sysroot`, when
+    /// cross-compiling with `--target`.
+    ///
+    /// The C compiler and the linker get `--sysroot`, and the directories
+    /// of the sysroot holding the C runtime and the libc are added to the
+    /// library search path: the multiarch directories of Debian-like
+    /// sysroots (like `usr/lib/aarch64-linux-gnu`), then `lib64`,
+    /// `usr/lib64`, `lib` and `usr/lib`. It fails if they aren't found.
+    /// An Apple SDK is expected to hold `usr/lib/libSystem.tbd`. Only
+    /// Linux and Apple targets are supported, and not with the `msvc`
+    /// linker flavor. Combine it with `--toolchain-root` to use a cross
+    /// toolchain.
+    #[structopt(long = "cross-sysroot", parse(from_os_str))]
+    cross_sysroot: Option<PathBuf>,
+
+    /// The kind of output to produce: `exe`, `pie` or `dylib`.
+    ///
+    /// `exe` is an executable with the toolchain defaults, `pie` forces a
+    /// position-independent executable, and `dylib` is a shared library
+    /// to `dlopen` from a host application. A shared library exports the
+    /// Wasm C API and `wasmer_staticlib_engine_new(store, name)`, which
+    /// creates the module from a store of a `STATICLIB` engine, like the
+    /// `main` of `wasmer_create_exe_main.c` does. Not available for
+    /// Windows targets.
+    #[structopt(long = "output-type", default_value = "exe")]
+    output_type: OutputType,
+
+    /// Produce a static position-independent executable, which has no
+    /// dynamic dependencies but still benefits from ASLR.
+    ///
+    /// Only available for Linux targets, with a toolchain and libc that
+    /// support `-static-pie` (like GCC 8+ or Clang 9+ with glibc 2.27+).
+    #[structopt(long = "static-pie")]
+    static_pie: bool,
+
+    /// Embed a build ID in the output, to match it with its debug file in
+    /// crash reports: `sha1`, `uuid` or `none`.
+    ///
+    /// `sha1` hashes the output, so identical builds get the same ID,
+    /// while `uuid` is random and makes every build different. `none`
+    /// removes the ID some toolchains add by default. Without this flag,
+    /// the toolchain default is kept. Only supported by the `gnu` and
+    /// `lld` linker flavors; Apple's linker always adds a UUID.
+    #[structopt(long = "build-id")]
+    build_id: Option<BuildId>,
+
+    /// Pass this version script to the linker, to choose the symbols
+    /// exported by the output.
+    ///
+    /// Only supported by the `gnu` and `lld` linker flavors. Mostly useful
+    /// with `--output-type dylib`, to export only some of the entrypoints.
+    #[structopt(long = "ld-version-script", parse(from_os_str))]
+    ld_version_script: Option<PathBuf>,
+
+    /// The visibility of the symbols of the module: `default` or `hidden`.
+    ///
+    /// `hidden` keeps the functions, trampolines, sections and metadata of
+    /// the module out of the exported symbols, so several shared libraries
+    /// produced by `create-exe` can be loaded in the same process.
+    ///
+    /// `hidden` can't be used with `--ld-version-script`.
+    #[structopt(long = "default-visibility", default_value = "default")]
+    default_visibility: SymbolVisibility,
+
+    /// Attach this symbol version, like `VERSION_1.0`, to the symbols
+    /// exported by a `--output-type dylib` shared library.
+    ///
+    /// The library gets a version definition with the name, and its
+    /// entrypoints are exported as `wasm_store_new@@VERSION_1.0`. An
+    /// application linked with the library records the version of the
+    /// symbols it uses, and the dynamic loader refuses to load a later
+    /// library that doesn't define that version anymore. To keep the
+    /// existing consumers working, only add entrypoints to a version once
+    /// it's shipped, and introduce a new version for incompatible changes.
+    /// The versions of a library are listed by `objdump -T`. Only
+    /// supported by the `gnu` and `lld` linker flavors.
+    #[structopt(
+        long = "emit-symbol-versions",
+        name = "VERSION",
+        conflicts_with = "ld-version-script"
+    )]
+    emit_symbol_versions: Option<String>,
+
+    /// Compression for the module metadata embedded in the executable:
+    /// `none`, `gzip` or `zstd`.
+    ///
+    /// The metadata is decompressed when the executable starts, before the
+    /// module is deserialized.
+    #[structopt(long = "compress-with", default_value = "none")]
+    compress_with: CompressionAlgorithm,
+
+    /// Compression level to use with `--compress-with gzip` (0-9).
+    #[structopt(long = "compress-level")]
+    compress_level: Option<u32>,
+
+    /// Compression level to use with `--compress-with zstd` (1-22).
+    #[structopt(long = "zstd-level")]
+    zstd_level: Option<i32>,
+
+    /// Prefix the symbols of the module with a hash of its contents:
+    /// `sha256`, `blake3` or `crc32`.
+    ///
+    /// This avoids symbol collisions when linking several modules together.
+    /// The symbol names depend on the algorithm, so objects and headers
+    /// produced with a different algorithm can't be linked with the new ones.
+    #[structopt(long = "prefix-algorithm")]
+    prefix_algorithm: Option<PrefixAlgorithm>,
+
+    /// Embed the original Wasm module in the executable, for debugging.
+    ///
+    /// The module is compressed like the metadata with `--compress-with`,
+    /// and can be recovered with `wasmer inspect --extract-source`.
+    #[structopt(long = "include-source")]
+    include_source: bool,
+
+    /// Write a JSON manifest of the inputs, settings and tools of the build
+    /// to this file, once the executable is created.
+    #[structopt(long = "output-manifest", parse(from_os_str))]
+    output_manifest: Option<PathBuf>,
+
+    /// Embed the build manifest in the executable, which prints it and
+    /// exits when run with `--wasmer-info` as its only argument.
+    ///
+    /// The embedded manifest is the one of `--output-manifest`, without
+    /// the hash of the executable itself. Shared libraries export it as
+    /// the `wasmer_build_manifest` C string.
+    #[structopt(long = "embed-manifest")]
+    embed_manifest: bool,
+
+    /// Store the SHA-256 of the embedded module in the executable, which
+    /// checks it when it starts and aborts if it doesn't match.
+    ///
+    /// The serialized module (its metadata) and the compiled functions are
+    /// hashed once the executable is linked, so it must keep its symbol
+    /// table. Not available with `--output-type dylib`, nor with the LLVM
+    /// compiler.
+    #[structopt(long = "hash-embedded-wasm")]
+    hash_embedded_wasm: bool,
+
+    /// Compress the whole executable with UPX, which must be installed
+    /// (or be in `--toolchain-root`).
+    ///
+    /// The executable decompresses itself in memory at each launch, which
+    /// costs a bit of startup time, and some antivirus software flags
+    /// packed executables. `--include-source` can't be extracted from a
+    /// packed executable until it's decompressed with `upx -d`.
+    #[structopt(long = "self-extract")]
+    self_extract: bool,
+
+    /// Call this export instead of `_start` when the executable runs, as
+    /// `ATOM=EXPORT` or `EXPORT`.
+    ///
+    /// The module is the only atom, named after its file stem. The
+    /// arguments of the executable (other than `--dir` and `--mapdir`) are
+    /// parsed as the parameters of the export, which must all be numbers,
+    /// and its results are printed, like with `wasmer run --invoke`.
+    /// Modules without imports are run without WASI.
+    #[structopt(long = "atom-entry", name = "ATOM=EXPORT")]
+    atom_entry: Option<AtomEntry>,
+
+    /// Run the executable once it's built, with the arguments after `--`,
+    /// and fail if it doesn't exit successfully.
+    ///
+    /// It runs in an empty temporary directory, and its output is printed
+    /// if it fails. The executable is kept, to investigate the failure.
+    /// Only available for executables built for the host.
+    #[structopt(long = "verify-run")]
+    verify_run: bool,
+
+    /// The arguments of the `--verify-run` executable.
+    #[structopt(name = "VERIFY ARGS", last = true, requires = "verify-run")]
+    verify_run_args: Vec<String>,
+
+    /// Print the objects and libraries passed to the linker, in link
+    /// order, and the linker command, and exit without linking.
+    ///
+    /// `--list-objects` (or `--list-objects=before`) exits before compiling
+    /// the module, listing the objects to generate by their file name.
+    /// `--list-objects=after` generates the objects first, and keeps them
+    /// in a temporary directory. The C runtime startup files added by the
+    /// linker aren't listed.
+    #[structopt(long = "list-objects", require_equals = true)]
+    list_objects: Option<Option<ListObjects>>,
+
+    /// Rename symbols of the module object before linking, like
+    /// `objcopy --redefine-sym`, as `OLD=NEW[,OLD=NEW...]` or the path of a
+    /// file with an `OLD NEW` pair per line, like `objcopy --redefine-syms`.
+    ///
+    /// Only the symbols defined by the module can be renamed, to names it
+    /// doesn't define yet. The generated C code refers to the new names.
+    /// Mach-O symbols are named without their leading underscore. Needs
+    /// `objcopy` (or the one of `--toolchain-root`).
+    #[structopt(long = "objcopy-redefine", name = "OLD=NEW,...|FILE")]
+    objcopy_redefine: Option<String>,
+
+    /// Prefix all the symbols defined by the module and its C glue with
+    /// `NAMESPACE_`, so executables built from the same module (or with
+    /// the same symbol prefix) can be linked together.
+    ///
+    /// Unlike `--prefix-algorithm`, which only changes the hash in the
+    /// names of the module symbols, this renames every global symbol of
+    /// the module object: the functions, the trampolines, the custom
+    /// sections and the metadata. The global symbols of the compiled C
+    /// glue are renamed too, except `main`: for `--output-type dylib`,
+    /// the entrypoint becomes `NAMESPACE_wasmer_staticlib_engine_new`.
+    /// The objects of `--include-source` and `--embed-manifest` are left
+    /// unchanged. Needs `objcopy` (or the one of `--toolchain-root`).
+    #[structopt(
+        long = "symbol-namespace",
+        alias = "map-symbols-to",
+        name = "NAMESPACE",
+        conflicts_with = "OLD=NEW,...|FILE"
+    )]
+    symbol_namespace: Option<String>,
+
+    /// Make the `wasmer_vm_*` runtime functions of libwasmer weak symbols,
+    /// so the executable can provide its own definitions of them.
+    ///
+    /// These are the functions the compiled module calls into, like
+    /// `wasmer_vm_memory32_grow`, `wasmer_vm_raise_trap` or the math
+    /// libcalls (`wasmer_vm_f32_ceil`, ...). To override some of them,
+    /// define them with the same signature in a static library passed
+    /// with `-l`, which is then linked before libwasmer. libwasmer is
+    /// weakened in a copy with `objcopy` (or the one of
+    /// `--toolchain-root`). Only supported by the `gnu` and `lld` linker
+    /// flavors.
+    #[structopt(long = "weak-runtime-symbols")]
+    weak_runtime_symbols: bool,
+
+    /// Remove the function exports whose name matches this glob (with `*`
+    /// and `?`) from the module before compiling it.
+    ///
+    /// Exports whose function is still called or referenced from the rest
+    /// of the module are kept, and so is the entry export (`_start`, or the
+    /// one of `--atom-entry`). The bodies of the functions that are no
+    /// longer reachable are replaced by an `unreachable` stub, so they
+    /// aren't compiled into the executable.
+    #[structopt(long = "strip-exports", name = "PATTERN")]
+    strip_exports: Option<String>,
+
+    /// Run this command once the output is linked, like
+    /// `codesign --sign ID {output}`, and fail if it doesn't exit
+    /// successfully.
+    ///
+    /// The command is split on whitespace and run without a shell, in the
+    /// temporary directory holding the objects. These tokens are replaced
+    /// in its arguments: `{output}` by the absolute path of the output,
+    /// `{objects}` by the objects passed to the linker (an argument made
+    /// of `{objects}` alone becomes one argument per object), and
+    /// `{target}` by the target triple. It runs after `--self-extract`
+    /// and before `--verify-run`.
+    #[structopt(long = "run-after-link", name = "COMMAND")]
+    run_after_link: Option<String>,
+
+    /// Optimize the module and the C glue together at link time with
+    /// ThinLTO, inlining across them.
+    ///
+    /// The module is emitted as LLVM bitcode instead of machine code,
+    /// and the C compiler turns it into ThinLTO bitcode, with the summary
+    /// the linker needs to optimize and generate the code of the objects
+    /// in parallel. The glue is compiled with `-flto=thin` too. Requires
+    /// the LLVM compiler, a `clang` at least as recent as the LLVM of
+    /// Wasmer, and the `lld` (or `ld64`) linker flavor. The bitcode can't
+    /// be rewritten by `objcopy` or hashed, so `--symbol-namespace`,
+    /// `--objcopy-redefine` and `--hash-embedded-wasm` are not supported.
+    #[structopt(
+        long = "thin-lto",
+        conflicts_with_all = &[
+            "NAMESPACE",
+            "OLD=NEW,...|FILE",
+            "hash-embedded-wasm",
+            "objects-only",
+            "link-from",
+        ]
+    )]
+    thin_lto: bool,
+
+    /// Build an Apple universal binary with a slice for each of these
+    /// architectures, like `x86_64,arm64`, instead of a binary for a
+    /// single target.
+    ///
+    /// The module is compiled and linked for each architecture, and the
+    /// slices are merged with `lipo`. Only available on macOS, with the
+    /// Xcode Command Line Tools: the C compiler must target both
+    /// architectures, and libwasmer must be a universal library too (as
+    /// merged with `lipo -create`). `--verify-run` runs the slice of the
+    /// host.
+    #[structopt(
+        long = "universal",
+        alias = "target-variant",
+        name = "ARCHS",
+        conflicts_with_all = &[
+            "target-triple",
+            "target-feature-profile",
+            "output-manifest",
+            "self-extract",
+            "list-objects",
+            "objects-only",
+            "link-from",
+        ]
+    )]
+    universal: Option<UniversalArchs>,
+
+    /// Only compile the module, writing its objects to this directory, and
+    /// stop before linking, to link them later with `--link-from`, like on
+    /// another machine with a known-good toolchain.
+    ///
+    /// The directory gets the objects (including the ones of
+    /// `--include-source`), the header of the C glue and
+    /// `wasmer_stage.json`, which records the target, the settings of the
+    /// glue and the version of Wasmer. The glue is compiled when linking,
+    /// with the C compiler of the link machine, so the link options, like
+    /// `--linker-flavor`, `-l` or `--output-type`, are given to
+    /// `--link-from`.
+    #[structopt(
+        long = "objects-only",
+        parse(from_os_str),
+        conflicts_with_all = &[
+            "OUTPUT PATH",
+            "link-from",
+            "embed-manifest",
+            "output-manifest",
+            "list-objects",
+            "verify-run",
+            "self-extract",
+            "COMMAND",
+        ]
+    )]
+    objects_only: Option<PathBuf>,
+
+    /// Link the objects written by `--objects-only` to this directory into
+    /// the output, instead of compiling a module.
+    ///
+    /// The output is built for the target of the objects, with the same
+    /// version of Wasmer, and the options of the compilation (like the
+    /// compiler, `--atom-entry` or `--symbol-namespace`) are the ones
+    /// given to `--objects-only`.
+    #[structopt(
+        long = "link-from",
+        parse(from_os_str),
+        conflicts_with_all = &[
+            "FILE",
+            "target-triple",
+            "embed-manifest",
+            "output-manifest",
+            "include-source",
+            "hash-embedded-wasm",
+            "ATOM=EXPORT",
+            "PATTERN",
+            "NAMESPACE",
+            "OLD=NEW,...|FILE",
+        ]
+    )]
+    link_from: Option<PathBuf>,
+}
+
+/// A symbol of the module renamed with `--objcopy-redefine` or
+/// `--symbol-namespace`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolRedefinition {
+    old: String,
+    new: String,
+}
+
+/// When `--list-objects` lists the inputs of the linker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListObjects {
+    /// Before compiling the module.
+    Before,
+    /// After compiling the module and the C code.
+    After,
+}
+
+impl FromStr for ListObjects {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "before" => Ok(Self::Before),
+            "after" => Ok(Self::After),
+            _ => bail!(
+                "unknown `--list-objects` value `{}`, expected `before` or `after`",
+                s
+            ),
+        }
+    }
+}
+
+/// The export called by the executable, provided with `--atom-entry`.
+#[derive(Debug, Clone)]
+struct AtomEntry {
+    atom: Option<String>,
+    export: String,
+}
+
+impl FromStr for AtomEntry {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (atom, export) = match s.split_once('=') {
+            Some((atom, export)) => (Some(atom.to_string()), export),
+            None => (None, s),
+        };
+        if export.is_empty() || atom.as_deref() == Some("") {
+            bail!("invalid entry `{}`, expected `ATOM=EXPORT` or `EXPORT`", s);
+        }
+        Ok(Self {
+            atom,
+            export: export.to_string(),
+        })
+    }
+}
+
+/// The macOS targets of the slices of a universal binary, provided with
+/// `--universal`.
+#[derive(Debug, Clone)]
+struct UniversalArchs(Vec<Triple>);
+
+impl FromStr for UniversalArchs {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut triples: Vec<Triple> = vec![];
+        for arch in s.split(',') {
+            let triple = match arch {
+                "x86_64" => "x86_64-apple-darwin",
+                "arm64" | "aarch64" => "aarch64-apple-darwin",
+                _ => bail!(
+                    "unknown architecture `{}`, expected `x86_64` or `arm64`",
+                    arch
+                ),
+            };
+            let triple = Triple::from_str(triple).map_err(|err| anyhow!("{}", err))?;
+            if triples.contains(&triple) {
+                bail!("the architecture `{}` is listed twice", arch);
+            }
+            triples.push(triple);
+        }
+        if triples.len() < 2 {
+            bail!("a universal binary needs at least two architectures, like `x86_64,arm64`");
+        }
+        Ok(Self(triples))
+    }
+}
+
+impl ToString for UniversalArchs {
+    fn to_string(&self) -> String {
+        self.0
+            .iter()
+            .map(|triple| triple.architecture.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The x86_64 microarchitecture levels supported by
+/// `--target-feature-profile`, following the x86-64 psABI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FeatureProfile {
+    Baseline,
+    V2,
+    V3,
+    V4,
+    /// The features of the host CPU.
+    Native,
+}
+
+impl FromStr for FeatureProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "baseline" => Ok(Self::Baseline),
+            "v2" => Ok(Self::V2),
+            "v3" => Ok(Self::V3),
+            "v4" => Ok(Self::V4),
+            "native" => Ok(Self::Native),
+            _ => bail!(
+                "unknown target feature profile `{}`, expected `baseline`, `v2`, `v3`, `v4` or `native`",
+                s
+            ),
+        }
+    }
+}
+
+impl ToString for FeatureProfile {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Baseline => "baseline".to_string(),
+            Self::V2 => "v2".to_string(),
+            Self::V3 => "v3".to_string(),
+            Self::V4 => "v4".to_string(),
+            Self::Native => "native".to_string(),
+        }
+    }
+}
+
+impl FeatureProfile {
+    /// The CPU features of the profile, including the ones of the lower
+    /// levels.
+    fn cpu_features(self) -> Vec<CpuFeature> {
+        const BASELINE: &[CpuFeature] = &[CpuFeature::SSE2];
+        const V2: &[CpuFeature] = &[
+            CpuFeature::SSE3,
+            CpuFeature::SSSE3,
+            CpuFeature::SSE41,
+            CpuFeature::SSE42,
+            CpuFeature::POPCNT,
+        ];
+        const V3: &[CpuFeature] = &[
+            CpuFeature::AVX,
+            CpuFeature::AVX2,
+            CpuFeature::BMI1,
+            CpuFeature::BMI2,
+            CpuFeature::LZCNT,
+        ];
+        const V4: &[CpuFeature] = &[
+            CpuFeature::AVX512F,
+            CpuFeature::AVX512DQ,
+            CpuFeature::AVX512VL,
+        ];
+        let levels: &[&[CpuFeature]] = match self {
+            Self::Baseline => &[BASELINE],
+            Self::V2 => &[BASELINE, V2],
+            Self::V3 => &[BASELINE, V2, V3],
+            Self::V4 => &[BASELINE, V2, V3, V4],
+            Self::Native => return CpuFeature::for_host().iter().collect(),
+        };
+        levels
+            .iter()
+            .flat_map(|level| level.iter().cloned())
+            .collect()
+    }
+}
+
+/// The compression algorithms supported by `--compress-with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompressionAlgorithm {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// The zstd compression level used without `--zstd-level`.
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+impl FromStr for CompressionAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            _ => bail!(
+                "unknown compression algorithm `{}`, expected `none`, `gzip` or `zstd`",
+                s
+            ),
+        }
+    }
+}
+
+impl CompressionAlgorithm {
+    fn to_metadata_compression(
+        self,
+        level: Option<u32>,
+        zstd_level: Option<i32>,
+    ) -> Result<MetadataCompression> {
+        if zstd_level.is_some() && self != Self::Zstd {
+            bail!("`--zstd-level` can only be used with `--compress-with zstd`");
+        }
+        match self {
+            Self::None => {
+                if level.is_some() {
+                    warning!("`--compress-level` is ignored when no compression is used");
+                }
+                Ok(MetadataCompression::None)
+            }
+            Self::Gzip => {
+                let level = level.unwrap_or(6);
+                if level > 9 {
+                    bail!(
+                        "the gzip compression level must be between 0 and 9, got {}",
+                        level
+                    );
+                }
+                Ok(MetadataCompression::Gzip(level))
+            }
+            Self::Zstd => {
+                if level.is_some() {
+                    bail!("use `--zstd-level` to set the zstd compression level, not `--compress-level`");
+                }
+                let level = zstd_level.unwrap_or(DEFAULT_ZSTD_LEVEL);
+                if !(1..=22).contains(&level) {
+                    bail!(
+                        "the zstd compression level must be between 1 and 22, got {}",
+                        level
+                    );
+                }
+                Ok(MetadataCompression::Zstd(level))
+            }
+        }
+    }
+}
+
+/// Parse `s` as the name of one of `variants`, naming the kind of value
+/// in the error otherwise.
+fn parse_variant<T: Copy>(variants: &[(&str, T)], what: &str, s: &str) -> Result<T> {
+    if let Some((_, value)) = variants.iter().find(|(name, _)| *name == s) {
+        return Ok(*value);
+    }
+    let names = variants
+        .iter()
+        .map(|(name, _)| format!("`{}`", name))
+        .collect::<Vec<_>>();
+    let expected = match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, others)) => format!("{} or {}", others.join(", "), last),
+        None => unreachable!("no variants"),
+    };
+    bail!("unknown {} `{}`, expected {}", what, s, expected)
+}
+
+/// The name of `value` in `variants`.
+fn variant_name<T: Copy + PartialEq>(variants: &[(&'static str, T)], value: T) -> &'static str {
+    variants
+        .iter()
+        .find(|(_, variant)| *variant == value)
+        .map(|(name, _)| *name)
+        .expect("every variant has a name")
+}
+
+/// The kinds of output supported by `--output-type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputType {
+    /// An executable, with the toolchain defaults.
+    Exe,
+    /// A position-independent executable.
+    Pie,
+    /// A shared library.
+    Dylib,
+}
+
+impl OutputType {
+    /// The name of each variant, as passed on the command line.
+    const VARIANTS: &'static [(&'static str, Self)] = &[
+        ("exe", Self::Exe),
+        ("pie", Self::Pie),
+        ("dylib", Self::Dylib),
+    ];
+}
+
+impl FromStr for OutputType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_variant(Self::VARIANTS, "output type", s)
+    }
+}
+
+impl fmt::Display for OutputType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(variant_name(Self::VARIANTS, *self))
+    }
+}
+
+/// The build IDs supported by `--build-id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BuildId {
+    /// A SHA-1 hash of the output.
+    Sha1,
+    /// A random UUID.
+    Uuid,
+    /// No build ID.
+    None,
+}
+
+impl BuildId {
+    /// The name of each variant, as passed on the command line.
+    const VARIANTS: &'static [(&'static str, Self)] = &[
+        ("sha1", Self::Sha1),
+        ("uuid", Self::Uuid),
+        ("none", Self::None),
+    ];
+}
+
+impl FromStr for BuildId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_variant(Self::VARIANTS, "build ID", s)
+    }
+}
+
+impl fmt::Display for BuildId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(variant_name(Self::VARIANTS, *self))
+    }
+}
+
+/// The symbol visibilities supported by `--default-visibility`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymbolVisibility {
+    /// The symbols of the module are exported.
+    Default,
+    /// The symbols of the module are hidden.
+    Hidden,
+}
+
+impl SymbolVisibility {
+    /// The name of each variant, as passed on the command line.
+    const VARIANTS: &'static [(&'static str, Self)] =
+        &[("default", Self::Default), ("hidden", Self::Hidden)];
+}
+
+impl FromStr for SymbolVisibility {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_variant(Self::VARIANTS, "visibility", s)
+    }
+}
+
+impl fmt::Display for SymbolVisibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(variant_name(Self::VARIANTS, *self))
+    }
+}
+
+/// The patterns matching the symbols defined by the module object.
+const MODULE_SYMBOL_PATTERNS: &[&str] = &[
+    "wasmer_function_*",
+    "wasmer_section_*",
+    "wasmer_trampoline_*",
+    "WASMER_METADATA*",
+    "WASMER_CODE_*",
+];
+
+/// The hash algorithms supported by `--prefix-algorithm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrefixAlgorithm {
+    Sha256,
+    Blake3,
+    Crc32,
+}
+
+impl PrefixAlgorithm {
+    /// The name of each variant, as passed on the command line.
+    const VARIANTS: &'static [(&'static str, Self)] = &[
+        ("sha256", Self::Sha256),
+        ("blake3", Self::Blake3),
+        ("crc32", Self::Crc32),
+    ];
+}
+
+impl FromStr for PrefixAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_variant(Self::VARIANTS, "prefix algorithm", s)
+    }
+}
+
+impl fmt::Display for PrefixAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(variant_name(Self::VARIANTS, *self))
+    }
+}
+
+impl PrefixAlgorithm {
+    /// Derive the symbol prefix, as lowercase hex, from the Wasm bytes.
+    fn prefix(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Sha256 => {
+                use sha2::{Digest, Sha256};
+                let hash = Sha256::digest(bytes);
+                hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+            }
+            Self::Blake3 => blake3::hash(bytes).to_hex().to_string(),
+            Self::Crc32 => {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(bytes);
+                format!("{:08x}", hasher.finalize())
+            }
+        }
+    }
+}
+
+/// The tools invoked to build the output.
+#[derive(Debug)]
+struct Toolchain {
+    c_compiler: PathBuf,
+    linker_flavor: LinkerFlavor,
+    linker: PathBuf,
+    /// The packer for `--self-extract`.
+    packer: Option<PathBuf>,
+    /// The object copier for `--objcopy-redefine`.
+    objcopy: Option<PathBuf>,
+    /// The slice merger for `--universal`.
+    lipo: Option<PathBuf>,
+    /// The target's headers and libraries, for `--cross-sysroot`.
+    sysroot: Option<Sysroot>,
+}
+
+/// The linker families supported by `--linker-flavor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkerFlavor {
+    /// The GNU linker, through the C compiler driver.
+    Gnu,
+    /// LLVM's linker, through the `clang` driver.
+    Lld,
+    /// Microsoft's `link.exe`.
+    Msvc,
+    /// Apple's linker, through the C compiler driver.
+    Ld64,
+}
+
+impl LinkerFlavor {
+    /// The name of each variant, as passed on the command line.
+    const VARIANTS: &'static [(&'static str, Self)] = &[
+        ("gnu", Self::Gnu),
+        ("lld", Self::Lld),
+        ("msvc", Self::Msvc),
+        ("ld64", Self::Ld64),
+    ];
+}
+
+impl FromStr for LinkerFlavor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_variant(Self::VARIANTS, "linker flavor", s)
+    }
+}
+
+impl fmt::Display for LinkerFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(variant_name(Self::VARIANTS, *self))
+    }
+}
+
+impl LinkerFlavor {
+    /// The linker flavor used for the target when none is provided.
+    fn default_for_target(triple: &Triple) -> Self {
+        match triple.operating_system {
+            OperatingSystem::Darwin | OperatingSystem::MacOSX { .. } | OperatingSystem::Ios => {
+                Self::Ld64
+            }
+            _ => Self::Gnu,
+        }
+    }
+
+    /// The linker binary used for this flavor when `--linker` isn't provided.
+    fn default_linker(self) -> &'static str {
+        match self {
+            // We keep using `clang` on Windows, as `cc` is not usually available.
+            Self::Gnu if cfg!(windows) => "clang",
+            Self::Gnu | Self::Ld64 => "cc",
+            Self::Lld => "clang",
+            Self::Msvc => "link.exe",
+        }
+    }
+
+    /// A hint on how to install the linker for this flavor.
+    fn install_hint(self) -> &'static str {
+        match self {
+            Self::Gnu => "install a C toolchain, such as `gcc` or `clang`",
+            Self::Lld => "install `clang` and `lld` from LLVM",
+            Self::Msvc => {
+                "install the Visual Studio Build Tools and run from a Developer Command Prompt"
+            }
+            Self::Ld64 => "install the Xcode Command Line Tools with `xcode-select --install`",
+        }
+    }
 }
 
 impl CreateExe {
     /// Runs logic for the `compile` subcommand
     pub fn execute(&self) -> Result<()> {
-        let target = self
-            .target_triple
-            .as_ref()
-            .map(|target_triple| {
-                let mut features = self
-                    .cpu_features
-                    .clone()
+        if let Some(stage_dir) = &self.link_from {
+            return self.link_from_stage(stage_dir);
+        }
+        if let Some(archs) = &self.universal {
+            return self.create_universal_binary(archs);
+        }
+        if let Some(target_triple) = &self.target_triple {
+            check_target_endianness(target_triple)?;
+        }
+        let target = if self.target_triple.is_some() || self.target_feature_profile.is_some() {
+            let target_triple = self.target_triple.clone().unwrap_or_else(Triple::host);
+            let mut features = self
+                .cpu_features
+                .clone()
+                .into_iter()
+                .fold(CpuFeature::set(), |a, b| a | b);
+            if let Some(profile) = self.target_feature_profile {
+                if target_triple.architecture != Architecture::X86_64 {
+                    bail!("`--target-feature-profile` is only supported for x86_64 targets");
+                }
+                if profile == FeatureProfile::Native
+                    && Triple::host().architecture != Architecture::X86_64
+                {
+                    bail!("`--target-feature-profile native` needs a x86_64 host");
+                }
+                features = profile
+                    .cpu_features()
                     .into_iter()
-                    .fold(CpuFeature::set(), |a, b| a | b);
-                // Cranelift requires SSE2, so we have this "hack" for now to facilitate
-                // usage
-                features |= CpuFeature::SSE2;
-                Target::new(target_triple.clone(), features)
-            })
-            .unwrap_or_default();
+                    .fold(features, |a, b| a | b);
+            }
+            // Cranelift requires SSE2, so we have this "hack" for now to facilitate
+            // usage
+            features |= CpuFeature::SSE2;
+            Target::new(target_triple, features)
+        } else {
+            Target::default()
+        };
+        self.check_link_options(target.triple())?;
+        let toolchain = self.get_toolchain()?;
+        let mut redefinitions = self.symbol_redefinitions()?;
+        if let Some(namespace) = &self.symbol_namespace {
+            if !is_c_identifier(namespace) {
+                bail!("the symbol namespace `{}` isn't a C identifier", namespace);
+            }
+        }
+        if self.list_objects() == Some(ListObjects::Before) {
+            let mut object_paths = vec![object_file_name("wasm")];
+            if self.include_source {
+                object_paths.push(object_file_name("wasm_source"));
+            }
+            if self.embed_manifest {
+                object_paths.push(object_file_name("wasm_manifest"));
+            }
+            print_link_inputs(&self.link_code(
+                &toolchain,
+                object_paths,
+                self.output().to_path_buf(),
+            ));
+            return Ok(());
+        }
         let engine_type = EngineType::Staticlib;
-        let (store, compiler_type) = self
+        let (mut compiler_config, compiler_type) = self.compiler.get_compiler_config()?;
+        if self.thin_lto {
+            compiler_config.emit_bitcode().with_context(|| {
+                format!(
+                    "`--thin-lto` requires the LLVM compiler, the `{}` compiler can't emit bitcode",
+                    compiler_type.to_string()
+                )
+            })?;
+        }
+        if self.hash_embedded_wasm && compiler_type == CompilerType::LLVM {
+            bail!("`--hash-embedded-wasm` isn't supported by the LLVM compiler, whose objects don't delimit the module code");
+        }
+        let mut engine = self
             .compiler
-            .get_store_for_target_and_engine(target.clone(), engine_type)?;
+            .get_staticlib_engine(target.clone(), compiler_config)?;
+        engine.set_metadata_compression(
+            self.compress_with
+                .to_metadata_compression(self.compress_level, self.zstd_level)?,
+        );
+        if let Some(prefix_algorithm) = self.prefix_algorithm {
+            engine.set_deterministic_prefixer(move |bytes| prefix_algorithm.prefix(bytes));
+        }
+        let store = Store::new(&engine);
 
         println!("Engine: {}", engine_type.to_string());
         println!("Compiler: {}", compiler_type.to_string());
         println!("Target: {}", target.triple());
+        if let Some(sysroot) = &toolchain.sysroot {
+            println!("Sysroot: {}", sysroot.root.display());
+        }
+        if self.thin_lto {
+            println!("LTO: thin");
+        }
+        if let Some(profile) = self.target_feature_profile {
+            println!(
+                "CPU features ({}): {}",
+                profile.to_string(),
+                target
+                    .cpu_features()
+                    .iter()
+                    .map(|feature| feature.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let working_dir = tempfile::tempdir()?;
+        let starting_cd = env::current_dir()?;
+        let version_script = self.version_script(&starting_cd)?;
+        env::set_current_dir(&working_dir)?;
+
+        let wasm_object_path = object_file_name("wasm");
+
+        let wasm_module_path = starting_cd.join(self.module_path());
+
+        let module = match &self.strip_exports {
+            Some(pattern) => self.compile_stripped_module(&store, &wasm_module_path, pattern)?,
+            None => {
+                Module::from_file(&store, &wasm_module_path).context("failed to compile Wasm")?
+            }
+        };
+        if self.thin_lto {
+            let wasm_bitcode_path = PathBuf::from("wasm.bc");
+            module.serialize_to_file(&wasm_bitcode_path)?;
+            run_c_compile(
+                &toolchain.c_compiler,
+                &wasm_bitcode_path,
+                &wasm_object_path,
+                self.target_triple.clone(),
+                None,
+                None,
+                &[],
+                true,
+            )
+            .context("Failed to convert the module to ThinLTO bitcode")?;
+        } else {
+            module.serialize_to_file(&wasm_object_path)?;
+        }
+        if let Some(namespace) = &self.symbol_namespace {
+            redefinitions = namespaced_symbols(&wasm_object_path, namespace)?;
+        }
+
+        let artifact: &wasmer_engine_staticlib::StaticlibArtifact =
+            module.artifact().as_ref().downcast_ref().context(
+                "Engine type is Staticlib but could not downcast artifact into StaticlibArtifact",
+            )?;
+        let symbol_registry = artifact.symbol_registry();
+        if let Some(prefix_algorithm) = self.prefix_algorithm {
+            println!(
+                "Symbol prefix: {} ({})",
+                artifact.prefix(),
+                prefix_algorithm
+            );
+        }
+        let metadata_length = artifact.metadata_length();
+        if self.compress_with != CompressionAlgorithm::None {
+            println!(
+                "Metadata size: {} (uncompressed: {})",
+                ByteSize(metadata_length as _),
+                ByteSize(artifact.uncompressed_metadata_length() as _)
+            );
+        }
+        let module_info = module.info();
+        let mut header_file_src = crate::c_gen::staticlib_header::generate_header_file(
+            module_info,
+            symbol_registry,
+            metadata_length,
+            None,
+        );
+        if !redefinitions.is_empty() {
+            // The C code is compiled after the header, so its references
+            // to the module symbols get renamed too
+            header_file_src = redefinitions
+                .iter()
+                .map(|redefinition| format!("#define {} {}\n", redefinition.old, redefinition.new))
+                .chain(std::iter::once(header_file_src))
+                .collect();
+        }
+
+        generate_header(header_file_src.as_bytes())?;
+        let manifest = if self.output_manifest.is_some() || self.embed_manifest {
+            Some(self.build_manifest(
+                &engine_type,
+                &compiler_type,
+                &target,
+                &module,
+                &wasm_module_path,
+                &starting_cd,
+                &toolchain,
+                &redefinitions,
+            )?)
+        } else {
+            None
+        };
+        let mut defines = vec![];
+        if self.embed_manifest {
+            defines.push("WASMER_EMBED_MANIFEST".to_string());
+        }
+        if self.hash_embedded_wasm {
+            // The hash is written to the executable once it's linked
+            defines.push("WASMER_HASH_EMBEDDED_WASM".to_string());
+        }
+        if let Some(entry) = &self.atom_entry {
+            defines.extend(self.entry_defines(entry, &module)?);
+        }
+        if let Some(objcopy) = toolchain
+            .objcopy
+            .as_ref()
+            .filter(|_| !redefinitions.is_empty())
+        {
+            redefine_symbols(objcopy, &wasm_object_path, &redefinitions)?;
+        }
+        let mut object_paths = vec![wasm_object_path];
+        if self.include_source {
+            object_paths.push(self.generate_source_object(&target, &wasm_module_path)?);
+        }
+        if let Some(manifest) = manifest.as_ref().filter(|_| self.embed_manifest) {
+            object_paths.push(generate_manifest_object(&target, manifest)?);
+        }
+        if let Some(stage_dir) = &self.objects_only {
+            Stage {
+                wasmer_version: crate::VERSION.to_string(),
+                target: target.triple().to_string(),
+                objects: object_paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect(),
+                header: HEADER_FILE.to_string(),
+                defines,
+                redefinitions,
+                symbol_namespace: self.symbol_namespace.clone(),
+            }
+            .write(&starting_cd.join(stage_dir))?;
+            eprintln!(
+                "✔ Objects written successfully to `{}`. Link them with `wasmer create-exe --link-from {} -o OUTPUT`.",
+                stage_dir.display(),
+                stage_dir.display(),
+            );
+            return Ok(());
+        }
+        let output_path = starting_cd.join(self.output());
+        let linked = self.link(
+            &toolchain,
+            object_paths,
+            &output_path,
+            version_script,
+            defines,
+            &redefinitions,
+            target.triple(),
+            working_dir,
+        )?;
+        if !linked {
+            return Ok(());
+        }
+
+        if let (Some(manifest_path), Some(mut manifest)) = (&self.output_manifest, manifest) {
+            manifest.output = Some(ManifestFile::new(self.output(), &output_path)?);
+            manifest.write(&starting_cd.join(manifest_path))?;
+            eprintln!(
+                "✔ Build manifest written successfully to `{}`.",
+                manifest_path.display(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Compile the C glue and link it with the objects at `object_paths`
+    /// into `output_path`, for `triple`, then run the steps following the
+    /// link.
+    ///
+    /// Returns `false` if the inputs of the link were only listed, for
+    /// `--list-objects=after`, keeping the `working_dir` holding them.
+    #[allow(clippy::too_many_arguments)]
+    fn link(
+        &self,
+        toolchain: &Toolchain,
+        object_paths: Vec<PathBuf>,
+        output_path: &Path,
+        version_script: Option<PathBuf>,
+        defines: Vec<String>,
+        redefinitions: &[SymbolRedefinition],
+        triple: &Triple,
+        working_dir: tempfile::TempDir,
+    ) -> Result<bool> {
+        let hash_embedded_wasm = defines
+            .iter()
+            .any(|define| define == "WASMER_HASH_EMBEDDED_WASM");
+        let mut link_code = self.compile_c(
+            &toolchain,
+            object_paths,
+            output_path.to_path_buf(),
+            version_script,
+            defines,
+            &redefinitions,
+        )?;
+        if let (Some(objcopy), Some(namespace)) = (&toolchain.objcopy, &self.symbol_namespace) {
+            // The C glue already refers to the renamed module symbols
+            let glue_object_path = object_file_name("wasmer_main");
+            let glue_redefinitions = namespaced_symbols(&glue_object_path, namespace)?;
+            redefine_symbols(objcopy, &glue_object_path, &glue_redefinitions)?;
+        }
+        if let Some(objcopy) = toolchain
+            .objcopy
+            .as_ref()
+            .filter(|_| self.weak_runtime_symbols)
+        {
+            link_code.libwasmer_path = weaken_runtime_symbols(objcopy, &link_code.libwasmer_path)?;
+        }
+        if self.list_objects() == Some(ListObjects::After) {
+            print_link_inputs(&link_code);
+            eprintln!("✔ Objects kept in `{}`.", working_dir.into_path().display());
+            return Ok(false);
+        }
+        link_code.run().context("Failed to link objects together")?;
+        if hash_embedded_wasm {
+            let hash = write_embedded_wasm_sha256(output_path, self.symbol_namespace.as_deref())?;
+            println!("Embedded module SHA-256: {}", hash);
+        }
+        if let Some(packer) = &toolchain.packer {
+            self_extract(packer, output_path)?;
+        }
+        if let Some(command) = &self.run_after_link {
+            run_after_link(command, output_path, &link_code.object_paths, triple)?;
+        }
+
+        match self.output_type {
+            OutputType::Dylib => eprintln!(
+                "✔ Shared library compiled successfully to `{}`. Call `{}wasmer_staticlib_engine_new` after loading it to create the module.",
+                self.output().display(),
+                self.symbol_namespace
+                    .as_ref()
+                    .map_or_else(String::new, |namespace| format!("{}_", namespace)),
+            ),
+            _ => eprintln!(
+                "✔ Native executable compiled successfully to `{}`.",
+                self.output().display(),
+            ),
+        }
+        if self.verify_run {
+            verify_run(output_path, &self.verify_run_args)?;
+        }
+        Ok(true)
+    }
+
+    /// Link the objects written by `--objects-only` to `stage_dir`, for
+    /// `--link-from`.
+    fn link_from_stage(&self, stage_dir: &Path) -> Result<()> {
+        let starting_cd = env::current_dir()?;
+        let stage_dir = starting_cd.join(stage_dir);
+        let stage = Stage::read(&stage_dir)?;
+        let triple = stage.triple()?;
+        // The C compiler and the linker get `-target` for other targets
+        let create_exe = Self {
+            target_triple: Some(triple.clone()).filter(|triple| *triple != Triple::host()),
+            symbol_namespace: stage.symbol_namespace.clone(),
+            ..self.clone()
+        };
+        create_exe.check_link_options(&triple)?;
+        if self.output_type == OutputType::Dylib
+            && stage.defines.iter().any(|define| {
+                define.starts_with("WASMER_ENTRY=") || define == "WASMER_HASH_EMBEDDED_WASM"
+            })
+        {
+            bail!("the objects were compiled with `--atom-entry` or `--hash-embedded-wasm`, which can't be used with `--output-type dylib`");
+        }
+        let toolchain = create_exe.get_toolchain()?;
+        println!("Target: {}", triple);
+        if let Some(sysroot) = &toolchain.sysroot {
+            println!("Sysroot: {}", sysroot.root.display());
+        }
+
+        let working_dir = tempfile::tempdir()?;
+        let output_path = starting_cd.join(self.output());
+        let version_script = self.version_script(&starting_cd)?;
+        env::set_current_dir(&working_dir)?;
+        stage.copy_files(&stage_dir)?;
+
+        create_exe.link(
+            &toolchain,
+            stage.objects.iter().map(PathBuf::from).collect(),
+            &output_path,
+            version_script,
+            stage.defines.clone(),
+            &stage.redefinitions,
+            &triple,
+            working_dir,
+        )?;
+        Ok(())
+    }
+
+    /// Build a slice of the `--universal` binary for each of `archs`, and
+    /// merge them with `lipo`.
+    fn create_universal_binary(&self, archs: &UniversalArchs) -> Result<()> {
+        if !matches!(
+            Triple::host().operating_system,
+            OperatingSystem::Darwin | OperatingSystem::MacOSX { .. }
+        ) {
+            bail!("`--universal` is only supported on macOS");
+        }
+        let toolchain = self.get_toolchain()?;
+        let lipo = toolchain.lipo.context("no lipo")?;
+        let starting_cd = env::current_dir()?;
+        let slices_dir = tempfile::tempdir()?;
+        let mut slice_paths = vec![];
+        for triple in &archs.0 {
+            println!("Slice: {}", triple.architecture);
+            let slice_path = slices_dir.path().join(triple.architecture.to_string());
+            let mut slice = self.clone();
+            slice.universal = None;
+            slice.target_triple = Some(triple.clone());
+            slice.output = Some(slice_path.clone());
+            slice.verify_run =
+                self.verify_run && triple.architecture == Triple::host().architecture;
+            slice
+                .execute()
+                .with_context(|| format!("failed to build the {} slice", triple.architecture))?;
+            // Every build leaves the current directory in its own
+            // temporary directory
+            env::set_current_dir(&starting_cd)?;
+            slice_paths.push(slice_path);
+        }
+
+        let output = Command::new(&lipo)
+            .arg("-create")
+            .arg("-output")
+            .arg(self.output())
+            .args(&slice_paths)
+            .output()
+            .with_context(|| {
+                format!(
+                    "failed to run `{}`, which `--universal` needs: {}",
+                    lipo.display(),
+                    LinkerFlavor::Ld64.install_hint()
+                )
+            })?;
+        if !output.status.success() {
+            bail!(
+                "`{}` failed to merge the slices ({}):\nstdout: {}\n\nstderr: {}",
+                lipo.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        eprintln!(
+            "✔ Universal binary ({}) compiled successfully to `{}`.",
+            archs.to_string(),
+            self.output().display(),
+        );
+        Ok(())
+    }
+
+    /// When to print the linker inputs, for `--list-objects`.
+    fn list_objects(&self) -> Option<ListObjects> {
+        self.list_objects
+            .map(|when| when.unwrap_or(ListObjects::Before))
+    }
+
+    /// The module to compile, required unless `--link-from` is set.
+    fn module_path(&self) -> &Path {
+        self.path
+            .as_deref()
+            .expect("the module is required without `--link-from`")
+    }
+
+    /// The output, required unless `--objects-only` is set.
+    fn output(&self) -> &Path {
+        self.output
+            .as_deref()
+            .expect("the output is required without `--objects-only`")
+    }
+
+    /// The absolute path of the `--ld-version-script`, relative to
+    /// `starting_cd`.
+    fn version_script(&self, starting_cd: &Path) -> Result<Option<PathBuf>> {
+        match &self.ld_version_script {
+            Some(path) => {
+                Ok(Some(starting_cd.join(path).canonicalize().with_context(
+                    || format!("failed to find `{}`", path.display()),
+                )?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Check the options of the link and of the steps following it, for
+    /// an output targeting `triple`.
+    fn check_link_options(&self, triple: &Triple) -> Result<()> {
+        if self.output_type == OutputType::Dylib {
+            if self.static_pie {
+                bail!("`--static-pie` can't be used with `--output-type dylib`");
+            }
+            if triple.operating_system == OperatingSystem::Windows {
+                bail!("`--output-type dylib` isn't supported for Windows targets");
+            }
+            let (linker_flavor, _) = self.get_linker();
+            if linker_flavor == LinkerFlavor::Msvc {
+                bail!("`--output-type dylib` isn't supported by the `msvc` linker flavor");
+            }
+            if self.hash_embedded_wasm {
+                bail!("`--hash-embedded-wasm` can't be used with `--output-type dylib`");
+            }
+            if self.atom_entry.is_some() {
+                bail!("`--atom-entry` can't be used with `--output-type dylib`");
+            }
+            if self.verify_run {
+                bail!("`--verify-run` can't be used with `--output-type dylib`");
+            }
+            if self.self_extract {
+                bail!("`--self-extract` can't be used with `--output-type dylib`");
+            }
+        } else if self.emit_symbol_versions.is_some() {
+            bail!("`--emit-symbol-versions` requires `--output-type dylib`");
+        }
+        if let Some(version) = &self.emit_symbol_versions {
+            let (linker_flavor, _) = self.get_linker();
+            if !matches!(linker_flavor, LinkerFlavor::Gnu | LinkerFlavor::Lld) {
+                bail!(
+                    "`--emit-symbol-versions` isn't supported by the `{}` linker flavor",
+                    linker_flavor
+                );
+            }
+            if version.is_empty()
+                || !version
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            {
+                bail!(
+                    "invalid symbol version `{}`, expected letters, digits, `_` and `.`",
+                    version
+                );
+            }
+        }
+        if self.static_pie {
+            if triple.operating_system != OperatingSystem::Linux {
+                bail!("`--static-pie` is only supported for Linux targets");
+            }
+            let (linker_flavor, _) = self.get_linker();
+            if !matches!(linker_flavor, LinkerFlavor::Gnu | LinkerFlavor::Lld) {
+                bail!(
+                    "`--static-pie` isn't supported by the `{}` linker flavor",
+                    linker_flavor
+                );
+            }
+        }
+        if self.cross_sysroot.is_some() {
+            let (linker_flavor, _) = self.get_linker();
+            if linker_flavor == LinkerFlavor::Msvc {
+                bail!("`--cross-sysroot` isn't supported by the `msvc` linker flavor");
+            }
+        }
+        if self.thin_lto {
+            let (linker_flavor, _) = self.get_linker();
+            if !matches!(linker_flavor, LinkerFlavor::Lld | LinkerFlavor::Ld64) {
+                bail!(
+                    "`--thin-lto` isn't supported by the `{}` linker flavor, use `lld` or `ld64`",
+                    linker_flavor
+                );
+            }
+        }
+        if self.build_id.is_some() {
+            let (linker_flavor, _) = self.get_linker();
+            if !matches!(linker_flavor, LinkerFlavor::Gnu | LinkerFlavor::Lld) {
+                bail!(
+                    "`--build-id` isn't supported by the `{}` linker flavor",
+                    linker_flavor
+                );
+            }
+        }
+        if self.ld_version_script.is_some() {
+            let (linker_flavor, _) = self.get_linker();
+            if !matches!(linker_flavor, LinkerFlavor::Gnu | LinkerFlavor::Lld) {
+                bail!(
+                    "`--ld-version-script` isn't supported by the `{}` linker flavor",
+                    linker_flavor
+                );
+            }
+        }
+        if self.default_visibility == SymbolVisibility::Hidden {
+            // Checked here since the default value would always conflict
+            if self.ld_version_script.is_some() {
+                bail!("`--default-visibility hidden` can't be used with `--ld-version-script`");
+            }
+            let (linker_flavor, _) = self.get_linker();
+            if linker_flavor == LinkerFlavor::Msvc {
+                bail!("`--default-visibility hidden` isn't supported by the `msvc` linker flavor");
+            }
+        }
+        if self.weak_runtime_symbols {
+            let (linker_flavor, _) = self.get_linker();
+            if !matches!(linker_flavor, LinkerFlavor::Gnu | LinkerFlavor::Lld) {
+                bail!(
+                    "`--weak-runtime-symbols` isn't supported by the `{}` linker flavor",
+                    linker_flavor
+                );
+            }
+        }
+        if self.verify_run && *triple != Triple::host() {
+            bail!(
+                "`--verify-run` can't run an executable built for `{}` on this host",
+                triple
+            );
+        }
+        if let Some(command) = &self.run_after_link {
+            if command.trim().is_empty() {
+                bail!("the `--run-after-link` command is empty");
+            }
+        }
+        Ok(())
+    }
 
-        let working_dir = tempfile::tempdir()?;
-        let starting_cd = env::current_dir()?;
-        let output_path = starting_cd.join(&self.output);
-        env::set_current_dir(&working_dir)?;
+    /// The defines making the executable call the `--atom-entry` export,
+    /// checking that its parameters can be parsed from the command line.
+    fn entry_defines(&self, entry: &AtomEntry, module: &Module) -> Result<Vec<String>> {
+        let atom_name = self
+            .module_path()
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if let Some(atom) = entry.atom.as_ref().filter(|atom| **atom != atom_name) {
+            bail!(
+                "unknown atom `{}`, the only atom is the module `{}`",
+                atom,
+                atom_name
+            );
+        }
+        let function = module
+            .exports()
+            .functions()
+            .find(|export| export.name() == entry.export)
+            .with_context(|| format!("the module has no `{}` function export", entry.export))?;
+        let signature = function.ty();
+        let type_codes = signature
+            .params()
+            .iter()
+            .chain(signature.results())
+            .map(|ty| match ty {
+                Type::I32 => Ok('i'),
+                Type::I64 => Ok('I'),
+                Type::F32 => Ok('f'),
+                Type::F64 => Ok('F'),
+                _ => bail!(
+                    "the `{}` export has the signature `{}`, but only numbers can be passed from and printed to the command line",
+                    entry.export,
+                    signature
+                ),
+            })
+            .collect::<Result<String>>()?;
 
-        #[cfg(not(windows))]
-        let wasm_object_path = PathBuf::from("wasm.o");
-        #[cfg(windows)]
-        let wasm_object_path = PathBuf::from("wasm.obj");
+        let mut defines = vec![
+            format!("WASMER_ENTRY={}", c_string_literal(&entry.export)),
+            format!(
+                "WASMER_ENTRY_PARAMS=\"{}\"",
+                &type_codes[..signature.params().len()]
+            ),
+        ];
+        if module.imports().next().is_none() {
+            defines.push("WASMER_NO_WASI".to_string());
+        }
+        Ok(defines)
+    }
 
-        let wasm_module_path = starting_cd.join(&self.path);
+    /// Describe the build, without its output.
+    #[allow(clippy::too_many_arguments)]
+    fn build_manifest(
+        &self,
+        engine_type: &EngineType,
+        compiler_type: &CompilerType,
+        target: &Target,
+        module: &Module,
+        wasm_module_path: &Path,
+        starting_cd: &Path,
+        toolchain: &Toolchain,
+        redefinitions: &[SymbolRedefinition],
+    ) -> Result<BuildManifest> {
+        Ok(BuildManifest {
+            wasmer_version: crate::VERSION,
+            input: ManifestFile::new(self.module_path(), wasm_module_path)?,
+            output: None,
+            engine: engine_type.to_string(),
+            compiler: compiler_type.to_string(),
+            target: target.triple().to_string(),
+            cpu_features: target
+                .cpu_features()
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+            wasm_features: enabled_wasm_features(module.artifact().features()),
+            metadata_compression: match self.compress_with {
+                CompressionAlgorithm::None => "none".to_string(),
+                CompressionAlgorithm::Gzip => {
+                    format!("gzip (level {})", self.compress_level.unwrap_or(6))
+                }
+                CompressionAlgorithm::Zstd => format!(
+                    "zstd (level {})",
+                    self.zstd_level.unwrap_or(DEFAULT_ZSTD_LEVEL)
+                ),
+            },
+            prefix_algorithm: self.prefix_algorithm.map(|algorithm| algorithm.to_string()),
+            libraries: self.libraries.clone(),
+            rpaths: self.rpaths.clone(),
+            output_type: self.output_type.to_string(),
+            static_pie: self.static_pie,
+            build_id: self.build_id.map(|build_id| build_id.to_string()),
+            ld_version_script: match &self.ld_version_script {
+                Some(path) => Some(ManifestFile::new(path, &starting_cd.join(path))?),
+                None => None,
+            },
+            default_visibility: self.default_visibility.to_string(),
+            symbol_version: self.emit_symbol_versions.clone(),
+            include_source: self.include_source,
+            embed_manifest: self.embed_manifest,
+            hash_embedded_wasm: self.hash_embedded_wasm,
+            weak_runtime_symbols: self.weak_runtime_symbols,
+            symbol_namespace: self.symbol_namespace.clone(),
+            // The namespace renames every symbol, so they aren't listed
+            redefined_symbols: redefinitions
+                .iter()
+                .filter(|_| self.symbol_namespace.is_none())
+                .map(|redefinition| format!("{}={}", redefinition.old, redefinition.new))
+                .collect(),
+            c_compiler: ManifestTool::new(&toolchain.c_compiler, None),
+            linker: ManifestTool::new(&toolchain.linker, Some(toolchain.linker_flavor.to_string())),
+            packer: toolchain
+                .packer
+                .as_ref()
+                .map(|packer| ManifestTool::new(packer, None)),
+            objcopy: toolchain
+                .objcopy
+                .as_ref()
+                .map(|objcopy| ManifestTool::new(objcopy, None)),
+        })
+    }
 
-        let module =
-            Module::from_file(&store, &wasm_module_path).context("failed to compile Wasm")?;
-        let _ = module.serialize_to_file(&wasm_object_path)?;
+    /// Write an object holding the Wasm module in its embedded source
+    /// section, returning its path.
+    fn generate_source_object(&self, target: &Target, wasm_module_path: &Path) -> Result<PathBuf> {
+        let source_object_path = object_file_name("wasm_source");
 
-        let artifact: &wasmer_engine_staticlib::StaticlibArtifact =
-            module.artifact().as_ref().downcast_ref().context(
-                "Engine type is Staticlib but could not downcast artifact into StaticlibArtifact",
-            )?;
-        let symbol_registry = artifact.symbol_registry();
-        let metadata_length = artifact.metadata_length();
-        let module_info = module.info();
-        let header_file_src = crate::c_gen::staticlib_header::generate_header_file(
-            module_info,
-            symbol_registry,
-            metadata_length,
+        let wasm = fs::read(wasm_module_path)?;
+        let compression = match self.compress_with {
+            CompressionAlgorithm::None => SourceCompression::None,
+            CompressionAlgorithm::Gzip => SourceCompression::Gzip(self.compress_level.unwrap_or(6)),
+            CompressionAlgorithm::Zstd => {
+                SourceCompression::Zstd(self.zstd_level.unwrap_or(DEFAULT_ZSTD_LEVEL))
+            }
+        };
+        let data = crate::embedded_source::encode(&wasm, compression)?;
+        println!(
+            "Embedded source size: {} (original: {})",
+            ByteSize(data.len() as _),
+            ByteSize(wasm.len() as _)
         );
 
-        generate_header(header_file_src.as_bytes())?;
-        self.compile_c(wasm_object_path, output_path)?;
-
-        eprintln!(
-            "✔ Native executable compiled successfully to `{}`.",
-            self.output.display(),
+        let mut obj = wasmer_object::get_object_for_target(target.triple())?;
+        let section_name = crate::embedded_source::section_name(obj.format());
+        let section_id = obj.add_section(
+            obj.segment_name(StandardSegment::Data).to_vec(),
+            section_name.as_bytes().to_vec(),
+            SectionKind::ReadOnlyData,
         );
-
-        Ok(())
+        obj.append_section_data(section_id, &data, 1);
+        let bytes = obj
+            .write()
+            .map_err(|e| anyhow!("failed to write the source object: {}", e))?;
+        fs::write(&source_object_path, bytes)?;
+        Ok(source_object_path)
     }
 
-    fn compile_c(&self, wasm_object_path: PathBuf, output_path: PathBuf) -> anyhow::Result<()> {
+    /// Compile the C code, returning the command linking it with the
+    /// objects at `object_paths`.
+    fn compile_c(
+        &self,
+        toolchain: &Toolchain,
+        object_paths: Vec<PathBuf>,
+        output_path: PathBuf,
+        version_script: Option<PathBuf>,
+        defines: Vec<String>,
+        redefinitions: &[SymbolRedefinition],
+    ) -> anyhow::Result<LinkCode> {
         use std::io::Write;
 
         // write C src to disk
         let c_src_path = Path::new("wasmer_main.c");
-        #[cfg(not(windows))]
-        let c_src_obj = PathBuf::from("wasmer_main.o");
-        #[cfg(windows)]
-        let c_src_obj = PathBuf::from("wasmer_main.obj");
+        let c_src_obj = object_file_name("wasmer_main");
 
         {
             let mut c_src_file = fs::OpenOptions::new()
@@ -121,26 +1792,697 @@ impl CreateExe {
                 .write(true)
                 .open(&c_src_path)
                 .context("Failed to open C source code file")?;
-            c_src_file.write_all(WASMER_MAIN_C_SOURCE)?;
+            c_src_file.write_all(match self.output_type {
+                OutputType::Dylib => WASMER_DYLIB_C_SOURCE,
+                _ => WASMER_MAIN_C_SOURCE,
+            })?;
+        }
+        let pic_flag = match self.output_type {
+            OutputType::Dylib => Some("-fPIC"),
+            OutputType::Pie => Some("-fPIE"),
+            OutputType::Exe if self.static_pie => Some("-fPIE"),
+            OutputType::Exe => None,
+        };
+        run_c_compile(
+            &toolchain.c_compiler,
+            &c_src_path,
+            &c_src_obj,
+            self.target_triple.clone(),
+            toolchain.sysroot.as_ref(),
+            pic_flag,
+            &defines,
+            self.thin_lto,
+        )
+        .context("Failed to compile C source code")?;
+        let flavor = toolchain.linker_flavor;
+        let mut version_script = version_script;
+        let mut unexported_symbols = vec![];
+        let hidden = self.default_visibility == SymbolVisibility::Hidden;
+        let module_symbols = MODULE_SYMBOL_PATTERNS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .chain(
+                redefinitions
+                    .iter()
+                    .map(|redefinition| redefinition.new.clone()),
+            )
+            .filter(|_| hidden);
+        if hidden && flavor == LinkerFlavor::Ld64 {
+            // Mach-O symbols have a leading underscore
+            unexported_symbols = module_symbols
+                .map(|pattern| format!("_{}", pattern))
+                .collect();
+        } else if hidden || self.emit_symbol_versions.is_some() {
+            // The `*` of the version node has the lowest priority, so the
+            // module symbols stay local
+            let mut contents = match &self.emit_symbol_versions {
+                Some(version) => format!("{} {{\n  global:\n    *;\n", version),
+                None => "{\n".to_string(),
+            };
+            if hidden {
+                contents.push_str("  local:\n");
+                contents.extend(module_symbols.map(|pattern| format!("    {};\n", pattern)));
+            }
+            contents.push_str("};\n");
+            let version_script_path = PathBuf::from("wasmer_visibility.map");
+            fs::write(&version_script_path, contents)?;
+            version_script = Some(version_script_path.canonicalize()?);
         }
-        run_c_compile(&c_src_path, &c_src_obj, self.target_triple.clone())
-            .context("Failed to compile C source code")?;
+        Ok(LinkCode {
+            version_script,
+            unexported_symbols,
+            ..self.link_code(toolchain, object_paths, output_path)
+        })
+    }
+
+    /// The command linking the C code with the objects at `object_paths`.
+    fn link_code(
+        &self,
+        toolchain: &Toolchain,
+        object_paths: Vec<PathBuf>,
+        output_path: PathBuf,
+    ) -> LinkCode {
         LinkCode {
-            object_paths: vec![c_src_obj, wasm_object_path],
+            linker_path: toolchain.linker.clone(),
+            flavor: toolchain.linker_flavor,
+            object_paths: std::iter::once(object_file_name("wasmer_main"))
+                .chain(object_paths)
+                .collect(),
             output_path,
             additional_libraries: self.libraries.clone(),
+            rpaths: self.get_rpaths(),
             target: self.target_triple.clone(),
+            output_type: self.output_type,
+            static_pie: self.static_pie,
+            build_id: self.build_id,
+            weak_runtime_symbols: self.weak_runtime_symbols,
+            thin_lto: self.thin_lto,
+            sysroot: toolchain.sysroot.clone(),
             ..Default::default()
         }
-        .run()
-        .context("Failed to link objects together")?;
+    }
 
-        Ok(())
+    /// Compile the module at `wasm_module_path`, without the exports
+    /// matching `pattern`, for `--strip-exports`.
+    fn compile_stripped_module(
+        &self,
+        store: &Store,
+        wasm_module_path: &Path,
+        pattern: &str,
+    ) -> Result<Module> {
+        let contents = fs::read(wasm_module_path)?;
+        #[cfg(feature = "wat")]
+        let contents = wat2wasm(&contents)?.into_owned();
+        let entry = match &self.atom_entry {
+            Some(entry) => entry.export.as_str(),
+            None => "_start",
+        };
+        let stripped = strip_exports::strip_exports(&contents, pattern, entry)?;
+        if stripped.removed_exports.is_empty() {
+            warning!("no export matching `{}` can be removed", pattern);
+        }
+        for name in &stripped.kept_exports {
+            warning!(
+                "`{}` matches `{}` but is kept, its function is used by the module",
+                name,
+                pattern
+            );
+        }
+        println!(
+            "Stripped exports: {} ({} function bodies removed)",
+            stripped.removed_exports.len(),
+            stripped.removed_functions
+        );
+        Module::new(store, &stripped.wasm).context("failed to compile Wasm")
+    }
+
+    /// The symbols renamed by `--objcopy-redefine`, read from its file
+    /// unless they're given in the command line.
+    fn symbol_redefinitions(&self) -> Result<Vec<SymbolRedefinition>> {
+        let value = match &self.objcopy_redefine {
+            Some(value) => value,
+            None => return Ok(vec![]),
+        };
+        let redefinitions = if value.contains('=') {
+            value
+                .split(',')
+                .map(|pair| match pair.split_once('=') {
+                    Some((old, new)) => Ok(SymbolRedefinition {
+                        old: old.to_string(),
+                        new: new.to_string(),
+                    }),
+                    None => bail!("invalid rename `{}`, expected `OLD=NEW`", pair),
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            let contents = fs::read_to_string(value)
+                .with_context(|| format!("failed to read the symbols to rename `{}`", value))?;
+            contents
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| {
+                    let words = line.split_whitespace().collect::<Vec<_>>();
+                    match words[..] {
+                        [old, new] => Ok(SymbolRedefinition {
+                            old: old.to_string(),
+                            new: new.to_string(),
+                        }),
+                        _ => bail!("invalid line `{}` in `{}`, expected `OLD NEW`", line, value),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        for (index, redefinition) in redefinitions.iter().enumerate() {
+            // The generated C code refers to the new names
+            for name in [&redefinition.old, &redefinition.new].iter() {
+                if !is_c_identifier(name) {
+                    bail!(
+                        "can't rename symbols to or from `{}`, which isn't a C identifier",
+                        name
+                    );
+                }
+            }
+            let previous = &redefinitions[..index];
+            if previous.iter().any(|other| other.old == redefinition.old) {
+                bail!("`{}` is renamed more than once", redefinition.old);
+            }
+            if previous.iter().any(|other| other.new == redefinition.new) {
+                bail!("several symbols are renamed to `{}`", redefinition.new);
+            }
+        }
+        Ok(redefinitions)
+    }
+
+    /// Get the linker flavor and the path to the linker.
+    fn get_linker(&self) -> (LinkerFlavor, PathBuf) {
+        let flavor = self.linker_flavor.unwrap_or_else(|| {
+            LinkerFlavor::default_for_target(
+                &self.target_triple.clone().unwrap_or_else(Triple::host),
+            )
+        });
+        let linker_path = self
+            .linker
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(flavor.default_linker()));
+        (flavor, linker_path)
+    }
+
+    /// Get the C compiler, the linker, the packer and the object copier,
+    /// looking them up in `--toolchain-root` when it's provided.
+    fn get_toolchain(&self) -> Result<Toolchain> {
+        let (linker_flavor, linker) = self.get_linker();
+        let c_compiler = PathBuf::from(C_COMPILER);
+        let packer = if self.self_extract {
+            Some(PathBuf::from(PACKER))
+        } else {
+            None
+        };
+        let objcopy = if self.objcopy_redefine.is_some()
+            || self.symbol_namespace.is_some()
+            || self.weak_runtime_symbols
+        {
+            Some(PathBuf::from(OBJCOPY))
+        } else {
+            None
+        };
+        let lipo = if self.universal.is_some() {
+            Some(PathBuf::from(LIPO))
+        } else {
+            None
+        };
+        let sysroot = match &self.cross_sysroot {
+            Some(root) => Some(Sysroot::find(
+                root,
+                &self.target_triple.clone().unwrap_or_else(Triple::host),
+            )?),
+            None => None,
+        };
+        let root = match &self.toolchain_root {
+            Some(root) => root.canonicalize().with_context(|| {
+                format!("failed to find the toolchain root `{}`", root.display())
+            })?,
+            None => {
+                return Ok(Toolchain {
+                    c_compiler,
+                    linker_flavor,
+                    linker,
+                    packer,
+                    objcopy,
+                    lipo,
+                    sysroot,
+                })
+            }
+        };
+        Ok(Toolchain {
+            c_compiler: find_toolchain_tool(&root, &c_compiler)?,
+            linker: find_toolchain_tool(&root, &linker)?,
+            linker_flavor,
+            packer: match packer {
+                Some(packer) => Some(find_toolchain_tool(&root, &packer)?),
+                None => None,
+            },
+            objcopy: match objcopy {
+                Some(objcopy) => Some(find_toolchain_tool(&root, &objcopy)?),
+                None => None,
+            },
+            lipo: match lipo {
+                Some(lipo) => Some(find_toolchain_tool(&root, &lipo)?),
+                None => None,
+            },
+            sysroot,
+        })
+    }
+
+    /// Get the `--rpath` entries, normalized for the target.
+    fn get_rpaths(&self) -> Vec<String> {
+        if self.rpaths.is_empty() {
+            return vec![];
+        }
+        let triple = self.target_triple.clone().unwrap_or_else(Triple::host);
+        if triple.operating_system == OperatingSystem::Windows {
+            warning!("`--rpath` is ignored when targeting Windows");
+            return vec![];
+        }
+        if self.libraries.is_empty() {
+            warning!("`--rpath` has no effect, as libwasmer is linked statically and no libraries were passed with `-l`");
+        }
+        self.rpaths
+            .iter()
+            .map(|rpath| normalize_rpath(rpath, &triple))
+            .collect()
+    }
+}
+
+/// The file name of an object, with the extension of the host.
+fn object_file_name(name: &str) -> PathBuf {
+    #[cfg(not(windows))]
+    let extension = "o";
+    #[cfg(windows)]
+    let extension = "obj";
+    PathBuf::from(format!("{}.{}", name, extension))
+}
+
+/// Print the objects and libraries passed to the linker by `link_code`,
+/// in link order, and the linker command, for `--list-objects`.
+fn print_link_inputs(link_code: &LinkCode) {
+    println!("Link inputs:");
+    for input in link_code.inputs() {
+        println!("  {}", input);
+    }
+    println!("Link command:");
+    println!("  {}", link_code.linker_path.display());
+    for arg in link_code.args() {
+        println!("  {}", arg.to_string_lossy());
+    }
+}
+
+/// Write an object defining the `wasmer_build_manifest` C string, holding
+/// the manifest as JSON, returning its path.
+fn generate_manifest_object(target: &Target, manifest: &BuildManifest) -> Result<PathBuf> {
+    let manifest_object_path = object_file_name("wasm_manifest");
+
+    let mut data = manifest.to_json()?.into_bytes();
+    data.push(0);
+
+    let mut obj = wasmer_object::get_object_for_target(target.triple())?;
+    let section_id = obj.section_id(StandardSection::ReadOnlyData);
+    let symbol_id = obj.add_symbol(Symbol {
+        name: b"wasmer_build_manifest".to_vec(),
+        value: 0,
+        size: data.len() as _,
+        kind: SymbolKind::Data,
+        scope: SymbolScope::Dynamic,
+        weak: false,
+        section: SymbolSection::Undefined,
+        flags: SymbolFlags::None,
+    });
+    obj.add_symbol_data(symbol_id, section_id, &data, 1);
+    let bytes = obj
+        .write()
+        .map_err(|e| anyhow!("failed to write the manifest object: {}", e))?;
+    fs::write(&manifest_object_path, bytes)?;
+    Ok(manifest_object_path)
+}
+
+/// Hash the module metadata and the module code of the executable at
+/// `executable_path`, as the executable does when it starts, and write the
+/// hash to it. Returns the hash, as lowercase hex.
+///
+/// The code is only final once linked, so the hash can't be computed
+/// before. Its absolute addresses, which the loader may relocate, are
+/// hashed as zeros. The module symbols are prefixed by `namespace`, if
+/// any.
+fn write_embedded_wasm_sha256(executable_path: &Path, namespace: Option<&str>) -> Result<String> {
+    use object::read::{self, Object, ObjectSection, ObjectSymbol};
+    use object::BinaryFormat;
+    use sha2::{Digest, Sha256};
+    use std::convert::TryInto;
+
+    let mut data = fs::read(executable_path)?;
+    let file = read::File::parse(&*data).context("failed to parse the executable")?;
+    // Mach-O symbols have a leading underscore
+    let underscore = match file.format() {
+        BinaryFormat::MachO => "_",
+        _ => "",
+    };
+    // The offset of the symbol `name` in the executable file
+    let symbol_offset = |name: &str| -> Result<usize> {
+        let name = format!("{}{}", underscore, name);
+        let symbol = file
+            .symbols()
+            .find(|symbol| symbol.name() == Ok(&*name))
+            .with_context(|| {
+                format!(
+                    "the executable has no `{}` symbol, it may have been stripped",
+                    name
+                )
+            })?;
+        let section = file.section_by_index(
+            symbol
+                .section_index()
+                .with_context(|| format!("the `{}` symbol isn't defined", name))?,
+        )?;
+        let (section_offset, _) = section
+            .file_range()
+            .with_context(|| format!("the `{}` symbol has no data", name))?;
+        Ok((section_offset + symbol.address() - section.address()) as usize)
+    };
+    let module_symbol_offset = |name: &str| match namespace {
+        Some(namespace) => symbol_offset(&format!("{}_{}", namespace, name)),
+        None => symbol_offset(name),
+    };
+    let metadata =
+        module_symbol_offset("WASMER_METADATA")?..module_symbol_offset("WASMER_METADATA_END")?;
+    let code = module_symbol_offset("WASMER_CODE_START")?..module_symbol_offset("WASMER_CODE_END")?;
+    let relocations = module_symbol_offset("WASMER_CODE_RELOCATIONS")?
+        ..module_symbol_offset("WASMER_CODE_RELOCATIONS_END")?;
+    let hash_offset = symbol_offset("wasmer_embedded_wasm_sha256")?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(
+        data.get(metadata)
+            .context("the module metadata is truncated")?,
+    );
+    let mut code = data
+        .get(code)
+        .context("the module code is truncated")?
+        .to_vec();
+    for relocation in data
+        .get(relocations)
+        .context("the module relocations are truncated")?
+        .chunks_exact(8)
+    {
+        let relocation: [u8; 8] = relocation.try_into().unwrap();
+        let offset = if file.is_little_endian() {
+            u64::from_le_bytes(relocation)
+        } else {
+            u64::from_be_bytes(relocation)
+        } as usize;
+        code.get_mut(offset..offset + 8)
+            .context("the module relocations are out of the module code")?
+            .fill(0);
+    }
+    hasher.update(&code);
+    let hash: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    data.get_mut(hash_offset..hash_offset + hash.len())
+        .context("the hash of the embedded module is truncated")?
+        .copy_from_slice(hash.as_bytes());
+    fs::write(executable_path, data)?;
+    Ok(hash)
+}
+
+/// Run the executable at `executable_path` in a temporary directory, and
+/// fail with its output if it doesn't exit successfully.
+fn verify_run(executable_path: &Path, args: &[String]) -> Result<()> {
+    let run_dir = tempfile::tempdir()?;
+    let output = Command::new(executable_path)
+        .current_dir(run_dir.path())
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run `{}`", executable_path.display()))?;
+    if !output.status.success() {
+        bail!(
+            "the executable failed its verification run ({}):\nstdout: {}\n\nstderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    eprintln!("✔ Verification run succeeded ({}).", output.status);
+    Ok(())
+}
+
+/// Run the `--run-after-link` command in the current directory, with its
+/// tokens replaced.
+fn run_after_link(
+    command: &str,
+    output_path: &Path,
+    object_paths: &[PathBuf],
+    triple: &Triple,
+) -> Result<()> {
+    let objects = object_paths
+        .iter()
+        .map(|path| {
+            path.canonicalize()
+                .unwrap_or_else(|_| path.clone())
+                .display()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    let mut args = vec![];
+    for arg in command.split_whitespace() {
+        if arg == "{objects}" {
+            args.extend(objects.iter().cloned());
+        } else {
+            args.push(
+                arg.replace("{output}", &output_path.display().to_string())
+                    .replace("{objects}", &objects.join(" "))
+                    .replace("{target}", &triple.to_string()),
+            );
+        }
+    }
+    let (program, args) = args
+        .split_first()
+        .context("the `--run-after-link` command is empty")?;
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("failed to run `{}`", program))?;
+    if !status.success() {
+        bail!(
+            "the `--run-after-link` command `{}` failed ({})",
+            command,
+            status
+        );
+    }
+    eprintln!("✔ Post-link command `{}` succeeded.", program);
+    Ok(())
+}
+
+/// Compress the executable at `executable_path` in place with `packer`,
+/// reporting the compressed and original sizes.
+fn self_extract(packer: &Path, executable_path: &Path) -> Result<()> {
+    let original_size = fs::metadata(executable_path)?.len();
+    let output = Command::new(packer)
+        .arg("--best")
+        .arg("-q")
+        .arg(executable_path)
+        .output()
+        .with_context(|| {
+            format!(
+                "failed to run `{}`, which `--self-extract` needs: install UPX or pass it in `--toolchain-root`",
+                packer.display()
+            )
+        })?;
+    if !output.status.success() {
+        bail!(
+            "`{}` failed to compress the executable ({}):\nstdout: {}\n\nstderr: {}",
+            packer.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    println!(
+        "Self-extracting executable: {} (original: {})",
+        ByteSize(fs::metadata(executable_path)?.len()),
+        ByteSize(original_size)
+    );
+    Ok(())
+}
+
+/// Rename the symbols of the module object at `object_path` in place
+/// with `objcopy`, checking that they're defined by the module and that
+/// their new names aren't.
+///
+/// The generated C code only refers to the module symbols it defines, so
+/// this keeps the references of the runtime glue consistent.
+fn redefine_symbols(
+    objcopy: &Path,
+    object_path: &Path,
+    redefinitions: &[SymbolRedefinition],
+) -> Result<()> {
+    use object::read::{self, Object, ObjectSymbol};
+    use object::BinaryFormat;
+
+    let data = fs::read(object_path)?;
+    let file = read::File::parse(&*data).context("failed to parse the module object")?;
+    // Mach-O symbols have a leading underscore
+    let prefix = match file.format() {
+        BinaryFormat::MachO => "_",
+        _ => "",
+    };
+    let defined_symbols = file
+        .symbols()
+        .filter(|symbol| !symbol.is_undefined())
+        .filter_map(|symbol| symbol.name().ok()?.strip_prefix(prefix))
+        .collect::<std::collections::HashSet<_>>();
+    for redefinition in redefinitions {
+        if !defined_symbols.contains(&*redefinition.old) {
+            bail!(
+                "`{}` isn't defined by the module, only its own symbols can be renamed",
+                redefinition.old
+            );
+        }
+        if defined_symbols.contains(&*redefinition.new) {
+            bail!(
+                "can't rename `{}` to `{}`, which is already defined by the module",
+                redefinition.old,
+                redefinition.new
+            );
+        }
+    }
+
+    let redefinitions_path = Path::new("wasmer_redefine_syms.txt");
+    fs::write(
+        redefinitions_path,
+        redefinitions
+            .iter()
+            .map(|redefinition| {
+                format!(
+                    "{0}{1} {0}{2}\n",
+                    prefix, redefinition.old, redefinition.new
+                )
+            })
+            .collect::<String>(),
+    )?;
+    let output = Command::new(objcopy)
+        .arg(format!("--redefine-syms={}", redefinitions_path.display()))
+        .arg(object_path)
+        .output()
+        .with_context(|| {
+            format!(
+                "failed to run `{}`, which renaming symbols needs: install binutils or pass it in `--toolchain-root`",
+                objcopy.display()
+            )
+        })?;
+    if !output.status.success() {
+        bail!(
+            "`{}` failed to rename the symbols ({}):\nstdout: {}\n\nstderr: {}",
+            objcopy.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    println!("Renamed symbols: {}", redefinitions.len());
+    Ok(())
+}
+
+/// The renames of `--symbol-namespace` for the global symbols defined by
+/// the object at `object_path`, except `main`.
+fn namespaced_symbols(object_path: &Path, namespace: &str) -> Result<Vec<SymbolRedefinition>> {
+    use object::read::{self, Object, ObjectSymbol};
+    use object::BinaryFormat;
+
+    let data = fs::read(object_path)?;
+    let file = read::File::parse(&*data)
+        .with_context(|| format!("failed to parse `{}`", object_path.display()))?;
+    // Mach-O symbols have a leading underscore
+    let prefix = match file.format() {
+        BinaryFormat::MachO => "_",
+        _ => "",
+    };
+    Ok(file
+        .symbols()
+        .filter(|symbol| symbol.is_global() && !symbol.is_undefined())
+        .filter_map(|symbol| symbol.name().ok()?.strip_prefix(prefix))
+        .filter(|name| *name != "main")
+        .map(|name| SymbolRedefinition {
+            old: name.to_string(),
+            new: format!("{}_{}", namespace, name),
+        })
+        .collect())
+}
+
+/// Copy libwasmer at `libwasmer_path` with `objcopy`, making its
+/// `wasmer_vm_*` runtime functions weak, and return the path of the copy.
+fn weaken_runtime_symbols(objcopy: &Path, libwasmer_path: &Path) -> Result<PathBuf> {
+    let weak_libwasmer_path = PathBuf::from("libwasmer_weak.a");
+    let output = Command::new(objcopy)
+        .arg("--wildcard")
+        .arg("--weaken-symbol=wasmer_vm_*")
+        .arg(libwasmer_path)
+        .arg(&weak_libwasmer_path)
+        .output()
+        .with_context(|| {
+            format!(
+                "failed to run `{}`, which `--weak-runtime-symbols` needs: install binutils or pass it in `--toolchain-root`",
+                objcopy.display()
+            )
+        })?;
+    if !output.status.success() {
+        bail!(
+            "`{}` failed to weaken the runtime symbols of libwasmer ({}):\nstdout: {}\n\nstderr: {}",
+            objcopy.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(weak_libwasmer_path)
+}
+
+/// Whether `name` can be used as an identifier in the generated C code.
+fn is_c_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A C string literal for `s`, escaping the bytes that aren't printable
+/// ASCII characters.
+fn c_string_literal(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' | b'?' => literal.push_str(&format!("\\{}", byte as char)),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Translate the executable-relative prefix of an rpath (`$ORIGIN` or
+/// `@loader_path`) to the one understood by the target's dynamic loader.
+fn normalize_rpath(rpath: &str, triple: &Triple) -> String {
+    match triple.operating_system {
+        OperatingSystem::Darwin | OperatingSystem::MacOSX { .. } | OperatingSystem::Ios => {
+            rpath.replace("$ORIGIN", "@loader_path")
+        }
+        _ => rpath.replace("@loader_path", "$ORIGIN"),
     }
 }
 
 fn generate_header(header_file_src: &[u8]) -> anyhow::Result<()> {
-    let header_file_path = Path::new("my_wasm.h");
+    let header_file_path = Path::new(HEADER_FILE);
     let mut header = std::fs::OpenOptions::new()
         .create(true)
         .truncate(true)
@@ -172,7 +2514,7 @@ fn get_wasmer_include_directory() -> anyhow::Result<PathBuf> {
 }
 
 /// path to the static libwasmer
-fn get_libwasmer_path() -> anyhow::Result<PathBuf> {
+pub(crate) fn get_libwasmer_path() -> anyhow::Result<PathBuf> {
     let mut path = get_wasmer_dir()?;
     path.push("lib");
 
@@ -185,19 +2527,45 @@ fn get_libwasmer_path() -> anyhow::Result<PathBuf> {
     Ok(path)
 }
 
+/// Find the tool named `tool` in `root/bin` or `root`. Paths to a tool,
+/// rather than bare names, are returned as is.
+fn find_toolchain_tool(root: &Path, tool: &Path) -> Result<PathBuf> {
+    if tool.components().count() > 1 {
+        return Ok(tool.to_path_buf());
+    }
+    let mut names = vec![tool.as_os_str().to_owned()];
+    if !env::consts::EXE_SUFFIX.is_empty() {
+        let mut name = tool.as_os_str().to_owned();
+        name.push(env::consts::EXE_SUFFIX);
+        names.push(name);
+    }
+    let directories = [root.join("bin"), root.to_path_buf()];
+    directories
+        .iter()
+        .flat_map(|directory| names.iter().map(move |name| directory.join(name)))
+        .find(|path| path.is_file())
+        .with_context(|| {
+            format!(
+                "`{}` was not found in the toolchain root: looked in `{}` and `{}`",
+                tool.display(),
+                directories[0].display(),
+                directories[1].display()
+            )
+        })
+}
+
 /// Compile the C code.
+#[allow(clippy::too_many_arguments)]
 fn run_c_compile(
+    c_compiler: &Path,
     path_to_c_src: &Path,
     output_name: &Path,
     target: Option<Triple>,
+    sysroot: Option<&Sysroot>,
+    pic_flag: Option<&str>,
+    defines: &[String],
+    thin_lto: bool,
 ) -> anyhow::Result<()> {
-    #[cfg(not(windows))]
-    let c_compiler = "cc";
-    // We must use a C++ compiler on Windows because wasm.h uses `static_assert`
-    // which isn't available in `clang` on Windows.
-    #[cfg(windows)]
-    let c_compiler = "clang++";
-
     let mut command = Command::new(c_compiler);
     let command = command
         .arg("-O2")
@@ -206,12 +2574,32 @@ fn run_c_compile(
         .arg("-I")
         .arg(get_wasmer_include_directory()?);
 
+    let command = if thin_lto {
+        command.arg("-flto=thin")
+    } else {
+        command
+    };
+
+    let command = if let Some(pic_flag) = pic_flag {
+        command.arg(pic_flag)
+    } else {
+        command
+    };
+
     let command = if let Some(target) = target {
         command.arg("-target").arg(format!("{}", target))
     } else {
         command
     };
 
+    let command = if let Some(sysroot) = sysroot {
+        command.arg(format!("--sysroot={}", sysroot.root.display()))
+    } else {
+        command
+    };
+
+    let command = command.args(defines.iter().map(|define| format!("-D{}", define)));
+
     let output = command.arg("-o").arg(output_name).output()?;
 
     if !output.status.success() {
@@ -231,86 +2619,449 @@ fn run_c_compile(
 struct LinkCode {
     /// Path to the linker used to run the linking command.
     linker_path: PathBuf,
+    /// The linker family, which determines the flags passed to the linker.
+    flavor: LinkerFlavor,
     /// String used as an optimization flag.
     optimization_flag: String,
     /// Paths of objects to link.
     object_paths: Vec<PathBuf>,
     /// Additional libraries to link against.
     additional_libraries: Vec<String>,
+    /// Runtime search paths for shared libraries.
+    rpaths: Vec<String>,
     /// Path to the output target.
     output_path: PathBuf,
     /// Path to the dir containing the static libwasmer library.
     libwasmer_path: PathBuf,
     /// The target to link the executable for.
     target: Option<Triple>,
+    /// The kind of output to link.
+    output_type: OutputType,
+    /// Whether to link a static position-independent executable.
+    static_pie: bool,
+    /// The build ID to embed, if not the toolchain default.
+    build_id: Option<BuildId>,
+    /// Path to the version script passed to the linker.
+    version_script: Option<PathBuf>,
+    /// Patterns of the symbols to hide from the exports, for `ld64`.
+    unexported_symbols: Vec<String>,
+    /// Whether the runtime symbols of libwasmer are weak, so the
+    /// additional libraries are linked before it to override them.
+    weak_runtime_symbols: bool,
+    /// Whether the objects are optimized together with ThinLTO.
+    thin_lto: bool,
+    /// The target's headers and libraries, for `--cross-sysroot`.
+    sysroot: Option<Sysroot>,
 }
 
 impl Default for LinkCode {
     fn default() -> Self {
-        #[cfg(not(windows))]
-        let linker = "cc";
-        #[cfg(windows)]
-        let linker = "clang";
+        let flavor = LinkerFlavor::default_for_target(&Triple::host());
         Self {
-            linker_path: PathBuf::from(linker),
+            linker_path: PathBuf::from(flavor.default_linker()),
+            flavor,
             optimization_flag: String::from("-O2"),
             object_paths: vec![],
             additional_libraries: vec![],
+            rpaths: vec![],
             output_path: PathBuf::from("a.out"),
             libwasmer_path: get_libwasmer_path().unwrap(),
             target: None,
+            output_type: OutputType::Exe,
+            static_pie: false,
+            build_id: None,
+            version_script: None,
+            unexported_symbols: vec![],
+            weak_runtime_symbols: false,
+            thin_lto: false,
+            sysroot: None,
         }
     }
 }
 
 impl LinkCode {
-    fn run(&self) -> anyhow::Result<()> {
-        let mut command = Command::new(&self.linker_path);
-        let command = command
-            .arg(&self.optimization_flag)
-            .args(
-                self.object_paths
-                    .iter()
-                    .map(|path| path.canonicalize().unwrap()),
-            )
-            .arg(
-                &self
-                    .libwasmer_path
-                    .canonicalize()
-                    .context("Failed to find libwasmer")?,
+    /// The objects and libraries passed to the linker, in link order. The
+    /// objects are canonicalized when they exist.
+    fn inputs(&self) -> Vec<String> {
+        let canonical_path = |path: &PathBuf| {
+            path.canonicalize()
+                .unwrap_or_else(|_| path.clone())
+                .display()
+                .to_string()
+        };
+        self.object_paths
+            .iter()
+            .map(canonical_path)
+            .chain(self.overriding_libraries())
+            .chain(std::iter::once(canonical_path(&self.libwasmer_path)))
+            .chain(self.libraries())
+            .collect()
+    }
+
+    /// The additional libraries linked before libwasmer, to override its
+    /// weak runtime symbols, as arguments of the linker.
+    fn overriding_libraries(&self) -> Vec<String> {
+        if !self.weak_runtime_symbols {
+            return vec![];
+        }
+        self.additional_libraries
+            .iter()
+            .map(|lib| format!("-l{}", lib))
+            .collect()
+    }
+
+    /// The libraries linked after libwasmer, as arguments of the linker.
+    fn libraries(&self) -> Vec<String> {
+        if self.flavor == LinkerFlavor::Msvc {
+            // We need userenv, sockets (Ws2_32), advapi32 for some system calls and bcrypt for random numbers.
+            ["userenv.lib", "Ws2_32.lib", "advapi32.lib", "bcrypt.lib"]
+                .iter()
+                .map(|lib| lib.to_string())
+                .chain(
+                    self.additional_libraries
+                        .iter()
+                        .map(|lib| format!("{}.lib", lib)),
+                )
+                .collect()
+        } else {
+            // Add libraries required per platform.
+            // We need userenv, sockets (Ws2_32), advapi32 for some system calls and bcrypt for random numbers.
+            #[cfg(windows)]
+            let system_libraries = ["-luserenv", "-lWs2_32", "-ladvapi32", "-lbcrypt"];
+            // On unix we need dlopen-related symbols, libmath for a few things, and pthreads.
+            #[cfg(not(windows))]
+            let system_libraries = ["-ldl", "-lm", "-pthread"];
+            let additional_libraries: &[String] = if self.weak_runtime_symbols {
+                &[]
+            } else {
+                &self.additional_libraries[..]
+            };
+            system_libraries
+                .iter()
+                .map(|lib| lib.to_string())
+                .chain(additional_libraries.iter().map(|lib| format!("-l{}", lib)))
+                .collect()
+        }
+    }
+
+    /// The arguments of the linker. The objects and libwasmer are
+    /// canonicalized when they exist.
+    fn args(&self) -> Vec<OsString> {
+        let canonical_path = |path: &PathBuf| {
+            path.canonicalize()
+                .unwrap_or_else(|_| path.clone())
+                .into_os_string()
+        };
+        let object_paths = self.object_paths.iter().map(canonical_path);
+        let libwasmer_path = canonical_path(&self.libwasmer_path);
+        let mut args: Vec<OsString> = vec![];
+        if self.flavor == LinkerFlavor::Msvc {
+            args.push("/NOLOGO".into());
+            args.extend(object_paths);
+            args.push(libwasmer_path);
+            args.extend(self.libraries().into_iter().map(OsString::from));
+            args.push(format!("/OUT:{}", self.output_path.display()).into());
+            return args;
+        }
+        args.push(self.optimization_flag.clone().into());
+        args.extend(object_paths);
+        args.extend(self.overriding_libraries().into_iter().map(OsString::from));
+        if self.output_type == OutputType::Dylib {
+            // The whole libwasmer is linked in, so the shared library
+            // exports the complete Wasm C API.
+            let is_apple = matches!(
+                self.target
+                    .clone()
+                    .unwrap_or_else(Triple::host)
+                    .operating_system,
+                OperatingSystem::Darwin | OperatingSystem::MacOSX { .. } | OperatingSystem::Ios
             );
-        let command = if let Some(target) = &self.target {
-            command.arg("-target").arg(format!("{}", target))
+            if is_apple {
+                args.push("-dynamiclib".into());
+                args.push(
+                    format!("-Wl,-force_load,{}", Path::new(&libwasmer_path).display()).into(),
+                );
+            } else {
+                args.push("-shared".into());
+                args.push("-Wl,--whole-archive".into());
+                args.push(libwasmer_path);
+                args.push("-Wl,--no-whole-archive".into());
+            }
         } else {
-            command
+            args.push(libwasmer_path);
+        }
+        if self.output_type == OutputType::Pie {
+            args.push("-pie".into());
+        }
+        if self.flavor == LinkerFlavor::Lld {
+            args.push("-fuse-ld=lld".into());
+        }
+        if self.thin_lto {
+            args.push("-flto=thin".into());
+        }
+        if let Some(target) = &self.target {
+            args.push("-target".into());
+            args.push(format!("{}", target).into());
+        }
+        if let Some(sysroot) = &self.sysroot {
+            args.push(format!("--sysroot={}", sysroot.root.display()).into());
+            args.extend(
+                sysroot
+                    .library_dirs
+                    .iter()
+                    .map(|dir| format!("-L{}", dir.display()).into()),
+            );
+        }
+        if self.static_pie {
+            args.push("-static-pie".into());
+        }
+        if let Some(build_id) = self.build_id {
+            args.push(format!("-Wl,--build-id={}", build_id).into());
+        }
+        args.extend(self.libraries().into_iter().map(OsString::from));
+        args.extend(
+            self.rpaths
+                .iter()
+                .map(|rpath| format!("-Wl,-rpath,{}", rpath).into()),
+        );
+        if let Some(version_script) = &self.version_script {
+            args.push(format!("-Wl,--version-script={}", version_script.display()).into());
+        }
+        args.extend(
+            self.unexported_symbols
+                .iter()
+                .map(|pattern| format!("-Wl,-unexported_symbol,{}", pattern).into()),
+        );
+        args.push("-o".into());
+        args.push(self.output_path.clone().into_os_string());
+        args
+    }
+
+    fn run(&self) -> anyhow::Result<()> {
+        self.libwasmer_path
+            .canonicalize()
+            .context("Failed to find libwasmer")?;
+        if self.flavor == LinkerFlavor::Msvc {
+            if self.target.is_some() {
+                warning!("`--target` is ignored by the `msvc` linker flavor");
+            }
+            if !self.rpaths.is_empty() {
+                warning!("`--rpath` is ignored by the `msvc` linker flavor");
+            }
+        }
+        let output = match Command::new(&self.linker_path).args(self.args()).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => bail!(
+                "the linker `{}` was not found: {}",
+                self.linker_path.display(),
+                self.flavor.install_hint()
+            ),
+            Err(e) => return Err(e.into()),
         };
-        // Add libraries required per platform.
-        // We need userenv, sockets (Ws2_32), advapi32 for some system calls and bcrypt for random numbers.
-        #[cfg(windows)]
-        let command = command
-            .arg("-luserenv")
-            .arg("-lWs2_32")
-            .arg("-ladvapi32")
-            .arg("-lbcrypt");
-        // On unix we need dlopen-related symbols, libmath for a few things, and pthreads.
-        #[cfg(not(windows))]
-        let command = command.arg("-ldl").arg("-lm").arg("-pthread");
-        let link_aganist_extra_libs = self
-            .additional_libraries
-            .iter()
-            .map(|lib| format!("-l{}", lib));
-        let command = command.args(link_aganist_extra_libs);
-        let output = command.arg("-o").arg(&self.output_path).output()?;
 
         if !output.status.success() {
             bail!(
-                "linking failed with: stdout: {}\n\nstderr: {}",
+                "linking failed with: stdout: {}\n\nstderr: {}{}",
                 std::str::from_utf8(&output.stdout)
                     .expect("stdout is not utf8! need to handle arbitrary bytes"),
                 std::str::from_utf8(&output.stderr)
-                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+                    .expect("stderr is not utf8! need to handle arbitrary bytes"),
+                if self.static_pie {
+                    "\n\nnote: `--static-pie` needs a toolchain and libc supporting `-static-pie`"
+                } else {
+                    ""
+                }
             );
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_variants_round_trip<T>(variants: &[(&str, T)])
+    where
+        T: Copy + PartialEq + fmt::Debug + fmt::Display + FromStr<Err = anyhow::Error>,
+    {
+        for (name, variant) in variants {
+            assert_eq!(name.parse::<T>().unwrap(), *variant);
+            assert_eq!(variant.to_string(), *name);
+        }
+    }
+
+    #[test]
+    fn test_variants_round_trip() {
+        assert_variants_round_trip(OutputType::VARIANTS);
+        assert_variants_round_trip(BuildId::VARIANTS);
+        assert_variants_round_trip(SymbolVisibility::VARIANTS);
+        assert_variants_round_trip(PrefixAlgorithm::VARIANTS);
+        assert_variants_round_trip(LinkerFlavor::VARIANTS);
+    }
+
+    #[test]
+    fn test_unknown_variant_lists_the_names() {
+        assert_eq!(
+            "elf".parse::<OutputType>().unwrap_err().to_string(),
+            "unknown output type `elf`, expected `exe`, `pie` or `dylib`"
+        );
+        assert_eq!(
+            "protected"
+                .parse::<SymbolVisibility>()
+                .unwrap_err()
+                .to_string(),
+            "unknown visibility `protected`, expected `default` or `hidden`"
+        );
+        assert_eq!(
+            parse_variant(&[("only", ())], "value", "other")
+                .unwrap_err()
+                .to_string(),
+            "unknown value `other`, expected `only`"
+        );
+    }
+
+    #[test]
+    fn test_metadata_compression_levels() {
+        use CompressionAlgorithm::*;
+
+        assert_eq!(
+            None.to_metadata_compression(Option::None, Option::None)
+                .unwrap(),
+            MetadataCompression::None
+        );
+        assert_eq!(
+            Gzip.to_metadata_compression(Option::None, Option::None)
+                .unwrap(),
+            MetadataCompression::Gzip(6)
+        );
+        assert_eq!(
+            Gzip.to_metadata_compression(Some(0), Option::None).unwrap(),
+            MetadataCompression::Gzip(0)
+        );
+        assert_eq!(
+            Gzip.to_metadata_compression(Some(10), Option::None)
+                .unwrap_err()
+                .to_string(),
+            "the gzip compression level must be between 0 and 9, got 10"
+        );
+        assert_eq!(
+            Zstd.to_metadata_compression(Option::None, Option::None)
+                .unwrap(),
+            MetadataCompression::Zstd(DEFAULT_ZSTD_LEVEL)
+        );
+        assert_eq!(
+            Zstd.to_metadata_compression(Option::None, Some(22))
+                .unwrap(),
+            MetadataCompression::Zstd(22)
+        );
+        for level in &[0, 23] {
+            assert_eq!(
+                Zstd.to_metadata_compression(Option::None, Some(*level))
+                    .unwrap_err()
+                    .to_string(),
+                format!(
+                    "the zstd compression level must be between 1 and 22, got {}",
+                    level
+                )
+            );
+        }
+        assert_eq!(
+            Zstd.to_metadata_compression(Some(3), Option::None)
+                .unwrap_err()
+                .to_string(),
+            "use `--zstd-level` to set the zstd compression level, not `--compress-level`"
+        );
+        assert_eq!(
+            Gzip.to_metadata_compression(Option::None, Some(3))
+                .unwrap_err()
+                .to_string(),
+            "`--zstd-level` can only be used with `--compress-with zstd`"
+        );
+        assert_eq!(
+            "brotli"
+                .parse::<CompressionAlgorithm>()
+                .unwrap_err()
+                .to_string(),
+            "unknown compression algorithm `brotli`, expected `none`, `gzip` or `zstd`"
+        );
+    }
+
+    #[test]
+    fn test_parse_universal_archs() {
+        let archs = "x86_64,arm64".parse::<UniversalArchs>().unwrap();
+        assert_eq!(archs.to_string(), "x86_64, aarch64");
+        assert_eq!(
+            archs.0,
+            vec![
+                Triple::from_str("x86_64-apple-darwin").unwrap(),
+                Triple::from_str("aarch64-apple-darwin").unwrap(),
+            ]
+        );
+        assert_eq!(
+            "x86_64".parse::<UniversalArchs>().unwrap_err().to_string(),
+            "a universal binary needs at least two architectures, like `x86_64,arm64`"
+        );
+        assert_eq!(
+            "arm64,aarch64"
+                .parse::<UniversalArchs>()
+                .unwrap_err()
+                .to_string(),
+            "the architecture `aarch64` is listed twice"
+        );
+        assert_eq!(
+            "x86_64,riscv64"
+                .parse::<UniversalArchs>()
+                .unwrap_err()
+                .to_string(),
+            "unknown architecture `riscv64`, expected `x86_64` or `arm64`"
+        );
+    }
+
+    #[test]
+    fn test_parse_atom_entry() {
+        let entry = "main".parse::<AtomEntry>().unwrap();
+        assert_eq!(entry.atom, Option::None);
+        assert_eq!(entry.export, "main");
+
+        let entry = "qjs=run".parse::<AtomEntry>().unwrap();
+        assert_eq!(entry.atom.as_deref(), Some("qjs"));
+        assert_eq!(entry.export, "run");
+
+        for invalid in &["", "=run", "qjs="] {
+            assert_eq!(
+                invalid.parse::<AtomEntry>().unwrap_err().to_string(),
+                format!(
+                    "invalid entry `{}`, expected `ATOM=EXPORT` or `EXPORT`",
+                    invalid
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_feature_profile() {
+        for (name, profile) in &[
+            ("baseline", FeatureProfile::Baseline),
+            ("v2", FeatureProfile::V2),
+            ("v3", FeatureProfile::V3),
+            ("v4", FeatureProfile::V4),
+            ("native", FeatureProfile::Native),
+        ] {
+            assert_eq!(name.parse::<FeatureProfile>().unwrap(), *profile);
+            assert_eq!(profile.to_string(), *name);
+        }
+        assert_eq!(
+            "v5".parse::<FeatureProfile>().unwrap_err().to_string(),
+            "unknown target feature profile `v5`, expected `baseline`, `v2`, `v3`, `v4` or `native`"
+        );
+        // Each level includes the features of the lower ones
+        let v2 = FeatureProfile::V2.cpu_features();
+        assert!(v2.contains(&CpuFeature::SSE2));
+        assert!(v2.contains(&CpuFeature::POPCNT));
+        assert!(!v2.contains(&CpuFeature::AVX2));
+        assert!(FeatureProfile::V4
+            .cpu_features()
+            .contains(&CpuFeature::AVX2));
+    }
+}
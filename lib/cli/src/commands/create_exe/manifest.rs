@@ -0,0 +1,113 @@
+//! The build manifest written by `create-exe --output-manifest`.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// A record of what went into a native executable, to audit and
+/// reproduce its build.
+///
+/// It doesn't contain anything that changes between runs of the same
+/// build (like timestamps or temporary paths).
+#[derive(Debug, Serialize)]
+pub struct BuildManifest {
+    pub wasmer_version: &'static str,
+    pub input: ManifestFile,
+    /// The produced file, missing from the manifest embedded in it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<ManifestFile>,
+    pub engine: String,
+    pub compiler: String,
+    pub target: String,
+    pub cpu_features: Vec<String>,
+    pub wasm_features: Vec<&'static str>,
+    pub metadata_compression: String,
+    pub prefix_algorithm: Option<String>,
+    pub libraries: Vec<String>,
+    pub rpaths: Vec<String>,
+    pub output_type: String,
+    pub static_pie: bool,
+    pub build_id: Option<String>,
+    pub ld_version_script: Option<ManifestFile>,
+    pub default_visibility: String,
+    pub symbol_version: Option<String>,
+    pub include_source: bool,
+    pub embed_manifest: bool,
+    pub hash_embedded_wasm: bool,
+    pub weak_runtime_symbols: bool,
+    pub symbol_namespace: Option<String>,
+    /// The symbols renamed with `--objcopy-redefine`, as `OLD=NEW`.
+    pub redefined_symbols: Vec<String>,
+    pub c_compiler: ManifestTool,
+    pub linker: ManifestTool,
+    /// The packer of `--self-extract`.
+    pub packer: Option<ManifestTool>,
+    /// The object copier of `--objcopy-redefine`.
+    pub objcopy: Option<ManifestTool>,
+}
+
+/// A file used or produced by the build.
+#[derive(Debug, Serialize)]
+pub struct ManifestFile {
+    /// The path, as provided in the command line.
+    pub path: String,
+    pub blake3: String,
+}
+
+impl ManifestFile {
+    /// Hash the file at `path`, recording it as `display_path`.
+    pub fn new(display_path: &Path, path: &Path) -> Result<Self> {
+        let contents =
+            fs::read(path).with_context(|| format!("failed to read `{}`", path.display()))?;
+        Ok(Self {
+            path: display_path.display().to_string(),
+            blake3: blake3::hash(&contents).to_hex().to_string(),
+        })
+    }
+}
+
+/// An external tool used by the build.
+#[derive(Debug, Serialize)]
+pub struct ManifestTool {
+    pub path: String,
+    pub flavor: Option<String>,
+    /// The first line printed by `<tool> --version`, if it supports it.
+    pub version: Option<String>,
+}
+
+impl ManifestTool {
+    pub fn new(path: &Path, flavor: Option<String>) -> Self {
+        let version = Command::new(path)
+            .arg("--version")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .map(str::to_string)
+            });
+        Self {
+            path: path.display().to_string(),
+            flavor,
+            version,
+        }
+    }
+}
+
+impl BuildManifest {
+    /// Serialize the manifest as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// Write the manifest as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json()?)
+            .with_context(|| format!("failed to write the build manifest `{}`", path.display()))
+    }
+}
@@ -0,0 +1,95 @@
+//! The objects of a module compiled by `create-exe --objects-only`, and
+//! linked later by `create-exe --link-from`.
+//!
+//! The stage directory holds the objects, the header of the C glue and
+//! `wasmer_stage.json`, recording what the link needs from the
+//! compilation: the target, the defines of the C glue and the renamed
+//! symbols. The C glue itself is compiled when linking, with the C
+//! compiler of the link machine.
+
+use super::SymbolRedefinition;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use wasmer::Triple;
+
+/// The file describing the stage.
+const STAGE_FILE: &str = "wasmer_stage.json";
+
+/// The compiled objects of a module, waiting to be linked.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Stage {
+    /// The version of Wasmer that compiled the objects, which must link
+    /// them with the same libwasmer.
+    pub wasmer_version: String,
+    /// The target triple of the objects.
+    pub target: String,
+    /// The file names of the objects, in link order.
+    pub objects: Vec<String>,
+    /// The header of the C glue.
+    pub header: String,
+    /// The defines of the C glue.
+    pub defines: Vec<String>,
+    /// The symbols of the module renamed with `--objcopy-redefine` or
+    /// `--symbol-namespace`.
+    pub redefinitions: Vec<SymbolRedefinition>,
+    pub symbol_namespace: Option<String>,
+}
+
+impl Stage {
+    /// Copy the objects and the header from the current directory to
+    /// `stage_dir`, and describe them.
+    pub fn write(&self, stage_dir: &Path) -> Result<()> {
+        fs::create_dir_all(stage_dir)
+            .with_context(|| format!("failed to create `{}`", stage_dir.display()))?;
+        for file in self.objects.iter().chain(std::iter::once(&self.header)) {
+            fs::copy(file, stage_dir.join(file))
+                .with_context(|| format!("failed to write `{}` to the stage", file))?;
+        }
+        fs::write(
+            stage_dir.join(STAGE_FILE),
+            serde_json::to_string_pretty(self)?,
+        )
+        .with_context(|| format!("failed to write `{}`", STAGE_FILE))
+    }
+
+    /// Read the stage of `stage_dir`, checking that it was compiled by
+    /// this version of Wasmer.
+    pub fn read(stage_dir: &Path) -> Result<Self> {
+        let stage_path = stage_dir.join(STAGE_FILE);
+        let contents = fs::read_to_string(&stage_path).with_context(|| {
+            format!(
+                "failed to read `{}`, is it a directory written by `--objects-only`?",
+                stage_path.display()
+            )
+        })?;
+        let stage: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("invalid stage `{}`", stage_path.display()))?;
+        if stage.wasmer_version != crate::VERSION {
+            bail!(
+                "the objects were compiled by Wasmer {}, but can only be linked by the same version (this is {})",
+                stage.wasmer_version,
+                crate::VERSION
+            );
+        }
+        Ok(stage)
+    }
+
+    /// The target triple of the objects.
+    pub fn triple(&self) -> Result<Triple> {
+        Triple::from_str(&self.target)
+            .map_err(|err| anyhow!("invalid target `{}` in the stage: {}", self.target, err))
+    }
+
+    /// Copy the objects and the header of `stage_dir` to the current
+    /// directory.
+    pub fn copy_files(&self, stage_dir: &Path) -> Result<()> {
+        for file in self.objects.iter().chain(std::iter::once(&self.header)) {
+            fs::copy(stage_dir.join(file), file)
+                .with_context(|| format!("failed to read `{}` from the stage", file))?;
+        }
+        Ok(())
+    }
+}
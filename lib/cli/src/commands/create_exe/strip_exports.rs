@@ -0,0 +1,207 @@
+//! Remove exported functions from a module before it's compiled, for
+//! `wasmer create-exe --strip-exports`.
+//!
+//! An export matching the pattern is only removed if nothing else needs
+//! its function: the other exports, the start function, the element
+//! segments and the globals are the roots, and a function is needed if
+//! it's reachable from them through calls and `ref.func`. The bodies of
+//! the functions that are no longer reachable are then replaced by a
+//! stub, which keeps the indices of all the functions.
+
+use crate::wasm_binary::{
+    glob_matches, split_sections, write_section, write_u32, Reader, STUB_FUNCTION_BODY,
+};
+use anyhow::Result;
+use std::collections::HashSet;
+use wasmer::wasmparser::{
+    ElementItem, ExternalKind, ImportSectionEntryType, Operator, Parser, Payload,
+};
+
+/// The result of stripping the exports of a module.
+pub struct StrippedModule {
+    /// The rewritten module.
+    pub wasm: Vec<u8>,
+    /// The names of the removed exports.
+    pub removed_exports: Vec<String>,
+    /// The names of the exports matching the pattern that were kept,
+    /// because their function is still needed.
+    pub kept_exports: Vec<String>,
+    /// The number of function bodies replaced by a stub.
+    pub removed_functions: usize,
+}
+
+/// The functions of a module, and how they refer to each other.
+#[derive(Default)]
+struct FunctionGraph {
+    imported_functions: u32,
+    /// The functions called or referenced by each defined function.
+    callees: Vec<Vec<u32>>,
+    /// The functions referenced outside of the function bodies.
+    roots: Vec<u32>,
+    /// The function exports, with their name.
+    exports: Vec<(String, u32)>,
+}
+
+/// Remove the function exports of `wasm` whose name matches the glob
+/// `pattern`, except `preserved` and the exports whose function is still
+/// needed, and stub the functions that are no longer reachable.
+pub fn strip_exports(wasm: &[u8], pattern: &str, preserved: &str) -> Result<StrippedModule> {
+    let graph = function_graph(wasm)?;
+    let stripped =
+        |name: &str| name != preserved && glob_matches(pattern.as_bytes(), name.as_bytes());
+
+    let mut roots = graph.roots.clone();
+    roots.extend(
+        graph
+            .exports
+            .iter()
+            .filter(|(name, _)| !stripped(name))
+            .map(|(_, index)| *index),
+    );
+    let reachable = graph.reachable(roots);
+
+    let mut removed_exports = vec![];
+    let mut kept_exports = vec![];
+    for (name, index) in graph.exports.iter().filter(|(name, _)| stripped(name)) {
+        if reachable.contains(index) {
+            kept_exports.push(name.clone());
+        } else {
+            removed_exports.push(name.clone());
+        }
+    }
+
+    let mut module = wasm[..8].to_vec();
+    let mut removed_functions = 0;
+    for section in split_sections(wasm)? {
+        match section.id {
+            7 => write_section(
+                &mut module,
+                7,
+                &remove_exports(section.data, &removed_exports)?,
+            ),
+            10 => {
+                let mut reader = Reader::new(section.data);
+                let count = reader.read_u32()?;
+                let mut contents = vec![];
+                write_u32(&mut contents, count);
+                for local_index in 0..count {
+                    let size = reader.read_u32()?;
+                    let mut body = reader.read_bytes(size as usize)?;
+                    if !reachable.contains(&(graph.imported_functions + local_index)) {
+                        body = STUB_FUNCTION_BODY;
+                        removed_functions += 1;
+                    }
+                    write_u32(&mut contents, body.len() as u32);
+                    contents.extend_from_slice(body);
+                }
+                write_section(&mut module, 10, &contents);
+            }
+            id => write_section(&mut module, id, section.data),
+        }
+    }
+    Ok(StrippedModule {
+        wasm: module,
+        removed_exports,
+        kept_exports,
+        removed_functions,
+    })
+}
+
+impl FunctionGraph {
+    /// The functions reachable from `roots`, including the imported ones.
+    fn reachable(&self, mut roots: Vec<u32>) -> HashSet<u32> {
+        let mut reachable = HashSet::new();
+        while let Some(index) = roots.pop() {
+            if !reachable.insert(index) || index < self.imported_functions {
+                continue;
+            }
+            if let Some(callees) = self.callees.get((index - self.imported_functions) as usize) {
+                roots.extend(callees);
+            }
+        }
+        reachable
+    }
+}
+
+/// Read the functions of `wasm`, and how they refer to each other.
+fn function_graph(wasm: &[u8]) -> Result<FunctionGraph> {
+    let mut graph = FunctionGraph::default();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    if let ImportSectionEntryType::Function(_) = import?.ty {
+                        graph.imported_functions += 1;
+                    }
+                }
+            }
+            Payload::GlobalSection(globals) => {
+                for global in globals {
+                    let mut init_expr = global?.init_expr.get_binary_reader();
+                    while !init_expr.eof() {
+                        if let Operator::RefFunc { function_index } = init_expr.read_operator()? {
+                            graph.roots.push(function_index);
+                        }
+                    }
+                }
+            }
+            Payload::ExportSection(exports) => {
+                for export in exports {
+                    let export = export?;
+                    if let ExternalKind::Function = export.kind {
+                        graph.exports.push((export.field.to_string(), export.index));
+                    }
+                }
+            }
+            Payload::StartSection { func, .. } => graph.roots.push(func),
+            Payload::ElementSection(elements) => {
+                for element in elements {
+                    for item in element?.items.get_items_reader()? {
+                        if let ElementItem::Func(index) = item? {
+                            graph.roots.push(index);
+                        }
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let mut callees = vec![];
+                let mut operators = body.get_operators_reader()?;
+                while !operators.eof() {
+                    match operators.read()? {
+                        Operator::Call { function_index }
+                        | Operator::ReturnCall { function_index }
+                        | Operator::RefFunc { function_index } => callees.push(function_index),
+                        _ => {}
+                    }
+                }
+                graph.callees.push(callees);
+            }
+            _ => {}
+        }
+    }
+    Ok(graph)
+}
+
+/// The contents of the export section `data`, without the function
+/// exports named in `removed`.
+fn remove_exports(data: &[u8], removed: &[String]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(data);
+    let mut entries = vec![];
+    let mut count = 0;
+    for _ in 0..reader.read_u32()? {
+        let start = reader.position;
+        let name = reader.read_name()?;
+        let kind = reader.read_u8()?;
+        reader.read_u32()?;
+        // 0x00 is the kind of the function exports
+        if kind == 0x00 && removed.iter().any(|removed| removed == name) {
+            continue;
+        }
+        entries.extend_from_slice(&data[start..reader.position]);
+        count += 1;
+    }
+    let mut contents = vec![];
+    write_u32(&mut contents, count);
+    contents.extend_from_slice(&entries);
+    Ok(contents)
+}
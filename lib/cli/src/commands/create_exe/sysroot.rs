@@ -0,0 +1,81 @@
+//! Find the libc of the target in a `--cross-sysroot`, for
+//! `wasmer create-exe`.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use wasmer::{OperatingSystem, Triple};
+
+/// The files expected in the library directories of a Linux sysroot: the
+/// C runtime and the libc, each with its alternative names.
+const LINUX_LIBRARIES: &[&[&str]] = &[&["crti.o"], &["libc.so", "libc.a"]];
+
+/// The libraries expected in an Apple SDK.
+const APPLE_LIBRARIES: &[&[&str]] = &[&["libSystem.tbd", "libSystem.dylib"]];
+
+/// The root of the target's headers and libraries, with the directories
+/// holding its C runtime and its libc.
+#[derive(Debug, Clone)]
+pub struct Sysroot {
+    pub root: PathBuf,
+    /// The directories passed to the linker with `-L`, in search order.
+    pub library_dirs: Vec<PathBuf>,
+}
+
+impl Sysroot {
+    /// Look for the C runtime and the libc of `triple` in `root`.
+    pub fn find(root: &Path, triple: &Triple) -> Result<Self> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("failed to find the sysroot `{}`", root.display()))?;
+        let (candidates, expected) = match triple.operating_system {
+            OperatingSystem::Linux => {
+                // Debian-like sysroots keep the libraries in a multiarch
+                // directory, like `usr/lib/aarch64-linux-gnu`
+                let multiarch = format!("{}-linux-{}", triple.architecture, triple.environment);
+                (
+                    vec![
+                        Path::new("lib").join(&multiarch),
+                        Path::new("usr/lib").join(&multiarch),
+                        PathBuf::from("lib64"),
+                        PathBuf::from("usr/lib64"),
+                        PathBuf::from("lib"),
+                        PathBuf::from("usr/lib"),
+                    ],
+                    LINUX_LIBRARIES,
+                )
+            }
+            OperatingSystem::Darwin | OperatingSystem::MacOSX { .. } | OperatingSystem::Ios => {
+                (vec![PathBuf::from("usr/lib")], APPLE_LIBRARIES)
+            }
+            _ => bail!(
+                "`--cross-sysroot` is only supported for Linux and Apple targets, not `{}`",
+                triple
+            ),
+        };
+
+        let mut library_dirs: Vec<PathBuf> = vec![];
+        for files in expected {
+            let dir = candidates
+                .iter()
+                .map(|dir| root.join(dir))
+                .find(|dir| files.iter().any(|file| dir.join(file).is_file()))
+                .with_context(|| {
+                    format!(
+                        "the sysroot `{}` has no `{}` for `{}`, looked in: {}",
+                        root.display(),
+                        files.join("` or `"),
+                        triple,
+                        candidates
+                            .iter()
+                            .map(|dir| dir.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })?;
+            if !library_dirs.contains(&dir) {
+                library_dirs.push(dir);
+            }
+        }
+        Ok(Self { root, library_dirs })
+    }
+}
@@ -0,0 +1,812 @@
+//! Create a standalone native object file for a given Wasm file.
+
+use crate::c_gen::staticlib_header::{SingleSymbol, INIT_ARRAY_CONSTRUCTOR};
+use crate::store::{CompilerOptions, CompilerType, EngineType};
+use crate::utils::{check_target_endianness, parse_byte, parse_relocation_model};
+use crate::warning;
+use crate::wasm_features::{detect_features, enabled_wasm_features};
+use anyhow::{Context, Result};
+use bytesize::ByteSize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use structopt::StructOpt;
+use wasmer::*;
+use wasmer_compiler::{BinaryFormat, CompilerConfig, RelocationModel};
+
+mod function_filter;
+mod incremental;
+mod merge;
+mod metadata;
+mod pass_timing;
+mod relocations;
+mod section_flags;
+mod target_cpus;
+
+use super::targets::supports_target;
+use function_filter::FunctionFilter;
+use incremental::{IncrementalCache, IncrementalCompilerConfig};
+use metadata::ObjectMetadata;
+use pass_timing::CompilerPassTimings;
+use section_flags::SectionFlagChanges;
+
+#[derive(Debug, StructOpt)]
+/// The options for the `wasmer create-obj` subcommand
+pub struct CreateObj {
+    /// Input file
+    #[structopt(
+        name = "FILE",
+        parse(from_os_str),
+        required_unless_one = &["OBJECT", "target-cpu-list"]
+    )]
+    path: Option<PathBuf>,
+
+    /// Output file
+    #[structopt(
+        name = "OUTPUT PATH",
+        short = "o",
+        parse(from_os_str),
+        required_unless = "target-cpu-list"
+    )]
+    output: Option<PathBuf>,
+
+    /// Output path for generated header file
+    #[structopt(name = "HEADER PATH", long = "header", parse(from_os_str))]
+    header_path: Option<PathBuf>,
+
+    /// Compilation Target triple
+    #[structopt(long = "target")]
+    target_triple: Option<Triple>,
+
+    /// Directory used to cache the compiled functions between runs.
+    ///
+    /// Each function is keyed by its body, signature, the module layout and
+    /// the compiler settings, so only the functions that changed are
+    /// recompiled. The produced object is identical to a full recompile.
+    ///
+    /// Only the compilers emitting their unwind information per function
+    /// can reuse cached functions: Singlepass, and Cranelift with
+    /// `--unwind-info none` (its `.eh_frame` describes the whole module).
+    /// LLVM emits the object directly, so it isn't supported.
+    #[structopt(long = "incremental-cache", parse(from_os_str))]
+    incremental_cache: Option<PathBuf>,
+
+    /// Print how the `--incremental-cache` entries were used: the hits,
+    /// the misses, and the bytes read from and written to the cache.
+    ///
+    /// An entry is looked up for the whole module first, and for each
+    /// function only if the module entry is missing.
+    #[structopt(long = "cache-stats", requires = "incremental-cache")]
+    cache_stats: bool,
+
+    /// Write the `--cache-stats` to this file as JSON, for CI.
+    #[structopt(
+        long = "cache-stats-json",
+        parse(from_os_str),
+        requires = "incremental-cache"
+    )]
+    cache_stats_json: Option<PathBuf>,
+
+    /// Relocation model of the emitted code: `static`, `pic` or `dynamic-no-pic`.
+    ///
+    /// Defaults to `pic`. `dynamic-no-pic` is only available for Apple
+    /// targets, and not every compiler supports every model.
+    #[structopt(long = "relocation-model", parse(try_from_str = parse_relocation_model))]
+    relocation_model: Option<RelocationModel>,
+
+    /// The unwind information emitted in the object: `full` (the
+    /// default) or `none`.
+    ///
+    /// The unwind tables (the DWARF `.eh_frame` of Cranelift) let
+    /// debuggers, profilers and backtraces walk through the Wasm
+    /// functions: without them, the object is smaller but the backtrace
+    /// of a trap stops at the first Wasm frame. Traps are caught with
+    /// signal handlers on Unix, which don't need the tables, but Windows
+    /// returns from a trap by unwinding the stack, so `none` isn't
+    /// supported for Windows targets. Singlepass emits no unwind
+    /// information, and LLVM always does.
+    #[structopt(long = "unwind-info", default_value = "full")]
+    unwind_info: UnwindInfo,
+
+    /// Merge objects produced by `create-obj` into a single object, instead
+    /// of compiling a Wasm file. Only ELF objects are supported.
+    ///
+    /// The symbols defined by the objects must be distinct, so each object
+    /// must be compiled with a different symbol prefix.
+    #[structopt(
+        long = "merge",
+        name = "OBJECT",
+        parse(from_os_str),
+        number_of_values = 1,
+        conflicts_with_all = &["FILE", "HEADER PATH"]
+    )]
+    merge: Vec<PathBuf>,
+
+    /// Prefix the symbols with the package and atom the module comes from,
+    /// given as `NAME@VERSION`, instead of leaving them unprefixed.
+    ///
+    /// The atom is named after the file stem of the module, so
+    /// `wasm2wat.wasm` with `wabt@1.0.37` gets the `wabt_1_0_37_wasm2wat`
+    /// prefix: every character other than an ASCII letter or digit
+    /// becomes `_`. The prefix is stable across rebuilds of the same
+    /// package version. Atoms whose names only differ by such characters
+    /// get the same prefix, and `--merge` reports their colliding symbols.
+    #[structopt(long = "prefix-from-package", name = "NAME@VERSION")]
+    prefix_from_package: Option<PackageId>,
+
+    /// Emit each compiled function in its own section, like
+    /// `-ffunction-sections` in C compilers.
+    ///
+    /// This lets the final link with `--gc-sections` drop the functions
+    /// that aren't referenced. Not supported by the LLVM compiler, which
+    /// emits the object itself.
+    #[structopt(long = "function-sections")]
+    function_sections: bool,
+
+    /// Emit the compiled functions as a single symbol, with a table of
+    /// their offsets in it, instead of a symbol each.
+    ///
+    /// The symbol table of the object shrinks to a handful of symbols,
+    /// but the linker can no longer discard the unused functions. The
+    /// generated header resolves the functions from the offsets table
+    /// when the module is loaded. Not supported by the LLVM compiler,
+    /// which emits the object itself.
+    #[structopt(
+        long = "single-symbol",
+        conflicts_with_all = &["function-sections", "emit-metadata-json"]
+    )]
+    single_symbol: bool,
+
+    /// Print every relocation of the emitted object, with its offset,
+    /// type, target symbol and addend, grouped by function.
+    #[structopt(long = "dump-relocations")]
+    dump_relocations: bool,
+
+    /// Write the relocations to this file instead of printing them.
+    #[structopt(
+        long = "dump-relocations-file",
+        parse(from_os_str),
+        requires = "dump-relocations"
+    )]
+    dump_relocations_file: Option<PathBuf>,
+
+    /// Write a JSON description of the object to this file: the symbol
+    /// prefix, the object format, the target, the compiler, and the
+    /// symbol, index, signature and exports of every function.
+    ///
+    /// The symbol names are the ones of the object's symbol table (with
+    /// the leading underscore of Mach-O objects).
+    #[structopt(long = "emit-metadata-json", parse(from_os_str))]
+    emit_metadata_json: Option<PathBuf>,
+
+    /// Compile with the Wasm features used by this reference module,
+    /// instead of the ones of the `--enable-*` and `--disable-*` flags.
+    ///
+    /// The features are the smallest set the reference module validates
+    /// with. Compiling a module that uses other features fails, so a family
+    /// of modules can share the feature set of one of them.
+    #[structopt(long = "wasm-features-from", name = "REFERENCE", parse(from_os_str))]
+    wasm_features_from: Option<PathBuf>,
+
+    /// Pad the object to this size in bytes, appending `--pad-byte` bytes
+    /// after its contents, for fixed-size flash regions.
+    ///
+    /// The padding follows the existing contents, so the sections and the
+    /// symbol table are unchanged. It fails if the object is already
+    /// larger.
+    #[structopt(long = "pad-to", name = "SIZE")]
+    pad_to: Option<u64>,
+
+    /// The byte used by `--pad-to`, in decimal or hexadecimal like `0xFF`.
+    /// Defaults to `0`.
+    #[structopt(long = "pad-byte", parse(try_from_str = parse_byte), requires = "SIZE")]
+    pad_byte: Option<u8>,
+
+    /// Clear the executable flag of the code sections, for loaders which
+    /// map the code as data and make it executable later, with `mprotect`
+    /// or `VirtualProtect`.
+    ///
+    /// The flags are patched in the section headers of the object, so the
+    /// symbols and the relocations are unchanged. Only ELF (`SHF_EXECINSTR`)
+    /// and COFF (`IMAGE_SCN_MEM_EXECUTE`) objects are supported: Mach-O
+    /// objects set their protections per segment, in the linker.
+    #[structopt(long = "text-noexec")]
+    text_noexec: bool,
+
+    /// Clear the writable flag of every loaded section, so the whole object
+    /// can be placed in read-only memory.
+    ///
+    /// The code is never writable: this makes the data sections holding
+    /// the module metadata and the read-only custom sections read-only
+    /// too, which the runtime only reads. Combines with `--text-noexec`
+    /// to load the object in a non-executable, read-only segment. Only ELF
+    /// (`SHF_WRITE`) and COFF (`IMAGE_SCN_MEM_WRITE`) objects are
+    /// supported, like `--text-noexec`.
+    #[structopt(long = "text-readonly")]
+    text_readonly: bool,
+
+    /// Register a constructor in `.init_array` that instantiates the module
+    /// when the program starts, which runs its start function.
+    ///
+    /// The constructor is defined in the generated header, so it's
+    /// registered by the C file including it, once the object is linked
+    /// statically in a program or in a shared library. The instance is
+    /// available as `wasmer_init_instance` afterwards. The constructors of
+    /// different objects run in link order, so the other constructors
+    /// can't rely on the instance. Only modules without imports are
+    /// supported, and not for Windows targets. Apple targets register it
+    /// in `__mod_init_func` instead.
+    #[structopt(long = "emit-init-array")]
+    emit_init_array: bool,
+
+    /// Print the time spent in each pass of the compiler, summed across
+    /// the compiled functions.
+    ///
+    /// Cranelift reports its own passes (the Wasm translation, the
+    /// optimizations, the register allocation, the emission, ...) and
+    /// compiles the functions on a single thread while timing them. LLVM
+    /// reports the Wasm translation, the IR optimization and the code
+    /// generation, which includes the register allocation. Not supported
+    /// by Singlepass. The functions reused from `--incremental-cache`
+    /// aren't compiled, so they aren't timed.
+    #[structopt(long = "compiler-pass-timing")]
+    compiler_pass_timing: bool,
+
+    /// Write the `--compiler-pass-timing` times to this file as JSON.
+    #[structopt(long = "compiler-pass-timing-json", parse(from_os_str))]
+    compiler_pass_timing_json: Option<PathBuf>,
+
+    /// Only compile the functions selected by this filter, as `index:N`
+    /// (counting the imported functions) or a glob of their name, like
+    /// `name:quicksort*`, and replace the body of the others by a trap.
+    ///
+    /// The names come from the name section of the module, or from its
+    /// exports. The object only holds the code of the selected functions,
+    /// to analyze how they're compiled: it isn't meant to be run, and its
+    /// header says so.
+    #[structopt(
+        long = "function-filter",
+        name = "FILTER",
+        conflicts_with = "emit-init-array"
+    )]
+    function_filter: Option<FunctionFilter>,
+
+    /// Print the CPUs known for the `--target` architecture (or the host),
+    /// with the CPU features they imply, and exit.
+    ///
+    /// The compilers are configured with CPU features rather than CPU
+    /// names, so pass the features of a CPU with `-m` to compile for it.
+    /// Only the features known to Wasmer are listed: Arm CPUs have none.
+    #[structopt(long = "target-cpu-list", conflicts_with_all = &["FILE", "OBJECT"])]
+    target_cpu_list: bool,
+
+    #[structopt(flatten)]
+    compiler: CompilerOptions,
+
+    #[structopt(short = "m", multiple = true)]
+    cpu_features: Vec<CpuFeature>,
+}
+
+/// The package of the module, provided with `--prefix-from-package`.
+#[derive(Debug, Clone)]
+struct PackageId {
+    name: String,
+    version: String,
+}
+
+impl FromStr for PackageId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.rsplit_once('@') {
+            Some((name, version)) if !name.is_empty() && !version.is_empty() => Ok(Self {
+                name: name.to_string(),
+                version: version.to_string(),
+            }),
+            _ => bail!("invalid package `{}`, expected `NAME@VERSION`", s),
+        }
+    }
+}
+
+impl PackageId {
+    /// The symbol prefix of the atom `atom` of the package.
+    fn prefix(&self, atom: &str) -> String {
+        format!("{}_{}_{}", self.name, self.version, atom)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+}
+
+/// The unwind information emitted in an object, set with `--unwind-info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnwindInfo {
+    /// The unwind tables the compiler generates.
+    Full,
+    /// No unwind tables.
+    None,
+}
+
+impl FromStr for UnwindInfo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "full" => Ok(Self::Full),
+            "none" => Ok(Self::None),
+            "compact" => bail!(
+                "compact unwind tables aren't generated by any compiler, expected `full` or `none`"
+            ),
+            _ => bail!("unknown unwind info `{}`, expected `full` or `none`", s),
+        }
+    }
+}
+
+impl CreateObj {
+    /// Runs logic for the `create-obj` subcommand
+    pub fn execute(&self) -> Result<()> {
+        if self.target_cpu_list {
+            return self.print_target_cpus();
+        }
+        match &self.path {
+            Some(path) => self
+                .inner_execute(path)
+                .context(format!("failed to create object for `{}`", path.display())),
+            None => self.merge().context("failed to merge objects"),
+        }
+    }
+
+    /// The output file, only optional with `--target-cpu-list`.
+    fn output(&self) -> &Path {
+        self.output
+            .as_deref()
+            .expect("the output is required without `--target-cpu-list`")
+    }
+
+    /// Print the CPUs known for the target, for `--target-cpu-list`.
+    fn print_target_cpus(&self) -> Result<()> {
+        let triple = self.target_triple.clone().unwrap_or_else(Triple::host);
+        let (_, compiler_type) = self.compiler.get_compiler_config()?;
+        if !supports_target(&compiler_type, &triple) {
+            bail!(
+                "the {} compiler can't compile for `{}`",
+                compiler_type.to_string(),
+                triple
+            );
+        }
+        let cpus = target_cpus::target_cpus(triple.architecture);
+        if cpus.is_empty() {
+            bail!("no CPUs are known for `{}`", triple.architecture);
+        }
+        println!(
+            "CPUs for `{}` with {} (pass their features with `-m`):",
+            triple,
+            compiler_type.to_string()
+        );
+        for cpu in cpus {
+            let features = cpu
+                .features
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect::<Vec<_>>();
+            println!(
+                "  {:<16} {}",
+                cpu.name,
+                if features.is_empty() {
+                    "(no features)".to_string()
+                } else {
+                    features.join(" ")
+                }
+            );
+        }
+        Ok(())
+    }
+
+    fn merge(&self) -> Result<()> {
+        let merged = merge::merge_objects(&self.merge)?;
+        fs::write(self.output(), merged)?;
+        self.patch_section_flags()?;
+        self.pad_output()?;
+        eprintln!(
+            "✔ {} objects merged successfully into `{}`.",
+            self.merge.len(),
+            self.output().display(),
+        );
+        Ok(())
+    }
+
+    fn inner_execute(&self, path: &Path) -> Result<()> {
+        if let Some(target_triple) = &self.target_triple {
+            check_target_endianness(target_triple)?;
+        }
+        let target = self
+            .target_triple
+            .as_ref()
+            .map(|target_triple| {
+                let mut features = self
+                    .cpu_features
+                    .clone()
+                    .into_iter()
+                    .fold(CpuFeature::set(), |a, b| a | b);
+                // Cranelift requires SSE2, so we have this "hack" for now to facilitate
+                // usage
+                features |= CpuFeature::SSE2;
+                Target::new(target_triple.clone(), features)
+            })
+            .unwrap_or_default();
+        let engine_type = EngineType::Staticlib;
+        let (mut compiler_config, compiler_type) = self.compiler.get_compiler_config()?;
+        if let Some(relocation_model) = self.relocation_model {
+            if relocation_model == RelocationModel::DynamicNoPic
+                && !matches!(
+                    target.triple().operating_system,
+                    OperatingSystem::Darwin | OperatingSystem::MacOSX { .. } | OperatingSystem::Ios
+                )
+            {
+                bail!(
+                    "the `{}` relocation model is only available for Apple targets",
+                    relocation_model
+                );
+            }
+            compiler_config
+                .relocation_model(relocation_model)
+                .with_context(|| {
+                    format!(
+                        "the `{}` compiler can't emit code with the `{}` relocation model",
+                        compiler_type.to_string(),
+                        relocation_model
+                    )
+                })?;
+        }
+        if self.unwind_info == UnwindInfo::None {
+            if target.triple().operating_system == OperatingSystem::Windows {
+                bail!("`--unwind-info none` isn't supported for Windows targets, which need the unwind information to recover from traps");
+            }
+            compiler_config.disable_unwind_info().with_context(|| {
+                format!(
+                    "the `{}` compiler can't leave out the unwind information",
+                    compiler_type.to_string()
+                )
+            })?;
+        }
+        if self.incremental_cache.is_some() {
+            match compiler_type {
+                CompilerType::LLVM => bail!(
+                    "`--incremental-cache` isn't supported by the LLVM compiler, which emits the object directly"
+                ),
+                CompilerType::Cranelift if self.unwind_info != UnwindInfo::None => bail!(
+                    "`--incremental-cache` requires `--unwind-info none` with Cranelift, whose unwind tables describe the whole module"
+                ),
+                _ => {}
+            }
+        }
+        let time_passes = self.compiler_pass_timing || self.compiler_pass_timing_json.is_some();
+        let pass_timings = if time_passes {
+            let pass_timings = Arc::new(CompilerPassTimings::default());
+            compiler_config
+                .pass_timings(pass_timings.clone())
+                .with_context(|| {
+                    format!(
+                        "the `{}` compiler can't time its passes",
+                        compiler_type.to_string()
+                    )
+                })?;
+            Some(pass_timings)
+        } else {
+            None
+        };
+        let features = match &self.wasm_features_from {
+            Some(reference_path) => self.reference_features(reference_path, path)?,
+            None => self
+                .compiler
+                .get_features(compiler_config.default_features_for_target(&target))?,
+        };
+        let incremental_cache = self
+            .incremental_cache
+            .as_ref()
+            .map(|cache_dir| {
+                IncrementalCache::new(
+                    cache_dir,
+                    format!(
+                        "{}:{}:{:?}:{:?}:{:?}:{:?}",
+                        crate::VERSION,
+                        compiler_type.to_string(),
+                        target,
+                        features,
+                        self.relocation_model,
+                        self.unwind_info,
+                    ),
+                )
+                .map(Arc::new)
+            })
+            .transpose()?;
+        let compiler_config: Box<dyn CompilerConfig> = match &incremental_cache {
+            Some(cache) => Box::new(IncrementalCompilerConfig::new(
+                compiler_config,
+                cache.clone(),
+            )),
+            None => compiler_config,
+        };
+        if self.function_sections && compiler_type == CompilerType::LLVM {
+            warning!("`--function-sections` is ignored by the LLVM compiler");
+        }
+        if self.single_symbol && compiler_type == CompilerType::LLVM {
+            bail!("`--single-symbol` isn't supported by the LLVM compiler");
+        }
+        if self.emit_init_array && target.triple().operating_system == OperatingSystem::Windows {
+            bail!("`--emit-init-array` isn't supported for Windows targets");
+        }
+        if (self.text_noexec || self.text_readonly)
+            && target.triple().binary_format == BinaryFormat::Macho
+        {
+            bail!("`--text-noexec` and `--text-readonly` aren't supported for Mach-O objects, whose protections are set per segment");
+        }
+        let mut engine = self.compiler.get_staticlib_engine_with_features(
+            target.clone(),
+            compiler_config,
+            features,
+        );
+        engine.set_function_sections(self.function_sections);
+        engine.set_single_symbol(self.single_symbol);
+        let prefix = match &self.prefix_from_package {
+            Some(package) => {
+                let atom = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .context("the module has no file name to name the atom after")?;
+                let prefix = package.prefix(atom);
+                let prefixer_prefix = prefix.clone();
+                engine.set_deterministic_prefixer(move |_| prefixer_prefix.clone());
+                Some(prefix)
+            }
+            None => None,
+        };
+        let store = Store::new(&engine);
+
+        println!("Engine: {}", engine_type.to_string());
+        println!("Compiler: {}", compiler_type.to_string());
+        println!("Target: {}", target.triple());
+        if let Some(prefix) = &prefix {
+            println!("Symbol prefix: {}", prefix);
+        }
+
+        let module = match &self.function_filter {
+            Some(filter) => self.compile_filtered_module(&store, path, filter)?,
+            None => Module::from_file(&store, path).context("failed to compile Wasm")?,
+        };
+        if self.emit_init_array && module.imports().next().is_some() {
+            bail!("`--emit-init-array` only supports modules without imports, which the constructor can instantiate alone");
+        }
+        let _ = module.serialize_to_file(self.output())?;
+        self.patch_section_flags()?;
+        self.pad_output()?;
+        eprintln!(
+            "✔ Object file compiled successfully to `{}`{}.",
+            self.output().display(),
+            if self.function_filter.is_some() {
+                " (for analysis only: the functions not selected by `--function-filter` trap)"
+            } else {
+                ""
+            }
+        );
+
+        if self.dump_relocations {
+            let object = fs::read(self.output())?;
+            match &self.dump_relocations_file {
+                Some(dump_path) => {
+                    let mut dump = fs::File::create(dump_path)
+                        .with_context(|| format!("failed to create `{}`", dump_path.display()))?;
+                    relocations::dump_relocations(&object, &mut dump)?;
+                    eprintln!(
+                        "✔ Relocations written successfully to `{}`.",
+                        dump_path.display(),
+                    );
+                }
+                None => relocations::dump_relocations(&object, &mut std::io::stdout())?,
+            }
+        }
+
+        if let Some(cache) = &incremental_cache {
+            let (reused, total) = cache.stats();
+            println!(
+                "Incremental cache: reused {} of {} functions",
+                reused, total
+            );
+            let stats = cache.entry_stats();
+            if self.cache_stats {
+                println!(
+                    "Cache stats: {} hits, {} misses, {} read, {} written",
+                    stats.hits,
+                    stats.misses,
+                    ByteSize(stats.bytes_read),
+                    ByteSize(stats.bytes_written)
+                );
+            }
+            if let Some(stats_path) = &self.cache_stats_json {
+                fs::write(stats_path, serde_json::to_string_pretty(&stats)?)
+                    .with_context(|| format!("failed to write `{}`", stats_path.display()))?;
+            }
+        }
+
+        if let Some(pass_timings) = &pass_timings {
+            let report = pass_timings.report(&compiler_type.to_string());
+            if self.compiler_pass_timing {
+                report.print();
+            }
+            if let Some(report_path) = &self.compiler_pass_timing_json {
+                fs::write(report_path, serde_json::to_string_pretty(&report)?)
+                    .with_context(|| format!("failed to write `{}`", report_path.display()))?;
+            }
+        }
+
+        let artifact: &wasmer_engine_staticlib::StaticlibArtifact =
+            module.artifact().as_ref().downcast_ref().context(
+                "Engine type is Staticlib but could not downcast artifact into StaticlibArtifact",
+            )?;
+        let symbol_registry = artifact.symbol_registry();
+        let metadata_length = artifact.metadata_length();
+        let module_info = module.info();
+
+        if let Some(metadata_path) = &self.emit_metadata_json {
+            ObjectMetadata::new(
+                &fs::read(self.output())?,
+                module_info,
+                symbol_registry,
+                artifact.prefix(),
+                target.triple().to_string(),
+                compiler_type.to_string(),
+                metadata_length,
+            )?
+            .write(metadata_path)?;
+            eprintln!(
+                "✔ Object metadata written successfully to `{}`.",
+                metadata_path.display(),
+            );
+        }
+        let single_symbol = if self.single_symbol {
+            Some(SingleSymbol {
+                code: artifact.code_symbol_name(),
+                offsets: artifact.function_offsets_symbol_name(),
+            })
+        } else {
+            None
+        };
+        let mut header_file_src = crate::c_gen::staticlib_header::generate_header_file(
+            module_info,
+            symbol_registry,
+            metadata_length,
+            single_symbol.as_ref(),
+        );
+        if self.emit_init_array {
+            header_file_src.push_str(INIT_ARRAY_CONSTRUCTOR);
+        }
+        if self.function_filter.is_some() {
+            header_file_src.insert_str(
+                0,
+                "/* Only the functions selected by `--function-filter` were compiled: the others\n   trap when called. This object is for analysis only, and isn't meant to be run. */\n\n",
+            );
+        }
+
+        let header_path = self.header_path.as_ref().cloned().unwrap_or_else(|| {
+            let mut hp = self.output().to_path_buf();
+            hp.set_extension("h");
+            hp
+        });
+        let mut header = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&header_path)?;
+        header.write_all(header_file_src.as_bytes())?;
+        eprintln!(
+            "✔ Header file generated successfully at `{}`.",
+            header_path.display(),
+        );
+
+        Ok(())
+    }
+
+    /// Compile the module at `path` with only the functions selected by
+    /// `filter`.
+    fn compile_filtered_module(
+        &self,
+        store: &Store,
+        path: &Path,
+        filter: &FunctionFilter,
+    ) -> Result<Module> {
+        let contents = fs::read(path)?;
+        #[cfg(feature = "wat")]
+        let contents = wat2wasm(&contents)?.into_owned();
+        let filtered = function_filter::filter_functions(&contents, filter)?;
+        if filtered.kept_functions.is_empty() {
+            bail!("no function defined by the module matches `--function-filter`");
+        }
+        println!(
+            "Function filter: compiled {} functions ({} stubbed)",
+            filtered.kept_functions.len(),
+            filtered.stubbed_functions
+        );
+        for (index, name) in &filtered.kept_functions {
+            match name.as_str() {
+                "" => println!("  {}", index),
+                name => println!("  {} (`{}`)", index, name),
+            }
+        }
+        Module::new(store, &filtered.wasm).context("failed to compile Wasm")
+    }
+
+    /// Patch the section flags of the output for `--text-noexec` and
+    /// `--text-readonly`, if they're set.
+    fn patch_section_flags(&self) -> Result<()> {
+        if !self.text_noexec && !self.text_readonly {
+            return Ok(());
+        }
+        let mut object = fs::read(self.output())?;
+        let changed = section_flags::patch_section_flags(
+            &mut object,
+            SectionFlagChanges {
+                text_noexec: self.text_noexec,
+                readonly: self.text_readonly,
+            },
+        )
+        .context("failed to change the section flags")?;
+        fs::write(self.output(), object)?;
+        for section in &changed {
+            println!("Section flags: `{}` {}", section.name, section.change);
+        }
+        Ok(())
+    }
+
+    /// Pad the output to the `--pad-to` size, if any.
+    fn pad_output(&self) -> Result<()> {
+        let size = match self.pad_to {
+            Some(size) => size,
+            None => return Ok(()),
+        };
+        let length = fs::metadata(self.output())?.len();
+        if length > size {
+            bail!(
+                "the object is {} bytes long, larger than the `--pad-to` size of {} bytes",
+                length,
+                size
+            );
+        }
+        let padding = vec![self.pad_byte.unwrap_or(0); (size - length) as usize];
+        fs::OpenOptions::new()
+            .append(true)
+            .open(self.output())?
+            .write_all(&padding)?;
+        println!("Padding: {} bytes", padding.len());
+        Ok(())
+    }
+
+    /// Detect the features of the `--wasm-features-from` reference module,
+    /// checking that the module at `path` doesn't need others.
+    fn reference_features(&self, reference_path: &Path, path: &Path) -> Result<Features> {
+        let features = detect_features(reference_path)?;
+        let enabled = enabled_wasm_features(&features);
+        let missing = enabled_wasm_features(&detect_features(path)?)
+            .into_iter()
+            .filter(|feature| !enabled.contains(feature))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!(
+                "the module uses features that `{}` doesn't: {}",
+                reference_path.display(),
+                missing.join(", ")
+            );
+        }
+        println!(
+            "Wasm features (from `{}`): {}",
+            reference_path.display(),
+            if enabled.is_empty() {
+                "none".to_string()
+            } else {
+                enabled.join(", ")
+            }
+        );
+        Ok(features)
+    }
+}
@@ -0,0 +1,122 @@
+//! Compile a subset of the functions of a module, for
+//! `wasmer create-obj --function-filter`.
+//!
+//! The bodies of the other defined functions are replaced by a stub
+//! trapping with `unreachable` before the module is compiled, which keeps
+//! the indices of all the functions, so the object only holds the code
+//! of the selected functions.
+
+use crate::wasm_binary::{
+    function_names, glob_matches, is_name_section, split_sections, write_section, write_u32,
+    Reader, STUB_FUNCTION_BODY,
+};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+use wasmer::wasmparser::{ExternalKind, ImportSectionEntryType, Parser, Payload};
+
+/// The functions to compile, as `index:N` or a glob of their name.
+#[derive(Debug, Clone)]
+pub enum FunctionFilter {
+    /// The function at this index, counting the imported functions.
+    Index(u32),
+    /// The functions whose name matches this glob.
+    Name(String),
+}
+
+impl FromStr for FunctionFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_prefix("index:") {
+            Some(index) => Ok(Self::Index(index.parse().with_context(|| {
+                format!("invalid function index `{}` in `{}`", index, s)
+            })?)),
+            None => Ok(Self::Name(s.strip_prefix("name:").unwrap_or(s).to_string())),
+        }
+    }
+}
+
+/// The result of filtering the functions of a module.
+pub struct FilteredModule {
+    /// The rewritten module.
+    pub wasm: Vec<u8>,
+    /// The indices and names of the defined functions that are kept.
+    pub kept_functions: Vec<(u32, String)>,
+    /// The number of function bodies replaced by a stub.
+    pub stubbed_functions: usize,
+}
+
+/// Replace the bodies of the defined functions of `wasm` that `filter`
+/// doesn't select by a stub.
+///
+/// The functions are named after the name section, or after their
+/// exports when it doesn't name them.
+pub fn filter_functions(wasm: &[u8], filter: &FunctionFilter) -> Result<FilteredModule> {
+    let sections = split_sections(wasm)?;
+    let mut imported_functions = 0;
+    let mut names = HashMap::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    if let ImportSectionEntryType::Function(_) = import?.ty {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            Payload::ExportSection(exports) => {
+                for export in exports {
+                    let export = export?;
+                    if let ExternalKind::Function = export.kind {
+                        names.entry(export.index).or_insert(export.field);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for section in sections.iter().filter(|section| is_name_section(section)) {
+        names.extend(function_names(section.data)?);
+    }
+    let selected = |index: u32| match filter {
+        FunctionFilter::Index(selected) => index == *selected,
+        FunctionFilter::Name(pattern) => names.get(&index).map_or(false, |name| {
+            glob_matches(pattern.as_bytes(), name.as_bytes())
+        }),
+    };
+
+    let mut module = wasm[..8].to_vec();
+    let mut kept_functions = vec![];
+    let mut stubbed_functions = 0;
+    for section in sections {
+        if section.id != 10 {
+            write_section(&mut module, section.id, section.data);
+            continue;
+        }
+        let mut reader = Reader::new(section.data);
+        let count = reader.read_u32()?;
+        let mut contents = vec![];
+        write_u32(&mut contents, count);
+        for local_index in 0..count {
+            let index = imported_functions + local_index;
+            let size = reader.read_u32()?;
+            let mut body = reader.read_bytes(size as usize)?;
+            if selected(index) {
+                let name = names.get(&index).copied().unwrap_or_default();
+                kept_functions.push((index, name.to_string()));
+            } else {
+                body = STUB_FUNCTION_BODY;
+                stubbed_functions += 1;
+            }
+            write_u32(&mut contents, body.len() as u32);
+            contents.extend_from_slice(body);
+        }
+        write_section(&mut module, 10, &contents);
+    }
+    Ok(FilteredModule {
+        wasm: module,
+        kept_functions,
+        stubbed_functions,
+    })
+}
@@ -0,0 +1,442 @@
+//! A function-level compilation cache used by `wasmer create-obj --incremental-cache`.
+//!
+//! The cache wraps the chosen compiler: functions whose compiled output is
+//! already cached are replaced by a trivial body before handing the module
+//! to the real compiler, and the cached output is spliced back into the
+//! resulting [`Compilation`] afterwards.
+//!
+//! The module-wide sections are taken from the stubbed compilation, so the
+//! compiler must not describe the functions in them: the `.eh_frame` of
+//! Cranelift would describe the stubs, so it's only supported without
+//! unwind information.
+
+use crate::warning;
+use anyhow::{Context, Result};
+use loupe::MemoryUsage;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use wasmer_compiler::{
+    Compilation, CompileError, CompileModuleInfo, CompiledFunction, Compiler, CompilerConfig,
+    FunctionBodyData, ModuleMiddleware, ModuleTranslationState, PassTimings, RelocationModel,
+    RelocationTarget, SourceLoc, SymbolRegistry, Target,
+};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{Features, LocalFunctionIndex};
+
+/// A function body that is valid for any signature: no locals, `unreachable`, `end`.
+const STUB_FUNCTION_BODY: &[u8] = &[0x00, 0x00, 0x0b];
+
+/// A compiled function, as stored in the cache.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedFunction {
+    /// Offset of the function body in the module it was compiled from.
+    ///
+    /// Source locations are relative to the module, so we rebase them
+    /// when the function moved.
+    module_offset: usize,
+    function: CompiledFunction,
+}
+
+/// The on-disk incremental cache.
+pub struct IncrementalCache {
+    dir: PathBuf,
+    settings: String,
+    reused_functions: AtomicUsize,
+    total_functions: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+/// How the entries of an [`IncrementalCache`] were used, for
+/// `--cache-stats`.
+#[derive(Debug, Serialize)]
+pub struct CacheStats {
+    /// The entries found in the cache.
+    pub hits: usize,
+    /// The entries looked up but missing (or corrupted).
+    pub misses: usize,
+    /// The size of the entries found.
+    pub bytes_read: u64,
+    /// The size of the entries written.
+    pub bytes_written: u64,
+}
+
+impl IncrementalCache {
+    /// Creates a cache in the provided directory for the given compiler settings.
+    ///
+    /// Entries compiled with different settings never collide.
+    pub fn new(dir: &Path, settings: String) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| {
+            format!("failed to create incremental cache at `{}`", dir.display())
+        })?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            settings,
+            reused_functions: AtomicUsize::new(0),
+            total_functions: AtomicUsize::new(0),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the number of reused functions and the total number of
+    /// functions seen by the cache.
+    pub fn stats(&self) -> (usize, usize) {
+        (
+            self.reused_functions.load(Ordering::SeqCst),
+            self.total_functions.load(Ordering::SeqCst),
+        )
+    }
+
+    /// Returns how the entries of the cache were used. The whole-module
+    /// entry is looked up first, and the function entries only if it's
+    /// missing.
+    pub fn entry_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::SeqCst),
+            misses: self.misses.load(Ordering::SeqCst),
+            bytes_read: self.bytes_read.load(Ordering::SeqCst),
+            bytes_written: self.bytes_written.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Hash of everything in the module, other than the function bodies,
+    /// that may change the code generated for a function.
+    fn layout_key(&self, target: &Target, module: &CompileModuleInfo) -> blake3::Hash {
+        let info = &module.module;
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.settings.as_bytes());
+        hasher.update(format!("{:?}", target).as_bytes());
+        hasher.update(format!("{:?}", module.features).as_bytes());
+        hasher.update(format!("{:?}", module.memory_styles).as_bytes());
+        hasher.update(format!("{:?}", module.table_styles).as_bytes());
+        hasher.update(format!("{:?}", info.signatures).as_bytes());
+        hasher.update(format!("{:?}", info.functions).as_bytes());
+        hasher.update(format!("{:?}", info.tables).as_bytes());
+        hasher.update(format!("{:?}", info.memories).as_bytes());
+        hasher.update(format!("{:?}", info.globals).as_bytes());
+        hasher.update(
+            format!(
+                "{}:{}:{}:{}",
+                info.num_imported_functions,
+                info.num_imported_tables,
+                info.num_imported_memories,
+                info.num_imported_globals
+            )
+            .as_bytes(),
+        );
+        hasher.finalize()
+    }
+
+    /// The key for a function: its body, its index (which determines its
+    /// signature) and the module layout.
+    fn function_key(
+        layout: &blake3::Hash,
+        index: LocalFunctionIndex,
+        body: &FunctionBodyData,
+    ) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(layout.as_bytes());
+        hasher.update(&(index.index() as u64).to_le_bytes());
+        hasher.update(body.data);
+        hasher.finalize().to_hex().to_string()
+    }
+
+    fn entry_path(&self, key: &str, extension: &str) -> PathBuf {
+        let mut path = self.dir.join(key);
+        path.set_extension(extension);
+        path
+    }
+
+    fn load<T: DeserializeOwned>(&self, key: &str, extension: &str) -> Option<T> {
+        let contents = match fs::read(self.entry_path(key, extension)) {
+            Ok(contents) => contents,
+            Err(_) => {
+                self.misses.fetch_add(1, Ordering::SeqCst);
+                return None;
+            }
+        };
+        match bincode::deserialize(&contents) {
+            Ok(entry) => {
+                self.hits.fetch_add(1, Ordering::SeqCst);
+                self.bytes_read
+                    .fetch_add(contents.len() as u64, Ordering::SeqCst);
+                Some(entry)
+            }
+            Err(e) => {
+                self.misses.fetch_add(1, Ordering::SeqCst);
+                warning!(
+                    "ignoring corrupted incremental cache entry `{}`: {}",
+                    key,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    fn store<T: Serialize>(&self, key: &str, extension: &str, entry: &T) {
+        let result = bincode::serialize(entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| {
+                // Write to a temporary file first, so concurrent runs never see
+                // a partially written entry.
+                let path = self.entry_path(key, extension);
+                let tmp_path = self.entry_path(key, &format!("{}.tmp", extension));
+                fs::write(&tmp_path, &contents)?;
+                fs::rename(&tmp_path, &path)?;
+                self.bytes_written
+                    .fetch_add(contents.len() as u64, Ordering::SeqCst);
+                Ok(())
+            });
+        if let Err(e) = result {
+            warning!("failed to write incremental cache entry `{}`: {}", key, e);
+        }
+    }
+}
+
+/// Rebases the source locations of a cached function to a new module offset.
+fn rebase_function(cached: CachedFunction, module_offset: usize) -> CompiledFunction {
+    let mut function = cached.function;
+    if cached.module_offset == module_offset {
+        return function;
+    }
+    let delta = module_offset as i64 - cached.module_offset as i64;
+    let rebase = |srcloc: &mut SourceLoc| {
+        if !srcloc.is_default() {
+            *srcloc = SourceLoc::new((srcloc.bits() as i64 + delta) as u32);
+        }
+    };
+    let address_map = &mut function.frame_info.address_map;
+    rebase(&mut address_map.start_srcloc);
+    rebase(&mut address_map.end_srcloc);
+    for instruction in address_map.instructions.iter_mut() {
+        rebase(&mut instruction.srcloc);
+    }
+    function
+}
+
+/// Functions referencing compiler-generated custom sections can't be reused,
+/// since section indices are assigned per compilation.
+fn is_cacheable(function: &CompiledFunction) -> bool {
+    function
+        .relocations
+        .iter()
+        .all(|relocation| !matches!(relocation.reloc_target, RelocationTarget::CustomSection(_)))
+}
+
+/// A [`CompilerConfig`] that wraps the compiler with an [`IncrementalCache`].
+pub struct IncrementalCompilerConfig {
+    inner: Box<dyn CompilerConfig>,
+    cache: Arc<IncrementalCache>,
+}
+
+impl IncrementalCompilerConfig {
+    /// Wraps the provided compiler config.
+    pub fn new(inner: Box<dyn CompilerConfig>, cache: Arc<IncrementalCache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+impl CompilerConfig for IncrementalCompilerConfig {
+    fn enable_pic(&mut self) {
+        self.inner.enable_pic()
+    }
+
+    fn relocation_model(&mut self, model: RelocationModel) -> Result<(), CompileError> {
+        self.inner.relocation_model(model)
+    }
+
+    fn enable_verifier(&mut self) {
+        self.inner.enable_verifier()
+    }
+
+    fn canonicalize_nans(&mut self, enable: bool) {
+        self.inner.canonicalize_nans(enable)
+    }
+
+    fn pass_timings(&mut self, timings: Arc<dyn PassTimings>) -> Result<(), CompileError> {
+        self.inner.pass_timings(timings)
+    }
+
+    fn disable_unwind_info(&mut self) -> Result<(), CompileError> {
+        self.inner.disable_unwind_info()
+    }
+
+    fn emit_bitcode(&mut self) -> Result<(), CompileError> {
+        self.inner.emit_bitcode()
+    }
+
+    fn compiler(self: Box<Self>) -> Box<dyn Compiler> {
+        Box::new(IncrementalCompiler {
+            inner: self.inner.compiler(),
+            cache: self.cache,
+        })
+    }
+
+    fn default_features_for_target(&self, target: &Target) -> Features {
+        self.inner.default_features_for_target(target)
+    }
+
+    fn push_middleware(&mut self, middleware: Arc<dyn ModuleMiddleware>) {
+        self.inner.push_middleware(middleware)
+    }
+}
+
+#[derive(MemoryUsage)]
+struct IncrementalCompiler {
+    inner: Box<dyn Compiler>,
+    #[loupe(skip)]
+    cache: Arc<IncrementalCache>,
+}
+
+impl Compiler for IncrementalCompiler {
+    fn validate_module<'data>(
+        &self,
+        features: &Features,
+        data: &'data [u8],
+    ) -> Result<(), CompileError> {
+        self.inner.validate_module(features, data)
+    }
+
+    fn compile_module<'data, 'module>(
+        &self,
+        target: &Target,
+        module: &'module CompileModuleInfo,
+        module_translation: &ModuleTranslationState,
+        function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
+    ) -> Result<Compilation, CompileError> {
+        let cache = &self.cache;
+        let layout = cache.layout_key(target, module);
+        let keys = function_body_inputs
+            .iter()
+            .map(|(index, body)| IncrementalCache::function_key(&layout, index, body))
+            .collect::<PrimaryMap<LocalFunctionIndex, String>>();
+        cache
+            .total_functions
+            .fetch_add(keys.len(), Ordering::SeqCst);
+
+        // If nothing changed we can reuse the whole compilation, including the
+        // module-wide sections (like the unwind tables).
+        let module_key = {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(layout.as_bytes());
+            for key in keys.values() {
+                hasher.update(key.as_bytes());
+            }
+            hasher.finalize().to_hex().to_string()
+        };
+        if let Some(compilation) = cache.load::<Compilation>(&module_key, "module") {
+            cache
+                .reused_functions
+                .fetch_add(keys.len(), Ordering::SeqCst);
+            return Ok(compilation);
+        }
+
+        let module_offsets = function_body_inputs
+            .values()
+            .map(|body| body.module_offset)
+            .collect::<PrimaryMap<LocalFunctionIndex, usize>>();
+        let cached_functions = function_body_inputs
+            .iter()
+            .map(|(index, body)| {
+                cache
+                    .load::<CachedFunction>(&keys[index], "func")
+                    .map(|cached| rebase_function(cached, body.module_offset))
+            })
+            .collect::<PrimaryMap<LocalFunctionIndex, Option<CompiledFunction>>>();
+        let stubbed_inputs = function_body_inputs
+            .iter()
+            .map(|(index, body)| FunctionBodyData {
+                data: if cached_functions[index].is_some() {
+                    STUB_FUNCTION_BODY
+                } else {
+                    body.data
+                },
+                module_offset: body.module_offset,
+            })
+            .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+
+        let compilation =
+            self.inner
+                .compile_module(target, module, module_translation, stubbed_inputs)?;
+        if compilation.get_debug().is_some() && cached_functions.values().any(Option::is_some) {
+            // The DWARF unwind tables are generated for the whole module, and
+            // they would describe the stubs instead of the cached functions
+            return Err(CompileError::Codegen(
+                "the incremental cache can't reuse functions with module-wide unwind tables"
+                    .to_string(),
+            ));
+        }
+
+        let functions = cached_functions
+            .into_iter()
+            .map(|(index, cached)| match cached {
+                Some(function) => {
+                    cache.reused_functions.fetch_add(1, Ordering::SeqCst);
+                    function
+                }
+                None => {
+                    let function = compilation.get(index).clone();
+                    if is_cacheable(&function) {
+                        cache.store(
+                            &keys[index],
+                            "func",
+                            &CachedFunction {
+                                module_offset: module_offsets[index],
+                                function: function.clone(),
+                            },
+                        );
+                    }
+                    function
+                }
+            })
+            .collect::<PrimaryMap<LocalFunctionIndex, CompiledFunction>>();
+        let compilation = Compilation::new(
+            functions,
+            compilation.get_custom_sections(),
+            compilation.get_function_call_trampolines(),
+            compilation.get_dynamic_function_trampolines(),
+            compilation.get_debug(),
+        );
+        cache.store(&module_key, "module", &compilation);
+        Ok(compilation)
+    }
+
+    fn experimental_native_compile_module<'data, 'module>(
+        &self,
+        target: &Target,
+        module: &'module CompileModuleInfo,
+        module_translation: &ModuleTranslationState,
+        function_body_inputs: &PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
+        symbol_registry: &dyn SymbolRegistry,
+        wasmer_metadata: &[u8],
+    ) -> Option<Result<Vec<u8>, CompileError>> {
+        let result = self.inner.experimental_native_compile_module(
+            target,
+            module,
+            module_translation,
+            function_body_inputs,
+            symbol_registry,
+            wasmer_metadata,
+        );
+        if result.is_some() {
+            warning!(
+                "the compiler emits the object directly, so the incremental cache is not used"
+            );
+        }
+        result
+    }
+
+    fn get_middlewares(&self) -> &[Arc<dyn ModuleMiddleware>] {
+        self.inner.get_middlewares()
+    }
+}
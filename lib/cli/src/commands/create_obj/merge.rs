@@ -0,0 +1,194 @@
+//! Merge several objects produced by `wasmer create-obj` into a single
+//! object, used by `wasmer create-obj --merge`.
+//!
+//! Every section of every input is kept as a distinct section of the
+//! output, with its symbols and relocations, so linking the merged object
+//! is the same as linking the inputs one by one. References to a symbol
+//! that another input defines are resolved to that definition.
+
+use anyhow::{Context, Result};
+use object::read::{self, Object as _, ObjectSection, ObjectSymbol};
+use object::write::{Object, Relocation, SectionId, Symbol, SymbolId, SymbolSection};
+use object::{
+    BinaryFormat, SectionIndex, SectionKind, SymbolFlags, SymbolIndex, SymbolKind, SymbolScope,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Merge the objects at the given paths, returning the merged object.
+pub fn merge_objects(paths: &[PathBuf]) -> Result<Vec<u8>> {
+    let contents = paths
+        .iter()
+        .map(|path| fs::read(path).with_context(|| format!("failed to read `{}`", path.display())))
+        .collect::<Result<Vec<_>>>()?;
+    let files = contents
+        .iter()
+        .zip(paths)
+        .map(|(data, path)| {
+            read::File::parse(&**data)
+                .with_context(|| format!("failed to parse `{}`", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let first = files.first().context("no objects to merge")?;
+    if first.format() != BinaryFormat::Elf {
+        bail!(
+            "merging objects is only supported for ELF objects, `{}` is a {:?} object",
+            paths[0].display(),
+            first.format()
+        );
+    }
+    for (file, path) in files.iter().zip(paths).skip(1) {
+        if file.format() != first.format() || file.architecture() != first.architecture() {
+            bail!(
+                "`{}` was built for a different target than `{}`",
+                path.display(),
+                paths[0].display()
+            );
+        }
+    }
+
+    let mut obj = Object::new(first.format(), first.architecture(), first.endianness());
+    let mut sections: HashMap<(usize, SectionIndex), SectionId> = HashMap::new();
+    let mut symbols: HashMap<(usize, SymbolIndex), SymbolId> = HashMap::new();
+    // The global symbols defined by the inputs, with the input defining them
+    let mut definitions: HashMap<Vec<u8>, (SymbolId, usize)> = HashMap::new();
+    let mut undefined: HashMap<Vec<u8>, SymbolId> = HashMap::new();
+
+    for (input, file) in files.iter().enumerate() {
+        for section in file.sections() {
+            if section.kind() == SectionKind::Metadata {
+                continue;
+            }
+            let section_id = obj.add_section(
+                section.segment_name()?.unwrap_or("").as_bytes().to_vec(),
+                section.name()?.as_bytes().to_vec(),
+                section.kind(),
+            );
+            let new_section = obj.section_mut(section_id);
+            new_section.flags = section.flags();
+            match section.kind() {
+                SectionKind::UninitializedData | SectionKind::UninitializedTls => {
+                    new_section.append_bss(section.size(), section.align());
+                }
+                _ => new_section.set_data(section.data()?.to_vec(), section.align()),
+            }
+            sections.insert((input, section.index()), section_id);
+        }
+
+        for symbol in file.symbols() {
+            if matches!(symbol.kind(), SymbolKind::Section | SymbolKind::File) {
+                continue;
+            }
+            let section = match symbol.section() {
+                read::SymbolSection::Section(index) => match sections.get(&(input, index)) {
+                    Some(section_id) => SymbolSection::Section(*section_id),
+                    None => continue,
+                },
+                read::SymbolSection::Absolute => SymbolSection::Absolute,
+                read::SymbolSection::Common => SymbolSection::Common,
+                // Undefined symbols are resolved when adding the relocations
+                _ => continue,
+            };
+            let name = symbol.name()?.as_bytes().to_vec();
+            let is_global = matches!(symbol.scope(), SymbolScope::Linkage | SymbolScope::Dynamic);
+            if is_global {
+                if let Some((_, other_input)) = definitions.get(&name) {
+                    bail!(
+                        "symbol `{}` is defined in both `{}` and `{}`; compile one of them with a different symbol prefix",
+                        String::from_utf8_lossy(&name),
+                        paths[*other_input].display(),
+                        paths[input].display()
+                    );
+                }
+            }
+            let symbol_id = obj.add_symbol(Symbol {
+                name: name.clone(),
+                value: symbol.address(),
+                size: symbol.size(),
+                kind: symbol.kind(),
+                scope: symbol.scope(),
+                weak: symbol.is_weak(),
+                section,
+                flags: SymbolFlags::None,
+            });
+            if is_global {
+                definitions.insert(name, (symbol_id, input));
+            }
+            symbols.insert((input, symbol.index()), symbol_id);
+        }
+    }
+
+    for (input, file) in files.iter().enumerate() {
+        for section in file.sections() {
+            let section_id = match sections.get(&(input, section.index())) {
+                Some(section_id) => *section_id,
+                None => continue,
+            };
+            for (offset, relocation) in section.relocations() {
+                let symbol_id = match relocation.target() {
+                    read::RelocationTarget::Symbol(index) => {
+                        if let Some(symbol_id) = symbols.get(&(input, index)) {
+                            *symbol_id
+                        } else {
+                            let symbol = file.symbol_by_index(index)?;
+                            if symbol.kind() == SymbolKind::Section {
+                                let section_index = symbol
+                                    .section_index()
+                                    .context("section symbol without a section")?;
+                                let section_id = sections
+                                    .get(&(input, section_index))
+                                    .context("relocation against a discarded section")?;
+                                obj.section_symbol(*section_id)
+                            } else {
+                                let name = symbol.name()?.as_bytes().to_vec();
+                                match definitions.get(&name) {
+                                    Some((symbol_id, _)) => *symbol_id,
+                                    None => *undefined.entry(name.clone()).or_insert_with(|| {
+                                        obj.add_symbol(Symbol {
+                                            name,
+                                            value: 0,
+                                            size: 0,
+                                            kind: symbol.kind(),
+                                            scope: symbol.scope(),
+                                            weak: symbol.is_weak(),
+                                            section: SymbolSection::Undefined,
+                                            flags: SymbolFlags::None,
+                                        })
+                                    }),
+                                }
+                            }
+                        }
+                    }
+                    read::RelocationTarget::Section(index) => {
+                        let section_id = sections
+                            .get(&(input, index))
+                            .context("relocation against a discarded section")?;
+                        obj.section_symbol(*section_id)
+                    }
+                    _ => bail!(
+                        "unsupported relocation at offset {:#x} of `{}`",
+                        offset,
+                        paths[input].display()
+                    ),
+                };
+                obj.add_relocation(
+                    section_id,
+                    Relocation {
+                        offset,
+                        size: relocation.size(),
+                        kind: relocation.kind(),
+                        encoding: relocation.encoding(),
+                        symbol: symbol_id,
+                        addend: relocation.addend(),
+                    },
+                )
+                .map_err(|e| anyhow!("failed to merge `{}`: {}", paths[input].display(), e))?;
+            }
+        }
+    }
+
+    obj.write()
+        .map_err(|e| anyhow!("failed to write the merged object: {}", e))
+}
@@ -0,0 +1,125 @@
+//! Describe an object produced by `wasmer create-obj`, used by
+//! `wasmer create-obj --emit-metadata-json`.
+
+use anyhow::{Context, Result};
+use object::read::{self, Object, ObjectSymbol};
+use object::BinaryFormat;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use wasmer::{ExportIndex, LocalFunctionIndex, Type};
+use wasmer_compiler::{Symbol, SymbolRegistry};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::ModuleInfo;
+
+/// The name of the symbol of the module metadata, before mangling.
+const METADATA_SYMBOL: &str = "WASMER_METADATA";
+
+/// A description of an object and of the functions it defines.
+#[derive(Debug, Serialize)]
+pub struct ObjectMetadata {
+    /// The prefix of the symbols of the module.
+    pub prefix: String,
+    pub format: String,
+    pub target: String,
+    pub compiler: String,
+    /// The symbol of the module metadata, and its length in bytes.
+    pub metadata_symbol: String,
+    pub metadata_length: usize,
+    pub functions: Vec<FunctionMetadata>,
+}
+
+/// A function defined by the object.
+#[derive(Debug, Serialize)]
+pub struct FunctionMetadata {
+    /// The index of the function in the Wasm module, counting the
+    /// imported functions.
+    pub index: u32,
+    /// The name of the symbol, as it appears in the object.
+    pub symbol: String,
+    /// The name of the function in the name section, if any.
+    pub name: Option<String>,
+    pub exports: Vec<String>,
+    pub params: Vec<String>,
+    pub results: Vec<String>,
+}
+
+impl ObjectMetadata {
+    /// Describe `object`, compiled from the module described by
+    /// `module_info`.
+    pub fn new(
+        object: &[u8],
+        module_info: &ModuleInfo,
+        symbol_registry: &dyn SymbolRegistry,
+        prefix: &str,
+        target: String,
+        compiler: String,
+        metadata_length: usize,
+    ) -> Result<Self> {
+        let file = read::File::parse(object).context("failed to parse the object")?;
+        // Mach-O symbols have a leading underscore
+        let symbols = file
+            .symbols()
+            .filter_map(|symbol| symbol.name().ok())
+            .map(|name| {
+                let unmangled = match file.format() {
+                    BinaryFormat::MachO => name.strip_prefix('_').unwrap_or(name),
+                    _ => name,
+                };
+                (unmangled, name)
+            })
+            .collect::<HashMap<_, _>>();
+        let symbol_name = |name: &str| -> Result<String> {
+            symbols
+                .get(name)
+                .map(|name| name.to_string())
+                .with_context(|| format!("the object has no `{}` symbol", name))
+        };
+
+        let mut exports: HashMap<_, Vec<String>> = HashMap::new();
+        for (name, export) in &module_info.exports {
+            if let ExportIndex::Function(index) = export {
+                exports.entry(*index).or_default().push(name.clone());
+            }
+        }
+        let functions = (0..module_info.functions.len() - module_info.num_imported_functions)
+            .map(|local_index| {
+                let local_index = LocalFunctionIndex::new(local_index);
+                let index = module_info.func_index(local_index);
+                let signature = &module_info.signatures[module_info.functions[index]];
+                Ok(FunctionMetadata {
+                    index: index.as_u32(),
+                    symbol: symbol_name(
+                        &symbol_registry.symbol_to_name(Symbol::LocalFunction(local_index)),
+                    )?,
+                    name: module_info.function_names.get(&index).cloned(),
+                    exports: exports.remove(&index).unwrap_or_default(),
+                    params: signature.params().iter().map(type_name).collect(),
+                    results: signature.results().iter().map(type_name).collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            prefix: prefix.to_string(),
+            format: format!("{:?}", file.format()).to_lowercase(),
+            target,
+            compiler,
+            metadata_symbol: symbol_name(METADATA_SYMBOL)?,
+            metadata_length,
+            functions,
+        })
+    }
+
+    /// Write the description as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
+
+/// The name of a Wasm type, like `i32`.
+fn type_name(ty: &Type) -> String {
+    ty.to_string().to_lowercase()
+}
@@ -0,0 +1,72 @@
+//! Collect the time spent in each pass of the compiler, for
+//! `wasmer create-obj --compiler-pass-timing`.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use wasmer_compiler::PassTimings;
+
+/// The time spent in each pass, summed across the compiled functions.
+#[derive(Debug, Default)]
+pub struct CompilerPassTimings {
+    /// The passes, in the order they first ran.
+    passes: Mutex<Vec<(String, Duration)>>,
+}
+
+/// The times of the passes, for `--compiler-pass-timing-json`.
+#[derive(Debug, Serialize)]
+pub struct PassTimingReport {
+    pub compiler: String,
+    pub passes: Vec<PassTiming>,
+    /// The sum of the times of the passes.
+    pub total_seconds: f64,
+}
+
+/// The time spent in a pass.
+#[derive(Debug, Serialize)]
+pub struct PassTiming {
+    pub pass: String,
+    pub seconds: f64,
+}
+
+impl PassTimings for CompilerPassTimings {
+    fn add(&self, pass: &str, duration: Duration) {
+        let mut passes = self.passes.lock().unwrap();
+        match passes.iter_mut().find(|(name, _)| name == pass) {
+            Some((_, total)) => *total += duration,
+            None => passes.push((pass.to_string(), duration)),
+        }
+    }
+}
+
+impl CompilerPassTimings {
+    /// The passes that ran with `compiler`, with their time.
+    pub fn report(&self, compiler: &str) -> PassTimingReport {
+        let passes = self
+            .passes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pass, duration)| PassTiming {
+                pass: pass.clone(),
+                seconds: duration.as_secs_f64(),
+            })
+            .collect::<Vec<_>>();
+        PassTimingReport {
+            compiler: compiler.to_string(),
+            total_seconds: passes.iter().map(|timing| timing.seconds).sum(),
+            passes,
+        }
+    }
+}
+
+impl PassTimingReport {
+    /// Print the time of each pass, and their total.
+    pub fn print(&self) {
+        println!("Compiler pass timing ({}):", self.compiler);
+        for timing in &self.passes {
+            println!("{:>10.3} ms  {}", timing.seconds * 1000.0, timing.pass);
+        }
+        println!("{:>10.3} ms  Total", self.total_seconds * 1000.0);
+    }
+}
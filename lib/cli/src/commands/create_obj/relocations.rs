@@ -0,0 +1,72 @@
+//! Print the relocations of an object produced by `wasmer create-obj`,
+//! used by `wasmer create-obj --dump-relocations`.
+
+use anyhow::{Context, Result};
+use object::read::{self, Object, ObjectSection, ObjectSymbol};
+use object::SymbolKind;
+use std::io::Write;
+
+/// Write every relocation of the object, grouped by the function (or
+/// other symbol) of the section it applies to.
+pub fn dump_relocations(data: &[u8], out: &mut dyn Write) -> Result<()> {
+    let file = read::File::parse(data).context("failed to parse the object")?;
+    let mut total = 0;
+    for section in file.sections() {
+        // The symbols defined in the section, sorted by address, to find
+        // the one each relocation applies to
+        let mut symbols = file
+            .symbols()
+            .filter(|symbol| {
+                symbol.section_index() == Some(section.index())
+                    && !matches!(symbol.kind(), SymbolKind::Section | SymbolKind::File)
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by_key(|symbol| symbol.address());
+
+        let mut current_symbol = None;
+        for (offset, relocation) in section.relocations() {
+            let symbol = symbols
+                .iter()
+                .take_while(|symbol| symbol.address() <= offset)
+                .last()
+                .and_then(|symbol| symbol.name().ok())
+                .unwrap_or("<no symbol>");
+            if current_symbol != Some(symbol) {
+                writeln!(out, "{} (section `{}`):", symbol, section.name()?)?;
+                current_symbol = Some(symbol);
+            }
+            let target = match relocation.target() {
+                read::RelocationTarget::Symbol(index) => {
+                    let target = file.symbol_by_index(index)?;
+                    match target.kind() {
+                        SymbolKind::Section => format!(
+                            "section `{}`",
+                            target
+                                .section_index()
+                                .and_then(|index| file.section_by_index(index).ok())
+                                .and_then(|section| section.name().ok().map(str::to_string))
+                                .unwrap_or_default()
+                        ),
+                        _ => target.name()?.to_string(),
+                    }
+                }
+                read::RelocationTarget::Section(index) => {
+                    format!("section `{}`", file.section_by_index(index)?.name()?)
+                }
+                _ => "<absolute>".to_string(),
+            };
+            writeln!(
+                out,
+                "  {:#010x} {:?} ({} bits) {} {:+}",
+                offset,
+                relocation.kind(),
+                relocation.size(),
+                target,
+                relocation.addend()
+            )?;
+            total += 1;
+        }
+    }
+    writeln!(out, "{} relocations", total)?;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Adjust the flags of the sections of an object produced by `wasmer
+//! create-obj`, for `--text-noexec` and `--text-readonly`.
+//!
+//! The flags are patched in the section headers of the written object, so
+//! its contents, its symbol table and its relocations are unchanged. Only
+//! ELF and COFF objects have protection flags per section: the protections
+//! of Mach-O objects are set per segment, by the linker.
+
+use anyhow::{Context, Result};
+use object::elf::{FileHeader32, FileHeader64, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE};
+use object::endian::{Endian, Endianness};
+use object::pe::{
+    ImageFileHeader, ImageSectionHeader, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ,
+    IMAGE_SCN_MEM_WRITE,
+};
+use object::read::elf::{FileHeader, SectionHeader};
+use object::{pod, FileKind, LittleEndian as LE};
+use std::mem;
+
+/// The section flags to change.
+#[derive(Debug, Clone, Copy)]
+pub struct SectionFlagChanges {
+    /// Clear the executable flag of the code sections.
+    pub text_noexec: bool,
+    /// Clear the writable flag of every loaded section.
+    pub readonly: bool,
+}
+
+/// A section whose flags were changed.
+#[derive(Debug)]
+pub struct ChangedSection {
+    pub name: String,
+    /// What changed, like `not executable`.
+    pub change: &'static str,
+}
+
+/// Patch the section flags of `data` in place, returning the sections that
+/// changed.
+pub fn patch_section_flags(
+    data: &mut [u8],
+    changes: SectionFlagChanges,
+) -> Result<Vec<ChangedSection>> {
+    match FileKind::parse(&*data).context("failed to parse the object")? {
+        FileKind::Elf32 => patch_elf::<FileHeader32<Endianness>>(data, changes),
+        FileKind::Elf64 => patch_elf::<FileHeader64<Endianness>>(data, changes),
+        FileKind::Coff => patch_coff(data, changes),
+        FileKind::MachO32 | FileKind::MachO64 => bail!(
+            "Mach-O objects have no protection flags per section, they're set per segment by the linker"
+        ),
+        kind => bail!("unsupported object format {:?}", kind),
+    }
+}
+
+/// The change of the flags of a section, if any.
+fn change_of(
+    executable: bool,
+    writable: bool,
+    changes: SectionFlagChanges,
+) -> Option<&'static str> {
+    let noexec = changes.text_noexec && executable;
+    let readonly = changes.readonly && writable;
+    match (noexec, readonly) {
+        (true, true) => Some("not executable, read-only"),
+        (true, false) => Some("not executable"),
+        (false, true) => Some("read-only"),
+        (false, false) => None,
+    }
+}
+
+fn patch_elf<Elf: FileHeader<Endian = Endianness>>(
+    data: &mut [u8],
+    changes: SectionFlagChanges,
+) -> Result<Vec<ChangedSection>> {
+    // The offsets and the new flags of the headers to patch, computed
+    // before borrowing the object mutably
+    let mut patches = vec![];
+    let mut changed = vec![];
+    let endian;
+    {
+        let data = &*data;
+        let header = Elf::parse(data).context("failed to parse the ELF header")?;
+        endian = header.endian()?;
+        let sections = header.sections(endian, data)?;
+        let section_headers_offset: u64 = header.e_shoff(endian).into();
+        let section_header_size = header.e_shentsize(endian) as usize;
+        for (index, section) in sections.iter().enumerate() {
+            let flags: u64 = section.sh_flags(endian).into();
+            if flags & u64::from(SHF_ALLOC) == 0 {
+                continue;
+            }
+            let change = match change_of(
+                flags & u64::from(SHF_EXECINSTR) != 0,
+                flags & u64::from(SHF_WRITE) != 0,
+                changes,
+            ) {
+                Some(change) => change,
+                None => continue,
+            };
+            let mut new_flags = flags;
+            if changes.text_noexec {
+                new_flags &= !u64::from(SHF_EXECINSTR);
+            }
+            if changes.readonly {
+                new_flags &= !u64::from(SHF_WRITE);
+            }
+            // `sh_flags` follows the 32-bit `sh_name` and `sh_type`
+            let offset = section_headers_offset as usize + index * section_header_size + 8;
+            patches.push((offset, new_flags));
+            let name = sections.section_name(endian, section)?;
+            changed.push(ChangedSection {
+                name: String::from_utf8_lossy(name).into_owned(),
+                change,
+            });
+        }
+    }
+    for (offset, flags) in patches {
+        if mem::size_of::<Elf::Word>() == 8 {
+            data[offset..offset + 8].copy_from_slice(&endian.write_u64_bytes(flags));
+        } else {
+            data[offset..offset + 4].copy_from_slice(&endian.write_u32_bytes(flags as u32));
+        }
+    }
+    Ok(changed)
+}
+
+fn patch_coff(data: &mut [u8], changes: SectionFlagChanges) -> Result<Vec<ChangedSection>> {
+    let (header, _) =
+        pod::from_bytes::<ImageFileHeader>(data).map_err(|()| anyhow!("invalid COFF header"))?;
+    let sections_offset =
+        mem::size_of::<ImageFileHeader>() + header.size_of_optional_header.get(LE) as usize;
+    let number_of_sections = header.number_of_sections.get(LE) as usize;
+    let (sections, _) = pod::slice_from_bytes::<ImageSectionHeader>(
+        data.get(sections_offset..).unwrap_or_default(),
+        number_of_sections,
+    )
+    .map_err(|()| anyhow!("invalid COFF section table"))?;
+
+    let mut patches = vec![];
+    let mut changed = vec![];
+    for (index, section) in sections.iter().enumerate() {
+        let characteristics = section.characteristics.get(LE);
+        // The sections which aren't loaded, like the debug information,
+        // have no memory flags
+        if characteristics & (IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_EXECUTE) == 0 {
+            continue;
+        }
+        let change = match change_of(
+            characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+            characteristics & IMAGE_SCN_MEM_WRITE != 0,
+            changes,
+        ) {
+            Some(change) => change,
+            None => continue,
+        };
+        let mut new_characteristics = characteristics;
+        if changes.text_noexec {
+            new_characteristics &= !IMAGE_SCN_MEM_EXECUTE;
+        }
+        if changes.readonly {
+            new_characteristics &= !IMAGE_SCN_MEM_WRITE;
+        }
+        // `Characteristics` is the last field of the section header
+        let offset = sections_offset + (index + 1) * mem::size_of::<ImageSectionHeader>() - 4;
+        patches.push((offset, new_characteristics));
+        changed.push(ChangedSection {
+            name: String::from_utf8_lossy(&section.name)
+                .trim_end_matches('\0')
+                .to_string(),
+            change,
+        });
+    }
+    for (offset, characteristics) in patches {
+        data[offset..offset + 4].copy_from_slice(&characteristics.to_le_bytes());
+    }
+    Ok(changed)
+}
@@ -0,0 +1,124 @@
+//! The CPUs known for each architecture, and the features they imply, for
+//! `wasmer create-obj --target-cpu-list`.
+//!
+//! The compilers are configured with CPU features rather than CPU names,
+//! so a CPU stands for the features passed with `-m`. Only the features
+//! known to Wasmer are listed.
+
+use wasmer::{Architecture, CpuFeature};
+
+/// A CPU, with the features it implies.
+pub struct TargetCpu {
+    pub name: &'static str,
+    pub features: &'static [CpuFeature],
+}
+
+const X86_64_V1: &[CpuFeature] = &[CpuFeature::SSE2];
+const X86_64_V2: &[CpuFeature] = &[
+    CpuFeature::SSE2,
+    CpuFeature::SSE3,
+    CpuFeature::SSSE3,
+    CpuFeature::SSE41,
+    CpuFeature::SSE42,
+    CpuFeature::POPCNT,
+];
+const SANDYBRIDGE: &[CpuFeature] = &[
+    CpuFeature::SSE2,
+    CpuFeature::SSE3,
+    CpuFeature::SSSE3,
+    CpuFeature::SSE41,
+    CpuFeature::SSE42,
+    CpuFeature::POPCNT,
+    CpuFeature::AVX,
+];
+const X86_64_V3: &[CpuFeature] = &[
+    CpuFeature::SSE2,
+    CpuFeature::SSE3,
+    CpuFeature::SSSE3,
+    CpuFeature::SSE41,
+    CpuFeature::SSE42,
+    CpuFeature::POPCNT,
+    CpuFeature::AVX,
+    CpuFeature::AVX2,
+    CpuFeature::BMI1,
+    CpuFeature::BMI2,
+    CpuFeature::LZCNT,
+];
+const X86_64_V4: &[CpuFeature] = &[
+    CpuFeature::SSE2,
+    CpuFeature::SSE3,
+    CpuFeature::SSSE3,
+    CpuFeature::SSE41,
+    CpuFeature::SSE42,
+    CpuFeature::POPCNT,
+    CpuFeature::AVX,
+    CpuFeature::AVX2,
+    CpuFeature::BMI1,
+    CpuFeature::BMI2,
+    CpuFeature::LZCNT,
+    CpuFeature::AVX512F,
+    CpuFeature::AVX512DQ,
+    CpuFeature::AVX512VL,
+];
+
+const X86_64_CPUS: &[TargetCpu] = &[
+    TargetCpu {
+        name: "x86-64",
+        features: X86_64_V1,
+    },
+    TargetCpu {
+        name: "x86-64-v2",
+        features: X86_64_V2,
+    },
+    TargetCpu {
+        name: "x86-64-v3",
+        features: X86_64_V3,
+    },
+    TargetCpu {
+        name: "x86-64-v4",
+        features: X86_64_V4,
+    },
+    TargetCpu {
+        name: "nehalem",
+        features: X86_64_V2,
+    },
+    TargetCpu {
+        name: "sandybridge",
+        features: SANDYBRIDGE,
+    },
+    TargetCpu {
+        name: "haswell",
+        features: X86_64_V3,
+    },
+    TargetCpu {
+        name: "skylake",
+        features: X86_64_V3,
+    },
+    TargetCpu {
+        name: "skylake-avx512",
+        features: X86_64_V4,
+    },
+    TargetCpu {
+        name: "znver1",
+        features: X86_64_V3,
+    },
+    TargetCpu {
+        name: "znver4",
+        features: X86_64_V4,
+    },
+];
+
+/// Wasmer knows no Arm features, so every Arm CPU is compiled for alike.
+const AARCH64_CPUS: &[TargetCpu] = &[TargetCpu {
+    name: "generic",
+    features: &[],
+}];
+
+/// The CPUs known for `architecture`.
+pub fn target_cpus(architecture: Architecture) -> &'static [TargetCpu] {
+    match architecture {
+        Architecture::X86_64 => X86_64_CPUS,
+        Architecture::Aarch64(_) => AARCH64_CPUS,
+        _ => &[],
+    }
+}
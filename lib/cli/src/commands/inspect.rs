@@ -1,10 +1,21 @@
+use crate::artifact_target::ArtifactTarget;
 use crate::store::StoreOptions;
+use crate::wasm_features::{detect_features, enabled_wasm_features};
 use anyhow::{Context, Result};
 use bytesize::ByteSize;
-use std::path::PathBuf;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use wasmer::*;
 
+mod cfg;
+mod diff;
+
+use cfg::{ControlFlowGraph, GraphFormat};
+use diff::ModuleDiff;
+
 #[derive(Debug, StructOpt)]
 /// The options for the `wasmer validate` subcommand
 pub struct Inspect {
@@ -12,10 +23,203 @@ pub struct Inspect {
     #[structopt(name = "FILE", parse(from_os_str))]
     path: PathBuf,
 
+    /// Print an estimate of the memory used by the module at runtime,
+    /// instead of its imports and exports.
+    ///
+    /// It adds up the initial size of the linear memories and tables and
+    /// the size of the compiled artifact, as a lower bound of the RSS.
+    #[structopt(long = "memory-estimate")]
+    memory_estimate: bool,
+
+    /// Print the memory estimate, the artifact target or the diff as JSON
+    #[structopt(long = "json")]
+    json: bool,
+
+    /// Extract the Wasm module embedded in a native executable created
+    /// with `wasmer create-exe --include-source`, instead of inspecting
+    /// a module.
+    #[structopt(
+        long = "extract-source",
+        requires = "OUTPUT PATH",
+        conflicts_with = "memory-estimate"
+    )]
+    extract_source: bool,
+
+    /// Print the control-flow graph of the function given by
+    /// `--function`, instead of inspecting the module.
+    ///
+    /// The nodes are the basic blocks of the Wasm code of the function.
+    #[structopt(
+        long = "cfg",
+        requires = "function",
+        conflicts_with_all = &["memory-estimate", "extract-source"]
+    )]
+    cfg: bool,
+
+    /// The index of the function for `--cfg`, counting the imported
+    /// functions
+    #[structopt(long = "function", requires = "cfg")]
+    function: Option<u32>,
+
+    /// The format of the graph printed by `--cfg`: `dot` or `mermaid`
+    #[structopt(long = "format", default_value = "dot")]
+    format: GraphFormat,
+
+    /// Check that `FILE`, a precompiled artifact, was compiled with every
+    /// Wasm feature used by the `--source` module, instead of inspecting
+    /// it.
+    ///
+    /// The features the artifact lacks are listed, and the command fails
+    /// if there are any. Only artifacts of the universal and dylib engines
+    /// are supported.
+    #[structopt(
+        long = "validate-features",
+        requires = "SOURCE",
+        conflicts_with_all = &["memory-estimate", "extract-source", "cfg"]
+    )]
+    validate_features: bool,
+
+    /// The Wasm module the artifact was compiled from, for
+    /// `--validate-features`
+    #[structopt(
+        long = "source",
+        name = "SOURCE",
+        parse(from_os_str),
+        requires = "validate-features"
+    )]
+    source: Option<PathBuf>,
+
+    /// Print the target triple, the compiler and the CPU features `FILE`,
+    /// a Universal artifact, was compiled for, on one line, instead of
+    /// inspecting it.
+    ///
+    /// Only the end of the artifact, where `wasmer compile` records them,
+    /// is read, so it's a cheap check of whether the artifact runs on a
+    /// host. Artifacts serialized by other means don't record them.
+    #[structopt(
+        long = "artifact-target",
+        conflicts_with_all = &["memory-estimate", "extract-source", "cfg", "validate-features"]
+    )]
+    artifact_target: bool,
+
+    /// Compare `FILE` with an older version of the module, instead of
+    /// inspecting it.
+    ///
+    /// The functions added, removed or changed (in signature or body), the
+    /// imports and exports added, removed or changed, and the changes of
+    /// the limits of the memories and tables are printed. A precompiled
+    /// artifact of the older version is only valid for `FILE` when the
+    /// modules are identical.
+    #[structopt(
+        long = "diff-source",
+        name = "OLD",
+        parse(from_os_str),
+        conflicts_with_all = &[
+            "memory-estimate",
+            "extract-source",
+            "cfg",
+            "validate-features",
+            "artifact-target",
+        ]
+    )]
+    diff_source: Option<PathBuf>,
+
+    /// Output file for `--extract-source` and `--cfg`
+    ///
+    /// The graph printed by `--cfg` goes to the standard output when not
+    /// given.
+    #[structopt(name = "OUTPUT PATH", short = "o", parse(from_os_str))]
+    output: Option<PathBuf>,
+
     #[structopt(flatten)]
     store: StoreOptions,
 }
 
+/// The approximate size of a table element in memory (a pointer).
+const TABLE_ELEMENT_SIZE: u64 = std::mem::size_of::<usize>() as u64;
+
+/// The estimated memory use of a module, printed by `--memory-estimate`.
+#[derive(Debug, Serialize)]
+struct MemoryEstimate {
+    memories: Vec<MemoryEstimateEntry>,
+    tables: Vec<MemoryEstimateEntry>,
+    compiled_artifact_bytes: u64,
+    /// The sum of the initial sizes and the compiled artifact size.
+    total_bytes: u64,
+}
+
+/// The estimated size of a memory or table.
+#[derive(Debug, Serialize)]
+struct MemoryEstimateEntry {
+    initial_bytes: u64,
+    /// `None` when the module doesn't declare a maximum.
+    maximum_bytes: Option<u64>,
+}
+
+impl MemoryEstimate {
+    fn new(module: &Module) -> Self {
+        let memories = module
+            .info()
+            .memories
+            .values()
+            .map(|memory| MemoryEstimateEntry {
+                initial_bytes: memory.minimum.bytes().0 as u64,
+                maximum_bytes: memory.maximum.map(|maximum| maximum.bytes().0 as u64),
+            })
+            .collect::<Vec<_>>();
+        let tables = module
+            .info()
+            .tables
+            .values()
+            .map(|table| MemoryEstimateEntry {
+                initial_bytes: table.minimum as u64 * TABLE_ELEMENT_SIZE,
+                maximum_bytes: table
+                    .maximum
+                    .map(|maximum| maximum as u64 * TABLE_ELEMENT_SIZE),
+            })
+            .collect::<Vec<_>>();
+        let compiled_artifact_bytes = loupe::size_of_val(module.artifact()) as u64;
+        let total_bytes = memories
+            .iter()
+            .chain(tables.iter())
+            .map(|entry| entry.initial_bytes)
+            .sum::<u64>()
+            + compiled_artifact_bytes;
+        Self {
+            memories,
+            tables,
+            compiled_artifact_bytes,
+            total_bytes,
+        }
+    }
+
+    fn print(&self) {
+        let print_entries = |entries: &[MemoryEstimateEntry]| {
+            for (index, entry) in entries.iter().enumerate() {
+                println!(
+                    "    {}: initial {}, maximum {}",
+                    index,
+                    ByteSize(entry.initial_bytes),
+                    entry
+                        .maximum_bytes
+                        .map(|maximum| ByteSize(maximum).to_string())
+                        .unwrap_or_else(|| "unbounded".to_string())
+                );
+            }
+        };
+        println!("Memory estimate:");
+        println!("  Memories:");
+        print_entries(&self.memories);
+        println!("  Tables:");
+        print_entries(&self.tables);
+        println!(
+            "  Compiled artifact: {}",
+            ByteSize(self.compiled_artifact_bytes)
+        );
+        println!("  Total: {}", ByteSize(self.total_bytes));
+    }
+}
+
 impl Inspect {
     /// Runs logic for the `validate` subcommand
     pub fn execute(&self) -> Result<()> {
@@ -23,9 +227,40 @@ impl Inspect {
             .context(format!("failed to inspect `{}`", self.path.display()))
     }
     fn inner_execute(&self) -> Result<()> {
+        if self.json && !self.memory_estimate && !self.artifact_target && self.diff_source.is_none()
+        {
+            bail!("`--json` requires `--memory-estimate`, `--artifact-target` or `--diff-source`");
+        }
+        if self.artifact_target {
+            return self.print_artifact_target();
+        }
+        if self.extract_source {
+            return self.extract_embedded_source();
+        }
+        if self.cfg {
+            return self.print_cfg();
+        }
+        if self.validate_features {
+            return self.validate_artifact_features();
+        }
+        if self.diff_source.is_some() {
+            return self.print_diff();
+        }
+        if self.output.is_some() {
+            bail!("`-o` requires `--extract-source` or `--cfg`");
+        }
         let (store, _engine_type, _compiler_type) = self.store.get_store()?;
         let module_contents = std::fs::read(&self.path)?;
         let module = Module::new(&store, &module_contents)?;
+        if self.memory_estimate {
+            let estimate = MemoryEstimate::new(&module);
+            if self.json {
+                println!("{}", serde_json::to_string_pretty(&estimate)?);
+            } else {
+                estimate.print();
+            }
+            return Ok(());
+        }
         println!(
             "Type: {}",
             if !is_wasm(&module_contents) {
@@ -71,4 +306,130 @@ impl Inspect {
         }
         Ok(())
     }
+
+    fn print_cfg(&self) -> Result<()> {
+        let function = self.function.context("no function index")?;
+        let contents = std::fs::read(&self.path)?;
+        #[cfg(feature = "wat")]
+        let contents = wat2wasm(&contents)?;
+        let graph = ControlFlowGraph::new(&contents, function)?.render(self.format);
+        match &self.output {
+            Some(output) => {
+                std::fs::write(output, graph)
+                    .with_context(|| format!("failed to write `{}`", output.display()))?;
+                eprintln!(
+                    "✔ Control-flow graph of function {} written to `{}`.",
+                    function,
+                    output.display()
+                );
+            }
+            None => print!("{}", graph),
+        }
+        Ok(())
+    }
+
+    fn print_diff(&self) -> Result<()> {
+        let old_path = self.diff_source.as_ref().context("no old module")?;
+        let old = std::fs::read(old_path)
+            .with_context(|| format!("failed to read `{}`", old_path.display()))?;
+        let new = std::fs::read(&self.path)?;
+        #[cfg(feature = "wat")]
+        let (old, new) = (wat2wasm(&old)?, wat2wasm(&new)?);
+        let diff = ModuleDiff::new(&old, &new)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+        } else {
+            diff.print();
+        }
+        Ok(())
+    }
+
+    fn validate_artifact_features(&self) -> Result<()> {
+        let source = self.source.as_ref().context("no source module")?;
+        let artifact_features = enabled_wasm_features(&artifact_features(&self.path)?);
+        let used_features = enabled_wasm_features(&detect_features(source)?);
+        let list = |features: &[&str]| {
+            if features.is_empty() {
+                "none".to_string()
+            } else {
+                features.join(", ")
+            }
+        };
+        println!(
+            "Features used by `{}`: {}",
+            source.display(),
+            list(&used_features)
+        );
+        println!("Features of the artifact: {}", list(&artifact_features));
+        let missing = used_features
+            .into_iter()
+            .filter(|feature| !artifact_features.contains(feature))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!(
+                "the artifact wasn't compiled with features used by `{}`: {}",
+                source.display(),
+                missing.join(", ")
+            );
+        }
+        eprintln!(
+            "✔ The artifact supports every feature used by `{}`.",
+            source.display()
+        );
+        Ok(())
+    }
+
+    fn print_artifact_target(&self) -> Result<()> {
+        let mut header = Vec::new();
+        File::open(&self.path)?.take(64).read_to_end(&mut header)?;
+        #[cfg(feature = "universal")]
+        if !wasmer_engine_universal::UniversalArtifact::is_deserializable(&header) {
+            bail!("not a precompiled artifact of the universal engine");
+        }
+        let target = ArtifactTarget::read(&self.path)?.context(
+            "the artifact doesn't record its target, as it wasn't compiled with `wasmer compile`",
+        )?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&target)?);
+        } else {
+            println!("{}", target.to_line());
+        }
+        Ok(())
+    }
+
+    fn extract_embedded_source(&self) -> Result<()> {
+        let output = self.output.as_ref().context("no output path")?;
+        let binary = std::fs::read(&self.path)?;
+        let wasm = crate::embedded_source::extract(&binary)?;
+        std::fs::write(output, &wasm)
+            .with_context(|| format!("failed to write `{}`", output.display()))?;
+        eprintln!(
+            "✔ Wasm module ({}) extracted successfully to `{}`.",
+            ByteSize(wasm.len() as _),
+            output.display()
+        );
+        Ok(())
+    }
+}
+
+/// Read the features a precompiled artifact was compiled with.
+fn artifact_features(path: &Path) -> Result<Features> {
+    let contents = std::fs::read(path)?;
+    #[cfg(feature = "universal")]
+    {
+        if wasmer_engine_universal::UniversalArtifact::is_deserializable(&contents) {
+            let engine = wasmer_engine_universal::Universal::headless().engine();
+            let module = unsafe { Module::deserialize(&Store::new(&engine), &contents)? };
+            return Ok(module.artifact().features().clone());
+        }
+    }
+    #[cfg(feature = "dylib")]
+    {
+        if wasmer_engine_dylib::DylibArtifact::is_deserializable(&contents) {
+            let engine = wasmer_engine_dylib::Dylib::headless().engine();
+            let module = unsafe { Module::deserialize_from_file(&Store::new(&engine), path)? };
+            return Ok(module.artifact().features().clone());
+        }
+    }
+    bail!("not a precompiled artifact of the universal or dylib engine")
 }
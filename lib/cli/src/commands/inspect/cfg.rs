@@ -0,0 +1,302 @@
+//! The Wasm-level control-flow graph of a function, printed by
+//! `wasmer inspect --cfg`.
+//!
+//! Each node is a basic block: a straight sequence of operators, ended by
+//! a branch, the start of a loop or the end of a structured block. Code
+//! made unreachable by a branch is skipped, but the blocks it opens are
+//! still tracked to resolve the branch targets.
+
+use anyhow::Result;
+use std::fmt::Write;
+use std::str::FromStr;
+use wasmer::wasmparser::{ImportSectionEntryType, Operator, Parser, Payload};
+
+/// The graph formats supported by `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+    /// Mermaid flowchart, which can be embedded in Markdown.
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "mermaid" => Ok(Self::Mermaid),
+            _ => bail!("unknown graph format `{}`, expected `dot` or `mermaid`", s),
+        }
+    }
+}
+
+/// A basic block.
+#[derive(Debug, Default)]
+struct Node {
+    /// The offset of the first operator in the module.
+    offset: Option<usize>,
+    operators: usize,
+    /// The name of the last operator, like `BrIf`.
+    last_operator: Option<String>,
+}
+
+/// A structured control-flow construct being visited.
+#[derive(Debug)]
+struct Frame {
+    /// Where a branch to this frame goes: the header of a loop, or the
+    /// node after the `end` of other frames.
+    branch_target: usize,
+    /// The node after the `end`.
+    end: usize,
+    /// For an `if`, the node ending with the condition, until its `else`.
+    condition: Option<usize>,
+}
+
+/// The control-flow graph of a function.
+#[derive(Debug)]
+pub struct ControlFlowGraph {
+    function_index: u32,
+    nodes: Vec<Node>,
+    edges: Vec<(usize, usize, Option<&'static str>)>,
+}
+
+/// The node of the function entry.
+const ENTRY: usize = 0;
+/// The node reached by returning from the function.
+const EXIT: usize = 1;
+
+impl ControlFlowGraph {
+    /// Build the graph of the function at `function_index` (counting the
+    /// imported functions) of a Wasm module.
+    pub fn new(wasm: &[u8], function_index: u32) -> Result<Self> {
+        let mut imported_functions = 0;
+        let mut defined_functions = 0;
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        if let ImportSectionEntryType::Function(_) = import?.ty {
+                            imported_functions += 1;
+                        }
+                    }
+                    if function_index < imported_functions {
+                        bail!("function {} is imported, it has no body", function_index);
+                    }
+                }
+                Payload::CodeSectionEntry(body) => {
+                    if imported_functions + defined_functions == function_index {
+                        let mut graph = Self {
+                            function_index,
+                            nodes: vec![Node::default(), Node::default()],
+                            edges: vec![],
+                        };
+                        graph.visit(
+                            body.get_operators_reader()?
+                                .into_iter_with_offsets()
+                                .collect::<Result<Vec<_>, _>>()?,
+                        )?;
+                        return Ok(graph);
+                    }
+                    defined_functions += 1;
+                }
+                _ => {}
+            }
+        }
+        bail!("the module has no function {}", function_index)
+    }
+
+    fn add_node(&mut self) -> usize {
+        self.nodes.push(Node::default());
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, from: Option<usize>, to: usize, label: Option<&'static str>) {
+        if let Some(from) = from {
+            self.edges.push((from, to, label));
+        }
+    }
+
+    /// Split the operators of the function body in basic blocks.
+    fn visit(&mut self, operators: Vec<(Operator, usize)>) -> Result<()> {
+        let mut frames = vec![Frame {
+            branch_target: EXIT,
+            end: EXIT,
+            condition: None,
+        }];
+        let branch_target =
+            |frames: &[Frame], depth: u32| frames[frames.len() - 1 - depth as usize].branch_target;
+        // The node being filled, `None` in unreachable code
+        let mut current = Some(ENTRY);
+        for (operator, offset) in operators {
+            if let Some(node) = current {
+                let node = &mut self.nodes[node];
+                node.offset.get_or_insert(offset);
+                node.operators += 1;
+                let name = format!("{:?}", operator);
+                let end = name
+                    .find(|c: char| !c.is_alphanumeric())
+                    .unwrap_or_else(|| name.len());
+                node.last_operator = Some(name[..end].to_string());
+            }
+            match operator {
+                Operator::Block { .. } => {
+                    let end = self.add_node();
+                    frames.push(Frame {
+                        branch_target: end,
+                        end,
+                        condition: None,
+                    });
+                }
+                Operator::Loop { .. } => {
+                    let header = self.add_node();
+                    self.add_edge(current, header, None);
+                    current = Some(header);
+                    frames.push(Frame {
+                        branch_target: header,
+                        end: self.add_node(),
+                        condition: None,
+                    });
+                }
+                Operator::If { .. } => {
+                    let then = self.add_node();
+                    self.add_edge(current, then, Some("true"));
+                    let end = self.add_node();
+                    frames.push(Frame {
+                        branch_target: end,
+                        end,
+                        condition: current,
+                    });
+                    current = Some(then);
+                }
+                Operator::Else => {
+                    let frame = frames.last_mut().unwrap();
+                    let condition = frame.condition.take();
+                    let end = frame.end;
+                    self.add_edge(current, end, None);
+                    let otherwise = self.add_node();
+                    self.add_edge(condition, otherwise, Some("false"));
+                    current = Some(otherwise);
+                }
+                Operator::End => {
+                    let frame = frames.pop().unwrap();
+                    self.add_edge(current, frame.end, None);
+                    // An `if` without `else`
+                    self.add_edge(frame.condition, frame.end, Some("false"));
+                    current = Some(frame.end);
+                }
+                Operator::Br { relative_depth } => {
+                    self.add_edge(current, branch_target(&frames, relative_depth), None);
+                    current = None;
+                }
+                Operator::BrIf { relative_depth } => {
+                    self.add_edge(
+                        current,
+                        branch_target(&frames, relative_depth),
+                        Some("br_if"),
+                    );
+                    if current.is_some() {
+                        let next = self.add_node();
+                        self.add_edge(current, next, None);
+                        current = Some(next);
+                    }
+                }
+                Operator::BrTable { table } => {
+                    for target in table.targets() {
+                        let (depth, is_default) = target?;
+                        self.add_edge(
+                            current,
+                            branch_target(&frames, depth),
+                            Some(if is_default { "default" } else { "br_table" }),
+                        );
+                    }
+                    current = None;
+                }
+                Operator::Return => {
+                    self.add_edge(current, EXIT, None);
+                    current = None;
+                }
+                Operator::Unreachable => current = None,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The label of a node.
+    fn node_label(&self, index: usize, line_break: &str) -> String {
+        let node = &self.nodes[index];
+        let mut label = format!("b{}", index);
+        match index {
+            ENTRY => label.push_str(" (entry)"),
+            EXIT => label.push_str(" (exit)"),
+            _ => {}
+        }
+        if let Some(offset) = node.offset {
+            let _ = write!(
+                label,
+                "{}{:#x}: {} operators{}{}",
+                line_break,
+                offset,
+                node.operators,
+                line_break,
+                node.last_operator.as_deref().unwrap_or_default()
+            );
+        }
+        label
+    }
+
+    /// Render the graph in the given format.
+    pub fn render(&self, format: GraphFormat) -> String {
+        let mut output = String::new();
+        match format {
+            GraphFormat::Dot => {
+                let _ = writeln!(output, "digraph \"function {}\" {{", self.function_index);
+                let _ = writeln!(output, "  node [shape=box, fontname=\"monospace\"];");
+                for index in 0..self.nodes.len() {
+                    let _ = writeln!(
+                        output,
+                        "  b{} [label=\"{}\"];",
+                        index,
+                        self.node_label(index, "\\n")
+                    );
+                }
+                for (from, to, label) in &self.edges {
+                    match label {
+                        Some(label) => {
+                            let _ =
+                                writeln!(output, "  b{} -> b{} [label=\"{}\"];", from, to, label);
+                        }
+                        None => {
+                            let _ = writeln!(output, "  b{} -> b{};", from, to);
+                        }
+                    }
+                }
+                output.push_str("}\n");
+            }
+            GraphFormat::Mermaid => {
+                output.push_str("flowchart TD\n");
+                for index in 0..self.nodes.len() {
+                    let _ = writeln!(
+                        output,
+                        "  b{}[\"{}\"]",
+                        index,
+                        self.node_label(index, "<br/>")
+                    );
+                }
+                for (from, to, label) in &self.edges {
+                    match label {
+                        Some(label) => {
+                            let _ = writeln!(output, "  b{} -->|{}| b{}", from, label, to);
+                        }
+                        None => {
+                            let _ = writeln!(output, "  b{} --> b{}", from, to);
+                        }
+                    }
+                }
+            }
+        }
+        output
+    }
+}
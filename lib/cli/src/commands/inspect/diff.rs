@@ -0,0 +1,452 @@
+//! The structural differences between two versions of a Wasm module,
+//! printed by `wasmer inspect --diff-source`.
+//!
+//! The functions defined by the modules are matched by name: their name
+//! in the `name` section, else their first export name, else their
+//! index. A function changed when its signature or the hash of its body
+//! (its locals and code) differs. The imports are matched by module and
+//! field name, the exports by name, and the memories and tables defined
+//! by the modules by index.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use wasmer::wasmparser::{
+    self, ExternalKind, ImportSectionEntryType, MemoryType as WpMemoryType, Name,
+    NameSectionReader, Parser, Payload, TypeDef,
+};
+use wasmer::{FunctionType, GlobalType, MemoryType, Pages, TableType, Type};
+
+/// The signature of a function, the type of an import or export, or the
+/// limits of a memory or table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Item {
+    #[serde(rename = "type")]
+    ty: String,
+    /// The hash of the body of a function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_hash: Option<String>,
+}
+
+impl Item {
+    fn new(ty: impl ToString) -> Self {
+        Self {
+            ty: ty.to_string(),
+            body_hash: None,
+        }
+    }
+}
+
+/// An added or removed item.
+#[derive(Debug, Serialize)]
+struct NamedItem {
+    name: String,
+    #[serde(flatten)]
+    item: Item,
+}
+
+/// A changed item.
+#[derive(Debug, Serialize)]
+struct ChangedItem {
+    name: String,
+    old: Item,
+    new: Item,
+}
+
+/// The differences between the items of a kind.
+#[derive(Debug, Default, Serialize)]
+struct Changes {
+    added: Vec<NamedItem>,
+    removed: Vec<NamedItem>,
+    changed: Vec<ChangedItem>,
+    unchanged: usize,
+}
+
+impl Changes {
+    fn new(old: &BTreeMap<String, Item>, new: &BTreeMap<String, Item>) -> Self {
+        let mut changes = Self::default();
+        for (name, old_item) in old {
+            match new.get(name) {
+                None => changes.removed.push(NamedItem {
+                    name: name.clone(),
+                    item: old_item.clone(),
+                }),
+                Some(new_item) if new_item != old_item => changes.changed.push(ChangedItem {
+                    name: name.clone(),
+                    old: old_item.clone(),
+                    new: new_item.clone(),
+                }),
+                Some(_) => changes.unchanged += 1,
+            }
+        }
+        for (name, new_item) in new {
+            if !old.contains_key(name) {
+                changes.added.push(NamedItem {
+                    name: name.clone(),
+                    item: new_item.clone(),
+                });
+            }
+        }
+        changes
+    }
+
+    fn print(&self, title: &str) {
+        if self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() {
+            println!("{}: unchanged ({})", title, self.unchanged);
+            return;
+        }
+        println!(
+            "{}: {} added, {} removed, {} changed, {} unchanged",
+            title,
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged
+        );
+        for added in &self.added {
+            println!("  + {}: {}", added.name, added.item.ty);
+        }
+        for removed in &self.removed {
+            println!("  - {}: {}", removed.name, removed.item.ty);
+        }
+        for changed in &self.changed {
+            if changed.old.ty == changed.new.ty {
+                println!("  ~ {}: body changed", changed.name);
+            } else {
+                println!(
+                    "  ~ {}: {} became {}{}",
+                    changed.name,
+                    changed.old.ty,
+                    changed.new.ty,
+                    if changed.old.body_hash != changed.new.body_hash {
+                        ", body changed"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+    }
+}
+
+/// The differences between two versions of a module.
+#[derive(Debug, Serialize)]
+pub struct ModuleDiff {
+    /// Whether the modules are byte for byte identical. A precompiled
+    /// artifact of one is only valid for the other when they are.
+    identical: bool,
+    functions: Changes,
+    imports: Changes,
+    exports: Changes,
+    memories: Changes,
+    tables: Changes,
+}
+
+impl ModuleDiff {
+    /// Compare `old` and `new`, two Wasm modules.
+    pub fn new(old: &[u8], new: &[u8]) -> Result<Self> {
+        let old_summary = ModuleSummary::parse(old).context("invalid old module")?;
+        let new_summary = ModuleSummary::parse(new).context("invalid new module")?;
+        Ok(Self {
+            identical: old == new,
+            functions: Changes::new(&old_summary.functions()?, &new_summary.functions()?),
+            imports: Changes::new(&old_summary.imports, &new_summary.imports),
+            exports: Changes::new(&old_summary.exports()?, &new_summary.exports()?),
+            memories: Changes::new(
+                &defined(&old_summary.memories, old_summary.imported_memories),
+                &defined(&new_summary.memories, new_summary.imported_memories),
+            ),
+            tables: Changes::new(
+                &defined(&old_summary.tables, old_summary.imported_tables),
+                &defined(&new_summary.tables, new_summary.imported_tables),
+            ),
+        })
+    }
+
+    pub fn print(&self) {
+        println!("Identical: {}", if self.identical { "yes" } else { "no" });
+        self.functions.print("Functions");
+        self.imports.print("Imports");
+        self.exports.print("Exports");
+        self.memories.print("Memories");
+        self.tables.print("Tables");
+    }
+}
+
+/// The parts of a module compared by the diff.
+#[derive(Debug, Default)]
+struct ModuleSummary {
+    signatures: Vec<FunctionType>,
+    /// The signatures of the functions, the imported ones first.
+    functions: Vec<FunctionType>,
+    imported_functions: usize,
+    tables: Vec<TableType>,
+    imported_tables: usize,
+    memories: Vec<MemoryType>,
+    imported_memories: usize,
+    globals: Vec<GlobalType>,
+    imports: BTreeMap<String, Item>,
+    /// The name, kind and index of the exports, in order.
+    export_entries: Vec<(String, ExternalKind, u32)>,
+    /// The names of the `name` section.
+    function_names: HashMap<u32, String>,
+    /// The hashes of the bodies of the functions defined by the module.
+    body_hashes: Vec<String>,
+}
+
+impl ModuleSummary {
+    fn parse(wasm: &[u8]) -> Result<Self> {
+        let mut summary = Self::default();
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::TypeSection(types) => {
+                    for ty in types {
+                        match ty? {
+                            TypeDef::Func(ty) => summary.signatures.push(FunctionType::new(
+                                ty.params
+                                    .iter()
+                                    .map(|ty| value_type(*ty))
+                                    .collect::<Result<Vec<_>>>()?,
+                                ty.returns
+                                    .iter()
+                                    .map(|ty| value_type(*ty))
+                                    .collect::<Result<Vec<_>>>()?,
+                            )),
+                            _ => bail!("module linking is not supported"),
+                        }
+                    }
+                }
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        let import = import?;
+                        let ty = match import.ty {
+                            ImportSectionEntryType::Function(index) => {
+                                let signature = summary.signature(index)?;
+                                summary.functions.push(signature.clone());
+                                summary.imported_functions += 1;
+                                format!("function {}", signature)
+                            }
+                            ImportSectionEntryType::Table(ty) => {
+                                let ty = table_type(ty)?;
+                                let description = format!("table {}", ty);
+                                summary.tables.push(ty);
+                                summary.imported_tables += 1;
+                                description
+                            }
+                            ImportSectionEntryType::Memory(ty) => {
+                                let ty = memory_type(ty)?;
+                                let description = format!("memory {}", ty);
+                                summary.memories.push(ty);
+                                summary.imported_memories += 1;
+                                description
+                            }
+                            ImportSectionEntryType::Global(ty) => {
+                                let ty = GlobalType::new(
+                                    value_type(ty.content_type)?,
+                                    ty.mutable.into(),
+                                );
+                                let description = format!("global {}", ty);
+                                summary.globals.push(ty);
+                                description
+                            }
+                            _ => bail!("module linking is not supported"),
+                        };
+                        summary.imports.insert(
+                            format!(
+                                "\"{}\".\"{}\"",
+                                import.module,
+                                import.field.unwrap_or_default()
+                            ),
+                            Item::new(ty),
+                        );
+                    }
+                }
+                Payload::FunctionSection(functions) => {
+                    for index in functions {
+                        let signature = summary.signature(index?)?;
+                        summary.functions.push(signature);
+                    }
+                }
+                Payload::TableSection(tables) => {
+                    for ty in tables {
+                        summary.tables.push(table_type(ty?)?);
+                    }
+                }
+                Payload::MemorySection(memories) => {
+                    for ty in memories {
+                        summary.memories.push(memory_type(ty?)?);
+                    }
+                }
+                Payload::GlobalSection(globals) => {
+                    for global in globals {
+                        let ty = global?.ty;
+                        summary.globals.push(GlobalType::new(
+                            value_type(ty.content_type)?,
+                            ty.mutable.into(),
+                        ));
+                    }
+                }
+                Payload::ExportSection(exports) => {
+                    for export in exports {
+                        let export = export?;
+                        summary.export_entries.push((
+                            export.field.to_string(),
+                            export.kind,
+                            export.index,
+                        ));
+                    }
+                }
+                Payload::CodeSectionEntry(body) => {
+                    let mut reader = body.get_binary_reader();
+                    let size = reader.bytes_remaining();
+                    let hash = blake3::hash(reader.read_bytes(size)?);
+                    summary.body_hashes.push(hash.to_hex()[..16].to_string());
+                }
+                Payload::CustomSection {
+                    name: "name",
+                    data,
+                    data_offset,
+                    ..
+                } => {
+                    // Like the compilers, ignore a malformed `name` section
+                    let mut names = match NameSectionReader::new(data, data_offset) {
+                        Ok(names) => names,
+                        Err(_) => continue,
+                    };
+                    while let Ok(subsection) = names.read() {
+                        if let Name::Function(functions) = subsection {
+                            if let Ok(mut map) = functions.get_map() {
+                                for _ in 0..map.get_count() {
+                                    if let Ok(naming) = map.read() {
+                                        summary
+                                            .function_names
+                                            .insert(naming.index, naming.name.to_string());
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(summary)
+    }
+
+    fn signature(&self, index: u32) -> Result<FunctionType> {
+        self.signatures
+            .get(index as usize)
+            .cloned()
+            .with_context(|| format!("invalid type index {}", index))
+    }
+
+    /// The functions defined by the module, by name.
+    fn functions(&self) -> Result<BTreeMap<String, Item>> {
+        let mut functions = BTreeMap::new();
+        for (local_index, body_hash) in self.body_hashes.iter().enumerate() {
+            let index = (self.imported_functions + local_index) as u32;
+            let signature = self
+                .functions
+                .get(index as usize)
+                .context("more function bodies than functions")?;
+            let name = self
+                .function_names
+                .get(&index)
+                .cloned()
+                .or_else(|| {
+                    self.export_entries
+                        .iter()
+                        .find(|(_, kind, export_index)| {
+                            matches!(kind, ExternalKind::Function) && *export_index == index
+                        })
+                        .map(|(name, _, _)| name.clone())
+                })
+                .unwrap_or_else(|| format!("#{}", index));
+            let name = if functions.contains_key(&name) {
+                format!("{} #{}", name, index)
+            } else {
+                name
+            };
+            functions.insert(
+                name,
+                Item {
+                    ty: signature.to_string(),
+                    body_hash: Some(body_hash.clone()),
+                },
+            );
+        }
+        Ok(functions)
+    }
+
+    /// The exports, by name, with the type of the exported items.
+    fn exports(&self) -> Result<BTreeMap<String, Item>> {
+        let invalid = || "invalid export index";
+        let mut exports = BTreeMap::new();
+        for (name, kind, index) in &self.export_entries {
+            let index = *index as usize;
+            let ty = match kind {
+                ExternalKind::Function => {
+                    format!(
+                        "function {}",
+                        self.functions.get(index).with_context(invalid)?
+                    )
+                }
+                ExternalKind::Table => {
+                    format!("table {}", self.tables.get(index).with_context(invalid)?)
+                }
+                ExternalKind::Memory => {
+                    format!("memory {}", self.memories.get(index).with_context(invalid)?)
+                }
+                ExternalKind::Global => {
+                    format!("global {}", self.globals.get(index).with_context(invalid)?)
+                }
+                _ => bail!("module linking is not supported"),
+            };
+            exports.insert(name.clone(), Item::new(ty));
+        }
+        Ok(exports)
+    }
+}
+
+/// The memories or tables defined by a module, by index, skipping the
+/// `imported` ones.
+fn defined<T: std::fmt::Display>(items: &[T], imported: usize) -> BTreeMap<String, Item> {
+    items
+        .iter()
+        .enumerate()
+        .skip(imported)
+        .map(|(index, item)| (index.to_string(), Item::new(item)))
+        .collect()
+}
+
+fn value_type(ty: wasmparser::Type) -> Result<Type> {
+    Ok(match ty {
+        wasmparser::Type::I32 => Type::I32,
+        wasmparser::Type::I64 => Type::I64,
+        wasmparser::Type::F32 => Type::F32,
+        wasmparser::Type::F64 => Type::F64,
+        wasmparser::Type::V128 => Type::V128,
+        wasmparser::Type::ExternRef => Type::ExternRef,
+        wasmparser::Type::FuncRef => Type::FuncRef,
+        ty => bail!("unsupported value type {:?}", ty),
+    })
+}
+
+fn table_type(ty: wasmparser::TableType) -> Result<TableType> {
+    Ok(TableType {
+        ty: value_type(ty.element_type)?,
+        minimum: ty.limits.initial,
+        maximum: ty.limits.maximum,
+    })
+}
+
+fn memory_type(ty: WpMemoryType) -> Result<MemoryType> {
+    match ty {
+        WpMemoryType::M32 { limits, shared } => Ok(MemoryType {
+            minimum: Pages(limits.initial),
+            maximum: limits.maximum.map(Pages),
+            shared,
+        }),
+        WpMemoryType::M64 { .. } => bail!("64-bit memories are not supported"),
+    }
+}
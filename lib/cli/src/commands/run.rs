@@ -1,25 +1,160 @@
 use crate::common::get_cache_dir;
+use crate::error::PrettyError;
 #[cfg(feature = "debug")]
 use crate::logging;
 use crate::store::{CompilerType, EngineType, StoreOptions};
 use crate::suggestions::suggest_function_exports;
+use crate::utils::parse_duration;
 use crate::warning;
 use anyhow::{anyhow, Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 use wasmer::*;
 #[cfg(feature = "cache")]
 use wasmer_cache::{Cache, FileSystemCache, Hash};
 
 use structopt::StructOpt;
 
+mod affinity;
+mod alloc_profile;
+#[cfg(feature = "wasi")]
+mod capture_output;
+mod cpu_time_limit;
+#[cfg(feature = "wasi")]
+mod deny_syscalls;
+#[cfg(feature = "wasi")]
+mod deterministic;
+mod interrupt_signal;
+mod memory_dump;
+#[cfg(unix)]
+mod memory_file;
+mod metering;
+#[cfg(feature = "wasi")]
+mod record;
+#[cfg(feature = "wasi")]
+mod sandbox;
+mod snapshot;
+mod timings;
+mod trace_calls;
+mod trap_on_grow;
+mod trap_report;
+mod trap_wat;
+mod unused_imports;
 #[cfg(feature = "wasi")]
 mod wasi;
+mod watch;
 
+/// The exit code used by `wasmer run` when the guest exit code doesn't
+/// match the one provided with `--assert-exit`.
+const ASSERT_EXIT_MISMATCH_CODE: i32 = 3;
+
+/// The exit code used by `wasmer run` when the guest traps and
+/// `--capture-trap-json` is provided.
+const TRAP_EXIT_CODE: i32 = 4;
+
+/// The exit code used by `wasmer run` when the guest is interrupted by
+/// `--time-limit-cpu`.
+const CPU_TIME_LIMIT_EXIT_CODE: i32 = 5;
+
+use affinity::CpuList;
+use alloc_profile::{AllocProfileTunables, AllocProfiler};
+use cpu_time_limit::CpuTimeWatchdog;
+use interrupt_signal::SignalInterrupter;
+use memory_dump::MemoryRange;
+#[cfg(unix)]
+use memory_file::FileMemoryTunables;
+#[cfg(feature = "wasi")]
+use sandbox::SandboxProfile;
+use snapshot::Snapshot;
+use timings::Timings;
+use trap_report::TrapReport;
+use unused_imports::ImportUsageReport;
 #[cfg(feature = "wasi")]
 use wasi::Wasi;
 
-#[derive(Debug, StructOpt, Clone)]
+/// Where `wasmer run` stops, set with `--exit-after`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitAfter {
+    /// Stop once the module is compiled, without running it.
+    Compile,
+    /// Run the module.
+    Run,
+}
+
+impl Default for ExitAfter {
+    fn default() -> Self {
+        Self::Run
+    }
+}
+
+impl FromStr for ExitAfter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "compile" => Ok(Self::Compile),
+            "run" => Ok(Self::Run),
+            _ => bail!("unknown phase `{}`, expected `compile` or `run`", s),
+        }
+    }
+}
+
+/// How a module runs, set with `--exec-model`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExecModel {
+    /// Run `_start`, or the `--invoke` function, once.
+    Command,
+    /// Run `_initialize`, then the `--invoke` functions.
+    Reactor,
+}
+
+impl Default for ExecModel {
+    fn default() -> Self {
+        Self::Command
+    }
+}
+
+impl FromStr for ExecModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "command" => Ok(Self::Command),
+            "reactor" => Ok(Self::Reactor),
+            _ => bail!(
+                "unknown execution model `{}`, expected `command` or `reactor`",
+                s
+            ),
+        }
+    }
+}
+
+/// A value to write in an exported global, provided with `--set-global`.
+#[derive(Debug, Clone)]
+struct GlobalAssignment {
+    name: String,
+    value: String,
+}
+
+impl FromStr for GlobalAssignment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((name, value)) if !name.is_empty() => Ok(Self {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+            _ => bail!("invalid global assignment `{}`, expected `NAME=VALUE`", s),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt, Clone, Default)]
 /// The options for the `wasmer run` subcommand
 pub struct Run {
     /// Disable the cache
@@ -31,8 +166,24 @@ pub struct Run {
     path: PathBuf,
 
     /// Invoke a specified function
-    #[structopt(long = "invoke", short = "i")]
-    invoke: Option<String>,
+    ///
+    /// With `--exec-model reactor`, the option can be repeated to call
+    /// several functions in order, in the same instance. They all get the
+    /// arguments of the module.
+    #[structopt(long = "invoke", short = "i", number_of_values = 1)]
+    invoke: Vec<String>,
+
+    /// How the module runs: `command` (default) or `reactor`.
+    ///
+    /// A `command` runs its `_start` export (or the `--invoke` function
+    /// instead) once, and is torn down when it returns. A `reactor` is a
+    /// library: its `_initialize` export is called once, then the
+    /// `--invoke` functions are called in the same instance, so they see
+    /// the state left by the previous ones. The module must export the
+    /// entry of its model. WASI reactors get the WASI imports, unlike
+    /// `--invoke` in the `command` model.
+    #[structopt(long = "exec-model", default_value = "command")]
+    exec_model: ExecModel,
 
     /// The command name is a string that will override the first argument passed
     /// to the wasm program. This is used in wapm to provide nicer output in
@@ -46,6 +197,322 @@ pub struct Run {
     #[structopt(long = "cache-key", hidden = true)]
     cache_key: Option<String>,
 
+    /// Check that the guest exits with the provided exit code.
+    ///
+    /// If it does, `wasmer` exits with `0`; otherwise it prints the mismatch
+    /// and exits with code `3`. Modules without an exit code (non-WASI ones,
+    /// or `--invoke`) are considered to exit with `0` when they return.
+    #[structopt(long = "assert-exit", name = "EXIT_CODE")]
+    assert_exit: Option<i32>,
+
+    /// Watch the file and run it again every time it changes, until
+    /// interrupted with Ctrl-C.
+    ///
+    /// The inputs read only once, `--preopen-stdin-as` and the descriptors
+    /// of `--preopen-fd`, would be drained on the reruns, so they can't be
+    /// used with it.
+    #[structopt(
+        long = "watch",
+        conflicts_with_all = &["EXIT_CODE", "GUEST_PATH", "HOST_FD:NAME"]
+    )]
+    watch: bool,
+
+    /// Call the `_initialize` export of the module and write the resulting
+    /// state to this file, to restore it later with `--restore`.
+    ///
+    /// Only the exported memories and mutable globals are saved: tables
+    /// and the WASI state (like open files) aren't. The snapshot can only
+    /// be restored for the same module, on the same kind of host.
+    #[structopt(
+        long = "snapshot-after-init",
+        name = "SNAPSHOT PATH",
+        parse(from_os_str)
+    )]
+    snapshot_after_init: Option<PathBuf>,
+
+    /// Restore the state saved with `--snapshot-after-init` instead of
+    /// calling the `_initialize` export of the module.
+    #[structopt(long = "restore", parse(from_os_str), conflicts_with = "SNAPSHOT PATH")]
+    restore: Option<PathBuf>,
+
+    /// Back the linear memory of the module with this file, mapped in
+    /// memory, so its contents persist across runs.
+    ///
+    /// The file is created if it doesn't exist, and grows with the memory.
+    /// An existing file is used as the initial contents of the memory, so
+    /// its size must be a multiple of 64 KiB and fit in the maximum size
+    /// of the memory, which is still enforced. The data segments of the
+    /// module are written to it again every time the module starts.
+    ///
+    /// The file holds the raw bytes of the memory, so it's only meaningful
+    /// to the same module. It's locked while the module runs: sharing it
+    /// with other processes is not supported. Only available on Unix.
+    #[structopt(long = "memory-file", parse(from_os_str))]
+    memory_file: Option<PathBuf>,
+
+    /// Write a profile of the growth of the linear memories of the module
+    /// to this file as JSON, once it has run: every `memory.grow`, with
+    /// the time since the module started and the sizes before and after
+    /// it, and the peak size of every memory.
+    ///
+    /// Only the growths are hooked, so the overhead is low, and the
+    /// module is compiled as usual.
+    #[structopt(
+        long = "alloc-profile",
+        parse(from_os_str),
+        conflicts_with = "INSTANCES"
+    )]
+    alloc_profile: Option<PathBuf>,
+
+    /// The profile of `--alloc-profile`, recorded by the memories.
+    #[structopt(skip)]
+    alloc_profiler: AllocProfiler,
+
+    /// Debug information split from a precompiled module with
+    /// `wasmer compile --split-debug`, used to symbolicate backtraces.
+    #[structopt(long = "debug-file", parse(from_os_str))]
+    debug_file: Option<PathBuf>,
+
+    /// When the module traps, write the trap code, the function and offset
+    /// where it occurred and the Wasm call stack to this file as JSON, and
+    /// exit with code `4`.
+    ///
+    /// The error is still printed as usual.
+    #[structopt(long = "capture-trap-json", name = "TRAP PATH", parse(from_os_str))]
+    capture_trap_json: Option<PathBuf>,
+
+    /// When the module traps, disassemble the function where it occurred
+    /// and print it as WAT on stderr, with the offset of every instruction
+    /// and the trapping one marked.
+    ///
+    /// The module is only disassembled if it traps, as it's slow for big
+    /// modules. Precompiled modules can't be disassembled.
+    #[structopt(long = "print-wat-on-trap", conflicts_with = "PATTERN")]
+    print_wat_on_trap: bool,
+
+    /// Write a value in an exported mutable global after instantiating the
+    /// module, before calling `_start` or the `--invoke` function.
+    ///
+    /// The value is parsed according to the type of the global. Only
+    /// exported mutable globals of a number type can be set.
+    #[structopt(
+        long = "set-global",
+        alias = "preload-globals",
+        name = "NAME=VALUE",
+        number_of_values = 1
+    )]
+    set_globals: Vec<GlobalAssignment>,
+
+    /// Run this module after the main one exits or traps, with the exit
+    /// code of the main module as its only argument (`1` if it trapped).
+    ///
+    /// The hook is run with the same WASI options, like the preopened
+    /// directories, so it can clean up after the main module. A trap or
+    /// non-zero exit of the hook is reported, but doesn't change the exit
+    /// code of `wasmer` unless `--hook-overrides-exit` is set.
+    #[structopt(long = "on-exit-hook", name = "HOOK PATH", parse(from_os_str))]
+    on_exit_hook: Option<PathBuf>,
+
+    /// Exit with the outcome of the `--on-exit-hook` module when it traps
+    /// or exits with a non-zero code, instead of the main module's.
+    #[structopt(long = "hook-overrides-exit", requires = "HOOK PATH")]
+    hook_overrides_exit: bool,
+
+    /// Compile the module once and run this many instances of it
+    /// concurrently, each on its own thread with its own memory and WASI
+    /// environment.
+    ///
+    /// The exit codes of the instances are collected and the timings are
+    /// reported on stderr. `wasmer` fails if an instance fails, and exits
+    /// with the first non-zero exit code otherwise.
+    #[structopt(
+        long = "instances",
+        name = "INSTANCES",
+        conflicts_with_all = &["watch", "SNAPSHOT PATH", "memory-file", "GUEST_PATH"]
+    )]
+    instances: Option<usize>,
+
+    /// Pin the threads running the module to these CPUs, like `0,2` or
+    /// `0-3`, to reduce the noise of benchmarks.
+    ///
+    /// With `--instances`, each instance is pinned to one of the CPUs, in
+    /// turn. Compilation isn't pinned. Only supported on Linux and Windows:
+    /// the option is ignored with a warning elsewhere.
+    #[structopt(long = "cpu-affinity", name = "CPUS")]
+    cpu_affinity: Option<CpuList>,
+
+    /// Stop after this phase: `compile` reports how long compiling the
+    /// module took and exits with `0` without running it, `run` (default)
+    /// runs it.
+    ///
+    /// A cached or precompiled module is only loaded, so pass
+    /// `--disable-cache` to time the compilation itself.
+    #[structopt(long = "exit-after", default_value = "run")]
+    exit_after: ExitAfter,
+
+    /// Write how long compiling and running the module took to this file,
+    /// as JSON.
+    #[structopt(
+        long = "timings-json",
+        parse(from_os_str),
+        conflicts_with = "INSTANCES"
+    )]
+    timings_json: Option<PathBuf>,
+
+    /// Only run modules importing from these namespaces, like
+    /// `wasi_snapshot_preview1`, and reject the others before
+    /// instantiating them.
+    ///
+    /// The disallowed imports are printed. The option can be repeated to
+    /// allow several namespaces. It also applies to the `--on-exit-hook`
+    /// module.
+    #[structopt(long = "allow-imports", name = "NAMESPACE", number_of_values = 1)]
+    allow_imports: Vec<String>,
+
+    /// Log the calls to the functions whose name matches this glob, like
+    /// `js_*`, to stderr, indented by call depth, with their arguments and
+    /// results.
+    ///
+    /// The names come from the name section of the module, or from its
+    /// exports. Imported functions can't be traced, and the traced
+    /// functions are slower. Precompiled modules and modules with a start
+    /// function aren't supported. The hooks are called through a new
+    /// table, which needs the reference types and bulk memory proposals.
+    #[structopt(
+        long = "trace-calls",
+        name = "PATTERN",
+        conflicts_with_all = &[
+            "SNAPSHOT PATH",
+            "restore",
+            "disable-reference-types",
+            "disable-bulk-memory",
+        ]
+    )]
+    trace_calls: Option<String>,
+
+    /// Write the linear memory of the module to this file once it has
+    /// run, after `_start` (or the `--invoke` function) returns or traps,
+    /// to inspect its state offline.
+    ///
+    /// Modules with several memories dump their memory `0`, unless
+    /// `--memory-index` is given. The memory must be exported.
+    #[structopt(long = "dump-memory-on-exit", name = "DUMP PATH", parse(from_os_str))]
+    dump_memory_on_exit: Option<PathBuf>,
+
+    /// Only dump this range of the memory with `--dump-memory-on-exit`, as
+    /// `START:LEN`, in bytes, like `0x1000:256`.
+    #[structopt(long = "dump-memory-range", requires = "DUMP PATH")]
+    dump_memory_range: Option<MemoryRange>,
+
+    /// The index of the memory dumped by `--dump-memory-on-exit`, counting
+    /// the imported memories first.
+    #[structopt(long = "memory-index", default_value = "0")]
+    memory_index: u32,
+
+    /// Interrupt the module once it has used this much CPU time, like
+    /// `2s` or `500ms`, and exit with code `5`. Unlike a wall-clock
+    /// timeout, the time spent waiting, like sleeping or blocked on I/O,
+    /// doesn't count.
+    ///
+    /// The CPU time of the whole `wasmer` process is measured, with
+    /// `clock_gettime(CLOCK_PROCESS_CPUTIME_ID)` on Unix and
+    /// `GetProcessTimes` on Windows, so it includes the host functions and
+    /// the kernel. The module is compiled with checks before every call and
+    /// in every loop, which work with all the compilers; a function
+    /// without either is only interrupted once it returns. Precompiled
+    /// modules aren't supported, and the cache is bypassed.
+    #[structopt(
+        long = "time-limit-cpu",
+        name = "DURATION",
+        parse(try_from_str = parse_duration)
+    )]
+    time_limit_cpu: Option<Duration>,
+
+    /// Set by the watchdog of `--time-limit-cpu` when it interrupts the
+    /// module.
+    #[structopt(skip)]
+    cpu_time_exceeded: Arc<AtomicBool>,
+
+    /// Interrupt the module when `wasmer` receives `SIGINT` (Ctrl-C) or
+    /// `SIGTERM`, so it traps and `wasmer` exits cleanly, with the usual
+    /// code `128 + signal` (`130` for Ctrl-C), instead of being killed.
+    ///
+    /// The module is compiled with the interrupt checks of
+    /// `--time-limit-cpu`, which work with all the compilers (Singlepass,
+    /// Cranelift and LLVM); precompiled modules aren't supported, and the
+    /// cache is bypassed. A module blocked in a host function, like
+    /// reading the standard input, is only interrupted once it returns: a
+    /// second signal kills `wasmer` right away. On Windows, Ctrl-C,
+    /// Ctrl-Break and closing the console interrupt the module.
+    #[structopt(long = "interrupt-on-signal", conflicts_with = "INSTANCES")]
+    interrupt_on_signal: bool,
+
+    /// Set to the signal which interrupted the module for
+    /// `--interrupt-on-signal`, `0` otherwise.
+    #[structopt(skip)]
+    interrupting_signal: Arc<AtomicI32>,
+
+    /// Trap as soon as the module grows its memory with `memory.grow`,
+    /// to check that it runs within its initial memory.
+    ///
+    /// The trap names the number of pages requested. `memory.grow 0`,
+    /// which only returns the size of the memory, doesn't trap. The module
+    /// is compiled with a middleware replacing `memory.grow`, so
+    /// precompiled modules aren't supported, and the cache is bypassed.
+    #[structopt(long = "trap-on-grow", alias = "memory-growth-callback-trap")]
+    trap_on_grow: bool,
+
+    /// Limit the module to this number of metering points, one for every
+    /// Wasm operator it executes, and trap once they're exhausted.
+    ///
+    /// The module is compiled with the metering middleware, so
+    /// precompiled modules aren't supported, and the cache is bypassed.
+    /// A trap caused by running out of points says so.
+    #[structopt(long = "metering-limit", name = "POINTS")]
+    metering_limit: Option<u64>,
+
+    /// When the module traps, print the metering points it had left on
+    /// stderr, read from the metering global at the time of the trap.
+    ///
+    /// `0 (exhausted)` means that the module ran out of points, any other
+    /// number that it trapped for another reason.
+    #[structopt(long = "print-metering-remaining-on-trap", requires = "POINTS")]
+    print_metering_remaining_on_trap: bool,
+
+    /// Once the module has run, print the imported functions it never
+    /// called on stderr, to find the host functions it doesn't need.
+    ///
+    /// Only the direct calls are counted: the imported functions placed
+    /// in a table or exported may have been called indirectly, and are
+    /// flagged as such. The module is compiled with a counter for every
+    /// imported function, so precompiled modules aren't supported, and
+    /// the cache is bypassed.
+    #[structopt(long = "report-unused-imports")]
+    report_unused_imports: bool,
+
+    /// Write the number of direct calls to every imported function to
+    /// this file as JSON, once the module has run, like
+    /// `--report-unused-imports`.
+    #[structopt(long = "unused-imports-json", parse(from_os_str))]
+    unused_imports_json: Option<PathBuf>,
+
+    /// Read the directories, environment variables, limits and imports
+    /// granted to the module from this TOML profile, with the keys named
+    /// after the flags, like `dir-access-mode = "0444"`.
+    ///
+    /// The flags take precedence over the profile: `--env` and `--mapdir`
+    /// per variable and guest directory, the other flags replace the
+    /// setting altogether. Unknown keys are rejected.
+    #[cfg(feature = "wasi")]
+    #[structopt(long = "sandbox-profile", parse(from_os_str))]
+    sandbox_profile: Option<PathBuf>,
+
+    /// Print the sandbox of the module, merged from `--sandbox-profile`
+    /// and the flags, as a TOML profile, and exit without running it.
+    #[cfg(feature = "wasi")]
+    #[structopt(long = "print-sandbox")]
+    print_sandbox: bool,
+
     #[structopt(flatten)]
     store: StoreOptions,
 
@@ -75,11 +542,121 @@ pub struct Run {
 impl Run {
     /// Execute the run command
     pub fn execute(&self) -> Result<()> {
+        #[cfg(feature = "wasi")]
+        if let Some(profile_path) = &self.sandbox_profile {
+            let profile = SandboxProfile::load(profile_path)?;
+            let mut run = self.clone();
+            run.sandbox_profile = None;
+            if run.allow_imports.is_empty() {
+                run.allow_imports = profile.allow_imports.clone();
+            }
+            run.wasi.apply_sandbox_profile(&profile)?;
+            return run.execute();
+        }
+        #[cfg(feature = "wasi")]
+        if self.print_sandbox {
+            let mut profile = SandboxProfile {
+                allow_imports: self.allow_imports.clone(),
+                ..SandboxProfile::default()
+            };
+            self.wasi.fill_sandbox_profile(&mut profile)?;
+            print!("{}", profile.to_toml()?);
+            return Ok(());
+        }
         #[cfg(feature = "debug")]
         if self.debug {
             logging::set_up_logging(self.verbose).unwrap();
         }
-        self.inner_execute().with_context(|| {
+        if self.watch {
+            return self.watch_and_execute();
+        }
+        let result = self.execute_once();
+        #[cfg(feature = "wasi")]
+        if let Some(capture_path) = &self.wasi.capture_output {
+            self.wasi.captured_output.write(capture_path, &result)?;
+        }
+        if let (Err(err), true) = (&result, self.print_wat_on_trap) {
+            if let Err(disassembly_err) = self.print_trapping_function(err) {
+                warning!("failed to print the trapping function: {}", disassembly_err);
+            }
+        }
+        let result = match result {
+            Err(err) if self.cpu_time_exceeded.load(Ordering::SeqCst) => {
+                PrettyError::print(err);
+                std::process::exit(CPU_TIME_LIMIT_EXIT_CODE);
+            }
+            Err(err) if self.interrupting_signal.load(Ordering::SeqCst) != 0 => {
+                PrettyError::print(err);
+                std::process::exit(128 + self.interrupting_signal.load(Ordering::SeqCst));
+            }
+            result => result,
+        };
+        let exit_code = match (result, &self.capture_trap_json) {
+            (Err(err), Some(trap_path)) => match TrapReport::from_error(&err) {
+                Some(report) => {
+                    report.write(trap_path)?;
+                    PrettyError::print(err);
+                    std::process::exit(TRAP_EXIT_CODE);
+                }
+                None => return Err(err),
+            },
+            (result, _) => result?,
+        };
+        self.exit_with_code(exit_code)
+    }
+
+    /// Print the function of the module where the trap of `error`
+    /// occurred, for `--print-wat-on-trap`.
+    fn print_trapping_function(&self, error: &anyhow::Error) -> Result<()> {
+        let contents = std::fs::read(&self.path)?;
+        #[cfg(feature = "wat")]
+        let contents = wat2wasm(&contents)
+            .map(|wasm| wasm.into_owned())
+            .unwrap_or(contents);
+        if !is_wasm(&contents) {
+            bail!("precompiled modules can't be disassembled");
+        }
+        trap_wat::print_trapping_function(&contents, error)
+    }
+
+    /// Run the module once, and then the `--on-exit-hook` if any,
+    /// returning the exit code of the guest.
+    fn execute_once(&self) -> Result<i32> {
+        let result = self.execute_main();
+        match &self.on_exit_hook {
+            Some(hook_path) => self.execute_exit_hook(hook_path, result),
+            None => result,
+        }
+    }
+
+    /// Run the module once, returning the exit code of the guest.
+    fn execute_main(&self) -> Result<i32> {
+        if self.cpu_affinity.is_some() && !affinity::SUPPORTED {
+            warning!("`--cpu-affinity` is only supported on Linux and Windows, ignoring it");
+        }
+        self.cpu_time_exceeded.store(false, Ordering::SeqCst);
+        self.interrupting_signal.store(0, Ordering::SeqCst);
+        match self.instances {
+            Some(instances) => self.execute_instances(instances),
+            None => self.inner_execute(),
+        }
+        .map_err(|err| match self.time_limit_cpu {
+            Some(limit) if self.cpu_time_exceeded.load(Ordering::SeqCst) => err.context(format!(
+                "the module exceeded its CPU time limit of {:?}",
+                limit
+            )),
+            _ => err,
+        })
+        .map_err(
+            |err| match self.interrupting_signal.load(Ordering::SeqCst) {
+                0 => err,
+                signal => err.context(format!(
+                    "the module was interrupted by {}",
+                    interrupt_signal::signal_name(signal)
+                )),
+            },
+        )
+        .with_context(|| {
             format!(
                 "failed to run `{}`{}",
                 self.path.display(),
@@ -92,13 +669,254 @@ impl Run {
         })
     }
 
-    fn inner_execute(&self) -> Result<()> {
+    /// Run the `--on-exit-hook` module after the main one, returning the
+    /// outcome `wasmer` exits with.
+    fn execute_exit_hook(&self, hook_path: &Path, main_result: Result<i32>) -> Result<i32> {
+        let main_exit_code = match &main_result {
+            Ok(exit_code) => *exit_code,
+            // The exit code of `wasmer` when the module fails
+            Err(_) => 1,
+        };
+        // Only the options shared with the main module, so the ones
+        // specific to it, like `--invoke`, don't apply to the hook. The
+        // hook gets its own CPU time limit and metering points.
+        let hook = Self {
+            path: hook_path.to_path_buf(),
+            args: vec![main_exit_code.to_string()],
+            disable_cache: self.disable_cache,
+            allow_imports: self.allow_imports.clone(),
+            time_limit_cpu: self.time_limit_cpu,
+            interrupt_on_signal: self.interrupt_on_signal,
+            metering_limit: self.metering_limit,
+            print_metering_remaining_on_trap: self.print_metering_remaining_on_trap,
+            store: self.store.clone(),
+            #[cfg(feature = "wasi")]
+            wasi: self.wasi.for_exit_hook(),
+            #[cfg(feature = "io-devices")]
+            enable_experimental_io_devices: self.enable_experimental_io_devices,
+            #[cfg(feature = "debug")]
+            debug: self.debug,
+            verbose: self.verbose,
+            ..Self::default()
+        };
+
+        match hook.execute_main() {
+            Ok(0) => main_result,
+            Ok(hook_exit_code) if self.hook_overrides_exit => {
+                // The error of the main module would be lost otherwise
+                if let Err(err) = main_result {
+                    PrettyError::print(err);
+                }
+                Ok(hook_exit_code)
+            }
+            Ok(hook_exit_code) => {
+                warning!(
+                    "the exit hook `{}` exited with code {}",
+                    hook_path.display(),
+                    hook_exit_code
+                );
+                main_result
+            }
+            Err(err) if self.hook_overrides_exit => {
+                if let Err(main_err) = main_result {
+                    PrettyError::print(main_err);
+                }
+                Err(err.context("the exit hook failed"))
+            }
+            Err(err) => {
+                PrettyError::print(err.context("the exit hook failed"));
+                main_result
+            }
+        }
+    }
+
+    /// Run the module every time the file changes, until interrupted.
+    fn watch_and_execute(&self) -> Result<()> {
+        let mut last_change = watch::wait_for_file(&self.path, None);
+        loop {
+            match self.execute_once() {
+                Ok(0) => {}
+                Ok(exit_code) => eprintln!("The module exited with code {}", exit_code),
+                Err(err) => PrettyError::print(err),
+            }
+            eprintln!("--- waiting for `{}` to change ---", self.path.display());
+            last_change = watch::wait_for_file(&self.path, Some(last_change));
+        }
+    }
+
+    /// Run `instances` instances of the module concurrently, compiling it
+    /// only once, and report how long they took.
+    #[allow(clippy::needless_collect)]
+    fn execute_instances(&self, instances: usize) -> Result<i32> {
+        if instances == 0 {
+            bail!("`--instances` must be at least 1");
+        }
+        if self.exit_after == ExitAfter::Compile {
+            bail!("`--exit-after compile` can't be used with `--instances`");
+        }
+        if self.dump_memory_on_exit.is_some() {
+            bail!("`--dump-memory-on-exit` can't be used with `--instances`");
+        }
+        if self.time_limit_cpu.is_some() {
+            bail!("`--time-limit-cpu` can't be used with `--instances`, as the CPU time is measured for the whole process");
+        }
+        if self.counts_import_calls() {
+            bail!("`--report-unused-imports` and `--unused-imports-json` can't be used with `--instances`");
+        }
+        let start = Instant::now();
+        let module = self.get_module()?;
+        let compile_time = start.elapsed();
+        self.check_imports(&module)?;
+
+        let run = Arc::new(self.clone());
+        // Every instance must be started before waiting for the first one
+        let handles = (0..instances)
+            .map(|index| {
+                let run = run.clone();
+                let module = module.clone();
+                thread::spawn(move || {
+                    let start = Instant::now();
+                    let result = run
+                        .pin_current_thread(Some(index))
+                        .and_then(|_| run.execute_module(&module));
+                    (result, start.elapsed())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut exit_code = 0;
+        let mut failures = 0;
+        let mut times = Vec::with_capacity(instances);
+        for (index, handle) in handles.into_iter().enumerate() {
+            let (result, time) = handle
+                .join()
+                .unwrap_or_else(|_| (Err(anyhow!("the thread panicked")), Duration::default()));
+            times.push(time);
+            match result {
+                Ok(0) => {}
+                Ok(code) => {
+                    eprintln!("Instance {} exited with code {}", index, code);
+                    if exit_code == 0 {
+                        exit_code = code;
+                    }
+                }
+                Err(err) => {
+                    PrettyError::print(err.context(format!("instance {} failed", index)));
+                    failures += 1;
+                }
+            }
+        }
+
+        eprintln!(
+            "Ran {} instances in {:.2?} (compilation: {:.2?}, per instance: min {:.2?}, mean {:.2?}, max {:.2?})",
+            instances,
+            start.elapsed(),
+            compile_time,
+            times.iter().min().unwrap(),
+            times.iter().sum::<Duration>() / instances as u32,
+            times.iter().max().unwrap(),
+        );
+        if failures > 0 {
+            bail!("{} of {} instances failed", failures, instances);
+        }
+        Ok(exit_code)
+    }
+
+    fn inner_execute(&self) -> Result<i32> {
+        let start = Instant::now();
         let module = self.get_module()?;
+        let compile_time = start.elapsed();
+        if self.exit_after == ExitAfter::Compile {
+            eprintln!(
+                "✔ Compiled `{}` in {:.2?}.",
+                self.path.display(),
+                compile_time
+            );
+            if let Some(timings_path) = &self.timings_json {
+                Timings::new(compile_time, None).write(timings_path)?;
+            }
+            return Ok(0);
+        }
+
+        self.check_imports(&module)?;
+        self.pin_current_thread(None)?;
+        let start = Instant::now();
+        self.alloc_profiler.start();
+        let result = self.execute_module(&module);
+        if let Some(timings_path) = &self.timings_json {
+            Timings::new(compile_time, Some(start.elapsed())).write(timings_path)?;
+        }
+        if let Some(profile_path) = &self.alloc_profile {
+            self.alloc_profiler.write(profile_path)?;
+        }
+        result
+    }
+
+    /// Check that the module only imports from the `--allow-imports`
+    /// namespaces, if any.
+    fn check_imports(&self, module: &Module) -> Result<()> {
+        if self.allow_imports.is_empty() {
+            return Ok(());
+        }
+        let disallowed_imports = module
+            .imports()
+            .filter(|import| !self.allow_imports.iter().any(|ns| ns == import.module()))
+            .map(|import| {
+                let ty = match import.ty() {
+                    ExternType::Function(ty) => ty.to_string(),
+                    ExternType::Global(ty) => ty.to_string(),
+                    ExternType::Table(ty) => ty.to_string(),
+                    ExternType::Memory(ty) => ty.to_string(),
+                };
+                format!("\n  \"{}\".\"{}\": {}", import.module(), import.name(), ty)
+            })
+            .collect::<String>();
+        if !disallowed_imports.is_empty() {
+            bail!(
+                "the module imports from namespaces not allowed by `--allow-imports`:{}",
+                disallowed_imports
+            );
+        }
+        Ok(())
+    }
+
+    /// Pin the current thread to the `--cpu-affinity` CPUs, or to one of
+    /// them for the instance at `instance_index` of `--instances`.
+    fn pin_current_thread(&self, instance_index: Option<usize>) -> Result<()> {
+        let cpus = match &self.cpu_affinity {
+            Some(CpuList(cpus)) if affinity::SUPPORTED => cpus,
+            _ => return Ok(()),
+        };
+        match instance_index {
+            Some(index) => affinity::pin_current_thread(&[cpus[index % cpus.len()]]),
+            None => affinity::pin_current_thread(cpus),
+        }
+    }
+
+    /// Instantiate and run a compiled module, returning the exit code of
+    /// the guest.
+    fn execute_module(&self, module: &Module) -> Result<i32> {
+        if self.exec_model == ExecModel::Reactor {
+            if self.snapshot_after_init.is_some() || self.restore.is_some() {
+                bail!("`--exec-model reactor` can't be used with `--snapshot-after-init` or `--restore`");
+            }
+            return self.execute_reactor(module);
+        }
+        if self.invoke.len() > 1 {
+            bail!("calling several `--invoke` functions requires `--exec-model reactor`");
+        }
+        if self.snapshot_after_init.is_some() || self.restore.is_some() {
+            return self.execute_with_snapshot(module);
+        }
         // Do we want to invoke a function?
-        if let Some(ref invoke) = self.invoke {
+        if let Some(invoke) = self.invoke.first() {
             let imports = imports! {};
-            let instance = Instance::new(&module, &imports)?;
-            let result = self.invoke_function(&instance, &invoke, &self.args)?;
+            let instance = Instance::new(module, &imports)?;
+            let _hooks = trace_calls::install_hooks(&instance)?;
+            self.set_globals(&instance)?;
+            let _interrupters = self.start_interrupters(&instance)?;
+            let result = self.invoke_function(&instance, &invoke, &self.args);
+            let result = self.finish_run(&instance, result)?;
             println!(
                 "{}",
                 result
@@ -107,7 +925,7 @@ impl Run {
                     .collect::<Vec<String>>()
                     .join(" ")
             );
-            return Ok(());
+            return Ok(0);
         }
         #[cfg(feature = "emscripten")]
         {
@@ -116,27 +934,30 @@ impl Run {
                 EmscriptenGlobals,
             };
             // TODO: refactor this
-            if is_emscripten_module(&module) {
-                let mut emscripten_globals = EmscriptenGlobals::new(module.store(), &module)
-                    .map_err(|e| anyhow!("{}", e))?;
+            if is_emscripten_module(module) {
+                let mut emscripten_globals =
+                    EmscriptenGlobals::new(module.store(), module).map_err(|e| anyhow!("{}", e))?;
                 let mut em_env = EmEnv::new(&emscripten_globals.data, Default::default());
                 let import_object =
                     generate_emscripten_env(module.store(), &mut emscripten_globals, &mut em_env);
-                let mut instance = match Instance::new(&module, &import_object) {
+                let mut instance = match Instance::new(module, &import_object) {
                     Ok(instance) => instance,
                     Err(e) => {
-                        let err: Result<(), _> = Err(e);
+                        let err: Result<i32, _> = Err(e);
                         #[cfg(feature = "wasi")]
                         {
-                            if Wasi::has_wasi_imports(&module) {
+                            if Wasi::has_wasi_imports(module) {
                                 return err.with_context(|| "This module has both Emscripten and WASI imports. Wasmer does not currently support Emscripten modules using WASI imports.");
                             }
                         }
                         return err.with_context(|| "Can't instantiate emscripten module");
                     }
                 };
+                let _hooks = trace_calls::install_hooks(&instance)?;
+                self.set_globals(&instance)?;
+                let _interrupters = self.start_interrupters(&instance)?;
 
-                run_emscripten_instance(
+                let result = run_emscripten_instance(
                     &mut instance,
                     &mut em_env,
                     &mut emscripten_globals,
@@ -147,8 +968,9 @@ impl Run {
                     },
                     self.args.iter().map(|arg| arg.as_str()).collect(),
                     None, //run.em_entrypoint.clone(),
-                )?;
-                return Ok(());
+                );
+                self.finish_run(&instance, result.map_err(anyhow::Error::from))?;
+                return Ok(0);
             }
         }
 
@@ -158,7 +980,7 @@ impl Run {
             use std::collections::BTreeSet;
             use wasmer_wasi::WasiVersion;
 
-            let wasi_versions = Wasi::get_versions(&module);
+            let wasi_versions = Wasi::get_versions(module);
             match wasi_versions {
                 Some(wasi_versions) if !wasi_versions.is_empty() => {
                     if wasi_versions.len() >= 2 {
@@ -178,19 +1000,19 @@ impl Run {
                         }
                     }
 
-                    let program_name = self
-                        .command_name
-                        .clone()
-                        .or_else(|| {
-                            self.path
-                                .file_name()
-                                .map(|f| f.to_string_lossy().to_string())
-                        })
-                        .unwrap_or_default();
-                    return self
+                    let instance = self.wasi.instantiate(
+                        module,
+                        self.get_program_name(),
+                        self.args.clone(),
+                    )?;
+                    let _hooks = trace_calls::install_hooks(&instance)?;
+                    self.set_globals(&instance)?;
+                    let _interrupters = self.start_interrupters(&instance)?;
+                    let result = self
                         .wasi
-                        .execute(module, program_name, self.args.clone())
+                        .execute(&instance)
                         .with_context(|| "WASI execution failed");
+                    return self.finish_run(&instance, result);
                 }
                 // not WASI
                 _ => (),
@@ -199,20 +1021,305 @@ impl Run {
 
         // Try to instantiate the wasm file, with no provided imports
         let imports = imports! {};
-        let instance = Instance::new(&module, &imports)?;
+        let instance = Instance::new(module, &imports)?;
+        let _hooks = trace_calls::install_hooks(&instance)?;
+        self.set_globals(&instance)?;
+        let _interrupters = self.start_interrupters(&instance)?;
         let start: Function = self.try_find_function(&instance, "_start", &[])?;
-        start.call(&[])?;
+        self.finish_run(&instance, start.call(&[]).map_err(anyhow::Error::from))?;
+
+        Ok(0)
+    }
+
+    /// Initialize the module, from the `--restore` snapshot or by calling
+    /// its `_initialize` export, and then run it.
+    fn execute_with_snapshot(&self, module: &Module) -> Result<i32> {
+        let module_contents = std::fs::read(&self.path)?;
+        #[cfg(feature = "wasi")]
+        let instance = if Wasi::has_wasi_imports(module) {
+            self.wasi
+                .instantiate(module, self.get_program_name(), self.args.clone())?
+        } else {
+            Instance::new(module, &imports! {})?
+        };
+        #[cfg(not(feature = "wasi"))]
+        let instance = Instance::new(module, &imports! {})?;
+        let _interrupters = self.start_interrupters(&instance)?;
+
+        match &self.restore {
+            Some(snapshot_path) => Snapshot::load(snapshot_path)?
+                .restore(&instance, &module_contents)
+                .with_context(|| {
+                    format!("failed to restore snapshot `{}`", snapshot_path.display())
+                })?,
+            None => {
+                let initialize = instance
+                    .exports
+                    .get_function("_initialize")
+                    .context("the module has no `_initialize` export to snapshot after")?;
+                initialize.call(&[])?;
+            }
+        }
+        if let Some(snapshot_path) = &self.snapshot_after_init {
+            Snapshot::capture(&instance, &module_contents)?.save(snapshot_path)?;
+            eprintln!(
+                "✔ Snapshot written successfully to `{}`.",
+                snapshot_path.display()
+            );
+        }
+
+        // The globals are set after the snapshot, so they aren't saved
+        self.set_globals(&instance)?;
+        if let Some(invoke) = self.invoke.first() {
+            let result = self.invoke_function(&instance, &invoke, &self.args);
+            let result = self.finish_run(&instance, result)?;
+            println!(
+                "{}",
+                result
+                    .iter()
+                    .map(|val| val.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            );
+        } else if let Ok(start) = instance.exports.get_function("_start") {
+            self.finish_run(&instance, start.call(&[]).map_err(anyhow::Error::from))?;
+        }
+        Ok(0)
+    }
+
+    /// Run a reactor module, for `--exec-model reactor`: call its
+    /// `_initialize` export, then the `--invoke` functions in order, in
+    /// the same instance.
+    fn execute_reactor(&self, module: &Module) -> Result<i32> {
+        #[cfg(feature = "wasi")]
+        let instance = if Wasi::has_wasi_imports(module) {
+            self.wasi
+                .instantiate(module, self.get_program_name(), self.args.clone())?
+        } else {
+            Instance::new(module, &imports! {})?
+        };
+        #[cfg(not(feature = "wasi"))]
+        let instance = Instance::new(module, &imports! {})?;
+        let _hooks = trace_calls::install_hooks(&instance)?;
+        self.set_globals(&instance)?;
+        let _interrupters = self.start_interrupters(&instance)?;
+
+        let initialize = instance.exports.get_function("_initialize").context(
+            "the module has no `_initialize` export, required by `--exec-model reactor`",
+        )?;
+        let result = initialize
+            .call(&[])
+            .map_err(anyhow::Error::from)
+            .and_then(|_| {
+                for invoke in &self.invoke {
+                    let result = self.invoke_function(&instance, invoke, &self.args)?;
+                    println!(
+                        "{}",
+                        result
+                            .iter()
+                            .map(|val| val.to_string())
+                            .collect::<Vec<String>>()
+                            .join(" ")
+                    );
+                }
+                Ok(0)
+            });
+        self.finish_run(&instance, result)
+    }
+
+    /// Start interrupting `instance` once it exceeds the `--time-limit-cpu`
+    /// or on a signal for `--interrupt-on-signal`, until the returned
+    /// interrupters are dropped.
+    fn start_interrupters(
+        &self,
+        instance: &Instance,
+    ) -> Result<(Option<CpuTimeWatchdog>, Option<SignalInterrupter>)> {
+        let watchdog = match self.time_limit_cpu {
+            Some(limit) => Some(CpuTimeWatchdog::start(
+                instance,
+                limit,
+                self.cpu_time_exceeded.clone(),
+            )?),
+            None => None,
+        };
+        let signal_interrupter = if self.interrupt_on_signal {
+            Some(SignalInterrupter::start(
+                instance,
+                self.interrupting_signal.clone(),
+            )?)
+        } else {
+            None
+        };
+        Ok((watchdog, signal_interrupter))
+    }
+
+    /// Whether the module is compiled with the interrupt checks, for
+    /// `--time-limit-cpu` and `--interrupt-on-signal`.
+    fn needs_interrupt_checks(&self) -> bool {
+        self.time_limit_cpu.is_some() || self.interrupt_on_signal
+    }
+
+    /// Finish running `instance` with `result`: explain a trap of
+    /// `--trap-on-grow`, report the unused imports and dump its memory for
+    /// `--dump-memory-on-exit`.
+    fn finish_run<T>(&self, instance: &Instance, result: Result<T>) -> Result<T> {
+        let result = if self.trap_on_grow {
+            trap_on_grow::check_memory_growth(instance, result)
+        } else {
+            result
+        };
+        let result = match self.metering_limit {
+            Some(limit) => metering::check_metering(
+                instance,
+                result,
+                limit,
+                self.print_metering_remaining_on_trap,
+            ),
+            None => result,
+        };
+        let result = self.report_unused_imports(instance, result);
+        self.dump_memory_on_exit(instance, result)
+    }
+
+    /// Whether the calls to the imported functions are counted, for
+    /// `--report-unused-imports` and `--unused-imports-json`.
+    fn counts_import_calls(&self) -> bool {
+        self.report_unused_imports || self.unused_imports_json.is_some()
+    }
+
+    /// Report the imported functions `instance` never called, once it has
+    /// run with `result`.
+    ///
+    /// If the module failed, its error is kept over the one of the report.
+    fn report_unused_imports<T>(&self, instance: &Instance, result: Result<T>) -> Result<T> {
+        if !self.counts_import_calls() {
+            return result;
+        }
+        let reported = ImportUsageReport::from_instance(instance).and_then(|report| {
+            if self.report_unused_imports {
+                report.print();
+            }
+            match &self.unused_imports_json {
+                Some(json_path) => report.write(json_path),
+                None => Ok(()),
+            }
+        });
+        match (reported, result) {
+            (Err(err), Ok(_)) => Err(err.context("failed to report the unused imports")),
+            (Err(err), Err(run_err)) => {
+                warning!("failed to report the unused imports: {}", err);
+                Err(run_err)
+            }
+            (Ok(()), result) => result,
+        }
+    }
 
+    /// Write the memory of `instance` to the `--dump-memory-on-exit` file,
+    /// once the module has run with `result`.
+    ///
+    /// If the module failed, its error is kept over the one of the dump.
+    fn dump_memory_on_exit<T>(&self, instance: &Instance, result: Result<T>) -> Result<T> {
+        let dump_path = match &self.dump_memory_on_exit {
+            Some(dump_path) => dump_path,
+            None => return result,
+        };
+        let dumped = memory_dump::dump_memory(
+            instance,
+            self.memory_index,
+            self.dump_memory_range,
+            dump_path,
+        );
+        match (dumped, result) {
+            (Err(err), Ok(_)) => Err(err.context("failed to dump the memory")),
+            (Err(err), Err(run_err)) => {
+                warning!("failed to dump the memory: {}", err);
+                Err(run_err)
+            }
+            (Ok(()), result) => result,
+        }
+    }
+
+    /// Get the program name passed to WASI as the first argument.
+    #[cfg(feature = "wasi")]
+    fn get_program_name(&self) -> String {
+        self.command_name
+            .clone()
+            .or_else(|| {
+                self.path
+                    .file_name()
+                    .map(|f| f.to_string_lossy().to_string())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Finishes the run given the exit code of the guest, checking it
+    /// against `--assert-exit` if it was provided.
+    fn exit_with_code(&self, exit_code: i32) -> Result<()> {
+        if let Some(expected_exit_code) = self.assert_exit {
+            if exit_code != expected_exit_code {
+                use colored::*;
+                eprintln!(
+                    "{}",
+                    format!(
+                        "{}: expected the module to exit with code {}, but it exited with code {}",
+                        "error".red(),
+                        expected_exit_code,
+                        exit_code
+                    )
+                    .bold()
+                );
+                std::process::exit(ASSERT_EXIT_MISMATCH_CODE);
+            }
+            return Ok(());
+        }
+        if exit_code != 0 {
+            // We should exit with the provided exit code
+            std::process::exit(exit_code);
+        }
+        Ok(())
+    }
+
+    /// Check that the flags rewriting or instrumenting the module at
+    /// compile time aren't used with a precompiled module.
+    fn check_precompiled_compatible(&self) -> Result<()> {
+        if self.trace_calls.is_some() {
+            bail!("`--trace-calls` can't be used with precompiled modules");
+        }
+        if self.time_limit_cpu.is_some() {
+            bail!("`--time-limit-cpu` can't be used with precompiled modules");
+        }
+        if self.interrupt_on_signal {
+            bail!("`--interrupt-on-signal` can't be used with precompiled modules");
+        }
+        if self.trap_on_grow {
+            bail!("`--trap-on-grow` can't be used with precompiled modules");
+        }
+        if self.metering_limit.is_some() {
+            bail!("`--metering-limit` can't be used with precompiled modules");
+        }
+        if self.counts_import_calls() {
+            bail!("`--report-unused-imports` and `--unused-imports-json` can't be used with precompiled modules");
+        }
         Ok(())
     }
 
     fn get_module(&self) -> Result<Module> {
         let contents = std::fs::read(self.path.clone())?;
         #[cfg(feature = "dylib")]
+        let precompiled = wasmer_engine_dylib::DylibArtifact::is_deserializable(&contents);
+        #[cfg(not(feature = "dylib"))]
+        let precompiled = false;
+        #[cfg(feature = "universal")]
+        let precompiled =
+            precompiled || wasmer_engine_universal::UniversalArtifact::is_deserializable(&contents);
+        if precompiled {
+            self.check_precompiled_compatible()?;
+        }
+        #[cfg(feature = "dylib")]
         {
             if wasmer_engine_dylib::DylibArtifact::is_deserializable(&contents) {
                 let engine = wasmer_engine_dylib::Dylib::headless().engine();
-                let store = Store::new(&engine);
+                let store = self.new_store(&engine, BaseTunables::for_target(engine.target()))?;
                 let module = unsafe { Module::deserialize_from_file(&store, &self.path)? };
                 return Ok(module);
             }
@@ -221,14 +1328,54 @@ impl Run {
         {
             if wasmer_engine_universal::UniversalArtifact::is_deserializable(&contents) {
                 let engine = wasmer_engine_universal::Universal::headless().engine();
-                let store = Store::new(&engine);
-                let module = unsafe { Module::deserialize_from_file(&store, &self.path)? };
+                let store = self.new_store(&engine, BaseTunables::for_target(engine.target()))?;
+                let module = match &self.debug_file {
+                    Some(debug_file) => {
+                        let debug = std::fs::read(debug_file).with_context(|| {
+                            format!("failed to read debug file `{}`", debug_file.display())
+                        })?;
+                        unsafe {
+                            let contents =
+                                wasmer_engine_universal::UniversalArtifact::merge_debug_info(
+                                    &contents, &debug,
+                                )?;
+                            Module::deserialize(&store, &contents)?
+                        }
+                    }
+                    None => unsafe { Module::deserialize_from_file(&store, &self.path)? },
+                };
                 return Ok(module);
             }
         }
-        let (store, engine_type, compiler_type) = self.store.get_store()?;
+        if self.debug_file.is_some() {
+            bail!("`--debug-file` can only be used with modules precompiled with the Universal engine");
+        }
+        let contents = match &self.trace_calls {
+            Some(pattern) => {
+                #[cfg(feature = "wat")]
+                let contents = wat2wasm(&contents)?;
+                trace_calls::instrument(&contents, pattern)?
+            }
+            None => contents,
+        };
+        let (store, engine_type, compiler_type) = self.get_store()?;
+        let store = if self.memory_file.is_some() || self.alloc_profile.is_some() {
+            self.new_store(
+                &**store.engine(),
+                BaseTunables::for_target(store.engine().target()),
+            )?
+        } else {
+            store
+        };
         #[cfg(feature = "cache")]
-        let module_result: Result<Module> = if !self.disable_cache && contents.len() > 0x1000 {
+        let module_result: Result<Module> = if !self.disable_cache
+            && self.trace_calls.is_none()
+            && !self.needs_interrupt_checks()
+            && !self.trap_on_grow
+            && self.metering_limit.is_none()
+            && !self.counts_import_calls()
+            && contents.len() > 0x1000
+        {
             self.get_module_from_cache(&store, &contents, &engine_type, &compiler_type)
         } else {
             Module::new(&store, &contents).map_err(|e| e.into())
@@ -249,6 +1396,81 @@ impl Run {
         Ok(module)
     }
 
+    /// Get the store to compile the module with, with the middlewares of
+    /// `--time-limit-cpu`, `--interrupt-on-signal`, `--trap-on-grow`,
+    /// `--metering-limit` and `--report-unused-imports` if they're set.
+    #[cfg(all(feature = "compiler", feature = "engine"))]
+    fn get_store(&self) -> Result<(Store, EngineType, CompilerType)> {
+        let mut middlewares: Vec<Arc<dyn ModuleMiddleware>> = vec![];
+        if self.needs_interrupt_checks() {
+            middlewares.push(Arc::new(cpu_time_limit::InterruptChecks::new()));
+        }
+        if self.trap_on_grow {
+            middlewares.push(Arc::new(trap_on_grow::TrapOnGrow::new()));
+        }
+        if let Some(limit) = self.metering_limit {
+            middlewares.push(metering::middleware(limit));
+        }
+        if self.counts_import_calls() {
+            middlewares.push(Arc::new(unused_imports::ImportCallCounters::new()));
+        }
+        self.store
+            .get_store_for_target_with_middlewares(Target::default(), middlewares)
+    }
+
+    #[cfg(not(all(feature = "compiler", feature = "engine")))]
+    fn get_store(&self) -> Result<(Store, EngineType, CompilerType)> {
+        if self.time_limit_cpu.is_some() {
+            bail!("`--time-limit-cpu` requires a compiler to add the interrupt checks");
+        }
+        if self.interrupt_on_signal {
+            bail!("`--interrupt-on-signal` requires a compiler to add the interrupt checks");
+        }
+        if self.trap_on_grow {
+            bail!("`--trap-on-grow` requires a compiler to replace `memory.grow`");
+        }
+        if self.metering_limit.is_some() {
+            bail!("`--metering-limit` requires a compiler to add the metering");
+        }
+        if self.counts_import_calls() {
+            bail!("`--report-unused-imports` requires a compiler to count the calls");
+        }
+        self.store.get_store()
+    }
+
+    /// Create a store with the given tunables, backing the memory of the
+    /// module with the `--memory-file` if provided.
+    fn new_store<E>(&self, engine: &E, tunables: BaseTunables) -> Result<Store>
+    where
+        E: Engine + ?Sized,
+    {
+        match &self.memory_file {
+            #[cfg(unix)]
+            Some(memory_file) => Ok(
+                self.new_profiled_store(engine, FileMemoryTunables::new(tunables, memory_file)?)
+            ),
+            #[cfg(not(unix))]
+            Some(_) => bail!("`--memory-file` is only supported on Unix"),
+            None => Ok(self.new_profiled_store(engine, tunables)),
+        }
+    }
+
+    /// Create a store with the given tunables, profiling the growth of the
+    /// memories if `--alloc-profile` is provided.
+    fn new_profiled_store<E, T>(&self, engine: &E, tunables: T) -> Store
+    where
+        E: Engine + ?Sized,
+        T: Tunables + Send + Sync + 'static,
+    {
+        match &self.alloc_profile {
+            Some(_) => Store::new_with_tunables(
+                engine,
+                AllocProfileTunables::new(tunables, self.alloc_profiler.clone()),
+            ),
+            None => Store::new_with_tunables(engine, tunables),
+        }
+    }
+
     #[cfg(feature = "cache")]
     fn get_module_from_cache(
         &self,
@@ -370,6 +1592,25 @@ impl Run {
             .clone())
     }
 
+    /// Write the `--set-global` values in the exported globals of the
+    /// instance.
+    fn set_globals(&self, instance: &Instance) -> Result<()> {
+        for assignment in &self.set_globals {
+            let global = instance
+                .exports
+                .get_global(&assignment.name)
+                .map_err(|_| anyhow!("the module has no exported global `{}`", assignment.name))?;
+            let ty = global.ty();
+            if ty.mutability == Mutability::Const {
+                bail!("the global `{}` isn't mutable", assignment.name);
+            }
+            let value = parse_value(&assignment.value, &ty.ty)
+                .with_context(|| format!("can't set the global `{}`", assignment.name))?;
+            global.set(value)?;
+        }
+        Ok(())
+    }
+
     fn invoke_function(
         &self,
         instance: &Instance,
@@ -391,34 +1632,35 @@ impl Run {
         let invoke_args = args
             .iter()
             .zip(func_ty.params().iter())
-            .map(|(arg, param_type)| match param_type {
-                ValType::I32 => {
-                    Ok(Val::I32(arg.parse().map_err(|_| {
-                        anyhow!("Can't convert `{}` into a i32", arg)
-                    })?))
-                }
-                ValType::I64 => {
-                    Ok(Val::I64(arg.parse().map_err(|_| {
-                        anyhow!("Can't convert `{}` into a i64", arg)
-                    })?))
-                }
-                ValType::F32 => {
-                    Ok(Val::F32(arg.parse().map_err(|_| {
-                        anyhow!("Can't convert `{}` into a f32", arg)
-                    })?))
-                }
-                ValType::F64 => {
-                    Ok(Val::F64(arg.parse().map_err(|_| {
-                        anyhow!("Can't convert `{}` into a f64", arg)
-                    })?))
-                }
-                _ => Err(anyhow!(
-                    "Don't know how to convert {} into {:?}",
-                    arg,
-                    param_type
-                )),
-            })
+            .map(|(arg, param_type)| parse_value(arg, param_type))
             .collect::<Result<Vec<_>>>()?;
         Ok(func.call(&invoke_args)?)
     }
 }
+
+/// Parse a value of the given type from the command line.
+fn parse_value(arg: &str, ty: &ValType) -> Result<Val> {
+    match ty {
+        ValType::I32 => {
+            Ok(Val::I32(arg.parse().map_err(|_| {
+                anyhow!("Can't convert `{}` into a i32", arg)
+            })?))
+        }
+        ValType::I64 => {
+            Ok(Val::I64(arg.parse().map_err(|_| {
+                anyhow!("Can't convert `{}` into a i64", arg)
+            })?))
+        }
+        ValType::F32 => {
+            Ok(Val::F32(arg.parse().map_err(|_| {
+                anyhow!("Can't convert `{}` into a f32", arg)
+            })?))
+        }
+        ValType::F64 => {
+            Ok(Val::F64(arg.parse().map_err(|_| {
+                anyhow!("Can't convert `{}` into a f64", arg)
+            })?))
+        }
+        _ => Err(anyhow!("Don't know how to convert {} into {:?}", arg, ty)),
+    }
+}
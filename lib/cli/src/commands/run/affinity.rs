@@ -0,0 +1,94 @@
+//! Pin the threads running a module to CPUs, used by
+//! `wasmer run --cpu-affinity`.
+
+use anyhow::{Context, Result};
+use std::str::FromStr;
+
+/// Whether threads can be pinned on this platform.
+pub const SUPPORTED: bool = cfg!(any(target_os = "linux", windows));
+
+/// A list of CPU indices, like `0,2` or `0-3,6`.
+#[derive(Debug, Clone)]
+pub struct CpuList(pub Vec<usize>);
+
+impl FromStr for CpuList {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_cpu = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .with_context(|| format!("invalid CPU `{}` in `{}`", cpu, s))
+        };
+        let mut cpus = vec![];
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse_cpu(first)?, parse_cpu(last)?);
+                    if first > last {
+                        bail!("invalid CPU range `{}` in `{}`", part, s);
+                    }
+                    cpus.extend(first..=last);
+                }
+                None => cpus.push(parse_cpu(part)?),
+            }
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        Ok(Self(cpus))
+    }
+}
+
+/// Restrict the current thread to run on the given CPUs.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpus: &[usize]) -> Result<()> {
+    use std::{io, mem};
+
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        for &cpu in cpus {
+            if cpu >= mem::size_of::<libc::cpu_set_t>() * 8 {
+                bail!("CPU {} is out of range", cpu);
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            bail!(
+                "failed to pin the thread to CPUs {:?}: {}",
+                cpus,
+                io::Error::last_os_error()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Restrict the current thread to run on the given CPUs.
+#[cfg(windows)]
+pub fn pin_current_thread(cpus: &[usize]) -> Result<()> {
+    use std::{io, mem};
+    use winapi::um::processthreadsapi::GetCurrentThread;
+    use winapi::um::winbase::SetThreadAffinityMask;
+
+    let mut mask = 0usize;
+    for &cpu in cpus {
+        if cpu >= mem::size_of::<usize>() * 8 {
+            bail!("CPU {} is out of range", cpu);
+        }
+        mask |= 1 << cpu;
+    }
+    if unsafe { SetThreadAffinityMask(GetCurrentThread(), mask) } == 0 {
+        bail!(
+            "failed to pin the thread to CPUs {:?}: {}",
+            cpus,
+            io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+/// Threads can't be pinned on this platform, see [`SUPPORTED`].
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn pin_current_thread(_cpus: &[usize]) -> Result<()> {
+    Ok(())
+}
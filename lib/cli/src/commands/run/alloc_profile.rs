@@ -0,0 +1,246 @@
+//! Profile the growth of the linear memories of a module, for `wasmer run
+//! --alloc-profile`.
+//!
+//! The memories defined by the module are created by tunables wrapping
+//! them, recording every `memory.grow` with the time since the module
+//! started and the size before and after it, and the peak size of every
+//! memory. Nothing is added to the compiled code: only the growths, which
+//! already call into the runtime, are hooked. `memory.grow 0`, which only
+//! returns the size of the memory, isn't recorded.
+
+use anyhow::{Context, Result};
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use serde::Serialize;
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use wasmer::vm::{
+    Memory, MemoryError, MemoryStyle, Table, TableStyle, VMMemoryDefinition, VMTableDefinition,
+};
+use wasmer::{MemoryType, Pages, TableType, Tunables};
+
+/// The profile of the memories of a run, shared by the clones.
+#[derive(Debug, Clone, Default)]
+pub struct AllocProfiler(Arc<Mutex<AllocProfile>>);
+
+impl AllocProfiler {
+    /// Forget the memories of the previous runs, and start measuring the
+    /// time of the growths.
+    pub fn start(&self) {
+        *self.0.lock().unwrap() = AllocProfile {
+            start: Some(Instant::now()),
+            ..AllocProfile::default()
+        };
+    }
+
+    /// Record a new memory, returning its index in the profile.
+    fn add_memory(&self, ty: &MemoryType, size: Pages) -> usize {
+        let mut profile = self.0.lock().unwrap();
+        profile.memories.push(MemoryProfile {
+            initial_pages: size.0,
+            maximum_pages: ty.maximum.map(|maximum| maximum.0),
+            peak_pages: size.0,
+            peak_bytes: size.bytes().0 as u64,
+            grows: 0,
+            failed_grows: 0,
+        });
+        profile.memories.len() - 1
+    }
+
+    /// Record a growth of the memory at `index`, from `previous` to
+    /// `new` pages.
+    fn record_grow(&self, index: usize, delta: Pages, previous: Pages, new: Pages, failed: bool) {
+        let mut profile = self.0.lock().unwrap();
+        let time_seconds = profile
+            .start
+            .map_or(0.0, |start| start.elapsed().as_secs_f64());
+        profile.events.push(GrowEvent {
+            time_seconds,
+            memory: index,
+            delta_pages: delta.0,
+            previous_pages: previous.0,
+            new_pages: new.0,
+            failed,
+        });
+        let memory = &mut profile.memories[index];
+        if failed {
+            memory.failed_grows += 1;
+        } else {
+            memory.grows += 1;
+        }
+        if new.0 > memory.peak_pages {
+            memory.peak_pages = new.0;
+            memory.peak_bytes = new.bytes().0 as u64;
+        }
+    }
+
+    /// Write the profile as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut profile = self.0.lock().unwrap();
+        profile.peak_bytes = profile
+            .memories
+            .iter()
+            .map(|memory| memory.peak_bytes)
+            .sum();
+        fs::write(path, serde_json::to_string_pretty(&*profile)?)
+            .with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
+
+/// The JSON written by `--alloc-profile`.
+#[derive(Debug, Default, Serialize)]
+struct AllocProfile {
+    #[serde(skip)]
+    start: Option<Instant>,
+    /// The sum of the peak sizes of the memories.
+    peak_bytes: u64,
+    memories: Vec<MemoryProfile>,
+    /// The growths of the memories, in order.
+    events: Vec<GrowEvent>,
+}
+
+/// The sizes of a memory, in Wasm pages of 64 KiB.
+#[derive(Debug, Serialize)]
+struct MemoryProfile {
+    initial_pages: u32,
+    /// `None` when the module doesn't declare a maximum.
+    maximum_pages: Option<u32>,
+    /// The high-water mark of the memory.
+    peak_pages: u32,
+    peak_bytes: u64,
+    grows: usize,
+    /// The growths that failed, like beyond the maximum of the memory.
+    failed_grows: usize,
+}
+
+/// A `memory.grow` of the module.
+#[derive(Debug, Serialize)]
+struct GrowEvent {
+    /// The time since the module started.
+    time_seconds: f64,
+    /// The index of the memory in `memories`.
+    memory: usize,
+    delta_pages: u32,
+    previous_pages: u32,
+    new_pages: u32,
+    failed: bool,
+}
+
+/// Tunables profiling the memories created by the base tunables.
+pub struct AllocProfileTunables<T> {
+    base: T,
+    profiler: AllocProfiler,
+}
+
+impl<T> AllocProfileTunables<T> {
+    pub fn new(base: T, profiler: AllocProfiler) -> Self {
+        Self { base, profiler }
+    }
+}
+
+impl<T: MemoryUsage> MemoryUsage for AllocProfileTunables<T> {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.base.size_of_val(tracker) - mem::size_of_val(&self.base)
+    }
+}
+
+impl<T: Tunables> Tunables for AllocProfileTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        self.base.memory_style(memory)
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        self.base.create_host_memory(ty, style)
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        let memory = self
+            .base
+            .create_vm_memory(ty, style, vm_definition_location)?;
+        let index = self.profiler.add_memory(ty, memory.size());
+        Ok(Arc::new(ProfiledMemory {
+            memory,
+            index,
+            profiler: self.profiler.clone(),
+        }))
+    }
+
+    fn create_host_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+    ) -> Result<Arc<dyn Table>, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<Arc<dyn Table>, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
+
+/// A memory recording its growths in the profile.
+#[derive(Debug)]
+struct ProfiledMemory {
+    memory: Arc<dyn Memory>,
+    /// The index of the memory in the profile.
+    index: usize,
+    profiler: AllocProfiler,
+}
+
+impl MemoryUsage for ProfiledMemory {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + MemoryUsage::size_of_val(&*self.memory, tracker)
+    }
+}
+
+impl Memory for ProfiledMemory {
+    fn ty(&self) -> MemoryType {
+        self.memory.ty()
+    }
+
+    fn style(&self) -> &MemoryStyle {
+        self.memory.style()
+    }
+
+    fn size(&self) -> Pages {
+        self.memory.size()
+    }
+
+    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+        let result = self.memory.grow(delta);
+        if delta.0 != 0 {
+            let (previous, new) = match &result {
+                Ok(previous) => (*previous, self.memory.size()),
+                Err(_) => (self.memory.size(), self.memory.size()),
+            };
+            self.profiler
+                .record_grow(self.index, delta, previous, new, result.is_err());
+        }
+        result
+    }
+
+    fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
+        self.memory.vmmemory()
+    }
+}
@@ -0,0 +1,137 @@
+//! Capture the stdout and stderr of a WASI module, for `wasmer run
+//! --capture-output`.
+//!
+//! The streams are collected in memory instead of being written to the
+//! terminal, and written to a JSON file with the exit code once the module
+//! has run. A stream that is valid UTF-8 is written as `{"utf8": "..."}`,
+//! any other as `{"base64": "..."}`, so binary output survives.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use wasmer_vfs::{FsError, VirtualFile};
+
+/// The stdout and stderr written by the module, shared by the clones.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput {
+    stdout: Arc<Mutex<Vec<u8>>>,
+    stderr: Arc<Mutex<Vec<u8>>>,
+}
+
+impl CapturedOutput {
+    /// Forget the streams of the previous runs.
+    pub fn clear(&self) {
+        self.stdout.lock().unwrap().clear();
+        self.stderr.lock().unwrap().clear();
+    }
+
+    /// The WASI stdout, capturing what the module writes.
+    pub fn stdout(&self) -> Box<dyn VirtualFile> {
+        Box::new(CaptureFile(self.stdout.clone()))
+    }
+
+    /// The WASI stderr, capturing what the module writes.
+    pub fn stderr(&self) -> Box<dyn VirtualFile> {
+        Box::new(CaptureFile(self.stderr.clone()))
+    }
+
+    /// Write the streams and the outcome of the run to `path`: the exit
+    /// code of the module, or the error it failed with.
+    pub fn write(&self, path: &Path, result: &Result<i32>) -> Result<()> {
+        let report = CaptureReport {
+            exit_code: result.as_ref().ok().copied(),
+            error: result.as_ref().err().map(|err| format!("{:#}", err)),
+            stdout: CapturedStream::new(&self.stdout.lock().unwrap()),
+            stderr: CapturedStream::new(&self.stderr.lock().unwrap()),
+        };
+        fs::write(path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
+
+/// The JSON written by `--capture-output`.
+#[derive(Debug, Serialize)]
+struct CaptureReport {
+    /// The exit code of the module, if it didn't fail.
+    exit_code: Option<i32>,
+    error: Option<String>,
+    stdout: CapturedStream,
+    stderr: CapturedStream,
+}
+
+/// A captured stream, as text when it's valid UTF-8.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CapturedStream {
+    Utf8(String),
+    Base64(String),
+}
+
+impl CapturedStream {
+    fn new(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Self::Utf8(text.to_string()),
+            Err(_) => Self::Base64(base64::encode(bytes)),
+        }
+    }
+}
+
+/// A write-only WASI file appending to a captured stream.
+#[derive(Debug)]
+struct CaptureFile(Arc<Mutex<Vec<u8>>>);
+
+impl Read for CaptureFile {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not read from a captured stream",
+        ))
+    }
+}
+
+impl Write for CaptureFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for CaptureFile {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "can not seek in a captured stream",
+        ))
+    }
+}
+
+impl VirtualFile for CaptureFile {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    fn created_time(&self) -> u64 {
+        0
+    }
+    fn size(&self) -> u64 {
+        0
+    }
+    fn set_len(&mut self, _new_size: u64) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+    fn unlink(&mut self) -> Result<(), FsError> {
+        Ok(())
+    }
+    fn bytes_available(&self) -> Result<usize, FsError> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,222 @@
+//! Interrupt a module once it has used too much CPU time, for
+//! `wasmer run --time-limit-cpu`.
+//!
+//! The compilers can't stop a running function from the outside, so the
+//! module is compiled with interrupt checks: a middleware adds an
+//! exported global, and checks it before every call and at the start of
+//! every loop iteration, trapping with `unreachable` once it's set. The
+//! checks are inserted by a middleware, so they work with every compiler,
+//! but precompiled modules don't have them.
+//!
+//! A watchdog thread compares the CPU time of the process, which includes
+//! the time spent in the host functions and in the kernel, with the limit
+//! and sets the global once it's exceeded. The time is read with
+//! `clock_gettime(CLOCK_PROCESS_CPUTIME_ID)` on Unix and
+//! `GetProcessTimes` on Windows, every few milliseconds.
+
+use anyhow::Result;
+#[cfg(feature = "compiler")]
+pub use middleware::InterruptChecks;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use wasmer::{Instance, Val};
+
+/// The exported global interrupting the module when set to `1`.
+pub(super) const INTERRUPT_GLOBAL: &str = "wasmer_cpu_time_interrupt";
+
+/// How often the watchdog reads the CPU time of the process.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[cfg(feature = "compiler")]
+mod middleware {
+    use super::INTERRUPT_GLOBAL;
+    use loupe::{MemoryUsage, MemoryUsageTracker};
+    use std::mem;
+    use std::sync::Mutex;
+    use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType};
+    use wasmer::{
+        FunctionMiddleware, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState,
+        ModuleMiddleware,
+    };
+    use wasmer_types::{
+        ExportIndex, GlobalIndex, GlobalInit, GlobalType, ModuleInfo, Mutability, Type,
+    };
+
+    /// The module-level middleware adding the interrupt global.
+    #[derive(Debug, Default)]
+    pub struct InterruptChecks {
+        interrupt_global: Mutex<Option<GlobalIndex>>,
+    }
+
+    impl InterruptChecks {
+        /// Creates an `InterruptChecks` middleware.
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl MemoryUsage for InterruptChecks {
+        fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+            mem::size_of_val(self)
+        }
+    }
+
+    impl ModuleMiddleware for InterruptChecks {
+        fn generate_function_middleware(
+            &self,
+            _: LocalFunctionIndex,
+        ) -> Box<dyn FunctionMiddleware> {
+            Box::new(FunctionInterruptChecks {
+                interrupt_global: self.interrupt_global.lock().unwrap().unwrap(),
+            })
+        }
+
+        fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+            let interrupt_global = module_info
+                .globals
+                .push(GlobalType::new(Type::I32, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::I32Const(0));
+            module_info.exports.insert(
+                INTERRUPT_GLOBAL.to_string(),
+                ExportIndex::Global(interrupt_global),
+            );
+            *self.interrupt_global.lock().unwrap() = Some(interrupt_global);
+        }
+    }
+
+    /// The function-level middleware inserting the checks.
+    #[derive(Debug)]
+    struct FunctionInterruptChecks {
+        interrupt_global: GlobalIndex,
+    }
+
+    impl FunctionInterruptChecks {
+        /// Trap if the interrupt global is set.
+        fn check(&self, state: &mut MiddlewareReaderState) {
+            state.extend(&[
+                Operator::GlobalGet {
+                    global_index: self.interrupt_global.as_u32(),
+                },
+                Operator::If {
+                    ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+                },
+                Operator::Unreachable,
+                Operator::End,
+            ]);
+        }
+    }
+
+    impl FunctionMiddleware for FunctionInterruptChecks {
+        fn feed<'a>(
+            &mut self,
+            operator: Operator<'a>,
+            state: &mut MiddlewareReaderState<'a>,
+        ) -> Result<(), MiddlewareError> {
+            match operator {
+                Operator::Call { .. } | Operator::CallIndirect { .. } => {
+                    self.check(state);
+                    state.push_operator(operator);
+                }
+                // Checked at the start of the body, so once per iteration
+                Operator::Loop { .. } => {
+                    state.push_operator(operator);
+                    self.check(state);
+                }
+                _ => state.push_operator(operator),
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Interrupts an instance once the process has used more than its CPU
+/// time limit since the watchdog started, until it's dropped.
+pub struct CpuTimeWatchdog {
+    stop: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CpuTimeWatchdog {
+    /// Start watching `instance`, setting `exceeded` when interrupting it.
+    pub fn start(instance: &Instance, limit: Duration, exceeded: Arc<AtomicBool>) -> Result<Self> {
+        let interrupt = instance.exports.get_global(INTERRUPT_GLOBAL)?.clone();
+        let start = process_cpu_time()?;
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || loop {
+            let used = process_cpu_time()
+                .map(|now| now.saturating_sub(start))
+                .unwrap_or_default();
+            if used >= limit {
+                exceeded.store(true, Ordering::SeqCst);
+                let _ = interrupt.set(Val::I32(1));
+                return;
+            }
+            match stopped.recv_timeout(POLL_INTERVAL.min(limit - used)) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+        });
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for CpuTimeWatchdog {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The CPU time used by all the threads of the process.
+#[cfg(unix)]
+fn process_cpu_time() -> Result<Duration> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut time) } != 0 {
+        bail!(
+            "failed to read the CPU time of the process: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+/// The CPU time used by all the threads of the process, in user and
+/// kernel mode.
+#[cfg(windows)]
+fn process_cpu_time() -> Result<Duration> {
+    use winapi::shared::minwindef::FILETIME;
+    use winapi::um::processthreadsapi::{GetCurrentProcess, GetProcessTimes};
+
+    let mut times = [FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    }; 4];
+    let [creation, exit, kernel, user] = &mut times;
+    if unsafe { GetProcessTimes(GetCurrentProcess(), creation, exit, kernel, user) } == 0 {
+        bail!(
+            "failed to read the CPU time of the process: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    // In units of 100 nanoseconds
+    let ticks = |time: &FILETIME| (time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64;
+    Ok(Duration::from_nanos((ticks(kernel) + ticks(user)) * 100))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn process_cpu_time() -> Result<Duration> {
+    bail!("`--time-limit-cpu` is only supported on Unix and Windows")
+}
@@ -0,0 +1,160 @@
+//! Deny WASI syscalls to a module, for `wasmer run --deny-syscall`.
+//!
+//! The syscalls are named after their `wasi_snapshot_preview1` imports,
+//! like `fd_write` or `clock_time_get`, which are also their names in
+//! `wasi_unstable`. A denied syscall doesn't reach the host: it returns an
+//! error to the module (`ENOSYS` by default), or traps with `--deny-mode
+//! trap`. `proc_exit` returns nothing, so it can only be denied by
+//! trapping.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use wasmer::{Exports, ExternType, Function, ImportObject, Module, RuntimeError, Type, Val};
+use wasmer_wasi::types::{
+    __wasi_errno_t, __WASI_EACCES, __WASI_ENOSYS, __WASI_ENOTCAPABLE, __WASI_EPERM,
+};
+
+/// The canonical names of the WASI syscalls.
+pub const WASI_SYSCALLS: &[&str] = &[
+    "args_get",
+    "args_sizes_get",
+    "clock_res_get",
+    "clock_time_get",
+    "environ_get",
+    "environ_sizes_get",
+    "fd_advise",
+    "fd_allocate",
+    "fd_close",
+    "fd_datasync",
+    "fd_fdstat_get",
+    "fd_fdstat_set_flags",
+    "fd_fdstat_set_rights",
+    "fd_filestat_get",
+    "fd_filestat_set_size",
+    "fd_filestat_set_times",
+    "fd_pread",
+    "fd_prestat_dir_name",
+    "fd_prestat_get",
+    "fd_pwrite",
+    "fd_read",
+    "fd_readdir",
+    "fd_renumber",
+    "fd_seek",
+    "fd_sync",
+    "fd_tell",
+    "fd_write",
+    "path_create_directory",
+    "path_filestat_get",
+    "path_filestat_set_times",
+    "path_link",
+    "path_open",
+    "path_readlink",
+    "path_remove_directory",
+    "path_rename",
+    "path_symlink",
+    "path_unlink_file",
+    "poll_oneoff",
+    "proc_exit",
+    "proc_raise",
+    "random_get",
+    "sched_yield",
+    "sock_recv",
+    "sock_send",
+    "sock_shutdown",
+];
+
+/// Parse the name of a WASI syscall, for `--deny-syscall`.
+pub fn parse_syscall(name: &str) -> Result<String> {
+    if !WASI_SYSCALLS.contains(&name) {
+        bail!(
+            "unknown WASI syscall `{}`, expected one of: {}",
+            name,
+            WASI_SYSCALLS.join(", ")
+        );
+    }
+    Ok(name.to_string())
+}
+
+/// What a denied syscall does, set with `--deny-mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenyMode {
+    /// Return this error to the module.
+    Error(__wasi_errno_t),
+    /// Trap, stopping the module.
+    Trap,
+}
+
+impl Default for DenyMode {
+    fn default() -> Self {
+        Self::Error(__WASI_ENOSYS)
+    }
+}
+
+impl FromStr for DenyMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" | "enosys" => Ok(Self::Error(__WASI_ENOSYS)),
+            "eperm" => Ok(Self::Error(__WASI_EPERM)),
+            "eacces" => Ok(Self::Error(__WASI_EACCES)),
+            "enotcapable" => Ok(Self::Error(__WASI_ENOTCAPABLE)),
+            "trap" => Ok(Self::Trap),
+            _ => bail!(
+                "unknown deny mode `{}`, expected `error`, `enosys`, `eperm`, `eacces`, `enotcapable` or `trap`",
+                s
+            ),
+        }
+    }
+}
+
+/// Generate the imports replacing the `denied` syscalls imported by
+/// `module`.
+pub fn generate_import_object(
+    module: &Module,
+    denied: &[String],
+    mode: DenyMode,
+) -> Result<ImportObject> {
+    let mut namespaces = BTreeMap::new();
+    for import in module.imports() {
+        let ty = match import.ty() {
+            ExternType::Function(ty) => ty.clone(),
+            _ => continue,
+        };
+        if !import.module().starts_with("wasi") || !denied.iter().any(|name| name == import.name())
+        {
+            continue;
+        }
+        let name = import.name().to_string();
+        let function = match mode {
+            DenyMode::Error(errno) => {
+                if ty.results() != [Type::I32] {
+                    bail!(
+                        "`{}` doesn't return an error, it can only be denied with `--deny-mode trap`",
+                        name
+                    );
+                }
+                Function::new(module.store(), ty, move |_| {
+                    Ok(vec![Val::I32(errno as i32)])
+                })
+            }
+            DenyMode::Trap => Function::new(module.store(), ty, move |_| {
+                Err(RuntimeError::new(format!(
+                    "the module called the WASI syscall `{}`, denied by `--deny-syscall`",
+                    name
+                )))
+            }),
+        };
+        namespaces
+            .entry(import.module().to_string())
+            .or_insert_with(Exports::new)
+            .insert(import.name(), function);
+    }
+
+    let mut import_object = ImportObject::new();
+    for (name, namespace) in namespaces {
+        import_object.register(name, namespace);
+    }
+    Ok(import_object)
+}
@@ -0,0 +1,174 @@
+//! Deterministic replacements for the nondeterministic WASI syscalls,
+//! used by `wasmer run --deterministic-wasi`.
+//!
+//! Only the following syscalls are replaced, in every WASI namespace
+//! imported by the module:
+//!
+//! * `clock_time_get` always returns the same timestamp for every clock:
+//!   `SOURCE_DATE_EPOCH` (in seconds) if set, or `0` otherwise.
+//! * `clock_res_get` always returns a resolution of 1 nanosecond.
+//! * `random_get` fills the buffer from a pseudo-random generator with a
+//!   fixed seed, so the same sequence of bytes is returned on every run.
+//!
+//! `wasmer run --seed` replaces only `random_get`, seeded with its value
+//! (which also replaces the fixed seed of `--deterministic-wasi`).
+//!
+//! This is meant for reproducible tests of guest programs, not for
+//! production: the clock never advances and the random bytes are
+//! predictable.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::env;
+use std::sync::{Arc, Mutex};
+use wasmer::{Array, Exports, Function, ImportObject, LazyInit, Memory, Store, WasmPtr, WasmerEnv};
+use wasmer_wasi::types::{
+    __wasi_clockid_t, __wasi_errno_t, __wasi_timestamp_t, __WASI_EFAULT, __WASI_ESUCCESS,
+};
+use wasmer_wasi::WasiVersion;
+
+/// The default seed of the generator used by `random_get`.
+const RANDOM_SEED: u64 = 0x5741_534d_4552_5741;
+
+#[derive(WasmerEnv, Clone)]
+struct DeterministicEnv {
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+    /// The fixed time returned by `clock_time_get`, in nanoseconds.
+    time: __wasi_timestamp_t,
+    /// The state of the generator used by `random_get`.
+    random_state: Arc<Mutex<u64>>,
+}
+
+impl DeterministicEnv {
+    fn memory(&self) -> &Memory {
+        self.memory_ref()
+            .expect("Memory should be set on `DeterministicEnv` first")
+    }
+
+    /// The next value of a SplitMix64 generator.
+    fn next_random(&self) -> u64 {
+        let mut state = self.random_state.lock().unwrap();
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+fn clock_res_get(
+    env: &DeterministicEnv,
+    _clock_id: __wasi_clockid_t,
+    resolution: WasmPtr<__wasi_timestamp_t>,
+) -> __wasi_errno_t {
+    match resolution.deref(env.memory()) {
+        Some(resolution) => {
+            resolution.set(1);
+            __WASI_ESUCCESS
+        }
+        None => __WASI_EFAULT,
+    }
+}
+
+fn clock_time_get(
+    env: &DeterministicEnv,
+    _clock_id: __wasi_clockid_t,
+    _precision: __wasi_timestamp_t,
+    time: WasmPtr<__wasi_timestamp_t>,
+) -> __wasi_errno_t {
+    match time.deref(env.memory()) {
+        Some(time) => {
+            time.set(env.time);
+            __WASI_ESUCCESS
+        }
+        None => __WASI_EFAULT,
+    }
+}
+
+fn random_get(env: &DeterministicEnv, buf: WasmPtr<u8, Array>, buf_len: u32) -> __wasi_errno_t {
+    let cells = match buf.deref(env.memory(), 0, buf_len) {
+        Some(cells) => cells,
+        None => return __WASI_EFAULT,
+    };
+    for chunk in cells.chunks(8) {
+        let bytes = env.next_random().to_le_bytes();
+        for (cell, byte) in chunk.iter().zip(bytes.iter()) {
+            cell.set(*byte);
+        }
+    }
+    __WASI_ESUCCESS
+}
+
+/// The time returned by the deterministic clock, in nanoseconds.
+fn fixed_time() -> Result<__wasi_timestamp_t> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => {
+            let seconds: u64 = epoch
+                .parse()
+                .with_context(|| format!("invalid `SOURCE_DATE_EPOCH`: `{}`", epoch))?;
+            seconds
+                .checked_mul(1_000_000_000)
+                .context("`SOURCE_DATE_EPOCH` is too large")
+        }
+        Err(_) => Ok(0),
+    }
+}
+
+/// Generate the imports overriding the nondeterministic syscalls of the
+/// given WASI versions, with `seed` (or a fixed one) for `random_get`.
+pub fn generate_import_object(
+    store: &Store,
+    wasi_versions: &BTreeSet<WasiVersion>,
+    seed: Option<u64>,
+) -> Result<ImportObject> {
+    let env = DeterministicEnv {
+        memory: LazyInit::new(),
+        time: fixed_time()?,
+        random_state: Arc::new(Mutex::new(seed.unwrap_or(RANDOM_SEED))),
+    };
+
+    let mut import_object = ImportObject::new();
+    for version in wasi_versions {
+        let mut namespace = Exports::new();
+        namespace.insert(
+            "clock_res_get",
+            Function::new_native_with_env(store, env.clone(), clock_res_get),
+        );
+        namespace.insert(
+            "clock_time_get",
+            Function::new_native_with_env(store, env.clone(), clock_time_get),
+        );
+        namespace.insert(
+            "random_get",
+            Function::new_native_with_env(store, env.clone(), random_get),
+        );
+        import_object.register(version.get_namespace_str(), namespace);
+    }
+    Ok(import_object)
+}
+
+/// Generate the imports overriding only `random_get` of the given WASI
+/// versions, with a generator seeded with `seed`.
+pub fn generate_seeded_import_object(
+    store: &Store,
+    wasi_versions: &BTreeSet<WasiVersion>,
+    seed: u64,
+) -> ImportObject {
+    let env = DeterministicEnv {
+        memory: LazyInit::new(),
+        time: 0,
+        random_state: Arc::new(Mutex::new(seed)),
+    };
+
+    let mut import_object = ImportObject::new();
+    for version in wasi_versions {
+        let mut namespace = Exports::new();
+        namespace.insert(
+            "random_get",
+            Function::new_native_with_env(store, env.clone(), random_get),
+        );
+        import_object.register(version.get_namespace_str(), namespace);
+    }
+    import_object
+}
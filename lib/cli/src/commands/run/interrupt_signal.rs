@@ -0,0 +1,165 @@
+//! Interrupt a module when `wasmer` receives `SIGINT` or `SIGTERM`, for
+//! `wasmer run --interrupt-on-signal`.
+//!
+//! The module is compiled with the interrupt checks of `--time-limit-cpu`.
+//! The signal handler only records the signal: a thread polling it sets
+//! the interrupt global, so the module traps at its next check and the
+//! instance is dropped as after any trap. A second signal received while
+//! the module is being interrupted, like when it's blocked in a host
+//! function, gets the default behavior, killing `wasmer`.
+//!
+//! On Windows, the console control events (Ctrl-C, Ctrl-Break and closing
+//! the console) are handled instead, and reported as `SIGINT`.
+
+use super::cpu_time_limit::INTERRUPT_GLOBAL;
+use anyhow::Result;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use wasmer::{Instance, Val};
+
+/// The signal numbers, which are the same on the supported Unix systems.
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+
+/// How often the interrupting thread checks for a signal.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The signal received by the process, `0` until one is.
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// The name of `signal`, like `SIGINT`.
+pub fn signal_name(signal: i32) -> String {
+    match signal {
+        SIGINT => "SIGINT".to_string(),
+        SIGTERM => "SIGTERM".to_string(),
+        _ => format!("signal {}", signal),
+    }
+}
+
+/// Interrupts an instance once the process receives `SIGINT` or
+/// `SIGTERM`, until it's dropped.
+pub struct SignalInterrupter {
+    stop: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SignalInterrupter {
+    /// Start watching the signals for `instance`, storing the signal in
+    /// `received` when interrupting it.
+    pub fn start(instance: &Instance, received: Arc<AtomicI32>) -> Result<Self> {
+        let interrupt = instance.exports.get_global(INTERRUPT_GLOBAL)?.clone();
+        RECEIVED_SIGNAL.store(0, Ordering::SeqCst);
+        install_handlers()?;
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || loop {
+            let signal = RECEIVED_SIGNAL.load(Ordering::SeqCst);
+            if signal != 0 {
+                received.store(signal, Ordering::SeqCst);
+                let _ = interrupt.set(Val::I32(1));
+                return;
+            }
+            match stopped.recv_timeout(POLL_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+        });
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for SignalInterrupter {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        restore_handlers();
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if RECEIVED_SIGNAL.swap(signal, Ordering::SeqCst) != 0 {
+        // `signal` and `raise` are async-signal-safe
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn install_handlers() -> Result<()> {
+    for signal in &[libc::SIGINT, libc::SIGTERM] {
+        let handler = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(*signal, handler) } == libc::SIG_ERR {
+            bail!(
+                "failed to handle {}: {}",
+                signal_name(*signal),
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restore_handlers() {
+    for signal in &[libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            libc::signal(*signal, libc::SIG_DFL);
+        }
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handle_console_event(
+    event: winapi::shared::minwindef::DWORD,
+) -> winapi::shared::minwindef::BOOL {
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT};
+
+    match event {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT => {
+            // Let the default handler terminate the process on the
+            // second event
+            (RECEIVED_SIGNAL.swap(SIGINT, Ordering::SeqCst) == 0) as _
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(windows)]
+fn install_handlers() -> Result<()> {
+    use winapi::um::consoleapi::SetConsoleCtrlHandler;
+
+    if unsafe { SetConsoleCtrlHandler(Some(handle_console_event), 1) } == 0 {
+        bail!(
+            "failed to handle the console events: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn restore_handlers() {
+    use winapi::um::consoleapi::SetConsoleCtrlHandler;
+
+    unsafe {
+        SetConsoleCtrlHandler(Some(handle_console_event), 0);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn install_handlers() -> Result<()> {
+    bail!("`--interrupt-on-signal` is only supported on Unix and Windows")
+}
+
+#[cfg(not(any(unix, windows)))]
+fn restore_handlers() {}
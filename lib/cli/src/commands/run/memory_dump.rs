@@ -0,0 +1,84 @@
+//! Dump the linear memory of an instance once it has run, for
+//! `wasmer run --dump-memory-on-exit`.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use wasmer::{ExportIndex, Instance};
+
+/// A range of bytes of a memory, like `0x1000:256`, set with
+/// `--dump-memory-range`.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryRange {
+    start: u64,
+    len: u64,
+}
+
+impl FromStr for MemoryRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_offset = |offset: &str| {
+            match offset.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => offset.parse::<u64>(),
+            }
+            .with_context(|| format!("invalid offset `{}` in `{}`", offset, s))
+        };
+        match s.split_once(':') {
+            Some((start, len)) => Ok(Self {
+                start: parse_offset(start)?,
+                len: parse_offset(len)?,
+            }),
+            None => bail!("invalid memory range `{}`, expected `START:LEN`", s),
+        }
+    }
+}
+
+/// Write the memory `index` of `instance` to `path`, or only its `range`.
+///
+/// The memory must be exported, as the memories of an instance can only
+/// be accessed through its exports.
+pub fn dump_memory(
+    instance: &Instance,
+    index: u32,
+    range: Option<MemoryRange>,
+    path: &Path,
+) -> Result<()> {
+    let name = instance
+        .module()
+        .info()
+        .exports
+        .iter()
+        .find_map(|(name, export)| match export {
+            ExportIndex::Memory(memory) if memory.as_u32() == index => Some(name),
+            _ => None,
+        })
+        .with_context(|| {
+            format!(
+                "the memory {} of the module isn't exported, so it can't be dumped",
+                index
+            )
+        })?;
+    let memory = instance.exports.get_memory(name)?;
+    // The module has stopped running, so nothing mutates the memory
+    let data = unsafe { memory.data_unchecked() };
+    let data = match range {
+        Some(MemoryRange { start, len }) => start
+            .checked_add(len)
+            .filter(|end| *end <= data.len() as u64)
+            .map(|end| &data[start as usize..end as usize])
+            .with_context(|| {
+                format!(
+                    "the range {:#x}:{} is out of the memory `{}`, of {} bytes",
+                    start,
+                    len,
+                    name,
+                    data.len()
+                )
+            })?,
+        None => data,
+    };
+    fs::write(path, data).with_context(|| format!("failed to write `{}`", path.display()))
+}
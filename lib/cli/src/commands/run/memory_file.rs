@@ -0,0 +1,308 @@
+//! Linear memories backed by a host file, used by `wasmer run --memory-file`.
+//!
+//! The file is mapped with `MAP_SHARED` in a reservation as large as the
+//! memory can grow (plus its guard pages), so the changes of the guest are
+//! written to the file, and the base of the memory never moves when it
+//! grows. Growing the memory extends the file and maps the new pages.
+
+use anyhow::{Context, Result};
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr::{self, NonNull};
+use std::sync::{Arc, Mutex};
+use std::{io, mem};
+use wasmer::vm::{
+    Memory, MemoryError, MemoryStyle, Table, TableStyle, VMMemoryDefinition, VMTableDefinition,
+};
+use wasmer::{BaseTunables, MemoryType, Pages, TableType, Tunables, WASM_PAGE_SIZE};
+
+/// Tunables backing the first memory defined by a module with a file,
+/// and delegating everything else to the base tunables.
+pub struct FileMemoryTunables {
+    base: BaseTunables,
+    /// The file, until a memory takes it.
+    file: Mutex<Option<File>>,
+}
+
+impl FileMemoryTunables {
+    /// Open (or create) the file at `path`, locking it for the lifetime
+    /// of the memory.
+    pub fn new(base: BaseTunables, path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open memory file `{}`", path.display()))?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            bail!(
+                "memory file `{}` is in use by another process",
+                path.display()
+            );
+        }
+        Ok(Self {
+            base,
+            file: Mutex::new(Some(file)),
+        })
+    }
+}
+
+impl MemoryUsage for FileMemoryTunables {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.base.size_of_val(tracker) - mem::size_of_val(&self.base)
+    }
+}
+
+impl Tunables for FileMemoryTunables {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        self.base.memory_style(memory)
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        self.base.create_host_memory(ty, style)
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        match self.file.lock().unwrap().take() {
+            Some(file) => Ok(Arc::new(FileMemory::new(
+                file,
+                ty,
+                style,
+                vm_definition_location,
+            )?)),
+            None => self
+                .base
+                .create_vm_memory(ty, style, vm_definition_location),
+        }
+    }
+
+    fn create_host_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+    ) -> Result<Arc<dyn Table>, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<Arc<dyn Table>, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
+
+/// A linear memory mapped from a file.
+#[derive(Debug)]
+struct FileMemory {
+    file: File,
+    /// The start of the reservation, which is the base of the memory.
+    base: NonNull<u8>,
+    reservation_size: usize,
+    /// The current size of the memory, which is the size of the file.
+    size: Mutex<Pages>,
+    /// The size the memory can't grow beyond.
+    maximum: Pages,
+    ty: MemoryType,
+    style: MemoryStyle,
+    vm_definition: NonNull<VMMemoryDefinition>,
+}
+
+// The memory accesses are synchronized by the VM, like for the
+// `LinearMemory` of `wasmer-vm`.
+unsafe impl Send for FileMemory {}
+unsafe impl Sync for FileMemory {}
+
+impl FileMemory {
+    /// Map `file` as a memory of type `ty`, sized to the largest of the
+    /// file and the minimum of the memory.
+    ///
+    /// # Safety
+    /// - `vm_definition` must point to a valid location in VM memory.
+    unsafe fn new(
+        file: File,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition: NonNull<VMMemoryDefinition>,
+    ) -> Result<Self, MemoryError> {
+        let (bound, offset_guard_size) = match style {
+            MemoryStyle::Static {
+                bound,
+                offset_guard_size,
+            } => (*bound, *offset_guard_size),
+            MemoryStyle::Dynamic { offset_guard_size } => (
+                ty.maximum.unwrap_or_else(Pages::max_value),
+                *offset_guard_size,
+            ),
+        };
+        let maximum = ty.maximum.map_or(bound, |maximum| maximum.min(bound));
+
+        let file_size = file
+            .metadata()
+            .map_err(|e| MemoryError::Region(e.to_string()))?
+            .len() as usize;
+        if file_size % WASM_PAGE_SIZE != 0 {
+            return Err(MemoryError::InvalidMemory {
+                reason: format!(
+                    "the memory file size ({} bytes) isn't a multiple of the Wasm page size",
+                    file_size
+                ),
+            });
+        }
+        let file_pages = file_size / WASM_PAGE_SIZE;
+        if file_pages > maximum.0 as usize {
+            return Err(MemoryError::InvalidMemory {
+                reason: format!(
+                    "the memory file holds {} pages, more than the maximum of {} pages of the memory",
+                    file_pages, maximum.0
+                ),
+            });
+        }
+        let size = ty.minimum.max(Pages(file_pages as u32));
+        if size > maximum {
+            return Err(MemoryError::MinimumMemoryTooLarge {
+                min_requested: size,
+                max_allowed: maximum,
+            });
+        }
+
+        let reservation_size = bound.bytes().0 + offset_guard_size as usize;
+        let base = libc::mmap(
+            ptr::null_mut(),
+            reservation_size,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if base == libc::MAP_FAILED {
+            return Err(MemoryError::Region(io::Error::last_os_error().to_string()));
+        }
+        let memory = Self {
+            file,
+            base: NonNull::new_unchecked(base as *mut u8),
+            reservation_size,
+            size: Mutex::new(size),
+            maximum,
+            ty: *ty,
+            style: style.clone(),
+            vm_definition,
+        };
+        memory.map_pages(Pages(0), size)?;
+        vm_definition.as_ptr().write(VMMemoryDefinition {
+            base: memory.base.as_ptr(),
+            current_length: size.bytes().0 as u32,
+        });
+        Ok(memory)
+    }
+
+    /// Extend the file to `start + count` pages if needed, and map these
+    /// pages of the file in the reservation.
+    fn map_pages(&self, start: Pages, count: Pages) -> Result<(), MemoryError> {
+        if count.0 == 0 {
+            return Ok(());
+        }
+        let offset = start.bytes().0;
+        let length = count.bytes().0;
+        let file_size = (offset + length) as u64;
+        let current_file_size = self
+            .file
+            .metadata()
+            .map_err(|e| MemoryError::Region(e.to_string()))?
+            .len();
+        if current_file_size < file_size {
+            self.file
+                .set_len(file_size)
+                .map_err(|e| MemoryError::Region(e.to_string()))?;
+        }
+        let address = unsafe {
+            libc::mmap(
+                self.base.as_ptr().add(offset) as *mut libc::c_void,
+                length,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                self.file.as_raw_fd(),
+                offset as libc::off_t,
+            )
+        };
+        if address == libc::MAP_FAILED {
+            return Err(MemoryError::Region(io::Error::last_os_error().to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FileMemory {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(
+                self.base.as_ptr() as *mut libc::c_void,
+                self.reservation_size,
+            );
+        }
+    }
+}
+
+impl MemoryUsage for FileMemory {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.size().bytes().0
+    }
+}
+
+impl Memory for FileMemory {
+    fn ty(&self) -> MemoryType {
+        let mut ty = self.ty;
+        ty.minimum = self.size();
+        ty
+    }
+
+    fn style(&self) -> &MemoryStyle {
+        &self.style
+    }
+
+    fn size(&self) -> Pages {
+        *self.size.lock().unwrap()
+    }
+
+    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+        let mut size = self.size.lock().unwrap();
+        let previous_size = *size;
+        if delta.0 == 0 {
+            return Ok(previous_size);
+        }
+        let new_size = previous_size
+            .checked_add(delta)
+            .filter(|new_size| *new_size <= self.maximum)
+            .ok_or(MemoryError::CouldNotGrow {
+                current: previous_size,
+                attempted_delta: delta,
+            })?;
+        self.map_pages(previous_size, delta)?;
+        *size = new_size;
+        unsafe {
+            (*self.vm_definition.as_ptr()).current_length = new_size.bytes().0 as u32;
+        }
+        Ok(previous_size)
+    }
+
+    fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
+        self.vm_definition
+    }
+}
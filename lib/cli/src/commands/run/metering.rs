@@ -0,0 +1,89 @@
+//! Limit the operators a module executes, for `wasmer run
+//! --metering-limit`, and report the points left when it traps, for
+//! `--print-metering-remaining-on-trap`.
+//!
+//! The module is compiled with the metering middleware of
+//! `wasmer-middlewares`, where every operator costs one point. The
+//! middleware exports the remaining points and whether they're exhausted
+//! as globals, which are read once the module has trapped: running out of
+//! points traps with `unreachable`, like a genuine fault would.
+
+use anyhow::Result;
+use wasmer::{Instance, RuntimeError, Val};
+
+/// The globals exported by the metering middleware.
+const REMAINING_POINTS_GLOBAL: &str = "wasmer_metering_remaining_points";
+const POINTS_EXHAUSTED_GLOBAL: &str = "wasmer_metering_points_exhausted";
+
+/// The metering middleware with the given limit of points.
+#[cfg(feature = "compiler")]
+pub fn middleware(limit: u64) -> std::sync::Arc<dyn wasmer::ModuleMiddleware> {
+    use wasmer::wasmparser::Operator;
+
+    fn cost(_operator: &Operator) -> u64 {
+        1
+    }
+
+    std::sync::Arc::new(wasmer_middlewares::Metering::new(limit, cost))
+}
+
+/// The metering points left in `instance`: `None` once they're exhausted.
+fn remaining_points(instance: &Instance) -> Option<Option<u64>> {
+    let get = |name: &str| {
+        instance
+            .exports
+            .get_global(name)
+            .ok()
+            .map(|global| global.get())
+    };
+    match (get(POINTS_EXHAUSTED_GLOBAL)?, get(REMAINING_POINTS_GLOBAL)?) {
+        (Val::I32(exhausted), _) if exhausted != 0 => Some(None),
+        (_, Val::I64(points)) => Some(Some(points as u64)),
+        _ => None,
+    }
+}
+
+/// Tell the traps of `result` caused by running out of the `limit`
+/// metering points from the other ones, printing the remaining points if
+/// `print_remaining` is set.
+pub fn check_metering<T>(
+    instance: &Instance,
+    result: Result<T>,
+    limit: u64,
+    print_remaining: bool,
+) -> Result<T> {
+    result.map_err(|err| {
+        let is_trap = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<RuntimeError>())
+            .map_or(false, |runtime_error| {
+                runtime_error.clone().to_trap().is_some()
+            });
+        if !is_trap {
+            return err;
+        }
+        match remaining_points(instance) {
+            Some(None) => {
+                if print_remaining {
+                    eprintln!("Metering points remaining: 0 (exhausted)");
+                }
+                err.context(format!(
+                    "the module ran out of its {} metering points",
+                    limit
+                ))
+            }
+            Some(Some(points)) => {
+                if print_remaining {
+                    eprintln!(
+                        "Metering points remaining: {} (used {} of {})",
+                        points,
+                        limit.saturating_sub(points),
+                        limit
+                    );
+                }
+                err
+            }
+            None => err,
+        }
+    })
+}
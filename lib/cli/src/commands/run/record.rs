@@ -0,0 +1,526 @@
+//! Record and replay the WASI syscalls of a module, used by
+//! `wasmer run --record` and `wasmer run --replay`.
+//!
+//! Every `wasi_snapshot_preview1` or `wasi_unstable` syscall is recorded,
+//! except the ones
+//! derived from the command line (`args_*`, `environ_*`) and the ones that
+//! don't return (`proc_exit`, `proc_raise`), which always go to the host.
+//! A recorded syscall is stored with its arguments, its results and the
+//! guest memory it writes to, like the bytes read by `fd_read` or
+//! `random_get`.
+//!
+//! When replaying, the recorded syscalls don't reach the host: their
+//! results and the bytes they wrote are restored from the trace, in order.
+//! The data the guest writes to its stdout and stderr is still printed.
+//! Replaying needs the same module, the same arguments and environment,
+//! and a guest that makes the same syscalls: the replay stops at the first
+//! syscall that doesn't match the trace.
+//!
+//! The host syscalls are implemented by native functions, which can't be
+//! called from the host, so they are called through a small generated
+//! module forwarding its exports to them.
+
+use crate::wasm_binary::{forwarding_module_with_memory, FORWARDED_NAMESPACE};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use wasmer::{
+    imports, ChainableNamedResolver, Exports, Extern, ExternType, Function, FunctionType,
+    ImportObject, Instance, LazyInit, Memory, Module, NamedResolver, RuntimeError, Type, Val,
+    WasmPtr, WasmerEnv,
+};
+
+/// The magic bytes at the start of a trace.
+const TRACE_MAGIC: &[u8; 8] = b"WASMTRC1";
+
+/// The WASI namespaces that can be recorded.
+const WASI_NAMESPACES: &[&str] = &["wasi_snapshot_preview1", "wasi_unstable"];
+
+/// The syscalls that always go to the host.
+const HOST_SYSCALLS: &[&str] = &[
+    "args_get",
+    "args_sizes_get",
+    "environ_get",
+    "environ_sizes_get",
+    "proc_exit",
+    "proc_raise",
+];
+
+/// The beginning of a trace, identifying the recorded module.
+#[derive(Debug, Serialize, Deserialize)]
+struct TraceHeader {
+    module_fingerprint: String,
+}
+
+/// A recorded syscall.
+#[derive(Debug, Serialize, Deserialize)]
+struct Syscall {
+    name: String,
+    /// The arguments, as 64-bit integers.
+    args: Vec<i64>,
+    results: Vec<i64>,
+    /// The guest memory written by the syscall.
+    writes: Vec<MemoryWrite>,
+}
+
+/// Bytes written in the guest memory.
+#[derive(Debug, Serialize, Deserialize)]
+struct MemoryWrite {
+    offset: u32,
+    data: Vec<u8>,
+}
+
+/// A fingerprint of the interface and the function signatures of a module,
+/// to check that a trace is replayed with the module it was recorded with.
+fn module_fingerprint(module: &Module) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for import in module.imports() {
+        hasher.update(
+            format!(
+                "import {}.{}: {:?}\n",
+                import.module(),
+                import.name(),
+                import.ty()
+            )
+            .as_bytes(),
+        );
+    }
+    for export in module.exports() {
+        hasher.update(format!("export {}: {:?}\n", export.name(), export.ty()).as_bytes());
+    }
+    let info = module.info();
+    for signature in info.functions.values() {
+        hasher.update(format!("function {:?}\n", info.signatures[*signature]).as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// The WASI namespace of the syscalls to record or replay, with the
+/// imported syscalls and their type.
+fn recorded_imports(module: &Module) -> Result<(&'static str, Vec<(String, FunctionType)>)> {
+    let mut wasi_namespace = None;
+    let mut syscalls = vec![];
+    for import in module.imports() {
+        let namespace = import.module();
+        if !namespace.starts_with("wasi") {
+            continue;
+        }
+        let namespace = match WASI_NAMESPACES.iter().find(|known| **known == namespace) {
+            Some(known) => *known,
+            None => bail!(
+                "only the `{}` syscalls can be recorded, but the module imports from `{}`",
+                WASI_NAMESPACES.join("` or `"),
+                namespace
+            ),
+        };
+        match wasi_namespace {
+            Some(other) if other != namespace => bail!(
+                "the syscalls of a module importing from both `{}` and `{}` can't be recorded",
+                other,
+                namespace
+            ),
+            _ => wasi_namespace = Some(namespace),
+        }
+        if let ExternType::Function(ty) = import.ty() {
+            if !HOST_SYSCALLS.contains(&import.name()) {
+                syscalls.push((import.name().to_string(), ty.clone()));
+            }
+        }
+    }
+    Ok((wasi_namespace.unwrap_or(WASI_NAMESPACES[0]), syscalls))
+}
+
+/// Generate the imports recording the syscalls of `module` to `trace_path`,
+/// calling the syscalls of `host_resolver`.
+pub fn generate_recording_import_object(
+    module: &Module,
+    host_resolver: &dyn NamedResolver,
+    trace_path: &Path,
+) -> Result<ImportObject> {
+    let (wasi_namespace, syscalls) = recorded_imports(module)?;
+    let mut host_syscalls = vec![];
+    for (name, _) in &syscalls {
+        let export = host_resolver
+            .resolve_by_name(wasi_namespace, name)
+            .with_context(|| format!("unknown WASI syscall `{}`", name))?;
+        match Extern::from_vm_export(module.store(), export) {
+            Extern::Function(function) => host_syscalls.push(function),
+            _ => bail!("the WASI syscall `{}` isn't a function", name),
+        }
+    }
+    let forwarding_module = Module::new(
+        module.store(),
+        forwarding_module_with_memory(&host_syscalls),
+    )
+    .context("failed to compile the syscall forwarding module")?;
+    let mut forwarded_syscalls = Exports::new();
+    for (index, function) in host_syscalls.into_iter().enumerate() {
+        forwarded_syscalls.insert(index.to_string(), function);
+    }
+
+    let mut trace = BufWriter::new(
+        File::create(trace_path)
+            .with_context(|| format!("failed to create `{}`", trace_path.display()))?,
+    );
+    trace.write_all(TRACE_MAGIC)?;
+    bincode::serialize_into(
+        &mut trace,
+        &TraceHeader {
+            module_fingerprint: module_fingerprint(module),
+        },
+    )?;
+    trace.flush()?;
+
+    let recorder = Arc::new(Recorder {
+        trace: Mutex::new(trace),
+        forwarding: Mutex::new(Forwarding {
+            module: forwarding_module,
+            host_syscalls: forwarded_syscalls,
+            instance: None,
+        }),
+    });
+    let mut namespace = Exports::new();
+    for (index, (name, ty)) in syscalls.into_iter().enumerate() {
+        let env = RecordEnv {
+            memory: LazyInit::new(),
+            wasi_namespace,
+            index,
+            name: name.clone(),
+            recorder: recorder.clone(),
+        };
+        namespace.insert(
+            name.as_str(),
+            Function::new_with_env(module.store(), ty, env, record_syscall),
+        );
+    }
+    let mut import_object = ImportObject::new();
+    import_object.register(wasi_namespace, namespace);
+    Ok(import_object)
+}
+
+/// Generate the imports replaying the syscalls recorded in `trace_path`
+/// for `module`.
+pub fn generate_replaying_import_object(
+    module: &Module,
+    trace_path: &Path,
+) -> Result<ImportObject> {
+    let (wasi_namespace, syscalls) = recorded_imports(module)?;
+    let mut trace = BufReader::new(
+        File::open(trace_path)
+            .with_context(|| format!("failed to open `{}`", trace_path.display()))?,
+    );
+    let mut magic = [0; 8];
+    if trace.read_exact(&mut magic).is_err() || magic != *TRACE_MAGIC {
+        bail!("`{}` isn't a syscall trace", trace_path.display());
+    }
+    let header: TraceHeader = bincode::deserialize_from(&mut trace)
+        .with_context(|| format!("failed to read `{}`", trace_path.display()))?;
+    if header.module_fingerprint != module_fingerprint(module) {
+        bail!(
+            "`{}` was recorded with a different module",
+            trace_path.display()
+        );
+    }
+    let mut recorded = VecDeque::new();
+    loop {
+        match bincode::deserialize_from::<_, Syscall>(&mut trace) {
+            Ok(syscall) => recorded.push_back(syscall),
+            // The end of the trace
+            Err(err) if matches!(*err, bincode::ErrorKind::Io(ref io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof) => {
+                break
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read `{}`", trace_path.display()))
+            }
+        }
+    }
+
+    let player = Arc::new(Mutex::new(Player { recorded, index: 0 }));
+    let mut namespace = Exports::new();
+    for (name, ty) in syscalls {
+        let result_types = ty.results().to_vec();
+        let env = ReplayEnv {
+            memory: LazyInit::new(),
+            name: name.clone(),
+            player: player.clone(),
+        };
+        namespace.insert(
+            name.as_str(),
+            Function::new_with_env(module.store(), ty, env, move |env, args| {
+                replay_syscall(env, args, &result_types)
+            }),
+        );
+    }
+    let mut import_object = ImportObject::new();
+    import_object.register(wasi_namespace, namespace);
+    Ok(import_object)
+}
+
+/// The state shared by the recording syscalls.
+struct Recorder {
+    trace: Mutex<BufWriter<File>>,
+    forwarding: Mutex<Forwarding>,
+}
+
+/// The module forwarding calls to the host syscalls, instantiated when the
+/// first syscall is made, once the memory of the guest is available.
+struct Forwarding {
+    module: Module,
+    host_syscalls: Exports,
+    instance: Option<Instance>,
+}
+
+impl Recorder {
+    /// Get the function forwarding to the host syscall at `index`.
+    fn forwarder(&self, index: usize, memory: &Memory) -> Result<Function, RuntimeError> {
+        let mut forwarding = self.forwarding.lock().unwrap();
+        if forwarding.instance.is_none() {
+            let mut host_imports = ImportObject::new();
+            host_imports.register(FORWARDED_NAMESPACE, forwarding.host_syscalls.clone());
+            let imports = imports! {
+                "env" => {
+                    "memory" => memory.clone(),
+                },
+            }
+            .chain_front(host_imports);
+            let instance = Instance::new(&forwarding.module, &imports)
+                .map_err(|err| RuntimeError::new(err.to_string()))?;
+            forwarding.instance = Some(instance);
+        }
+        forwarding
+            .instance
+            .as_ref()
+            .unwrap()
+            .exports
+            .get_function(&index.to_string())
+            .map(Function::clone)
+            .map_err(|err| RuntimeError::new(err.to_string()))
+    }
+
+    fn write(&self, syscall: &Syscall) -> Result<(), RuntimeError> {
+        let mut trace = self.trace.lock().unwrap();
+        // The trace is flushed after every syscall, as the process may
+        // exit without unwinding
+        bincode::serialize_into(&mut *trace, syscall)
+            .map_err(|err| err.to_string())
+            .and_then(|_| trace.flush().map_err(|err| err.to_string()))
+            .map_err(|err| RuntimeError::new(format!("failed to write the trace: {}", err)))
+    }
+}
+
+#[derive(WasmerEnv, Clone)]
+struct RecordEnv {
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+    wasi_namespace: &'static str,
+    /// The index of the syscall in its forwarding module.
+    index: usize,
+    name: String,
+    recorder: Arc<Recorder>,
+}
+
+fn record_syscall(env: &RecordEnv, args: &[Val]) -> Result<Vec<Val>, RuntimeError> {
+    let memory = env
+        .memory_ref()
+        .ok_or_else(|| RuntimeError::new("the module exports no memory"))?;
+    let forwarder = env.recorder.forwarder(env.index, memory)?;
+    let results = forwarder.call(args)?.into_vec();
+    let args = args.iter().map(val_to_i64).collect::<Vec<_>>();
+    let writes = written_regions(env.wasi_namespace, &env.name, &args, memory)
+        .into_iter()
+        .filter_map(|(offset, length)| {
+            read_bytes(memory, offset, length).map(|data| MemoryWrite { offset, data })
+        })
+        .collect();
+    env.recorder.write(&Syscall {
+        name: env.name.clone(),
+        args,
+        results: results.iter().map(val_to_i64).collect(),
+        writes,
+    })?;
+    Ok(results)
+}
+
+/// The recorded syscalls being replayed.
+struct Player {
+    recorded: VecDeque<Syscall>,
+    /// The index of the next syscall in the trace.
+    index: usize,
+}
+
+#[derive(WasmerEnv, Clone)]
+struct ReplayEnv {
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+    name: String,
+    player: Arc<Mutex<Player>>,
+}
+
+fn replay_syscall(
+    env: &ReplayEnv,
+    args: &[Val],
+    result_types: &[Type],
+) -> Result<Vec<Val>, RuntimeError> {
+    let memory = env
+        .memory_ref()
+        .ok_or_else(|| RuntimeError::new("the module exports no memory"))?;
+    let args = args.iter().map(val_to_i64).collect::<Vec<_>>();
+    let syscall = {
+        let mut player = env.player.lock().unwrap();
+        let index = player.index;
+        player.index += 1;
+        match player.recorded.pop_front() {
+            Some(syscall) if syscall.name == env.name && syscall.args == args => syscall,
+            Some(syscall) => {
+                return Err(RuntimeError::new(format!(
+                    "the replay diverged from the trace at syscall {}: the module called `{}{:?}`, but `{}{:?}` was recorded",
+                    index, env.name, args, syscall.name, syscall.args
+                )))
+            }
+            None => {
+                return Err(RuntimeError::new(format!(
+                    "the replay diverged from the trace at syscall {}: the module called `{}{:?}` after the end of the trace",
+                    index, env.name, args
+                )))
+            }
+        }
+    };
+    for write in &syscall.writes {
+        if !write_bytes(memory, write.offset, &write.data) {
+            return Err(RuntimeError::new(
+                "the recorded syscall writes outside of the memory",
+            ));
+        }
+    }
+    if env.name == "fd_write" && syscall.results == [0] {
+        print_written_data(memory, &args)
+            .map_err(|err| RuntimeError::new(format!("failed to print the output: {}", err)))?;
+    }
+    Ok(syscall
+        .results
+        .iter()
+        .zip(result_types)
+        .map(|(value, ty)| match ty {
+            Type::I64 => Val::I64(*value),
+            _ => Val::I32(*value as i32),
+        })
+        .collect())
+}
+
+/// Print the data of a replayed `fd_write` to the stdout or stderr, if
+/// it's the file descriptor it was written to.
+fn print_written_data(memory: &Memory, args: &[i64]) -> io::Result<()> {
+    let mut output: Box<dyn Write> = match args[0] {
+        1 => Box::new(io::stdout()),
+        2 => Box::new(io::stderr()),
+        _ => return Ok(()),
+    };
+    let mut remaining = read_u32(memory, args[3] as u32).unwrap_or(0);
+    for (offset, length) in iovec_regions(memory, args[1] as u32, args[2] as u32) {
+        let data = read_bytes(memory, offset, length.min(remaining)).unwrap_or_default();
+        output.write_all(&data)?;
+        remaining -= data.len() as u32;
+    }
+    output.flush()
+}
+
+fn val_to_i64(val: &Val) -> i64 {
+    match val {
+        Val::I32(value) => *value as i64,
+        Val::I64(value) => *value,
+        _ => 0,
+    }
+}
+
+/// The regions of the guest memory a syscall of `wasi_namespace` writes
+/// to, as offsets and lengths, from its arguments (`args`) and the memory
+/// after the call.
+fn written_regions(
+    wasi_namespace: &str,
+    name: &str,
+    args: &[i64],
+    memory: &Memory,
+) -> Vec<(u32, u32)> {
+    let arg = |index: usize| args[index] as u32;
+    // `wasi_unstable` has a 32-bit `nlink`
+    let filestat_size = if wasi_namespace == "wasi_unstable" {
+        56
+    } else {
+        64
+    };
+    match name {
+        "clock_res_get" | "fd_tell" => vec![(arg(1), 8)],
+        "clock_time_get" => vec![(arg(2), 8)],
+        "fd_seek" => vec![(arg(3), 8)],
+        "random_get" | "fd_prestat_dir_name" => vec![(arg(0), arg(1))],
+        "fd_prestat_get" => vec![(arg(1), 8)],
+        "fd_fdstat_get" => vec![(arg(1), 24)],
+        "fd_filestat_get" => vec![(arg(1), filestat_size)],
+        "path_filestat_get" => vec![(arg(4), filestat_size)],
+        "path_open" => vec![(arg(8), 4)],
+        "fd_read" | "fd_pread" => {
+            let mut regions = iovec_regions(memory, arg(1), arg(2));
+            regions.push((arg(args.len() - 1), 4));
+            regions
+        }
+        "fd_write" | "fd_pwrite" => vec![(arg(args.len() - 1), 4)],
+        "fd_readdir" => vec![(arg(1), arg(2)), (arg(4), 4)],
+        "path_readlink" => vec![(arg(3), arg(4)), (arg(5), 4)],
+        "poll_oneoff" => vec![(arg(1), arg(2).saturating_mul(32)), (arg(3), 4)],
+        "sock_recv" => {
+            let mut regions = iovec_regions(memory, arg(1), arg(2));
+            regions.push((arg(4), 4));
+            regions.push((arg(5), 2));
+            regions
+        }
+        "sock_send" => vec![(arg(4), 4)],
+        _ => vec![],
+    }
+}
+
+/// The buffers of an array of `iovec`s.
+fn iovec_regions(memory: &Memory, iovs: u32, iovs_len: u32) -> Vec<(u32, u32)> {
+    let mut regions = vec![];
+    for index in 0..iovs_len {
+        let iov = iovs as u64 + index as u64 * 8;
+        match (
+            read_u32(memory, iov as u32).filter(|_| iov + 8 <= u32::MAX as u64),
+            read_u32(memory, iov as u32 + 4),
+        ) {
+            (Some(offset), Some(length)) => regions.push((offset, length)),
+            _ => break,
+        }
+    }
+    regions
+}
+
+fn read_u32(memory: &Memory, offset: u32) -> Option<u32> {
+    WasmPtr::<u32>::new(offset)
+        .deref(memory)
+        .map(|cell| cell.get())
+}
+
+fn read_bytes(memory: &Memory, offset: u32, length: u32) -> Option<Vec<u8>> {
+    let view = memory.view::<u8>();
+    let end = (offset as usize).checked_add(length as usize)?;
+    view.get(offset as usize..end)
+        .map(|cells| cells.iter().map(|cell| cell.get()).collect())
+}
+
+fn write_bytes(memory: &Memory, offset: u32, data: &[u8]) -> bool {
+    let view = memory.view::<u8>();
+    match view.get(offset as usize..offset as usize + data.len()) {
+        Some(cells) => {
+            for (cell, byte) in cells.iter().zip(data) {
+                cell.set(*byte);
+            }
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,76 @@
+//! Sandbox profiles, gathering the capabilities granted to a module and
+//! the limits it runs with in a TOML file, for `wasmer run
+//! --sandbox-profile`.
+//!
+//! The keys are named after the flags of `wasmer run`:
+//!
+//! ```toml
+//! dir = ["data"]
+//! dir-access-mode = "0444"
+//! limit-open-files = 16
+//! allow-imports = ["wasi_snapshot_preview1"]
+//!
+//! [mapdir]
+//! "/config" = "config/prod"
+//!
+//! [env]
+//! LOG_LEVEL = "info"
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The settings of a sandbox profile. They're all optional, and unknown
+/// keys are rejected, so a typo can't silently drop a restriction.
+///
+/// The tables are declared last, as TOML emits them after the values.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SandboxProfile {
+    /// The host directories preopened with their own path, like `--dir`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dir: Vec<PathBuf>,
+    /// The octal permission bits of the preopened directories, like
+    /// `--dir-access-mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_access_mode: Option<String>,
+    /// The working directory of the module, like `--working-dir`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    /// The maximum number of open file descriptors, like
+    /// `--limit-open-files`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_open_files: Option<u32>,
+    /// Make the WASI syscalls reproducible, like `--deterministic-wasi`.
+    #[serde(default)]
+    pub deterministic_wasi: bool,
+    /// The only namespaces the module may import from, like
+    /// `--allow-imports`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_imports: Vec<String>,
+    /// The host directories preopened at a guest path, by guest path, like
+    /// `--mapdir`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mapdir: BTreeMap<String, PathBuf>,
+    /// The environment variables of the module, like `--env`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl SandboxProfile {
+    /// Read the profile at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read the sandbox profile {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("invalid sandbox profile {}", path.display()))
+    }
+
+    /// The profile as TOML, for `--print-sandbox`.
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
@@ -0,0 +1,132 @@
+//! Checkpointing of instances for `wasmer run --snapshot-after-init`
+//! and `wasmer run --restore`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use wasmer::{Instance, Mutability, Pages, Val, WASM_PAGE_SIZE};
+
+/// The header of the snapshot files.
+const SNAPSHOT_MAGIC_HEADER: &[u8; 16] = b"\0wasmer-snapshot";
+
+/// The version of the snapshot format, bumped on incompatible changes.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The state of an instance, captured after its initialization.
+///
+/// Only the exported memories and exported mutable globals are captured:
+/// tables, non-exported state and the WASI state (such as open file
+/// descriptors) are recreated from scratch when restoring.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    /// The hash of the module the snapshot was taken from.
+    module_hash: [u8; 32],
+    memories: Vec<(String, Vec<u8>)>,
+    globals: Vec<(String, GlobalValue)>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum GlobalValue {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    V128(u128),
+}
+
+impl Snapshot {
+    /// Capture the state of the instance of the module with the given contents.
+    pub fn capture(instance: &Instance, module_contents: &[u8]) -> Result<Self> {
+        let memories = instance
+            .exports
+            .iter()
+            .memories()
+            .map(|(name, memory)| (name.clone(), unsafe { memory.data_unchecked() }.to_vec()))
+            .collect();
+        let globals = instance
+            .exports
+            .iter()
+            .globals()
+            .filter(|(_, global)| global.ty().mutability == Mutability::Var)
+            .map(|(name, global)| {
+                let value = match global.get() {
+                    Val::I32(value) => GlobalValue::I32(value),
+                    Val::I64(value) => GlobalValue::I64(value),
+                    Val::F32(value) => GlobalValue::F32(value),
+                    Val::F64(value) => GlobalValue::F64(value),
+                    Val::V128(value) => GlobalValue::V128(value),
+                    _ => bail!("the reference global `{}` can't be snapshotted", name),
+                };
+                Ok((name.clone(), value))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            module_hash: *blake3::hash(module_contents).as_bytes(),
+            memories,
+            globals,
+        })
+    }
+
+    /// Restore the state into a fresh instance of the module with the
+    /// given contents.
+    pub fn restore(&self, instance: &Instance, module_contents: &[u8]) -> Result<()> {
+        if self.module_hash != *blake3::hash(module_contents).as_bytes() {
+            bail!("the snapshot was taken from a different module");
+        }
+        for (name, data) in &self.memories {
+            let memory = instance
+                .exports
+                .get_memory(name)
+                .with_context(|| format!("the snapshotted memory `{}` is not exported", name))?;
+            let current_size = memory.data_size() as usize;
+            if current_size < data.len() {
+                memory.grow(Pages(((data.len() - current_size) / WASM_PAGE_SIZE) as u32))?;
+            }
+            let memory_data = unsafe { memory.data_unchecked_mut() };
+            memory_data[..data.len()].copy_from_slice(data);
+        }
+        for (name, value) in &self.globals {
+            let global = instance
+                .exports
+                .get_global(name)
+                .with_context(|| format!("the snapshotted global `{}` is not exported", name))?;
+            global.set(match *value {
+                GlobalValue::I32(value) => Val::I32(value),
+                GlobalValue::I64(value) => Val::I64(value),
+                GlobalValue::F32(value) => Val::F32(value),
+                GlobalValue::F64(value) => Val::F64(value),
+                GlobalValue::V128(value) => Val::V128(value),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Write the snapshot to a file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut contents = SNAPSHOT_MAGIC_HEADER.to_vec();
+        bincode::serialize_into(&mut contents, self)?;
+        fs::write(path, contents)
+            .with_context(|| format!("failed to write snapshot `{}`", path.display()))
+    }
+
+    /// Read a snapshot from a file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read(path)
+            .with_context(|| format!("failed to read snapshot `{}`", path.display()))?;
+        if !contents.starts_with(SNAPSHOT_MAGIC_HEADER) {
+            bail!("`{}` is not a wasmer snapshot", path.display());
+        }
+        let snapshot: Self = bincode::deserialize(&contents[SNAPSHOT_MAGIC_HEADER.len()..])
+            .with_context(|| format!("the snapshot `{}` is corrupted", path.display()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            bail!(
+                "the snapshot `{}` was taken by an incompatible version of wasmer",
+                path.display()
+            );
+        }
+        Ok(snapshot)
+    }
+}
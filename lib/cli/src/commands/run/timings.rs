@@ -0,0 +1,33 @@
+//! The timings of a run, written by `wasmer run --timings-json`.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long the phases of a run took, in seconds.
+#[derive(Debug, Serialize)]
+pub struct Timings {
+    /// Getting the compiled module, which is only loading it when it's
+    /// cached or precompiled.
+    compile_seconds: f64,
+    /// Instantiating and running the module, `None` with
+    /// `--exit-after compile`.
+    run_seconds: Option<f64>,
+}
+
+impl Timings {
+    pub fn new(compile_time: Duration, run_time: Option<Duration>) -> Self {
+        Self {
+            compile_seconds: compile_time.as_secs_f64(),
+            run_seconds: run_time.map(|run_time| run_time.as_secs_f64()),
+        }
+    }
+
+    /// Write the timings as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
@@ -0,0 +1,470 @@
+//! Trace the calls to the functions of a module, used by
+//! `wasmer run --trace-calls`.
+//!
+//! The module is rewritten before it's compiled: the body of every traced
+//! function is moved to a new function, and replaced with a wrapper
+//! calling the body between two hooks. The wrapper keeps the index of the
+//! traced function, so the calls, the exports and the tables don't change.
+//!
+//! The hooks are host functions, but importing them would shift the
+//! indices of all the functions. They are instead called indirectly
+//! through a new table, exported as `wasmer_trace_calls` and filled once
+//! the module is instantiated. The names and the signatures of the traced
+//! functions are stored in a custom section of the same name.
+//!
+//! Host functions created from closures can't be called indirectly, so
+//! the table holds the functions of a small generated module forwarding
+//! to them.
+
+use crate::wasm_binary::{
+    append_entries, forwarding_module, function_names, glob_matches, is_name_section,
+    section_order, split_sections, write_i32, write_name, write_section, write_u32, Reader,
+    FORWARDED_NAMESPACE,
+};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use wasmer::wasmparser::{ImportSectionEntryType, ImportSectionReader, TypeDef, TypeSectionReader};
+use wasmer::{Exports, Function, FunctionType, ImportObject, Instance, Module, Type, Val};
+
+/// The name of the table of hooks and of the custom section describing
+/// the traced functions.
+const TRACE_CALLS: &str = "wasmer_trace_calls";
+
+/// A function matching the `--trace-calls` pattern.
+struct TracedFunction {
+    /// The index of the function, counting the imported functions.
+    index: u32,
+    name: String,
+    /// The encoded parameter and result types.
+    params: Vec<u8>,
+    results: Vec<u8>,
+}
+
+/// Instrument the functions of a Wasm module whose name matches the glob
+/// `pattern`, where `*` matches any sequence of characters and `?` any
+/// single character.
+///
+/// The names come from the name section, and from the exports for the
+/// functions it doesn't name. Imported functions can't be traced.
+pub fn instrument(wasm: &[u8], pattern: &str) -> Result<Vec<u8>> {
+    let sections = split_sections(wasm)?;
+    let mut types: Vec<(Vec<u8>, Vec<u8>)> = vec![];
+    let mut imported_functions = 0;
+    let mut tables = 0;
+    let mut function_types = vec![];
+    let mut names = HashMap::new();
+    for section in &sections {
+        match section.id {
+            1 => {
+                for ty in TypeSectionReader::new(section.data, section.offset)? {
+                    match ty? {
+                        TypeDef::Func(ty) => types.push((
+                            ty.params
+                                .iter()
+                                .map(|ty| value_type_code(*ty))
+                                .collect::<Result<_>>()?,
+                            ty.returns
+                                .iter()
+                                .map(|ty| value_type_code(*ty))
+                                .collect::<Result<_>>()?,
+                        )),
+                        _ => bail!("`--trace-calls` only supports function types"),
+                    }
+                }
+            }
+            2 => {
+                for import in ImportSectionReader::new(section.data, section.offset)? {
+                    match import?.ty {
+                        ImportSectionEntryType::Function(_) => imported_functions += 1,
+                        ImportSectionEntryType::Table(_) => tables += 1,
+                        _ => {}
+                    }
+                }
+            }
+            3 => {
+                let mut reader = Reader::new(section.data);
+                for _ in 0..reader.read_u32()? {
+                    function_types.push(reader.read_u32()?);
+                }
+            }
+            4 => tables += Reader::new(section.data).read_u32()?,
+            // The export names, which don't override the debug names
+            7 => {
+                let mut reader = Reader::new(section.data);
+                for _ in 0..reader.read_u32()? {
+                    let name = reader.read_name()?;
+                    let kind = reader.read_u8()?;
+                    let index = reader.read_u32()?;
+                    if kind == 0x00 {
+                        names.entry(index).or_insert_with(|| name.to_string());
+                    }
+                }
+            }
+            8 => bail!("`--trace-calls` doesn't support modules with a start function"),
+            _ => {}
+        }
+    }
+    if let Some(section) = sections.iter().find(|section| is_name_section(section)) {
+        for (index, name) in function_names(section.data)? {
+            names.insert(index, name.to_string());
+        }
+    }
+
+    let defined_functions = function_types.len() as u32;
+    let mut traced = vec![];
+    for (defined_index, type_index) in function_types.iter().enumerate() {
+        let index = imported_functions + defined_index as u32;
+        let name = match names.get(&index) {
+            Some(name) if glob_matches(pattern.as_bytes(), name.as_bytes()) => name,
+            _ => continue,
+        };
+        let (params, results) = types
+            .get(*type_index as usize)
+            .context("a function has an invalid type")?;
+        traced.push(TracedFunction {
+            index,
+            name: name.clone(),
+            params: params.clone(),
+            results: results.clone(),
+        });
+    }
+    if traced.is_empty() {
+        bail!(
+            "no function matches the `--trace-calls` pattern `{}`",
+            pattern
+        );
+    }
+
+    // The types of the hooks of each traced function: `enter` takes the
+    // parameters, and `exit` takes and returns the results
+    let mut hook_types = HashMap::new();
+    let mut new_types = vec![];
+    let mut hook_type = |params: &[u8], results: &[u8]| {
+        let mut ty = vec![0x60];
+        write_u32(&mut ty, params.len() as u32);
+        ty.extend_from_slice(params);
+        write_u32(&mut ty, results.len() as u32);
+        ty.extend_from_slice(results);
+        let index = (types.len() + hook_types.len()) as u32;
+        *hook_types.entry(ty.clone()).or_insert_with(|| {
+            new_types.push(ty);
+            index
+        })
+    };
+    let hooks = traced
+        .iter()
+        .map(|function| {
+            (
+                hook_type(&function.params, &[]),
+                hook_type(&function.results, &function.results),
+            )
+        })
+        .collect::<Vec<_>>();
+    let hook_count = 2 * traced.len() as u32;
+
+    let mut module = wasm[..8].to_vec();
+    let mut table_written = false;
+    let mut export_written = false;
+    for section in &sections {
+        // The table and the export sections, for modules without them
+        if !table_written && section.id != 0 && section_order(section.id) > section_order(4) {
+            write_section(
+                &mut module,
+                4,
+                &append_entries(&[], 1, &table_entry(hook_count))?,
+            );
+            table_written = true;
+        }
+        if !export_written && section.id != 0 && section_order(section.id) > section_order(7) {
+            write_section(
+                &mut module,
+                7,
+                &append_entries(&[], 1, &export_entry(tables))?,
+            );
+            export_written = true;
+        }
+        match section.id {
+            1 => write_section(
+                &mut module,
+                1,
+                &append_entries(section.data, new_types.len() as u32, &new_types.concat())?,
+            ),
+            3 => {
+                let mut entries = vec![];
+                for function in &traced {
+                    let defined_index = function.index - imported_functions;
+                    write_u32(&mut entries, function_types[defined_index as usize]);
+                }
+                write_section(
+                    &mut module,
+                    3,
+                    &append_entries(section.data, traced.len() as u32, &entries)?,
+                )
+            }
+            4 => {
+                write_section(
+                    &mut module,
+                    4,
+                    &append_entries(section.data, 1, &table_entry(hook_count))?,
+                );
+                table_written = true;
+            }
+            7 => {
+                write_section(
+                    &mut module,
+                    7,
+                    &append_entries(section.data, 1, &export_entry(tables))?,
+                );
+                export_written = true;
+            }
+            10 => write_section(
+                &mut module,
+                10,
+                &instrument_code(
+                    section.data,
+                    &traced,
+                    &hooks,
+                    imported_functions + defined_functions,
+                    tables,
+                )?,
+            ),
+            0 if is_name_section(section) => {
+                let traced_names = traced
+                    .iter()
+                    .enumerate()
+                    .map(|(position, function)| {
+                        (
+                            imported_functions + defined_functions + position as u32,
+                            function.name.as_str(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                write_section(
+                    &mut module,
+                    0,
+                    &append_function_names(section.data, &traced_names)?,
+                )
+            }
+            id => write_section(&mut module, id, section.data),
+        }
+    }
+
+    let mut description = vec![];
+    write_name(&mut description, TRACE_CALLS);
+    write_u32(&mut description, traced.len() as u32);
+    for function in &traced {
+        write_name(&mut description, &function.name);
+        for types in &[&function.params, &function.results] {
+            write_u32(&mut description, types.len() as u32);
+            description.extend_from_slice(types);
+        }
+    }
+    write_section(&mut module, 0, &description);
+    Ok(module)
+}
+
+/// Replace the bodies of the traced functions with their wrapper, and
+/// append their original bodies after the defined functions, starting at
+/// the index `first_body`.
+fn instrument_code(
+    code: &[u8],
+    traced: &[TracedFunction],
+    hooks: &[(u32, u32)],
+    first_body: u32,
+    table: u32,
+) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(code);
+    let count = reader.read_u32()?;
+    let mut bodies = (0..count)
+        .map(|_| {
+            let size = reader.read_u32()?;
+            reader.read_bytes(size as usize)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let first_defined = first_body - count;
+    let mut wrappers = vec![];
+    for (position, (function, (enter_type, exit_type))) in traced.iter().zip(hooks).enumerate() {
+        // No locals, then the `enter` hook with the parameters, the
+        // original body, and the `exit` hook with the results
+        let mut wrapper = vec![0x00];
+        let param_count = function.params.len() as u32;
+        for param in 0..param_count {
+            wrapper.push(0x20);
+            write_u32(&mut wrapper, param);
+        }
+        wrapper.push(0x41);
+        write_i32(&mut wrapper, 2 * position as i32);
+        wrapper.push(0x11);
+        write_u32(&mut wrapper, *enter_type);
+        write_u32(&mut wrapper, table);
+        for param in 0..param_count {
+            wrapper.push(0x20);
+            write_u32(&mut wrapper, param);
+        }
+        wrapper.push(0x10);
+        write_u32(&mut wrapper, first_body + position as u32);
+        wrapper.push(0x41);
+        write_i32(&mut wrapper, 2 * position as i32 + 1);
+        wrapper.push(0x11);
+        write_u32(&mut wrapper, *exit_type);
+        write_u32(&mut wrapper, table);
+        wrapper.push(0x0b);
+        wrappers.push(wrapper);
+    }
+    let mut moved_bodies = vec![];
+    for (function, wrapper) in traced.iter().zip(&wrappers) {
+        let body = &mut bodies[(function.index - first_defined) as usize];
+        moved_bodies.push(*body);
+        *body = wrapper.as_slice();
+    }
+
+    let mut section = vec![];
+    write_u32(&mut section, count + traced.len() as u32);
+    for body in bodies.iter().chain(&moved_bodies) {
+        write_u32(&mut section, body.len() as u32);
+        section.extend_from_slice(body);
+    }
+    Ok(section)
+}
+
+/// Fill the table of hooks of an instance of a module instrumented by
+/// [`instrument`], which logs the calls to stderr. Other instances are
+/// left as is.
+///
+/// Returns the instance of the module forwarding to the hooks, which must
+/// be kept while the instrumented instance runs.
+pub fn install_hooks(instance: &Instance) -> Result<Option<Instance>> {
+    let description = match instance.module().custom_sections(TRACE_CALLS).next() {
+        Some(description) => description,
+        None => return Ok(None),
+    };
+    let table = instance.exports.get_table(TRACE_CALLS)?;
+    let store = instance.module().store();
+    let depth = Arc::new(AtomicUsize::new(0));
+    let mut reader = Reader::new(&description);
+    let mut hooks = vec![];
+    for _ in 0..reader.read_u32()? {
+        let name: Arc<str> = Arc::from(reader.read_name()?);
+        let params = reader.read_types()?;
+        let results = reader.read_types()?;
+
+        let enter = {
+            let (name, depth) = (name.clone(), depth.clone());
+            let no_results: &[Type] = &[];
+            Function::new(store, FunctionType::new(params, no_results), move |args| {
+                let depth = depth.fetch_add(1, Ordering::SeqCst);
+                eprintln!("{}-> {}({})", "  ".repeat(depth), name, join_values(args));
+                Ok(vec![])
+            })
+        };
+        let exit = {
+            let depth = depth.clone();
+            Function::new(
+                store,
+                FunctionType::new(results.clone(), results),
+                move |results| {
+                    let depth = depth.fetch_sub(1, Ordering::SeqCst).saturating_sub(1);
+                    if results.is_empty() {
+                        eprintln!("{}<- {}", "  ".repeat(depth), name);
+                    } else {
+                        eprintln!(
+                            "{}<- {} = {}",
+                            "  ".repeat(depth),
+                            name,
+                            join_values(results)
+                        );
+                    }
+                    Ok(results.to_vec())
+                },
+            )
+        };
+        hooks.push(enter);
+        hooks.push(exit);
+    }
+
+    let forwarding_module = Module::new(store, forwarding_module(&hooks))
+        .context("failed to compile the `--trace-calls` hooks")?;
+    let count = hooks.len() as u32;
+    let mut namespace = Exports::new();
+    for (index, hook) in hooks.into_iter().enumerate() {
+        namespace.insert(index.to_string(), hook);
+    }
+    let mut imports = ImportObject::new();
+    imports.register(FORWARDED_NAMESPACE, namespace);
+    let forwarding = Instance::new(&forwarding_module, &imports)?;
+    for index in 0..count {
+        let hook = forwarding.exports.get_function(&index.to_string())?;
+        table.set(index, Val::FuncRef(Some(hook.clone())))?;
+    }
+    Ok(Some(forwarding))
+}
+
+fn join_values(values: &[Val]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A name section naming the functions in `new_names` too, whose indices
+/// must be greater than the ones already named.
+fn append_function_names(data: &[u8], new_names: &[(u32, &str)]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(data);
+    let mut section = vec![];
+    write_name(&mut section, reader.read_name()?);
+    while !reader.is_empty() {
+        let id = reader.read_u8()?;
+        let size = reader.read_u32()?;
+        let subsection = reader.read_bytes(size as usize)?;
+        let subsection = if id == 1 {
+            let mut entries = vec![];
+            for (index, name) in new_names {
+                write_u32(&mut entries, *index);
+                write_name(&mut entries, name);
+            }
+            append_entries(subsection, new_names.len() as u32, &entries)?
+        } else {
+            subsection.to_vec()
+        };
+        section.push(id);
+        write_u32(&mut section, subsection.len() as u32);
+        section.extend(subsection);
+    }
+    Ok(section)
+}
+
+/// A `funcref` table holding exactly `size` elements.
+fn table_entry(size: u32) -> Vec<u8> {
+    let mut entry = vec![0x70, 0x01];
+    write_u32(&mut entry, size);
+    write_u32(&mut entry, size);
+    entry
+}
+
+/// The export of the table of hooks.
+fn export_entry(table: u32) -> Vec<u8> {
+    let mut entry = vec![];
+    write_name(&mut entry, TRACE_CALLS);
+    entry.push(0x01);
+    write_u32(&mut entry, table);
+    entry
+}
+
+fn value_type_code(ty: wasmer::wasmparser::Type) -> Result<u8> {
+    use wasmer::wasmparser::Type as WpType;
+    Ok(match ty {
+        WpType::I32 => 0x7f,
+        WpType::I64 => 0x7e,
+        WpType::F32 => 0x7d,
+        WpType::F64 => 0x7c,
+        WpType::V128 => 0x7b,
+        WpType::FuncRef => 0x70,
+        WpType::ExternRef => 0x6f,
+        WpType::ExnRef | WpType::Func | WpType::EmptyBlockType => {
+            bail!("`--trace-calls` doesn't support the value type {:?}", ty)
+        }
+    })
+}
@@ -0,0 +1,127 @@
+//! Trap when a module grows its memory, for `wasmer run --trap-on-grow`.
+//!
+//! The module is compiled with a middleware replacing every `memory.grow`:
+//! the requested number of pages is stored in an exported global, and the
+//! module traps with `unreachable` if it isn't `0`. `memory.grow 0` only
+//! returns the current size, so it's replaced with `memory.size`. Once
+//! the module has trapped, the global tells the growth from the other
+//! `unreachable` traps.
+
+use anyhow::Result;
+#[cfg(feature = "compiler")]
+pub use middleware::TrapOnGrow;
+use wasmer::{Instance, Val};
+
+/// The exported global holding the number of pages of the last
+/// `memory.grow`.
+const GROW_DELTA_GLOBAL: &str = "wasmer_trap_on_grow_delta";
+
+#[cfg(feature = "compiler")]
+mod middleware {
+    use super::GROW_DELTA_GLOBAL;
+    use loupe::{MemoryUsage, MemoryUsageTracker};
+    use std::mem;
+    use std::sync::Mutex;
+    use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType};
+    use wasmer::{
+        FunctionMiddleware, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState,
+        ModuleMiddleware,
+    };
+    use wasmer_types::{
+        ExportIndex, GlobalIndex, GlobalInit, GlobalType, ModuleInfo, Mutability, Type,
+    };
+
+    /// The module-level middleware adding the global of the requested
+    /// pages.
+    #[derive(Debug, Default)]
+    pub struct TrapOnGrow {
+        delta_global: Mutex<Option<GlobalIndex>>,
+    }
+
+    impl TrapOnGrow {
+        /// Creates a `TrapOnGrow` middleware.
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl MemoryUsage for TrapOnGrow {
+        fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+            mem::size_of_val(self)
+        }
+    }
+
+    impl ModuleMiddleware for TrapOnGrow {
+        fn generate_function_middleware(
+            &self,
+            _: LocalFunctionIndex,
+        ) -> Box<dyn FunctionMiddleware> {
+            Box::new(FunctionTrapOnGrow {
+                delta_global: self.delta_global.lock().unwrap().unwrap(),
+            })
+        }
+
+        fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+            let delta_global = module_info
+                .globals
+                .push(GlobalType::new(Type::I32, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::I32Const(0));
+            module_info.exports.insert(
+                GROW_DELTA_GLOBAL.to_string(),
+                ExportIndex::Global(delta_global),
+            );
+            *self.delta_global.lock().unwrap() = Some(delta_global);
+        }
+    }
+
+    /// The function-level middleware replacing `memory.grow`.
+    #[derive(Debug)]
+    struct FunctionTrapOnGrow {
+        delta_global: GlobalIndex,
+    }
+
+    impl FunctionMiddleware for FunctionTrapOnGrow {
+        fn feed<'a>(
+            &mut self,
+            operator: Operator<'a>,
+            state: &mut MiddlewareReaderState<'a>,
+        ) -> Result<(), MiddlewareError> {
+            match operator {
+                Operator::MemoryGrow { mem, mem_byte } => {
+                    let global_index = self.delta_global.as_u32();
+                    state.extend(&[
+                        Operator::GlobalSet { global_index },
+                        Operator::GlobalGet { global_index },
+                        Operator::If {
+                            ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+                        },
+                        Operator::Unreachable,
+                        Operator::End,
+                        Operator::MemorySize { mem, mem_byte },
+                    ]);
+                }
+                _ => state.push_operator(operator),
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Explain the trap of `result` if `instance` trapped growing its memory.
+pub fn check_memory_growth<T>(instance: &Instance, result: Result<T>) -> Result<T> {
+    result.map_err(|err| {
+        match instance
+            .exports
+            .get_global(GROW_DELTA_GLOBAL)
+            .map(|global| global.get())
+        {
+            Ok(Val::I32(delta)) if delta != 0 => err.context(format!(
+                "the module tried to grow its memory by {} pages, and `--trap-on-grow` is set",
+                delta as u32
+            )),
+            _ => err,
+        }
+    })
+}
@@ -0,0 +1,72 @@
+//! Structured trap information for `wasmer run --capture-trap-json`.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use wasmer::{FrameInfo, RuntimeError};
+use wasmer_vm::TrapCode;
+
+/// The trap of a module, with the Wasm call stack that led to it.
+#[derive(Debug, Serialize)]
+pub struct TrapReport {
+    message: String,
+    /// `None` for errors raised by host functions.
+    trap_code: Option<TrapCode>,
+    /// The index of the function where the trap occurred, if known.
+    function_index: Option<u32>,
+    /// The offset of the trapping instruction in that function.
+    function_offset: Option<usize>,
+    /// The Wasm call stack, innermost frame first.
+    frames: Vec<TrapFrame>,
+}
+
+/// A frame of the Wasm call stack.
+#[derive(Debug, Serialize)]
+struct TrapFrame {
+    module_name: String,
+    function_index: u32,
+    function_name: Option<String>,
+    module_offset: usize,
+    function_offset: usize,
+}
+
+impl From<&FrameInfo> for TrapFrame {
+    fn from(frame: &FrameInfo) -> Self {
+        Self {
+            module_name: frame.module_name().to_string(),
+            function_index: frame.func_index(),
+            function_name: frame.function_name().map(str::to_string),
+            module_offset: frame.module_offset(),
+            function_offset: frame.func_offset(),
+        }
+    }
+}
+
+impl TrapReport {
+    /// Build the report from the error of a run, if it was caused by a
+    /// trap.
+    pub fn from_error(error: &anyhow::Error) -> Option<Self> {
+        let runtime_error = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<RuntimeError>())?;
+        let frames = runtime_error
+            .trace()
+            .iter()
+            .map(TrapFrame::from)
+            .collect::<Vec<_>>();
+        Some(Self {
+            message: runtime_error.message(),
+            trap_code: runtime_error.clone().to_trap(),
+            function_index: frames.first().map(|frame| frame.function_index),
+            function_offset: frames.first().map(|frame| frame.function_offset),
+            frames,
+        })
+    }
+
+    /// Write the report as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
@@ -0,0 +1,113 @@
+//! Disassembly of the trapping function, for
+//! `wasmer run --print-wat-on-trap`.
+//!
+//! The module is printed with `wasmprinter`, which writes every
+//! instruction of a function body on its own line, in order. The offsets
+//! of the instructions are read again with `wasmparser`, so the line of
+//! the trapping instruction is the one of the instruction at the offset
+//! of the innermost Wasm frame.
+
+use anyhow::{Context, Result};
+use wasmer::wasmparser::{ImportSectionEntryType, Parser, Payload};
+use wasmer::RuntimeError;
+
+/// The location of a trap in the original module.
+struct TrapLocation {
+    /// The index of the function, counting the imported functions.
+    function_index: u32,
+    /// The offset of the trapping instruction in the module.
+    module_offset: usize,
+}
+
+/// Print the function of `wasm` where the trap of `error` occurred as
+/// WAT, with the offset of each instruction and the trapping instruction
+/// marked.
+///
+/// Nothing is printed if `error` wasn't caused by a trap in a Wasm
+/// function.
+pub fn print_trapping_function(wasm: &[u8], error: &anyhow::Error) -> Result<()> {
+    let location = match trap_location(error) {
+        Some(location) => location,
+        None => return Ok(()),
+    };
+    let (offsets, imported_functions) = instruction_offsets(wasm, location.function_index)?;
+    let local_index = (location.function_index - imported_functions) as usize;
+    let wat = wasmprinter::print_bytes(wasm).context("failed to disassemble the module")?;
+    let lines = wat.lines().collect::<Vec<_>>();
+    let header_position = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.starts_with("  (func "))
+        .nth(local_index)
+        .map(|(position, _)| position)
+        .context("the trapping function wasn't found in the disassembled module")?;
+    let mut body = lines[header_position + 1..].iter().peekable();
+
+    // The last offset is the one of the `end` of the body, which isn't
+    // printed
+    let trapping_instruction = offsets
+        .iter()
+        .rposition(|offset| *offset <= location.module_offset)
+        .unwrap_or(0);
+    eprintln!(
+        "Trapping function (index {}, offset {:#x}):",
+        location.function_index, location.module_offset
+    );
+    eprintln!("{:>10}  {}", "", lines[header_position].trim_start());
+    while let Some(line) = body.next_if(|line| line.trim_start().starts_with("(local ")) {
+        eprintln!("{:>10}  {}", "", line.trim_start());
+    }
+    for (index, (line, offset)) in body.zip(&offsets[..offsets.len() - 1]).enumerate() {
+        let marker = if index == trapping_instruction {
+            "-->"
+        } else {
+            ""
+        };
+        eprintln!("{:>3} {:#06x}  {}", marker, offset, &line[2..]);
+    }
+    Ok(())
+}
+
+/// The location of the trap of `error`, in its innermost Wasm frame.
+fn trap_location(error: &anyhow::Error) -> Option<TrapLocation> {
+    let runtime_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<RuntimeError>())?;
+    let frame = runtime_error.trace().first()?;
+    Some(TrapLocation {
+        function_index: frame.func_index(),
+        module_offset: frame.module_offset(),
+    })
+}
+
+/// The offsets of the instructions of the function at `function_index`
+/// in `wasm`, including the `end` of its body, and the number of
+/// imported functions.
+fn instruction_offsets(wasm: &[u8], function_index: u32) -> Result<(Vec<usize>, u32)> {
+    let mut imported_functions = 0;
+    let mut local_index = 0;
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    if let ImportSectionEntryType::Function(_) = import?.ty {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                if imported_functions + local_index == function_index {
+                    let mut operators = body.get_operators_reader()?;
+                    let mut offsets = vec![];
+                    while !operators.eof() {
+                        offsets.push(operators.read_with_offset()?.1);
+                    }
+                    return Ok((offsets, imported_functions));
+                }
+                local_index += 1;
+            }
+            _ => {}
+        }
+    }
+    bail!("the module has no function at index {}", function_index)
+}
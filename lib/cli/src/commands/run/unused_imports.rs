@@ -0,0 +1,238 @@
+//! Report the imported functions a module never called, for `wasmer run
+//! --report-unused-imports` and `--unused-imports-json`.
+//!
+//! The module is compiled with a middleware adding an exported `i64`
+//! global for every imported function, and incrementing it before every
+//! direct call to the function. Once the module has run, the imported
+//! functions whose counter is still `0` are reported.
+//!
+//! The calls through a table (`call_indirect`) and the calls made by the
+//! runtime (the start function, or the host calling an export) can't be
+//! attributed to a function: the imported functions placed in a table,
+//! exported or used as the start function are reported as unused only if
+//! they're never called directly, and flagged as possibly called
+//! indirectly.
+
+use anyhow::{Context, Result};
+#[cfg(feature = "compiler")]
+pub use middleware::ImportCallCounters;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use wasmer::{Instance, Val};
+use wasmer_types::{ExportIndex, FunctionIndex, ImportIndex};
+
+/// The prefix of the exported globals counting the calls to the imported
+/// functions, followed by the index of the function.
+const CALL_COUNTER_PREFIX: &str = "wasmer_import_calls_";
+
+#[cfg(feature = "compiler")]
+mod middleware {
+    use super::CALL_COUNTER_PREFIX;
+    use loupe::{MemoryUsage, MemoryUsageTracker};
+    use std::mem;
+    use std::sync::Mutex;
+    use wasmer::wasmparser::Operator;
+    use wasmer::{
+        FunctionMiddleware, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState,
+        ModuleMiddleware,
+    };
+    use wasmer_types::{
+        ExportIndex, GlobalIndex, GlobalInit, GlobalType, ModuleInfo, Mutability, Type,
+    };
+
+    /// The counters of the calls, added to the module.
+    #[derive(Debug, Clone, Copy)]
+    struct Counters {
+        /// The counter of the first imported function, the others follow.
+        first_global: GlobalIndex,
+        imported_functions: u32,
+    }
+
+    /// The module-level middleware adding a call counter for every
+    /// imported function.
+    #[derive(Debug, Default)]
+    pub struct ImportCallCounters {
+        counters: Mutex<Option<Counters>>,
+    }
+
+    impl ImportCallCounters {
+        /// Creates an `ImportCallCounters` middleware.
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl MemoryUsage for ImportCallCounters {
+        fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+            mem::size_of_val(self)
+        }
+    }
+
+    impl ModuleMiddleware for ImportCallCounters {
+        fn generate_function_middleware(
+            &self,
+            _: LocalFunctionIndex,
+        ) -> Box<dyn FunctionMiddleware> {
+            Box::new(FunctionImportCallCounters {
+                counters: self.counters.lock().unwrap().unwrap(),
+            })
+        }
+
+        fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+            let imported_functions = module_info.num_imported_functions as u32;
+            let first_global = GlobalIndex::from_u32(module_info.globals.len() as u32);
+            for index in 0..imported_functions {
+                let counter = module_info
+                    .globals
+                    .push(GlobalType::new(Type::I64, Mutability::Var));
+                module_info
+                    .global_initializers
+                    .push(GlobalInit::I64Const(0));
+                module_info.exports.insert(
+                    format!("{}{}", CALL_COUNTER_PREFIX, index),
+                    ExportIndex::Global(counter),
+                );
+            }
+            *self.counters.lock().unwrap() = Some(Counters {
+                first_global,
+                imported_functions,
+            });
+        }
+    }
+
+    /// The function-level middleware counting the calls.
+    #[derive(Debug)]
+    struct FunctionImportCallCounters {
+        counters: Counters,
+    }
+
+    impl FunctionMiddleware for FunctionImportCallCounters {
+        fn feed<'a>(
+            &mut self,
+            operator: Operator<'a>,
+            state: &mut MiddlewareReaderState<'a>,
+        ) -> Result<(), MiddlewareError> {
+            if let Operator::Call { function_index } = operator {
+                if function_index < self.counters.imported_functions {
+                    let global_index = self.counters.first_global.as_u32() + function_index;
+                    state.extend(&[
+                        Operator::GlobalGet { global_index },
+                        Operator::I64Const { value: 1 },
+                        Operator::I64Add,
+                        Operator::GlobalSet { global_index },
+                    ]);
+                }
+            }
+            state.push_operator(operator);
+            Ok(())
+        }
+    }
+}
+
+/// How often an imported function was called.
+#[derive(Debug, Serialize)]
+pub struct ImportUsage {
+    module: String,
+    name: String,
+    signature: String,
+    /// The number of direct calls.
+    calls: u64,
+    /// Whether the function is in a table, exported or the start function,
+    /// so it may have been called without being counted.
+    maybe_called_indirectly: bool,
+}
+
+/// The calls to the imported functions of an instance.
+#[derive(Debug, Serialize)]
+pub struct ImportUsageReport {
+    imports: Vec<ImportUsage>,
+}
+
+impl ImportUsageReport {
+    /// Read the call counters of `instance`.
+    pub fn from_instance(instance: &Instance) -> Result<Self> {
+        let info = instance.module().info();
+        let mut escaping: HashSet<FunctionIndex> = info
+            .table_initializers
+            .iter()
+            .flat_map(|initializer| initializer.elements.iter().copied())
+            .chain(
+                info.passive_elements
+                    .values()
+                    .flat_map(|elements| elements.iter().copied()),
+            )
+            .chain(info.start_function)
+            .collect();
+        escaping.extend(info.exports.values().filter_map(|export| match export {
+            ExportIndex::Function(index) => Some(*index),
+            _ => None,
+        }));
+
+        let mut imports = vec![];
+        for ((module, name, _), import) in &info.imports {
+            let index = match import {
+                ImportIndex::Function(index) => *index,
+                _ => continue,
+            };
+            let counter = instance
+                .exports
+                .get_global(&format!("{}{}", CALL_COUNTER_PREFIX, index.as_u32()))
+                .context("the module has no import call counters")?;
+            let calls = match counter.get() {
+                Val::I64(calls) => calls as u64,
+                _ => 0,
+            };
+            imports.push(ImportUsage {
+                module: module.clone(),
+                name: name.clone(),
+                signature: info.signatures[info.functions[index]].to_string(),
+                calls,
+                maybe_called_indirectly: escaping.contains(&index),
+            });
+        }
+        Ok(Self { imports })
+    }
+
+    /// Print the imported functions that were never called on stderr.
+    pub fn print(&self) {
+        let unused = self
+            .imports
+            .iter()
+            .filter(|import| import.calls == 0)
+            .collect::<Vec<_>>();
+        if unused.is_empty() {
+            eprintln!(
+                "All the {} imported functions were called.",
+                self.imports.len()
+            );
+            return;
+        }
+        eprintln!(
+            "Unused imports: {} of the {} imported functions were never called:",
+            unused.len(),
+            self.imports.len()
+        );
+        for import in unused {
+            eprintln!(
+                "  \"{}\".\"{}\": {}{}",
+                import.module,
+                import.name,
+                import.signature,
+                if import.maybe_called_indirectly {
+                    " (may have been called indirectly)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    /// Write the report as JSON, with the calls to every imported
+    /// function.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
@@ -1,13 +1,22 @@
-use crate::utils::{parse_envvar, parse_mapdir};
+use super::capture_output::CapturedOutput;
+use super::deny_syscalls::{self, DenyMode};
+use super::sandbox::SandboxProfile;
+use super::{deterministic, record};
+use crate::utils::{parse_env_file, parse_envvar, parse_mapdir};
 use anyhow::{Context, Result};
 use std::collections::BTreeSet;
-use std::path::PathBuf;
-use wasmer::{Instance, Module};
-use wasmer_wasi::{get_wasi_versions, WasiError, WasiState, WasiVersion};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use wasmer::{ChainableNamedResolver, Instance, Module};
+use wasmer_vfs::host_fs;
+use wasmer_wasi::{get_wasi_versions, VirtualFile, WasiError, WasiState, WasiVersion};
 
 use structopt::StructOpt;
 
-#[derive(Debug, StructOpt, Clone)]
+#[derive(Debug, StructOpt, Clone, Default)]
 /// WASI Options
 pub struct Wasi {
     /// WASI pre-opened directory
@@ -18,10 +27,42 @@ pub struct Wasi {
     #[structopt(long = "mapdir", name = "GUEST_DIR:HOST_DIR", multiple = true, parse(try_from_str = parse_mapdir))]
     mapped_dirs: Vec<(String, PathBuf)>,
 
+    /// Restrict what the module can do in the directories of `--dir`,
+    /// `--mapdir` and `--working-dir`, with Unix permission bits in octal,
+    /// like `0444`.
+    ///
+    /// The mode sets the WASI rights of the preopened directories: any read
+    /// bit allows reading, and any write bit allows writing and creating
+    /// files, so `0444` makes them read-only for the module. It doesn't
+    /// change the permission bits the module sees, which WASI doesn't
+    /// report, nor the host files.
+    #[structopt(long = "dir-access-mode", name = "MODE", parse(try_from_str = parse_dir_access_mode))]
+    dir_access_mode: Option<u32>,
+
+    /// Set the working directory of the module, a guest path of (or
+    /// inside) a directory of `--dir` or `--mapdir`.
+    ///
+    /// WASI has no working directory: modules resolve their relative paths
+    /// from the directory preopened as `.`. The host directory of this path
+    /// is preopened as `.`, so it can't be combined with another `.`
+    /// directory, and `PWD` is set to it unless `--env` sets it.
+    #[structopt(long = "working-dir", name = "WORKING_DIR")]
+    working_dir: Option<String>,
+
     /// Pass custom environment variables
     #[structopt(long = "env", name = "KEY=VALUE", multiple = true, parse(try_from_str = parse_envvar))]
     env_vars: Vec<(String, String)>,
 
+    /// Pass the environment variables of a dotenv-style file, with a
+    /// `KEY=VALUE` pair per line.
+    ///
+    /// Lines starting with `#` are comments, and values can be quoted:
+    /// `'...'` is taken literally, `"..."` understands `\n`, `\"` and
+    /// `\\`. The variables of the file are overridden by `--env` (and by
+    /// the `env` table of `--sandbox-profile`).
+    #[structopt(long = "env-file", name = "ENV_FILE", parse(from_os_str))]
+    env_file: Option<PathBuf>,
+
     /// Enable experimental IO devices
     #[cfg(feature = "experimental-io-devices")]
     #[structopt(long = "enable-experimental-io-devices")]
@@ -34,6 +75,141 @@ pub struct Wasi {
     /// Require WASI modules to only import 1 version of WASI.
     #[structopt(long = "deny-multiple-wasi-versions")]
     pub deny_multiple_wasi_versions: bool,
+
+    /// Write the WASI stdout of the module to this file, instead of the
+    /// stdout of `wasmer`. The file is created or truncated.
+    #[structopt(long = "stdout-file", parse(from_os_str))]
+    stdout_file: Option<PathBuf>,
+
+    /// Write the WASI stderr of the module to this file, instead of the
+    /// stderr of `wasmer`. The file is created or truncated.
+    #[structopt(long = "stderr-file", parse(from_os_str))]
+    stderr_file: Option<PathBuf>,
+
+    /// Capture the WASI stdout and stderr of the module, instead of
+    /// writing them to the ones of `wasmer`, and write them with the exit
+    /// code of the module (or the error it failed with) to this JSON file.
+    ///
+    /// Each stream is written as `{"utf8": "..."}` when it's valid UTF-8,
+    /// and as `{"base64": "..."}` otherwise. The exit hook isn't captured.
+    #[structopt(
+        long = "capture-output",
+        name = "CAPTURE PATH",
+        parse(from_os_str),
+        conflicts_with_all = &["stdout-file", "stderr-file", "replay", "INSTANCES", "watch"]
+    )]
+    pub capture_output: Option<PathBuf>,
+
+    /// The streams captured for `--capture-output`.
+    #[structopt(skip)]
+    pub captured_output: CapturedOutput,
+
+    /// Make the nondeterministic WASI syscalls reproducible, for testing.
+    ///
+    /// The clocks always return `SOURCE_DATE_EPOCH` (or `0` if unset),
+    /// `random_get` returns bytes from a fixed seed and the environment
+    /// variables are sorted by name. Don't use it in production.
+    #[structopt(long = "deterministic-wasi")]
+    deterministic: bool,
+
+    /// Seed the pseudo-random generator of `random_get` with this value,
+    /// so the module gets the same random bytes on every run.
+    ///
+    /// Unlike `--deterministic-wasi`, the clocks are left untouched. With
+    /// it, the seed replaces its fixed one. The random bytes are
+    /// predictable, so it's only meant for testing and fuzzing.
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
+
+    /// Record the WASI syscalls of the module, with their results and the
+    /// data they return, to this trace file, to replay them with
+    /// `--replay`.
+    ///
+    /// Only `wasi_snapshot_preview1` and `wasi_unstable` modules can be
+    /// recorded. Every syscall is recorded except `args_*`, `environ_*`, `proc_exit` and
+    /// `proc_raise`, which are derived from the command line or don't
+    /// return.
+    #[structopt(
+        long = "record",
+        name = "TRACE PATH",
+        parse(from_os_str),
+        conflicts_with = "INSTANCES"
+    )]
+    pub record: Option<PathBuf>,
+
+    /// Replay the WASI syscalls recorded with `--record` instead of running
+    /// them on the host.
+    ///
+    /// The trace must have been recorded with the same module, arguments
+    /// and environment variables. The replay fails at the first syscall
+    /// that doesn't match the trace. The data written to the stdout and
+    /// stderr is printed again.
+    #[structopt(
+        long = "replay",
+        parse(from_os_str),
+        conflicts_with_all = &["TRACE PATH", "INSTANCES"]
+    )]
+    pub replay: Option<PathBuf>,
+
+    /// Deny these WASI syscalls to the module, as a comma-separated list of
+    /// their names, like `fd_write,clock_time_get`.
+    ///
+    /// The names are the ones of the `wasi_snapshot_preview1` imports. A
+    /// denied syscall returns an error to the module without reaching the
+    /// host, or traps with `--deny-mode trap`.
+    #[structopt(
+        long = "deny-syscall",
+        alias = "restrict-syscalls",
+        name = "SYSCALLS",
+        use_delimiter = true,
+        number_of_values = 1,
+        parse(try_from_str = deny_syscalls::parse_syscall)
+    )]
+    deny_syscall: Vec<String>,
+
+    /// What the syscalls of `--deny-syscall` do: `error` (or `enosys`),
+    /// `eperm`, `eacces` and `enotcapable` return that error, `trap`
+    /// stops the module.
+    #[structopt(long = "deny-mode", default_value = "error")]
+    deny_mode: DenyMode,
+
+    /// Limit the number of WASI file descriptors open at once, including
+    /// the standard streams and the preopened directories.
+    ///
+    /// Opening more files fails with `EMFILE` in the module.
+    #[structopt(long = "limit-open-files", name = "N")]
+    limit_open_files: Option<u32>,
+
+    /// Read the stdin of `wasmer` into a file at this guest path, for
+    /// programs that only read their input from files.
+    ///
+    /// The parent directory of the path is mapped to a temporary host
+    /// directory holding the file, so it can't also be passed to `--dir`
+    /// or `--mapdir`.
+    #[structopt(long = "preopen-stdin-as", name = "GUEST_PATH")]
+    preopen_stdin_as: Option<String>,
+
+    /// Preopen a file descriptor inherited from the parent process, like a
+    /// listening socket or a pipe, as `HOST_FD:NAME`.
+    ///
+    /// The descriptors get the guest file descriptors following the
+    /// preopened directories, in order. WASI can only describe preopened
+    /// directories, so the module finds the file descriptor of `NAME` in
+    /// the `WASMER_PREOPEN_FD_NAME` environment variable. On Windows,
+    /// `HOST_FD` is the value of a handle, which must have been created
+    /// inheritable and inherited by `wasmer`.
+    #[structopt(
+        long = "preopen-fd",
+        name = "HOST_FD:NAME",
+        multiple = true,
+        parse(try_from_str = parse_preopen_fd)
+    )]
+    preopen_fds: Vec<(u32, String)>,
+
+    /// The host directory holding the `--preopen-stdin-as` file, kept
+    /// until the module has run.
+    #[structopt(skip)]
+    stdin_dir: Arc<Mutex<Option<TempDir>>>,
 }
 
 #[allow(dead_code)]
@@ -52,16 +228,101 @@ impl Wasi {
         get_wasi_versions(&module, false).is_some()
     }
 
-    /// Helper function for executing Wasi from the `Run` command.
-    pub fn execute(&self, module: Module, program_name: String, args: Vec<String>) -> Result<()> {
+    /// Helper function for instantiating a module with the WASI imports.
+    pub fn instantiate(
+        &self,
+        module: &Module,
+        program_name: String,
+        args: Vec<String>,
+    ) -> Result<Instance> {
         let args = args.iter().cloned().map(|arg| arg.into_bytes());
 
+        let mut env_vars = self.env_vars()?;
+        if let Some(working_dir) = &self.working_dir {
+            if !env_vars.iter().any(|(key, _)| key == "PWD") {
+                env_vars.push(("PWD".to_string(), working_dir.clone()));
+            }
+        }
+        if self.deterministic {
+            env_vars.sort();
+        }
+
         let mut wasi_state_builder = WasiState::new(program_name);
-        wasi_state_builder
-            .args(args)
-            .envs(self.env_vars.clone())
-            .preopen_dirs(self.pre_opened_directories.clone())?
-            .map_dirs(self.mapped_dirs.clone())?;
+        wasi_state_builder.args(args).envs(env_vars);
+        match self.dir_access_mode {
+            Some(mode) => {
+                let (read, write) = (mode & 0o444 != 0, mode & 0o222 != 0);
+                for dir in &self.pre_opened_directories {
+                    wasi_state_builder
+                        .preopen(|p| p.directory(dir).read(read).write(write).create(write))?;
+                }
+                for (alias, dir) in &self.mapped_dirs {
+                    wasi_state_builder.preopen(|p| {
+                        p.directory(dir)
+                            .alias(alias)
+                            .read(read)
+                            .write(write)
+                            .create(write)
+                    })?;
+                }
+            }
+            None => {
+                wasi_state_builder
+                    .preopen_dirs(self.pre_opened_directories.clone())?
+                    .map_dirs(self.mapped_dirs.clone())?;
+            }
+        }
+
+        if let Some(working_dir) = &self.working_dir {
+            let host_dir = self.working_dir_host_path(working_dir)?;
+            let (read, write) = self
+                .dir_access_mode
+                .map_or((true, true), |mode| (mode & 0o444 != 0, mode & 0o222 != 0));
+            wasi_state_builder.preopen(|p| {
+                p.directory(&host_dir)
+                    .alias(".")
+                    .read(read)
+                    .write(write)
+                    .create(write)
+            })?;
+        }
+
+        if let Some(guest_path) = &self.preopen_stdin_as {
+            let (guest_dir, host_dir) = self.materialize_stdin(guest_path)?;
+            wasi_state_builder.map_dir(&guest_dir, host_dir.path())?;
+            *self.stdin_dir.lock().unwrap() = Some(host_dir);
+        }
+
+        for (host_fd, name) in &self.preopen_fds {
+            let file = inherited_file(*host_fd)?;
+            wasi_state_builder.preopen_file(
+                name,
+                Box::new(host_fs::File::new(
+                    file,
+                    PathBuf::from(format!("/dev/fd/{}", host_fd)),
+                    true,
+                    true,
+                    false,
+                )),
+            )?;
+        }
+
+        if let Some(limit) = self.limit_open_files {
+            wasi_state_builder.open_files_limit(limit);
+        }
+
+        if self.capture_output.is_some() {
+            self.captured_output.clear();
+            wasi_state_builder
+                .stdout(self.captured_output.stdout())
+                .stderr(self.captured_output.stderr());
+        }
+        if let Some(stdout_file) = &self.stdout_file {
+            wasi_state_builder.stdout(Self::create_output_file(stdout_file)?);
+        }
+        if let Some(stderr_file) = &self.stderr_file {
+            wasi_state_builder.stderr(Self::create_output_file(stderr_file)?);
+        }
 
         #[cfg(feature = "experimental-io-devices")]
         {
@@ -72,19 +333,255 @@ impl Wasi {
         }
 
         let mut wasi_env = wasi_state_builder.finalize()?;
-        let resolver = wasi_env.import_object_for_all_wasi_versions(&module)?;
-        let instance = Instance::new(&module, &resolver)?;
+        let mut resolver = wasi_env.import_object_for_all_wasi_versions(module)?;
+        if self.deterministic {
+            let wasi_versions = get_wasi_versions(module, false).unwrap_or_default();
+            resolver = Box::new(
+                deterministic::generate_import_object(module.store(), &wasi_versions, self.seed)?
+                    .chain_back(resolver),
+            );
+        } else if let Some(seed) = self.seed {
+            let wasi_versions = get_wasi_versions(module, false).unwrap_or_default();
+            resolver = Box::new(
+                deterministic::generate_seeded_import_object(module.store(), &wasi_versions, seed)
+                    .chain_back(resolver),
+            );
+        }
+        if !self.deny_syscall.is_empty() {
+            resolver = Box::new(
+                deny_syscalls::generate_import_object(module, &self.deny_syscall, self.deny_mode)?
+                    .chain_back(resolver),
+            );
+        }
+        if let Some(trace_path) = &self.record {
+            let recording =
+                record::generate_recording_import_object(module, &resolver, trace_path)?;
+            resolver = Box::new(recording.chain_back(resolver));
+        }
+        if let Some(trace_path) = &self.replay {
+            resolver = Box::new(
+                record::generate_replaying_import_object(module, trace_path)?.chain_back(resolver),
+            );
+        }
+        Ok(Instance::new(module, &resolver)?)
+    }
+
+    /// Find the host directory of the `--working-dir` guest path, in the
+    /// innermost directory of `--dir` or `--mapdir` holding it.
+    fn working_dir_host_path(&self, working_dir: &str) -> Result<PathBuf> {
+        // The leading `/` of the guest paths is optional
+        let guest_path = |path: &str| PathBuf::from(path.trim_start_matches('/'));
+        let preopened = self
+            .pre_opened_directories
+            .iter()
+            .map(|dir| (dir.to_string_lossy().into_owned(), dir.clone()))
+            .chain(self.mapped_dirs.iter().cloned())
+            .collect::<Vec<_>>();
+        if preopened
+            .iter()
+            .any(|(guest_dir, _)| guest_path(guest_dir) == Path::new("."))
+            || self
+                .preopen_stdin_as
+                .as_ref()
+                .map_or(false, |path| !path.contains('/'))
+        {
+            bail!("`--working-dir` can't be used with a directory preopened as `.`");
+        }
+
+        let working_dir_path = guest_path(working_dir);
+        let (relative_path, host_dir) = preopened
+            .iter()
+            .filter_map(|(guest_dir, host_dir)| {
+                let relative_path = working_dir_path.strip_prefix(guest_path(guest_dir)).ok()?;
+                Some((relative_path.to_path_buf(), host_dir))
+            })
+            .min_by_key(|(relative_path, _)| relative_path.components().count())
+            .with_context(|| {
+                format!(
+                    "the working directory `{}` isn't in a directory of `--dir` or `--mapdir`",
+                    working_dir
+                )
+            })?;
+        let host_path = host_dir.join(relative_path);
+        if !host_path.is_dir() {
+            bail!(
+                "the working directory `{}` doesn't exist (`{}` on the host)",
+                working_dir,
+                host_path.display()
+            );
+        }
+        Ok(host_path)
+    }
+
+    /// Write the stdin of `wasmer` to a temporary host directory, with the
+    /// file name of `guest_path`, and return the guest directory to map it
+    /// to.
+    fn materialize_stdin(&self, guest_path: &str) -> Result<(String, TempDir)> {
+        let (guest_dir, file_name) = match guest_path.rsplit_once('/') {
+            Some(("", file_name)) => ("/", file_name),
+            Some((guest_dir, file_name)) => (guest_dir, file_name),
+            None => (".", guest_path),
+        };
+        if file_name.is_empty() || file_name == "." || file_name == ".." {
+            bail!(
+                "`--preopen-stdin-as` expects a file path, got `{}`",
+                guest_path
+            );
+        }
+        if self
+            .mapped_dirs
+            .iter()
+            .any(|(alias, _)| alias.trim_end_matches('/') == guest_dir)
+            || self
+                .pre_opened_directories
+                .iter()
+                .any(|dir| dir == Path::new(guest_dir))
+        {
+            bail!(
+                "the directory `{}` of `--preopen-stdin-as` is already mapped",
+                guest_dir
+            );
+        }
+
+        let host_dir = tempfile::tempdir()?;
+        let mut input = Vec::new();
+        io::stdin()
+            .read_to_end(&mut input)
+            .context("failed to read stdin")?;
+        fs::write(host_dir.path().join(file_name), input)?;
+        Ok((guest_dir.to_string(), host_dir))
+    }
 
+    /// Create (or truncate) a host file to redirect a standard stream to.
+    fn create_output_file(path: &Path) -> Result<Box<dyn VirtualFile>> {
+        let file = fs::File::create(path)
+            .with_context(|| format!("failed to create `{}`", path.display()))?;
+        Ok(Box::new(host_fs::File::new(
+            file,
+            path.to_path_buf(),
+            false,
+            true,
+            false,
+        )))
+    }
+
+    /// The WASI options of the `--on-exit-hook` module: the same
+    /// directories, environment and restrictions, but not the options
+    /// redirecting, capturing or recording the I/O of the main module.
+    pub fn for_exit_hook(&self) -> Self {
+        Self {
+            pre_opened_directories: self.pre_opened_directories.clone(),
+            mapped_dirs: self.mapped_dirs.clone(),
+            dir_access_mode: self.dir_access_mode,
+            working_dir: self.working_dir.clone(),
+            env_vars: self.env_vars.clone(),
+            env_file: self.env_file.clone(),
+            #[cfg(feature = "experimental-io-devices")]
+            enable_experimental_io_devices: self.enable_experimental_io_devices,
+            allow_multiple_wasi_versions: self.allow_multiple_wasi_versions,
+            deny_multiple_wasi_versions: self.deny_multiple_wasi_versions,
+            deterministic: self.deterministic,
+            seed: self.seed,
+            deny_syscall: self.deny_syscall.clone(),
+            deny_mode: self.deny_mode,
+            limit_open_files: self.limit_open_files,
+            preopen_stdin_as: self.preopen_stdin_as.clone(),
+            preopen_fds: self.preopen_fds.clone(),
+            stdin_dir: self.stdin_dir.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Merge the WASI settings of a `--sandbox-profile` into the flags.
+    /// The flags take precedence: `--env` and `--mapdir` per variable and
+    /// guest directory, the other flags replace the setting altogether.
+    pub fn apply_sandbox_profile(&mut self, profile: &SandboxProfile) -> Result<()> {
+        if self.pre_opened_directories.is_empty() {
+            self.pre_opened_directories = profile.dir.clone();
+        }
+        for (alias, dir) in &profile.mapdir {
+            if !self
+                .mapped_dirs
+                .iter()
+                .any(|(guest_dir, _)| guest_dir == alias)
+            {
+                self.mapped_dirs.push((alias.clone(), dir.clone()));
+            }
+        }
+        if self.dir_access_mode.is_none() {
+            self.dir_access_mode = profile
+                .dir_access_mode
+                .as_deref()
+                .map(parse_dir_access_mode)
+                .transpose()?;
+        }
+        if self.working_dir.is_none() {
+            self.working_dir = profile.working_dir.clone();
+        }
+        for (key, value) in &profile.env {
+            if !self.env_vars.iter().any(|(name, _)| name == key) {
+                self.env_vars.push((key.clone(), value.clone()));
+            }
+        }
+        if self.limit_open_files.is_none() {
+            self.limit_open_files = profile.limit_open_files;
+        }
+        self.deterministic |= profile.deterministic_wasi;
+        Ok(())
+    }
+
+    /// The environment variables of `--env-file`, overridden by `--env`.
+    /// A variable set twice keeps its last value.
+    fn env_vars(&self) -> Result<Vec<(String, String)>> {
+        let file_env_vars = match &self.env_file {
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read `{}`", path.display()))?;
+                parse_env_file(&contents)
+                    .with_context(|| format!("invalid env file `{}`", path.display()))?
+            }
+            None => vec![],
+        };
+        let mut env_vars: Vec<(String, String)> = vec![];
+        for (key, value) in file_env_vars
+            .into_iter()
+            .chain(self.env_vars.iter().cloned())
+        {
+            env_vars.retain(|(name, _)| *name != key);
+            env_vars.push((key, value));
+        }
+        Ok(env_vars)
+    }
+
+    /// Fill the WASI settings of `profile` with the flags, for
+    /// `--print-sandbox`.
+    pub fn fill_sandbox_profile(&self, profile: &mut SandboxProfile) -> Result<()> {
+        profile.dir = self.pre_opened_directories.clone();
+        profile.mapdir = self.mapped_dirs.iter().cloned().collect();
+        profile.dir_access_mode = self.dir_access_mode.map(|mode| format!("{:04o}", mode));
+        profile.working_dir = self.working_dir.clone();
+        profile.env = self.env_vars()?.into_iter().collect();
+        profile.limit_open_files = self.limit_open_files;
+        profile.deterministic_wasi = self.deterministic;
+        Ok(())
+    }
+
+    /// Helper function for executing Wasi from the `Run` command, running
+    /// the `_start` function of an instance created with `instantiate`.
+    ///
+    /// Returns the exit code of the guest program (`0` if `_start` returned
+    /// normally).
+    pub fn execute(&self, instance: &Instance) -> Result<i32> {
         let start = instance.exports.get_function("_start")?;
         let result = start.call(&[]);
 
         match result {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(0),
             Err(err) => {
                 let err: anyhow::Error = match err.downcast::<WasiError>() {
                     Ok(WasiError::Exit(exit_code)) => {
-                        // We should exit with the provided exit code
-                        std::process::exit(exit_code as _);
+                        // The caller decides how to exit with the provided exit code
+                        return Ok(exit_code as _);
                     }
                     Ok(err) => err.into(),
                     Err(err) => err.into(),
@@ -95,3 +592,65 @@ impl Wasi {
         .with_context(|| "failed to run WASI `_start` function")
     }
 }
+
+/// Parse a `HOST_FD:NAME` pair of `--preopen-fd`.
+fn parse_preopen_fd(entry: &str) -> Result<(u32, String)> {
+    let (host_fd, name) = entry
+        .split_once(':')
+        .with_context(|| format!("expected `HOST_FD:NAME`, got `{}`", entry))?;
+    let host_fd = host_fd
+        .parse::<u32>()
+        .with_context(|| format!("invalid file descriptor `{}`", host_fd))?;
+    if host_fd <= 2 {
+        bail!(
+            "file descriptor {} is a standard stream, and is already open in the module",
+            host_fd
+        );
+    }
+    if name.is_empty() {
+        bail!("the file descriptor {} needs a name", host_fd);
+    }
+    if name.contains('=') {
+        bail!(
+            "the name `{}` can't contain `=`, it's part of an environment variable",
+            name
+        );
+    }
+    Ok((host_fd, name.to_string()))
+}
+
+/// Duplicate the file descriptor (or the handle, on Windows) `host_fd`
+/// inherited from the parent process.
+fn inherited_file(host_fd: u32) -> Result<fs::File> {
+    #[cfg(unix)]
+    let file = {
+        use std::os::unix::io::FromRawFd;
+        unsafe { fs::File::from_raw_fd(host_fd as _) }
+    };
+    #[cfg(windows)]
+    let file = {
+        use std::os::windows::io::{FromRawHandle, RawHandle};
+        unsafe { fs::File::from_raw_handle(host_fd as usize as RawHandle) }
+    };
+    // The inherited descriptor is left open for the next runs of `--watch`
+    let file = std::mem::ManuallyDrop::new(file);
+    file.try_clone()
+        .with_context(|| format!("the file descriptor {} isn't open", host_fd))
+}
+
+/// Parse the octal permission bits of `--dir-access-mode`.
+fn parse_dir_access_mode(mode: &str) -> Result<u32> {
+    let bits = u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|bits| *bits <= 0o777)
+        .with_context(|| {
+            format!(
+                "invalid mode `{}`, expected octal permission bits like `0444`",
+                mode
+            )
+        })?;
+    if bits & 0o666 == 0 {
+        bail!("mode `{}` allows neither reading nor writing", mode);
+    }
+    Ok(bits)
+}
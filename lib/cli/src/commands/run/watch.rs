@@ -0,0 +1,41 @@
+//! Polling of the module file for `wasmer run --watch`.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the file must stay unchanged before it's considered
+/// completely written.
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Wait until the file exists with a modification time different from
+/// `last_change` and has stopped being written to, returning its new
+/// modification time.
+///
+/// A missing file (as happens while it's being atomically replaced) is
+/// waited for rather than reported as an error.
+pub fn wait_for_file(path: &Path, last_change: Option<SystemTime>) -> SystemTime {
+    loop {
+        let change = match modification_time(path) {
+            Some(change) if Some(change) != last_change => change,
+            _ => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        thread::sleep(DEBOUNCE_INTERVAL);
+        if modification_time(path) == Some(change) {
+            return change;
+        }
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,111 @@
+//! List the targets `wasmer create-exe` and `wasmer create-obj` can build for.
+
+use super::create_exe::get_libwasmer_path;
+use crate::store::CompilerType;
+use anyhow::Result;
+use serde::Serialize;
+use std::str::FromStr;
+use structopt::StructOpt;
+use wasmer::{Architecture, Triple};
+
+/// The targets listed in addition to the host.
+const KNOWN_TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "aarch64-unknown-linux-gnu",
+    "x86_64-unknown-linux-musl",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+];
+
+#[derive(Debug, StructOpt)]
+/// The options for the `wasmer targets` subcommand
+pub struct Targets {
+    /// Print the targets as JSON.
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+/// What can be built for a target.
+#[derive(Debug, Serialize)]
+struct TargetSupport {
+    triple: String,
+    host: bool,
+    /// The enabled compilers that can compile for the target, used by
+    /// `create-obj`.
+    compilers: Vec<String>,
+    /// Whether a libwasmer to link executables for the target is
+    /// installed, used by `create-exe`.
+    runtime: bool,
+}
+
+impl Targets {
+    /// Runs logic for the `targets` subcommand
+    pub fn execute(&self) -> Result<()> {
+        let host = Triple::host();
+        let mut triples = vec![host.clone()];
+        triples.extend(
+            KNOWN_TARGETS
+                .iter()
+                .map(|triple| Triple::from_str(triple).unwrap())
+                .filter(|triple| *triple != host),
+        );
+        // The installed libwasmer is built for the host
+        let has_libwasmer = get_libwasmer_path().map_or(false, |path| path.exists());
+        let targets = triples
+            .into_iter()
+            .map(|triple| {
+                let is_host = triple.architecture == host.architecture
+                    && triple.operating_system == host.operating_system;
+                TargetSupport {
+                    compilers: CompilerType::enabled()
+                        .iter()
+                        .filter(|compiler| supports_target(compiler, &triple))
+                        .map(|compiler| compiler.to_string())
+                        .collect(),
+                    runtime: is_host && has_libwasmer,
+                    host: triple == host,
+                    triple: triple.to_string(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&targets)?);
+            return Ok(());
+        }
+        println!("{:<32} {:<28} RUNTIME", "TARGET", "COMPILERS");
+        for target in &targets {
+            println!(
+                "{:<32} {:<28} {}",
+                if target.host {
+                    format!("{} (host)", target.triple)
+                } else {
+                    target.triple.clone()
+                },
+                if target.compilers.is_empty() {
+                    "-".to_string()
+                } else {
+                    target.compilers.join(", ")
+                },
+                if target.runtime { "yes" } else { "no" }
+            );
+        }
+        if !has_libwasmer {
+            eprintln!("No libwasmer was found: set `WASMER_DIR` to the Wasmer installation to use `create-exe`.");
+        }
+        Ok(())
+    }
+}
+
+/// Whether the compiler can generate code for the target.
+pub(crate) fn supports_target(compiler: &CompilerType, triple: &Triple) -> bool {
+    match compiler {
+        CompilerType::Singlepass => triple.architecture == Architecture::X86_64,
+        CompilerType::Cranelift | CompilerType::LLVM => matches!(
+            triple.architecture,
+            Architecture::X86_64 | Architecture::Aarch64(_)
+        ),
+        CompilerType::Headless => false,
+    }
+}
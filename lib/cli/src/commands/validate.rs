@@ -1,15 +1,43 @@
+use crate::common::feature_flag_hint;
 use crate::store::StoreOptions;
 use anyhow::{bail, Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use wasmer::*;
 
+mod batch;
+
+use batch::BatchReport;
+
 #[derive(Debug, StructOpt)]
 /// The options for the `wasmer validate` subcommand
 pub struct Validate {
     /// File to validate as WebAssembly
-    #[structopt(name = "FILE", parse(from_os_str))]
-    path: PathBuf,
+    #[structopt(name = "FILE", parse(from_os_str), required_unless = "DIR")]
+    path: Option<PathBuf>,
+
+    /// Validate every `.wasm` and `.wasmer` file under a directory,
+    /// recursively, instead of a single file.
+    ///
+    /// A summary of the results is printed, and the command fails if any
+    /// module is invalid.
+    #[structopt(
+        long = "batch",
+        name = "DIR",
+        parse(from_os_str),
+        conflicts_with = "FILE"
+    )]
+    batch: Option<PathBuf>,
+
+    /// The number of modules to validate in parallel with `--batch`.
+    ///
+    /// Defaults to the number of CPUs.
+    #[structopt(long = "jobs", short = "j", requires = "DIR")]
+    jobs: Option<usize>,
+
+    /// Print the `--batch` results as JSON
+    #[structopt(long = "json", requires = "DIR")]
+    json: bool,
 
     #[structopt(flatten)]
     store: StoreOptions,
@@ -18,17 +46,64 @@ pub struct Validate {
 impl Validate {
     /// Runs logic for the `validate` subcommand
     pub fn execute(&self) -> Result<()> {
-        self.inner_execute()
-            .context(format!("failed to validate `{}`", self.path.display()))
+        match (&self.path, &self.batch) {
+            (_, Some(dir)) => self
+                .validate_batch(dir)
+                .context(format!("failed to validate `{}`", dir.display())),
+            (Some(path), None) => self
+                .inner_execute(path)
+                .context(format!("failed to validate `{}`", path.display())),
+            (None, None) => bail!("no file to validate"),
+        }
+    }
+    fn inner_execute(&self, path: &Path) -> Result<()> {
+        let (store, _engine_type, _compiler_type) = self.store.get_store()?;
+        let module_contents = std::fs::read(path)?;
+        validate_module(&store, path, &module_contents)?;
+        eprintln!("Validation passed for `{}`.", path.display());
+        Ok(())
     }
-    fn inner_execute(&self) -> Result<()> {
+
+    fn validate_batch(&self, dir: &Path) -> Result<()> {
         let (store, _engine_type, _compiler_type) = self.store.get_store()?;
-        let module_contents = std::fs::read(&self.path)?;
-        if !is_wasm(&module_contents) {
-            bail!("`wasmer validate` only validates WebAssembly files");
+        let jobs = self.jobs.unwrap_or_else(num_cpus::get);
+        if jobs == 0 {
+            bail!("`--jobs` must be at least 1");
+        }
+        let report = BatchReport::new(&store, dir, jobs)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            report.print();
+        }
+        if report.failed() > 0 {
+            bail!(
+                "{} of {} modules failed validation",
+                report.failed(),
+                report.total()
+            );
         }
-        Module::validate(&store, &module_contents)?;
-        eprintln!("Validation passed for `{}`.", self.path.display());
         Ok(())
     }
 }
+
+/// Validate a module. Files with the `.wasmer` extension are checked to
+/// be artifacts that can be loaded by the engine.
+fn validate_module(store: &Store, path: &Path, module_contents: &[u8]) -> Result<()> {
+    if path.extension().and_then(|extension| extension.to_str()) == Some("wasmer") {
+        // We only load the artifact, without instantiating it.
+        unsafe { Module::deserialize(store, module_contents)? };
+        return Ok(());
+    }
+    if !is_wasm(module_contents) {
+        bail!("`wasmer validate` only validates WebAssembly files");
+    }
+    if let Err(e) = Module::validate(store, module_contents) {
+        let message = e.to_string();
+        match feature_flag_hint(&message) {
+            Some(flag) => bail!("{} (try passing `{}`)", message, flag),
+            None => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Validate every module under a directory, used by
+//! `wasmer validate --batch`.
+
+use super::validate_module;
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmer::Store;
+
+/// The extensions of the files validated in a directory.
+const MODULE_EXTENSIONS: &[&str] = &["wasm", "wasmer"];
+
+/// The result of validating one module.
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    path: PathBuf,
+    valid: bool,
+    /// The reason the module is invalid, if it is.
+    error: Option<String>,
+}
+
+/// The results of validating all the modules of a directory.
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    passed: usize,
+    failed: usize,
+    results: Vec<BatchResult>,
+}
+
+impl BatchReport {
+    /// Validate the modules under `dir` with `jobs` threads.
+    pub fn new(store: &Store, dir: &Path, jobs: usize) -> Result<Self> {
+        let mut paths = Vec::new();
+        collect_modules(dir, &mut paths)?;
+        paths.sort();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .context("failed to start the validation threads")?;
+        let results = pool.install(|| {
+            paths
+                .into_par_iter()
+                .map(|path| {
+                    let result = fs::read(&path)
+                        .map_err(Into::into)
+                        .and_then(|contents| validate_module(store, &path, &contents));
+                    BatchResult {
+                        valid: result.is_ok(),
+                        error: result.err().map(|e| format!("{:#}", e)),
+                        path,
+                    }
+                })
+                .collect::<Vec<_>>()
+        });
+        let failed = results.iter().filter(|result| !result.valid).count();
+        Ok(Self {
+            passed: results.len() - failed,
+            failed,
+            results,
+        })
+    }
+
+    /// The number of modules that failed validation.
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// The number of modules validated.
+    pub fn total(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn print(&self) {
+        for result in self.results.iter().filter(|result| !result.valid) {
+            eprintln!(
+                "Validation failed for `{}`: {}",
+                result.path.display(),
+                result.error.as_deref().unwrap_or_default()
+            );
+        }
+        eprintln!(
+            "{} modules validated: {} passed, {} failed.",
+            self.total(),
+            self.passed,
+            self.failed
+        );
+    }
+}
+
+/// Collect the modules under `dir`, recursively.
+fn collect_modules(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("failed to read `{}`", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_modules(&path, paths)?;
+        } else if path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| MODULE_EXTENSIONS.contains(&extension))
+        {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
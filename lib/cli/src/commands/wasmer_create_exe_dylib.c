@@ -0,0 +1,9 @@
+// The entrypoint of the shared libraries created with
+// `wasmer create-exe --output-type dylib`.
+//
+// `my_wasm.h` defines `wasmer_staticlib_engine_new`, which creates the
+// module from the metadata embedded in the library. The Wasm C API of
+// libwasmer and the compiled functions of the module are exported too.
+
+#include "wasmer.h"
+#include "my_wasm.h"
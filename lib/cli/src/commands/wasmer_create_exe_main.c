@@ -7,8 +7,18 @@
 
 #define own
 
-// TODO: make this define templated so that the Rust code can toggle it on/off
+// `wasmer create-exe` defines `WASMER_NO_WASI` for modules without imports
+#ifndef WASMER_NO_WASI
 #define WASI
+#endif
+
+#ifdef WASMER_ENTRY
+#include <stdint.h>
+
+// The arguments passed to the `WASMER_ENTRY` export.
+static char **entry_args;
+static int entry_argc;
+#endif
 
 static void print_wasmer_error() {
   int error_len = wasmer_last_error_length();
@@ -81,12 +91,301 @@ static void handle_arguments(wasi_config_t *wasi_config, int argc,
     } else {
       // guest argument
       wasi_config_arg(wasi_config, argv[i]);
+#ifdef WASMER_ENTRY
+      entry_args[entry_argc++] = argv[i];
+#endif
     }
   }
 }
 #endif
 
+#ifdef WASMER_HASH_EMBEDDED_WASM
+#include <stdint.h>
+
+static const uint32_t sha256_k[64] = {
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1,
+    0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3,
+    0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786,
+    0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147,
+    0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+    0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+    0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a,
+    0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+    0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2};
+
+#define ROTR(x, n) (((x) >> (n)) | ((x) << (32 - (n))))
+
+static void sha256_block(uint32_t state[8], const uint8_t block[64]) {
+  uint32_t w[64];
+  for (int i = 0; i < 16; ++i) {
+    w[i] = ((uint32_t)block[i * 4] << 24) | ((uint32_t)block[i * 4 + 1] << 16) |
+           ((uint32_t)block[i * 4 + 2] << 8) | (uint32_t)block[i * 4 + 3];
+  }
+  for (int i = 16; i < 64; ++i) {
+    uint32_t s0 = ROTR(w[i - 15], 7) ^ ROTR(w[i - 15], 18) ^ (w[i - 15] >> 3);
+    uint32_t s1 = ROTR(w[i - 2], 17) ^ ROTR(w[i - 2], 19) ^ (w[i - 2] >> 10);
+    w[i] = w[i - 16] + s0 + w[i - 7] + s1;
+  }
+  uint32_t a = state[0], b = state[1], c = state[2], d = state[3],
+           e = state[4], f = state[5], g = state[6], h = state[7];
+  for (int i = 0; i < 64; ++i) {
+    uint32_t t1 = h + (ROTR(e, 6) ^ ROTR(e, 11) ^ ROTR(e, 25)) +
+                  ((e & f) ^ (~e & g)) + sha256_k[i] + w[i];
+    uint32_t t2 =
+        (ROTR(a, 2) ^ ROTR(a, 13) ^ ROTR(a, 22)) + ((a & b) ^ (a & c) ^ (b & c));
+    h = g;
+    g = f;
+    f = e;
+    e = d + t1;
+    d = c;
+    c = b;
+    b = a;
+    a = t1 + t2;
+  }
+  state[0] += a;
+  state[1] += b;
+  state[2] += c;
+  state[3] += d;
+  state[4] += e;
+  state[5] += f;
+  state[6] += g;
+  state[7] += h;
+}
+
+struct sha256 {
+  uint32_t state[8];
+  uint8_t block[64];
+  size_t block_length;
+  uint64_t length;
+};
+
+static void sha256_init(struct sha256 *sha) {
+  static const uint32_t initial_state[8] = {
+      0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+      0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19};
+  memcpy(sha->state, initial_state, sizeof(initial_state));
+  sha->block_length = 0;
+  sha->length = 0;
+}
+
+static void sha256_update(struct sha256 *sha, const uint8_t *data,
+                          size_t length) {
+  sha->length += length;
+  for (size_t i = 0; i < length; ++i) {
+    sha->block[sha->block_length++] = data[i];
+    if (sha->block_length == 64) {
+      sha256_block(sha->state, sha->block);
+      sha->block_length = 0;
+    }
+  }
+}
+
+// Write the SHA-256 of the data hashed by `sha` as lowercase hex to `hex`,
+// which must hold 65 bytes.
+static void sha256_hex(struct sha256 *sha, char *hex) {
+  // The padding: a `1` bit, zeros, and the length in bits
+  uint64_t bit_length = sha->length * 8;
+  uint8_t padding[72] = {0x80};
+  size_t padding_length =
+      (sha->block_length < 56 ? 56 : 120) - sha->block_length;
+  for (int i = 0; i < 8; ++i) {
+    padding[padding_length + i] = (uint8_t)(bit_length >> ((7 - i) * 8));
+  }
+  sha256_update(sha, padding, padding_length + 8);
+  for (int i = 0; i < 8; ++i) {
+    sprintf(hex + i * 8, "%08x", sha->state[i]);
+  }
+}
+
+// The bounds of the module metadata and of its compiled code, and the
+// offsets of the absolute addresses in the code, which the loader may
+// relocate.
+extern const uint8_t WASMER_METADATA_END[];
+extern const uint8_t WASMER_CODE_START[];
+extern const uint8_t WASMER_CODE_END[];
+extern const uint64_t WASMER_CODE_RELOCATIONS[];
+extern const uint64_t WASMER_CODE_RELOCATIONS_END[];
+
+// The expected hash, written to the executable by `wasmer create-exe` once
+// it's linked, since linking changes the code. `volatile` keeps the
+// compiler from assuming it's still the placeholder.
+static volatile const char wasmer_embedded_wasm_sha256[65] =
+    "0000000000000000000000000000000000000000000000000000000000000000";
+
+// Check that the embedded module, its metadata and its code, wasn't
+// modified since `wasmer create-exe` hashed it. The relocated addresses
+// are hashed as zeros.
+static void check_embedded_wasm_hash() {
+  static const uint8_t relocated[8] = {0};
+  struct sha256 sha;
+  sha256_init(&sha);
+  sha256_update(&sha, WASMER_METADATA, WASMER_METADATA_END - WASMER_METADATA);
+  size_t offset = 0;
+  for (const uint64_t *relocation = WASMER_CODE_RELOCATIONS;
+       relocation < WASMER_CODE_RELOCATIONS_END; ++relocation) {
+    sha256_update(&sha, WASMER_CODE_START + offset, *relocation - offset);
+    sha256_update(&sha, relocated, sizeof(relocated));
+    offset = *relocation + sizeof(relocated);
+  }
+  sha256_update(&sha, WASMER_CODE_START + offset,
+                (WASMER_CODE_END - WASMER_CODE_START) - offset);
+  char hash[65];
+  sha256_hex(&sha, hash);
+  char expected[65];
+  for (int i = 0; i < 65; ++i) {
+    expected[i] = wasmer_embedded_wasm_sha256[i];
+  }
+  if (strcmp(hash, expected) != 0) {
+    fprintf(stderr,
+            "The embedded Wasm module has been modified: expected SHA-256 "
+            "`%s`, found `%s`\n",
+            expected, hash);
+    exit(1);
+  }
+}
+#endif
+
+#ifdef WASMER_ENTRY
+// Call the `WASMER_ENTRY` export with the entry arguments, parsed according
+// to `WASMER_ENTRY_PARAMS` (`i` for `i32`, `I` for `i64`, `f` for `f32` and
+// `F` for `f64`), and print its results. Returns the exit code.
+static int call_entry(wasm_module_t *module, wasm_instance_t *instance) {
+  wasm_exporttype_vec_t export_types;
+  wasm_module_exports(module, &export_types);
+  wasm_extern_vec_t exports;
+  wasm_instance_exports(instance, &exports);
+
+  wasm_func_t *entry = NULL;
+  for (size_t i = 0; i < export_types.size && i < exports.size; ++i) {
+    const wasm_name_t *name = wasm_exporttype_name(export_types.data[i]);
+    if (name->size == strlen(WASMER_ENTRY) &&
+        memcmp(name->data, WASMER_ENTRY, name->size) == 0) {
+      entry = wasm_extern_as_func(exports.data[i]);
+      break;
+    }
+  }
+  wasm_exporttype_vec_delete(&export_types);
+  if (!entry) {
+    fprintf(stderr, "`%s` function not found\n", WASMER_ENTRY);
+    wasm_extern_vec_delete(&exports);
+    return -1;
+  }
+
+  const char *params = WASMER_ENTRY_PARAMS;
+  size_t param_count = strlen(params);
+  if ((size_t)entry_argc != param_count) {
+    fprintf(stderr, "`%s` expects %zu arguments, got %d\n", WASMER_ENTRY,
+            param_count, entry_argc);
+    wasm_extern_vec_delete(&exports);
+    return 1;
+  }
+  // One more value than needed, so the allocation is never empty
+  wasm_val_t *arg_values = calloc(param_count + 1, sizeof(wasm_val_t));
+  for (size_t i = 0; i < param_count; ++i) {
+    char *arg = entry_args[i];
+    char *end = arg;
+    switch (params[i]) {
+    case 'i':
+      arg_values[i].kind = WASM_I32;
+      arg_values[i].of.i32 = (int32_t)strtol(arg, &end, 0);
+      break;
+    case 'I':
+      arg_values[i].kind = WASM_I64;
+      arg_values[i].of.i64 = (int64_t)strtoll(arg, &end, 0);
+      break;
+    case 'f':
+      arg_values[i].kind = WASM_F32;
+      arg_values[i].of.f32 = strtof(arg, &end);
+      break;
+    case 'F':
+      arg_values[i].kind = WASM_F64;
+      arg_values[i].of.f64 = strtod(arg, &end);
+      break;
+    }
+    if (end == arg || *end != '\0') {
+      fprintf(stderr, "invalid argument `%s` for parameter %zu of `%s`\n",
+              arg, i, WASMER_ENTRY);
+      free(arg_values);
+      wasm_extern_vec_delete(&exports);
+      return 1;
+    }
+  }
+
+  size_t result_count = wasm_func_result_arity(entry);
+  wasm_val_t *result_values = calloc(result_count + 1, sizeof(wasm_val_t));
+  wasm_val_vec_t args = {param_count, arg_values};
+  wasm_val_vec_t results = {result_count, result_values};
+  own wasm_trap_t *trap = wasm_func_call(entry, &args, &results);
+  int exit_code = 0;
+  if (trap) {
+    wasm_message_t message;
+    wasm_trap_message(trap, &message);
+    fprintf(stderr, "`%s` trapped: %.*s\n", WASMER_ENTRY, (int)message.size,
+            message.data);
+    wasm_byte_vec_delete(&message);
+    wasm_trap_delete(trap);
+    exit_code = 1;
+  } else {
+    for (size_t i = 0; i < result_count; ++i) {
+      const char *separator = i == 0 ? "" : " ";
+      switch (result_values[i].kind) {
+      case WASM_I32:
+        printf("%s%d", separator, result_values[i].of.i32);
+        break;
+      case WASM_I64:
+        printf("%s%lld", separator, (long long)result_values[i].of.i64);
+        break;
+      case WASM_F32:
+        printf("%s%g", separator, result_values[i].of.f32);
+        break;
+      case WASM_F64:
+        printf("%s%g", separator, result_values[i].of.f64);
+        break;
+      default:
+        break;
+      }
+    }
+    printf("\n");
+  }
+
+  free(arg_values);
+  free(result_values);
+  wasm_extern_vec_delete(&exports);
+  return exit_code;
+}
+#endif
+
+#ifdef WASMER_EMBED_MANIFEST
+// The JSON build manifest, embedded by `wasmer create-exe --embed-manifest`.
+extern const char wasmer_build_manifest[];
+#endif
+
 int main(int argc, char *argv[]) {
+#ifdef WASMER_EMBED_MANIFEST
+  // `--wasmer-info` prints how the executable was built instead of running
+  // the module.
+  if (argc == 2 && strcmp(argv[1], "--wasmer-info") == 0) {
+    fputs(wasmer_build_manifest, stdout);
+    return 0;
+  }
+#endif
+
+#ifdef WASMER_HASH_EMBEDDED_WASM
+  check_embedded_wasm_hash();
+#endif
+
+#ifdef WASMER_ENTRY
+  entry_args = (char **)malloc(argc * sizeof(char *));
+#ifndef WASI
+  // Without WASI, every argument is passed to the entry export
+  for (int i = 1; i < argc; ++i) {
+    entry_args[entry_argc++] = argv[i];
+  }
+#endif
+#endif
+
   wasm_config_t *config = wasm_config_new();
   wasm_config_set_engine(config, STATICLIB);
   wasm_engine_t *engine = wasm_engine_new_with_config(config);
@@ -142,7 +441,10 @@ int main(int argc, char *argv[]) {
     return -1;
   }
 
-#ifdef WASI
+  int exit_code = 0;
+#ifdef WASMER_ENTRY
+  exit_code = call_entry(module, instance);
+#elif defined(WASI)
   own wasm_func_t *start_function = wasi_get_start_function(instance);
   if (!start_function) {
     fprintf(stderr, "`_start` function not found\n");
@@ -165,5 +467,5 @@ int main(int argc, char *argv[]) {
   wasm_module_delete(module);
   wasm_store_delete(store);
   wasm_engine_delete(engine);
-  return 0;
+  return exit_code;
 }
@@ -5,7 +5,7 @@ use std::env;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-#[derive(Debug, StructOpt, Clone)]
+#[derive(Debug, StructOpt, Clone, Default)]
 /// The WebAssembly features that can be passed through the
 /// Command Line args.
 pub struct WasmFeatures {
@@ -29,9 +29,74 @@ pub struct WasmFeatures {
     #[structopt(long = "enable-bulk-memory")]
     pub bulk_memory: bool,
 
+    /// Enable support for the tail call proposal.
+    #[structopt(long = "enable-tail-call")]
+    pub tail_call: bool,
+
+    /// Enable support for the module linking proposal.
+    #[structopt(long = "enable-module-linking")]
+    pub module_linking: bool,
+
+    /// Enable support for the multi memory proposal.
+    #[structopt(long = "enable-multi-memory")]
+    pub multi_memory: bool,
+
+    /// Enable support for the 64-bit memory proposal.
+    #[structopt(long = "enable-memory64")]
+    pub memory64: bool,
+
+    /// Enable support for the exceptions proposal.
+    #[structopt(long = "enable-exceptions")]
+    pub exceptions: bool,
+
     /// Enable support for all pre-standard proposals.
     #[structopt(long = "enable-all")]
     pub all: bool,
+
+    /// Disable support for the SIMD proposal.
+    #[structopt(long = "disable-simd", conflicts_with = "simd")]
+    pub disable_simd: bool,
+
+    /// Disable support for the reference types proposal.
+    #[structopt(long = "disable-reference-types", conflicts_with = "reference-types")]
+    pub disable_reference_types: bool,
+
+    /// Disable support for the multi value proposal.
+    #[structopt(long = "disable-multi-value", conflicts_with = "multi-value")]
+    pub disable_multi_value: bool,
+
+    /// Disable support for the bulk memory proposal.
+    #[structopt(long = "disable-bulk-memory", conflicts_with = "bulk-memory")]
+    pub disable_bulk_memory: bool,
+}
+
+/// Suggest the command line flag that enables the Wasm feature named
+/// in a validation error, if any.
+pub fn feature_flag_hint(message: &str) -> Option<&'static str> {
+    let message = message.to_lowercase();
+    if !message.contains("not enabled") && !message.contains("requires") {
+        return None;
+    }
+    let hints = [
+        ("simd", "--enable-simd"),
+        ("threads", "--enable-threads"),
+        ("shared memor", "--enable-threads"),
+        ("reference types", "--enable-reference-types"),
+        ("bulk memory", "--enable-bulk-memory"),
+        ("multi-value", "--enable-multi-value"),
+        ("multi value", "--enable-multi-value"),
+        ("tail call", "--enable-tail-call"),
+        ("module linking", "--enable-module-linking"),
+        ("multi-memory", "--enable-multi-memory"),
+        ("multiple memories", "--enable-multi-memory"),
+        ("memory64", "--enable-memory64"),
+        ("64-bit memor", "--enable-memory64"),
+        ("exceptions", "--enable-exceptions"),
+    ];
+    hints
+        .iter()
+        .find(|(needle, _)| message.contains(needle))
+        .map(|(_, flag)| *flag)
 }
 
 /// Get the cache dir
@@ -0,0 +1,106 @@
+//! The Wasm module embedded in native executables by
+//! `wasmer create-exe --include-source`, and extracted by
+//! `wasmer inspect --extract-source`.
+//!
+//! The module is stored in a dedicated section of the executable, with a
+//! small header: the magic bytes, the compression used (`0` for none, `1`
+//! for gzip, `2` for zstd) and the length of the stored bytes as a
+//! little-endian `u64`.
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use object::read::{Object, ObjectSection};
+use object::BinaryFormat;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+/// The magic bytes at the start of the embedded source.
+const MAGIC_HEADER: &[u8; 8] = b"WASMSRC\0";
+
+/// The length of the header preceding the stored bytes.
+const HEADER_LENGTH: usize = MAGIC_HEADER.len() + 1 + 8;
+
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_GZIP: u8 = 1;
+const COMPRESSION_ZSTD: u8 = 2;
+
+/// The compression of the embedded source, with its level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceCompression {
+    /// The module is stored as is.
+    None,
+    /// The module is compressed with gzip, with the given level (0-9).
+    Gzip(u32),
+    /// The module is compressed with zstd, with the given level (1-22).
+    Zstd(i32),
+}
+
+/// The name of the section holding the embedded source for a binary
+/// format.
+///
+/// The COFF name is kept under 8 characters so it isn't truncated in
+/// images.
+pub fn section_name(format: BinaryFormat) -> &'static str {
+    match format {
+        BinaryFormat::MachO => "__wasmer_source",
+        BinaryFormat::Coff | BinaryFormat::Pe => ".wsrc",
+        _ => ".wasmer_source",
+    }
+}
+
+/// Encode a Wasm module to embed it, with the given compression.
+pub fn encode(wasm: &[u8], compression: SourceCompression) -> Result<Vec<u8>> {
+    let (compression, stored) = match compression {
+        SourceCompression::None => (COMPRESSION_NONE, wasm.to_vec()),
+        SourceCompression::Gzip(level) => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+            encoder.write_all(wasm)?;
+            (COMPRESSION_GZIP, encoder.finish()?)
+        }
+        SourceCompression::Zstd(level) => (COMPRESSION_ZSTD, zstd::encode_all(wasm, level)?),
+    };
+    let mut encoded = Vec::with_capacity(HEADER_LENGTH + stored.len());
+    encoded.extend_from_slice(MAGIC_HEADER);
+    encoded.push(compression);
+    encoded.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+    encoded.extend_from_slice(&stored);
+    Ok(encoded)
+}
+
+/// Decode an embedded Wasm module.
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < HEADER_LENGTH || &data[..MAGIC_HEADER.len()] != MAGIC_HEADER {
+        bail!("the embedded source is corrupted");
+    }
+    let compression = data[MAGIC_HEADER.len()];
+    let length = u64::from_le_bytes(data[MAGIC_HEADER.len() + 1..HEADER_LENGTH].try_into()?);
+    let stored = data
+        .get(HEADER_LENGTH..HEADER_LENGTH + length as usize)
+        .context("the embedded source is truncated")?;
+    match compression {
+        COMPRESSION_NONE => Ok(stored.to_vec()),
+        COMPRESSION_GZIP => {
+            let mut wasm = Vec::new();
+            GzDecoder::new(stored).read_to_end(&mut wasm)?;
+            Ok(wasm)
+        }
+        COMPRESSION_ZSTD => {
+            zstd::decode_all(stored).context("failed to decompress the embedded source")
+        }
+        _ => bail!(
+            "unknown compression `{}` of the embedded source",
+            compression
+        ),
+    }
+}
+
+/// Extract the Wasm module embedded in a native executable.
+pub fn extract(binary: &[u8]) -> Result<Vec<u8>> {
+    let file = object::File::parse(binary).context("not a native executable")?;
+    let section = file
+        .section_by_name(section_name(file.format()))
+        .context("no embedded source: the executable wasn't created with `--include-source`")?;
+    decode(section.data()?)
+}
@@ -19,6 +19,11 @@ macro_rules! warning {
 }
 
 impl PrettyError {
+    /// Print an error without exiting the process
+    pub fn print(error: Error) {
+        eprintln!("{:?}", PrettyError { error });
+    }
+
     /// Process a `Result` printing any errors and exiting
     /// the process after
     pub fn report<T>(result: Result<T, Error>) -> ! {
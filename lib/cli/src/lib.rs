@@ -16,8 +16,10 @@
 #[macro_use]
 extern crate anyhow;
 
+pub mod artifact_target;
 pub mod commands;
 pub mod common;
+pub mod embedded_source;
 #[macro_use]
 pub mod error;
 pub mod c_gen;
@@ -27,6 +29,8 @@ pub mod logging;
 pub mod store;
 pub mod suggestions;
 pub mod utils;
+pub mod wasm_binary;
+pub mod wasm_features;
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
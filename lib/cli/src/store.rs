@@ -12,7 +12,7 @@ use wasmer::*;
 #[cfg(feature = "compiler")]
 use wasmer_compiler::CompilerConfig;
 
-#[derive(Debug, Clone, StructOpt)]
+#[derive(Debug, Clone, StructOpt, Default)]
 /// The compiler and engine options
 pub struct StoreOptions {
     #[structopt(flatten)]
@@ -43,7 +43,7 @@ pub struct StoreOptions {
     object_file: bool,
 }
 
-#[derive(Debug, Clone, StructOpt)]
+#[derive(Debug, Clone, StructOpt, Default)]
 /// The compiler options
 pub struct CompilerOptions {
     /// Use Singlepass compiler.
@@ -114,6 +114,36 @@ impl CompilerOptions {
         if self.features.reference_types || self.features.all {
             features.reference_types(true);
         }
+        if self.features.tail_call {
+            features.tail_call(true);
+        }
+        if self.features.module_linking {
+            features.module_linking(true);
+        }
+        if self.features.multi_memory {
+            features.multi_memory(true);
+        }
+        if self.features.memory64 {
+            features.memory64(true);
+        }
+        if self.features.exceptions {
+            features.exceptions(true);
+        }
+        // Disabling a feature takes precedence over `--enable-all`
+        if self.features.disable_simd {
+            features.simd(false);
+        }
+        if self.features.disable_reference_types {
+            features.reference_types(false);
+        }
+        if self.features.disable_multi_value {
+            features.multi_value(false);
+        }
+        if self.features.disable_bulk_memory {
+            // The reference types proposal depends on bulk memory
+            features.bulk_memory(false);
+            features.reference_types(false);
+        }
         Ok(features)
     }
 
@@ -129,7 +159,8 @@ impl CompilerOptions {
         Ok((store, compiler_type))
     }
 
-    fn get_engine_by_type(
+    /// Gets the engine of the given type for a target and compiler config.
+    pub(crate) fn get_engine_by_type(
         &self,
         target: Target,
         compiler_config: Box<dyn CompilerConfig>,
@@ -168,6 +199,33 @@ impl CompilerOptions {
         Ok(engine)
     }
 
+    /// Gets the Staticlib engine for a given target, so it can be
+    /// configured further before creating a `Store` with it.
+    #[cfg(feature = "staticlib")]
+    pub(crate) fn get_staticlib_engine(
+        &self,
+        target: Target,
+        compiler_config: Box<dyn CompilerConfig>,
+    ) -> Result<wasmer_engine_staticlib::StaticlibEngine> {
+        let features = self.get_features(compiler_config.default_features_for_target(&target))?;
+        Ok(self.get_staticlib_engine_with_features(target, compiler_config, features))
+    }
+
+    /// Get the staticlib engine, with the given Wasm features instead of
+    /// the ones of the options.
+    #[cfg(feature = "staticlib")]
+    pub(crate) fn get_staticlib_engine_with_features(
+        &self,
+        target: Target,
+        compiler_config: Box<dyn CompilerConfig>,
+        features: Features,
+    ) -> wasmer_engine_staticlib::StaticlibEngine {
+        wasmer_engine_staticlib::Staticlib::new(compiler_config)
+            .target(target)
+            .features(features)
+            .engine()
+    }
+
     /// Get the Compiler Config for the current options
     #[allow(unused_variables)]
     pub(crate) fn get_compiler_config(&self) -> Result<(Box<dyn CompilerConfig>, CompilerType)> {
@@ -379,7 +437,20 @@ impl StoreOptions {
         &self,
         target: Target,
     ) -> Result<(Store, EngineType, CompilerType)> {
-        let (compiler_config, compiler_type) = self.compiler.get_compiler_config()?;
+        self.get_store_for_target_with_middlewares(target, vec![])
+    }
+
+    /// Gets the store for a given target, with the given middlewares
+    /// pushed to the compiler.
+    pub fn get_store_for_target_with_middlewares(
+        &self,
+        target: Target,
+        middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    ) -> Result<(Store, EngineType, CompilerType)> {
+        let (mut compiler_config, compiler_type) = self.compiler.get_compiler_config()?;
+        for middleware in middlewares {
+            compiler_config.push_middleware(middleware);
+        }
         let (engine, engine_type) = self.get_engine_with_compiler(target, compiler_config)?;
         let store = Store::new(&*engine);
         Ok((store, engine_type, compiler_type))
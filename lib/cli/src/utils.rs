@@ -2,6 +2,9 @@
 use anyhow::{bail, Result};
 use std::env;
 use std::path::PathBuf;
+use std::time::Duration;
+#[cfg(feature = "compiler")]
+use wasmer_compiler::{Endianness, RelocationModel, Triple};
 
 /// Whether or not Wasmer should print with color
 pub fn wasmer_should_print_color() -> bool {
@@ -64,9 +67,150 @@ pub fn parse_envvar(entry: &str) -> Result<(String, String)> {
     }
 }
 
+/// Parses the contents of a dotenv-style file, for `--env-file`.
+///
+/// Every line is a `KEY=VALUE` pair, optionally prefixed by `export`.
+/// Blank lines and lines starting with `#` are ignored. A value can be
+/// single-quoted (taken literally) or double-quoted (with the `\n`, `\"`
+/// and `\\` escapes); an unquoted value is trimmed and ends at ` #`.
+pub fn parse_env_file(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut env_vars = vec![];
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.find('=') {
+            Some(position) => (line[..position].trim(), line[position + 1..].trim_start()),
+            None => bail!(
+                "line {}: expected `<name>=<value>`, found `{}`",
+                number + 1,
+                line
+            ),
+        };
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            bail!("line {}: invalid variable name `{}`", number + 1, key);
+        }
+        let value = match parse_env_file_value(value) {
+            Some(value) => value,
+            None => bail!("line {}: unterminated quote in `{}`", number + 1, line),
+        };
+        env_vars.push((key.to_string(), value));
+    }
+    Ok(env_vars)
+}
+
+/// Parses a value of a dotenv-style file, returning `None` if a quote
+/// isn't closed.
+fn parse_env_file_value(value: &str) -> Option<String> {
+    if let Some(quoted) = value.strip_prefix('\'') {
+        return quoted
+            .find('\'')
+            .map(|position| quoted[..position].to_string());
+    }
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut unquoted = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Some(unquoted),
+                '\\' => match chars.next()? {
+                    'n' => unquoted.push('\n'),
+                    c => unquoted.push(c),
+                },
+                c => unquoted.push(c),
+            }
+        }
+        return None;
+    }
+    let value = match value.find(" #") {
+        Some(position) => &value[..position],
+        None => value,
+    };
+    Some(value.trim_end().to_string())
+}
+
+/// Parses a byte, in decimal or in hexadecimal with a `0x` prefix.
+pub fn parse_byte(entry: &str) -> Result<u8> {
+    let byte = match entry
+        .strip_prefix("0x")
+        .or_else(|| entry.strip_prefix("0X"))
+    {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => entry.parse(),
+    };
+    match byte {
+        Ok(byte) => Ok(byte),
+        Err(_) => bail!(
+            "invalid byte `{}`, expected a number from 0 to 255 or 0x00 to 0xFF",
+            entry
+        ),
+    }
+}
+
+/// Parses a duration, like `500ms`, `2s` or `1.5m`, in seconds without a
+/// unit.
+pub fn parse_duration(entry: &str) -> Result<Duration> {
+    let (value, unit) = match entry.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(position) => entry.split_at(position),
+        None => (entry, "s"),
+    };
+    let seconds_per_unit = match unit {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        _ => bail!(
+            "invalid duration `{}`, expected a number followed by `ms`, `s` or `m`",
+            entry
+        ),
+    };
+    match value.parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => {
+            Ok(Duration::from_secs_f64(value * seconds_per_unit))
+        }
+        _ => bail!(
+            "invalid duration `{}`, expected a number followed by `ms`, `s` or `m`",
+            entry
+        ),
+    }
+}
+
+/// Parses a relocation model.
+#[cfg(feature = "compiler")]
+pub fn parse_relocation_model(entry: &str) -> Result<RelocationModel> {
+    match entry {
+        "static" => Ok(RelocationModel::Static),
+        "pic" => Ok(RelocationModel::Pic),
+        "dynamic-no-pic" => Ok(RelocationModel::DynamicNoPic),
+        _ => bail!(
+            "unknown relocation model `{}`, expected `static`, `pic` or `dynamic-no-pic`",
+            entry
+        ),
+    }
+}
+
+/// Checks that artifacts can be generated for the byte order of `triple`.
+///
+/// Wasm memories are little-endian, and the compilers don't swap the bytes
+/// of memory accesses and data segments for big-endian targets, so they
+/// are rejected instead of producing an artifact that corrupts data.
+#[cfg(feature = "compiler")]
+pub fn check_target_endianness(triple: &Triple) -> Result<()> {
+    match triple.endianness() {
+        Ok(Endianness::Little) => Ok(()),
+        Ok(Endianness::Big) => bail!(
+            "the target `{}` is big-endian, and only little-endian targets are supported",
+            triple
+        ),
+        Err(()) => bail!("the byte order of the target `{}` is unknown", triple),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::parse_envvar;
+    use super::{parse_byte, parse_duration, parse_env_file, parse_envvar};
+    use std::time::Duration;
 
     #[test]
     fn test_parse_envvar() {
@@ -89,4 +233,100 @@ mod tests {
             ("A".into(), "B=C=D".into())
         );
     }
+
+    #[test]
+    fn test_parse_env_file() {
+        let contents = r#"
+# The log settings
+LEVEL=debug # overridden by `--env`
+export GREETING = "hello \"world\"\n"
+RAW='a\b # c'
+EMPTY=
+URL=http://localhost:8080/#top
+"#;
+        assert_eq!(
+            parse_env_file(contents).unwrap(),
+            vec![
+                ("LEVEL".into(), "debug".into()),
+                ("GREETING".into(), "hello \"world\"\n".into()),
+                ("RAW".into(), "a\\b # c".into()),
+                ("EMPTY".into(), "".into()),
+                ("URL".into(), "http://localhost:8080/#top".into()),
+            ]
+        );
+        assert_eq!(
+            parse_env_file("A=1\nB").unwrap_err().to_string(),
+            "line 2: expected `<name>=<value>`, found `B`"
+        );
+        assert_eq!(
+            parse_env_file("A=\"1").unwrap_err().to_string(),
+            "line 1: unterminated quote in `A=\"1`"
+        );
+        assert!(parse_env_file("A B=1").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_file_quoting() {
+        let contents = r#"
+  # An indented comment
+export PATH_LIKE=/bin:/usr/bin
+DOUBLE="a # b" # a comment after the quotes
+SINGLE='keeps \n and "quotes"'
+ESCAPED="a \\ b"
+SPACED = value with spaces   # trailing comment
+"#;
+        assert_eq!(
+            parse_env_file(contents).unwrap(),
+            vec![
+                ("PATH_LIKE".into(), "/bin:/usr/bin".into()),
+                ("DOUBLE".into(), "a # b".into()),
+                ("SINGLE".into(), "keeps \\n and \"quotes\"".into()),
+                ("ESCAPED".into(), "a \\ b".into()),
+                ("SPACED".into(), "value with spaces".into()),
+            ]
+        );
+        assert_eq!(
+            parse_env_file("\nA='1").unwrap_err().to_string(),
+            "line 2: unterminated quote in `A='1`"
+        );
+        assert_eq!(
+            parse_env_file("export A B=1").unwrap_err().to_string(),
+            "line 1: invalid variable name `A B`"
+        );
+        assert_eq!(
+            parse_env_file("=1").unwrap_err().to_string(),
+            "line 1: invalid variable name ``"
+        );
+        // Only the prefix followed by a space is removed
+        assert_eq!(
+            parse_env_file("exported=1").unwrap(),
+            vec![("exported".into(), "1".into())]
+        );
+    }
+
+    #[test]
+    fn test_parse_byte() {
+        assert_eq!(parse_byte("255").unwrap(), 0xFF);
+        assert_eq!(parse_byte("0xff").unwrap(), 0xFF);
+        assert_eq!(parse_byte("0X0a").unwrap(), 10);
+        assert_eq!(
+            parse_byte("256").unwrap_err().to_string(),
+            "invalid byte `256`, expected a number from 0 to 255 or 0x00 to 0xFF"
+        );
+        assert!(parse_byte("0x").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_duration("2").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(
+            parse_duration("2h").unwrap_err().to_string(),
+            "invalid duration `2h`, expected a number followed by `ms`, `s` or `m`"
+        );
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("s").is_err());
+    }
 }
@@ -0,0 +1,323 @@
+//! Reading and writing the binary format of Wasm modules, for the
+//! commands rewriting modules before they're compiled, like
+//! `wasmer run --trace-calls` or `wasmer create-exe --strip-exports`.
+
+use anyhow::{Context, Result};
+use wasmer::{Function, Type};
+
+/// The namespace of the functions imported by a [`forwarding_module`].
+pub const FORWARDED_NAMESPACE: &str = "forwarded";
+
+/// The body of a function that traps when called: no locals, and
+/// `unreachable`. It's valid whatever the signature of the function.
+pub const STUB_FUNCTION_BODY: &[u8] = &[0x00, 0x00, 0x0b];
+
+/// A section of a module.
+pub struct Section<'a> {
+    /// The id of the section, `0` for custom sections.
+    pub id: u8,
+    /// The contents of the section, after its size.
+    pub data: &'a [u8],
+    /// The offset of `data` in the module.
+    pub offset: usize,
+}
+
+/// Whether `name` matches the glob `pattern`.
+pub fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position after the last `*`, and the position in `name` it matches up to
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Split a Wasm module in sections.
+pub fn split_sections(wasm: &[u8]) -> Result<Vec<Section>> {
+    if wasm.len() < 8 || &wasm[..4] != b"\0asm" {
+        bail!("the module isn't a Wasm binary");
+    }
+    let mut reader = Reader::new(&wasm[8..]);
+    let mut sections = vec![];
+    while !reader.is_empty() {
+        let id = reader.read_u8()?;
+        let size = reader.read_u32()?;
+        let offset = 8 + reader.position;
+        sections.push(Section {
+            id,
+            data: reader.read_bytes(size as usize)?,
+            offset,
+        });
+    }
+    Ok(sections)
+}
+
+/// The position of a section in a module.
+pub fn section_order(id: u8) -> u8 {
+    match id {
+        // The data count section is before the code and data sections
+        12 => 10,
+        10 | 11 => id + 1,
+        _ => id,
+    }
+}
+
+/// Whether `section` is the name section.
+pub fn is_name_section(section: &Section) -> bool {
+    section.id == 0 && matches!(Reader::new(section.data).read_name(), Ok(name) if name == "name")
+}
+
+/// The function names of a name section.
+pub fn function_names(data: &[u8]) -> Result<Vec<(u32, &str)>> {
+    let mut reader = Reader::new(data);
+    reader.read_name()?;
+    let mut names = vec![];
+    while !reader.is_empty() {
+        let id = reader.read_u8()?;
+        let size = reader.read_u32()?;
+        let mut subsection = Reader::new(reader.read_bytes(size as usize)?);
+        if id == 1 {
+            for _ in 0..subsection.read_u32()? {
+                names.push((subsection.read_u32()?, subsection.read_name()?));
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The contents of a vector (like most sections) with `count` more
+/// encoded `entries`.
+pub fn append_entries(data: &[u8], count: u32, entries: &[u8]) -> Result<Vec<u8>> {
+    let (old_count, old_entries) = if data.is_empty() {
+        (0, data)
+    } else {
+        let mut reader = Reader::new(data);
+        let old_count = reader.read_u32()?;
+        (old_count, &data[reader.position..])
+    };
+    let mut contents = vec![];
+    write_u32(&mut contents, old_count + count);
+    contents.extend_from_slice(old_entries);
+    contents.extend_from_slice(entries);
+    Ok(contents)
+}
+
+/// Write a section with its id and its size.
+pub fn write_section(module: &mut Vec<u8>, id: u8, data: &[u8]) {
+    module.push(id);
+    write_u32(module, data.len() as u32);
+    module.extend_from_slice(data);
+}
+
+/// Write an unsigned LEB128 integer.
+pub fn write_u32(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Write a signed LEB128 integer.
+pub fn write_i32(bytes: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Write a name, prefixed by its length.
+pub fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    write_u32(bytes, name.len() as u32);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+/// Write a list of value types, prefixed by its length.
+pub fn write_types(bytes: &mut Vec<u8>, types: &[Type]) {
+    write_u32(bytes, types.len() as u32);
+    bytes.extend(types.iter().map(|ty| match ty {
+        Type::I32 => 0x7f,
+        Type::I64 => 0x7e,
+        Type::F32 => 0x7d,
+        Type::F64 => 0x7c,
+        Type::V128 => 0x7b,
+        Type::ExternRef => 0x6f,
+        Type::FuncRef => 0x70,
+    }));
+}
+
+/// Encode a module importing `functions` from [`FORWARDED_NAMESPACE`],
+/// named by their index, and exporting a function forwarding to each of
+/// them, under the same name.
+///
+/// Host functions created from closures can't be called indirectly, and
+/// native host functions can't be called from the host: the forwarding
+/// functions can be called both ways.
+pub fn forwarding_module(functions: &[Function]) -> Vec<u8> {
+    encode_forwarding_module(functions, false)
+}
+
+/// A [`forwarding_module`] which also imports the memory as `env.memory`
+/// and exports it as `memory`, for host functions using the memory of the
+/// instance calling them.
+pub fn forwarding_module_with_memory(functions: &[Function]) -> Vec<u8> {
+    encode_forwarding_module(functions, true)
+}
+
+fn encode_forwarding_module(functions: &[Function], memory: bool) -> Vec<u8> {
+    let count = functions.len() as u32;
+    let mut types = vec![];
+    let mut imports = vec![];
+    let mut exports = vec![];
+    let mut code = vec![];
+    write_u32(&mut types, count);
+    write_u32(&mut imports, count + memory as u32);
+    write_u32(&mut exports, count + memory as u32);
+    write_u32(&mut code, count);
+    if memory {
+        write_name(&mut imports, "env");
+        write_name(&mut imports, "memory");
+        // A memory without limits, so any memory can be imported
+        imports.extend_from_slice(&[0x02, 0x00, 0x00]);
+        write_name(&mut exports, "memory");
+        exports.extend_from_slice(&[0x02, 0x00]);
+    }
+    for (index, function) in functions.iter().enumerate() {
+        let ty = function.ty();
+        let index = index as u32;
+        types.push(0x60);
+        write_types(&mut types, ty.params());
+        write_types(&mut types, ty.results());
+        write_name(&mut imports, FORWARDED_NAMESPACE);
+        write_name(&mut imports, &index.to_string());
+        imports.push(0x00);
+        write_u32(&mut imports, index);
+        write_name(&mut exports, &index.to_string());
+        exports.push(0x00);
+        write_u32(&mut exports, count + index);
+
+        // No locals, then `local.get` of every parameter and `call`
+        let mut body = vec![0x00];
+        for param in 0..ty.params().len() as u32 {
+            body.push(0x20);
+            write_u32(&mut body, param);
+        }
+        body.push(0x10);
+        write_u32(&mut body, index);
+        body.push(0x0b);
+        write_u32(&mut code, body.len() as u32);
+        code.extend(body);
+    }
+    // Each defined function has the type of the function it forwards to
+    let mut function_types = vec![];
+    write_u32(&mut function_types, count);
+    for index in 0..count {
+        write_u32(&mut function_types, index);
+    }
+
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+    write_section(&mut module, 1, &types);
+    write_section(&mut module, 2, &imports);
+    write_section(&mut module, 3, &function_types);
+    write_section(&mut module, 7, &exports);
+    write_section(&mut module, 10, &code);
+    module
+}
+
+/// A reader of the encoded integers, names and types of a module.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    /// The position of the next byte to read in `bytes`.
+    pub position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// A reader at the start of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    /// Whether every byte was read.
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    /// Read `length` bytes.
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .context("unexpected end of the module")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    /// Read a byte.
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    /// Read an unsigned LEB128 integer.
+    pub fn read_u32(&mut self) -> Result<u32> {
+        let mut value = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("invalid LEB128 integer in the module")
+    }
+
+    /// Read a name, prefixed by its length.
+    pub fn read_name(&mut self) -> Result<&'a str> {
+        let length = self.read_u32()?;
+        Ok(std::str::from_utf8(self.read_bytes(length as usize)?)?)
+    }
+
+    /// Read a vector of value types.
+    pub fn read_types(&mut self) -> Result<Vec<Type>> {
+        (0..self.read_u32()?)
+            .map(|_| {
+                Ok(match self.read_u8()? {
+                    0x7f => Type::I32,
+                    0x7e => Type::I64,
+                    0x7d => Type::F32,
+                    0x7c => Type::F64,
+                    0x7b => Type::V128,
+                    0x6f => Type::ExternRef,
+                    0x70 => Type::FuncRef,
+                    code => bail!("invalid value type {:#x}", code),
+                })
+            })
+            .collect()
+    }
+}
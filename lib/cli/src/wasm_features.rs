@@ -0,0 +1,88 @@
+//! Detect the Wasm features used by a module, for
+//! `wasmer create-obj --wasm-features-from` and
+//! `wasmer inspect --validate-features`, and name the enabled ones.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use wasmer::wasmparser::{Validator, WasmFeatures};
+use wasmer::Features;
+
+/// The toggles of the detected features, with the features others depend
+/// on (like bulk memory for reference types) last, so they are only kept
+/// when the module needs them.
+const FEATURE_TOGGLES: &[fn(&mut Features, bool) -> &mut Features] = &[
+    Features::exceptions,
+    Features::memory64,
+    Features::multi_memory,
+    Features::module_linking,
+    Features::tail_call,
+    Features::threads,
+    Features::simd,
+    Features::reference_types,
+    Features::bulk_memory,
+    Features::multi_value,
+];
+
+/// Read the module at `path` and find the smallest set of features it
+/// validates with.
+pub fn detect_features(path: &Path) -> Result<Features> {
+    let contents =
+        fs::read(path).with_context(|| format!("failed to read `{}`", path.display()))?;
+    #[cfg(feature = "wat")]
+    let contents = wasmer::wat2wasm(&contents)?;
+
+    let mut features = Features::new();
+    for toggle in FEATURE_TOGGLES {
+        toggle(&mut features, true);
+    }
+    validate(&contents, &features)
+        .with_context(|| format!("`{}` isn't a valid Wasm module", path.display()))?;
+    for toggle in FEATURE_TOGGLES {
+        toggle(&mut features, false);
+        if validate(&contents, &features).is_err() {
+            toggle(&mut features, true);
+        }
+    }
+    Ok(features)
+}
+
+/// Validate a module with the given features enabled.
+fn validate(contents: &[u8], features: &Features) -> Result<()> {
+    let mut validator = Validator::new();
+    validator.wasm_features(WasmFeatures {
+        bulk_memory: features.bulk_memory,
+        threads: features.threads,
+        reference_types: features.reference_types,
+        multi_value: features.multi_value,
+        simd: features.simd,
+        tail_call: features.tail_call,
+        module_linking: features.module_linking,
+        multi_memory: features.multi_memory,
+        memory64: features.memory64,
+        exceptions: features.exceptions,
+        deterministic_only: false,
+    });
+    validator.validate_all(contents)?;
+    Ok(())
+}
+
+/// The names of the enabled Wasm features.
+pub fn enabled_wasm_features(features: &Features) -> Vec<&'static str> {
+    [
+        ("threads", features.threads),
+        ("reference-types", features.reference_types),
+        ("simd", features.simd),
+        ("bulk-memory", features.bulk_memory),
+        ("multi-value", features.multi_value),
+        ("tail-call", features.tail_call),
+        ("module-linking", features.module_linking),
+        ("multi-memory", features.multi_memory),
+        ("memory64", features.memory64),
+        ("exceptions", features.exceptions),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| *name)
+    .collect()
+}
@@ -15,18 +15,19 @@ use crate::translator::{
 };
 use cranelift_codegen::ir;
 use cranelift_codegen::print_errors::pretty_error;
-use cranelift_codegen::{binemit, Context};
+use cranelift_codegen::{binemit, timing, Context};
 #[cfg(feature = "unwind")]
 use gimli::write::{Address, EhFrame, FrameTable};
 use loupe::MemoryUsage;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::sync::Arc;
+use std::time::Duration;
 use wasmer_compiler::CompileError;
 use wasmer_compiler::{CallingConvention, ModuleTranslationState, Target};
 use wasmer_compiler::{
     Compilation, CompileModuleInfo, CompiledFunction, CompiledFunctionFrameInfo,
     CompiledFunctionUnwindInfo, Compiler, Dwarf, FunctionBinaryReader, FunctionBody,
-    FunctionBodyData, MiddlewareBinaryReader, ModuleMiddleware, ModuleMiddlewareChain,
+    FunctionBodyData, MiddlewareBinaryReader, ModuleMiddleware, ModuleMiddlewareChain, PassTimings,
     SectionIndex,
 };
 #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
@@ -86,10 +87,11 @@ impl Compiler for CraneliftCompiler {
 
         // Generate the frametable
         #[cfg(feature = "unwind")]
-        let dwarf_frametable = if function_body_inputs.is_empty() {
-            // If we have no function body inputs, we don't need to
-            // construct the `FrameTable`. Constructing it, with empty
-            // FDEs will cause some issues in Linux.
+        let dwarf_frametable = if function_body_inputs.is_empty() || !self.config.enable_unwind_info
+        {
+            // If we have no function body inputs (or no unwind info), we
+            // don't need to construct the `FrameTable`. Constructing it,
+            // with empty FDEs will cause some issues in Linux.
             None
         } else {
             use std::sync::Mutex;
@@ -134,111 +136,134 @@ impl Compiler for CraneliftCompiler {
         #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
         let probestack_trampoline_relocation_target = SectionIndex::new(custom_sections.len() - 1);
 
-        let functions = function_body_inputs
-            .iter()
-            .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
-            .par_iter()
-            .map_init(FuncTranslator::new, |func_translator, (i, input)| {
-                let func_index = module.func_index(*i);
-                let mut context = Context::new();
-                let mut func_env = FuncEnvironment::new(
-                    isa.frontend_config(),
-                    module,
-                    &signatures,
-                    &memory_styles,
-                    &table_styles,
-                );
-                context.func.name = get_function_name(func_index);
-                context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
-                let mut reader =
-                    MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
-                reader.set_middleware_chain(
-                    self.config
-                        .middlewares
-                        .generate_function_middleware_chain(*i),
-                );
+        let compile_functions = || {
+            function_body_inputs
+                .iter()
+                .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
+                .par_iter()
+                .map_init(FuncTranslator::new, |func_translator, (i, input)| {
+                    let func_index = module.func_index(*i);
+                    let mut context = Context::new();
+                    let mut func_env = FuncEnvironment::new(
+                        isa.frontend_config(),
+                        module,
+                        &signatures,
+                        &memory_styles,
+                        &table_styles,
+                    );
+                    context.func.name = get_function_name(func_index);
+                    context.func.signature = signatures[module.functions[func_index]].clone();
+                    // if generate_debug_info {
+                    //     context.func.collect_debug_info();
+                    // }
+                    let mut reader =
+                        MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
+                    reader.set_middleware_chain(
+                        self.config
+                            .middlewares
+                            .generate_function_middleware_chain(*i),
+                    );
 
-                func_translator.translate(
-                    module_translation_state,
-                    &mut reader,
-                    &mut context.func,
-                    &mut func_env,
-                    *i,
-                )?;
+                    func_translator.translate(
+                        module_translation_state,
+                        &mut reader,
+                        &mut context.func,
+                        &mut func_env,
+                        *i,
+                    )?;
 
-                let mut code_buf: Vec<u8> = Vec::new();
-                let mut reloc_sink = RelocSink::new(
-                    &module,
-                    func_index,
-                    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
-                    probestack_trampoline_relocation_target,
-                );
-                let mut trap_sink = TrapSink::new();
-                let mut stackmap_sink = binemit::NullStackMapSink {};
-                context
-                    .compile_and_emit(
-                        &*isa,
-                        &mut code_buf,
-                        &mut reloc_sink,
-                        &mut trap_sink,
-                        &mut stackmap_sink,
-                    )
-                    .map_err(|error| {
-                        CompileError::Codegen(pretty_error(&context.func, Some(&*isa), error))
-                    })?;
+                    let mut code_buf: Vec<u8> = Vec::new();
+                    let mut reloc_sink = RelocSink::new(
+                        &module,
+                        func_index,
+                        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+                        probestack_trampoline_relocation_target,
+                    );
+                    let mut trap_sink = TrapSink::new();
+                    let mut stackmap_sink = binemit::NullStackMapSink {};
+                    context
+                        .compile_and_emit(
+                            &*isa,
+                            &mut code_buf,
+                            &mut reloc_sink,
+                            &mut trap_sink,
+                            &mut stackmap_sink,
+                        )
+                        .map_err(|error| {
+                            CompileError::Codegen(pretty_error(&context.func, Some(&*isa), error))
+                        })?;
 
-                let unwind_info = match compiled_function_unwind_info(&*isa, &context)? {
-                    #[cfg(feature = "unwind")]
-                    CraneliftUnwindInfo::FDE(fde) => {
-                        if let Some((dwarf_frametable, cie_id)) = &dwarf_frametable {
-                            dwarf_frametable
-                                .lock()
-                                .expect("Can't write into DWARF frametable")
-                                .add_fde(
-                                    *cie_id,
-                                    fde.to_fde(Address::Symbol {
-                                        // The symbol is the kind of relocation.
-                                        // "0" is used for functions
-                                        symbol: WriterRelocate::FUNCTION_SYMBOL,
-                                        // We use the addend as a way to specify the
-                                        // function index
-                                        addend: i.index() as _,
-                                    }),
-                                );
-                            // The unwind information is inserted into the dwarf section
-                            Some(CompiledFunctionUnwindInfo::Dwarf)
-                        } else {
-                            None
+                    let unwind_info = if self.config.enable_unwind_info {
+                        compiled_function_unwind_info(&*isa, &context)?
+                    } else {
+                        CraneliftUnwindInfo::None
+                    };
+                    let unwind_info = match unwind_info {
+                        #[cfg(feature = "unwind")]
+                        CraneliftUnwindInfo::FDE(fde) => {
+                            if let Some((dwarf_frametable, cie_id)) = &dwarf_frametable {
+                                dwarf_frametable
+                                    .lock()
+                                    .expect("Can't write into DWARF frametable")
+                                    .add_fde(
+                                        *cie_id,
+                                        fde.to_fde(Address::Symbol {
+                                            // The symbol is the kind of relocation.
+                                            // "0" is used for functions
+                                            symbol: WriterRelocate::FUNCTION_SYMBOL,
+                                            // We use the addend as a way to specify the
+                                            // function index
+                                            addend: i.index() as _,
+                                        }),
+                                    );
+                                // The unwind information is inserted into the dwarf section
+                                Some(CompiledFunctionUnwindInfo::Dwarf)
+                            } else {
+                                None
+                            }
                         }
-                    }
-                    other => other.maybe_into_to_windows_unwind(),
-                };
+                        other => other.maybe_into_to_windows_unwind(),
+                    };
 
-                let range = reader.range();
-                let address_map = get_function_address_map(&context, range, code_buf.len(), &*isa);
+                    let range = reader.range();
+                    let address_map =
+                        get_function_address_map(&context, range, code_buf.len(), &*isa);
 
-                // We transform the Cranelift JumpTable's into compiler JumpTables
-                let func_jt_offsets = transform_jump_table(context.func.jt_offsets);
+                    // We transform the Cranelift JumpTable's into compiler JumpTables
+                    let func_jt_offsets = transform_jump_table(context.func.jt_offsets);
 
-                Ok(CompiledFunction {
-                    body: FunctionBody {
-                        body: code_buf,
-                        unwind_info,
-                    },
-                    jt_offsets: func_jt_offsets,
-                    relocations: reloc_sink.func_relocs,
-                    frame_info: CompiledFunctionFrameInfo {
-                        address_map,
-                        traps: trap_sink.traps,
-                    },
+                    Ok(CompiledFunction {
+                        body: FunctionBody {
+                            body: code_buf,
+                            unwind_info,
+                        },
+                        jt_offsets: func_jt_offsets,
+                        relocations: reloc_sink.func_relocs,
+                        frame_info: CompiledFunctionFrameInfo {
+                            address_map,
+                            traps: trap_sink.traps,
+                        },
+                    })
                 })
-            })
-            .collect::<Result<Vec<_>, CompileError>>()?
-            .into_iter()
-            .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+                .collect::<Result<Vec<_>, CompileError>>()
+        };
+        let functions = match &self.config.pass_timings {
+            // Cranelift times the passes by thread, so the functions are
+            // compiled on a single one to collect all their times
+            Some(pass_timings) => rayon::ThreadPoolBuilder::new()
+                .num_threads(1)
+                .build()
+                .map_err(|error| CompileError::Resource(error.to_string()))?
+                .install(|| {
+                    timing::take_current();
+                    let functions = compile_functions();
+                    report_pass_times(&timing::take_current(), &**pass_timings);
+                    functions
+                }),
+            None => compile_functions(),
+        }?
+        .into_iter()
+        .collect::<PrimaryMap<LocalFunctionIndex, _>>();
 
         #[cfg(feature = "unwind")]
         let dwarf = {
@@ -297,3 +322,20 @@ impl Compiler for CraneliftCompiler {
         ))
     }
 }
+
+/// Report the Cranelift pass times of a thread to `timings`.
+///
+/// `PassTimes` only exposes the times in the table it prints, with a line
+/// per pass that ran: its total and self times in seconds, and its name.
+fn report_pass_times(pass_times: &timing::PassTimes, timings: &dyn PassTimings) {
+    for line in pass_times.to_string().lines() {
+        let mut columns = line.split_whitespace();
+        let mut next_time = || columns.next().and_then(|time| time.parse::<f64>().ok());
+        let self_time = match (next_time(), next_time()) {
+            (Some(_total), Some(self_time)) => self_time,
+            _ => continue,
+        };
+        let pass = columns.collect::<Vec<_>>().join(" ");
+        timings.add(&pass, Duration::from_secs_f64(self_time));
+    }
+}
@@ -4,7 +4,8 @@ use cranelift_codegen::settings::{self, Configurable};
 use loupe::MemoryUsage;
 use std::sync::Arc;
 use wasmer_compiler::{
-    Architecture, Compiler, CompilerConfig, CpuFeature, ModuleMiddleware, Target,
+    Architecture, CompileError, Compiler, CompilerConfig, CpuFeature, ModuleMiddleware,
+    PassTimings, RelocationModel, Target,
 };
 
 // Runtime Environment
@@ -33,9 +34,15 @@ pub struct Cranelift {
     enable_nan_canonicalization: bool,
     enable_verifier: bool,
     enable_pic: bool,
+    relocation_model: Option<RelocationModel>,
+    /// Whether the functions get unwind information.
+    pub(crate) enable_unwind_info: bool,
     opt_level: CraneliftOptLevel,
     /// The middleware chain.
     pub(crate) middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    /// Where the times of the compiler passes are reported, if timed.
+    #[loupe(skip)]
+    pub(crate) pass_timings: Option<Arc<dyn PassTimings>>,
 }
 
 impl Cranelift {
@@ -47,7 +54,10 @@ impl Cranelift {
             enable_verifier: false,
             opt_level: CraneliftOptLevel::Speed,
             enable_pic: false,
+            relocation_model: None,
+            enable_unwind_info: true,
             middlewares: vec![],
+            pass_timings: None,
         }
     }
 
@@ -131,7 +141,11 @@ impl Cranelift {
             .enable("avoid_div_traps")
             .expect("should be valid flag");
 
-        if self.enable_pic {
+        let is_pic = match self.relocation_model {
+            Some(model) => model == RelocationModel::Pic,
+            None => self.enable_pic,
+        };
+        if is_pic {
             flags.enable("is_pic").expect("should be a valid flag");
         }
 
@@ -181,6 +195,19 @@ impl CompilerConfig for Cranelift {
         self.enable_pic = true;
     }
 
+    fn relocation_model(&mut self, model: RelocationModel) -> Result<(), CompileError> {
+        match model {
+            RelocationModel::Static | RelocationModel::Pic => {
+                self.relocation_model = Some(model);
+                Ok(())
+            }
+            RelocationModel::DynamicNoPic => Err(CompileError::UnsupportedFeature(format!(
+                "relocation model `{}`",
+                model
+            ))),
+        }
+    }
+
     fn enable_verifier(&mut self) {
         self.enable_verifier = true;
     }
@@ -193,6 +220,16 @@ impl CompilerConfig for Cranelift {
         self.enable_nan_canonicalization = enable;
     }
 
+    fn pass_timings(&mut self, timings: Arc<dyn PassTimings>) -> Result<(), CompileError> {
+        self.pass_timings = Some(timings);
+        Ok(())
+    }
+
+    fn disable_unwind_info(&mut self) -> Result<(), CompileError> {
+        self.enable_unwind_info = false;
+        Ok(())
+    }
+
     /// Transform it into the compiler
     fn compiler(self: Box<Self>) -> Box<dyn Compiler> {
         Box::new(CraneliftCompiler::new(*self))
@@ -176,6 +176,10 @@ impl LLVMCompiler {
             merged_module.verify().unwrap();
         }
 
+        if self.config().emit_bitcode {
+            return Ok(merged_module.write_bitcode_to_memory().as_slice().to_vec());
+        }
+
         let memory_buffer = target_machine
             .write_to_memory_buffer(&merged_module, FileType::Object)
             .unwrap();
@@ -9,7 +9,10 @@ use loupe::MemoryUsage;
 use std::fmt::Debug;
 use std::sync::Arc;
 use target_lexicon::Architecture;
-use wasmer_compiler::{Compiler, CompilerConfig, ModuleMiddleware, Target, Triple};
+use wasmer_compiler::{
+    CompileError, Compiler, CompilerConfig, ModuleMiddleware, PassTimings, RelocationModel, Target,
+    Triple,
+};
 use wasmer_types::{FunctionType, LocalFunctionIndex};
 
 /// The InkWell ModuleInfo type
@@ -45,10 +48,16 @@ pub struct LLVM {
     #[loupe(skip)]
     pub(crate) opt_level: LLVMOptLevel,
     is_pic: bool,
+    relocation_model: Option<RelocationModel>,
     #[loupe(skip)]
     pub(crate) callbacks: Option<Arc<dyn LLVMCallbacks>>,
     /// The middleware chain.
     pub(crate) middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    /// Where the times of the compilation phases are reported, if timed.
+    #[loupe(skip)]
+    pub(crate) pass_timings: Option<Arc<dyn PassTimings>>,
+    /// Whether the native objects are emitted as bitcode.
+    pub(crate) emit_bitcode: bool,
 }
 
 impl LLVM {
@@ -60,8 +69,11 @@ impl LLVM {
             enable_verifier: false,
             opt_level: LLVMOptLevel::Aggressive,
             is_pic: false,
+            relocation_model: None,
             callbacks: None,
             middlewares: vec![],
+            pass_timings: None,
+            emit_bitcode: false,
         }
     }
 
@@ -79,10 +91,12 @@ impl LLVM {
     }
 
     fn reloc_mode(&self) -> RelocMode {
-        if self.is_pic {
-            RelocMode::PIC
-        } else {
-            RelocMode::Static
+        match self.relocation_model {
+            Some(RelocationModel::Static) => RelocMode::Static,
+            Some(RelocationModel::Pic) => RelocMode::PIC,
+            Some(RelocationModel::DynamicNoPic) => RelocMode::DynamicNoPic,
+            None if self.is_pic => RelocMode::PIC,
+            None => RelocMode::Static,
         }
     }
 
@@ -92,7 +106,7 @@ impl LLVM {
         // any benefit from large code model and there's some cost on all
         // platforms, plus some platforms (MachO) don't support PIC + large
         // at all.
-        if self.is_pic {
+        if matches!(self.reloc_mode(), RelocMode::PIC) {
             CodeModel::Small
         } else {
             CodeModel::Large
@@ -197,6 +211,12 @@ impl CompilerConfig for LLVM {
         self.is_pic = true;
     }
 
+    /// Override the relocation model implied by `enable_pic`.
+    fn relocation_model(&mut self, model: RelocationModel) -> Result<(), CompileError> {
+        self.relocation_model = Some(model);
+        Ok(())
+    }
+
     /// Whether to verify compiler IR.
     fn enable_verifier(&mut self) {
         self.enable_verifier = true;
@@ -210,6 +230,20 @@ impl CompilerConfig for LLVM {
         self.enable_nan_canonicalization = enable;
     }
 
+    /// Time the translation, the optimization and the code generation
+    /// (instruction selection, register allocation and emission) of the
+    /// functions.
+    fn pass_timings(&mut self, timings: Arc<dyn PassTimings>) -> Result<(), CompileError> {
+        self.pass_timings = Some(timings);
+        Ok(())
+    }
+
+    /// Emit the native objects as the bitcode of the merged module.
+    fn emit_bitcode(&mut self) -> Result<(), CompileError> {
+        self.emit_bitcode = true;
+        Ok(())
+    }
+
     /// Transform it into the compiler.
     fn compiler(self: Box<Self>) -> Box<dyn Compiler> {
         Box::new(LLVMCompiler::new(*self))
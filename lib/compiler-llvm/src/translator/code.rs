@@ -20,6 +20,7 @@ use inkwell::{
     AddressSpace, AtomicOrdering, AtomicRMWBinOp, DLLStorageClass, FloatPredicate, IntPredicate,
 };
 use smallvec::SmallVec;
+use std::time::Instant;
 
 use crate::abi::{get_abi, Abi};
 use crate::config::{CompiledKind, LLVM};
@@ -69,6 +70,7 @@ impl FuncTranslator {
         _table_styles: &PrimaryMap<TableIndex, TableStyle>,
         symbol_registry: &dyn SymbolRegistry,
     ) -> Result<Module, CompileError> {
+        let translation_start = Instant::now();
         // The function type, used for the callbacks.
         let function = CompiledKind::Local(*local_func_index);
         let func_index = wasm_module.func_index(*local_func_index);
@@ -217,11 +219,15 @@ impl FuncTranslator {
         }
 
         fcg.finalize(wasm_fn_type)?;
+        if let Some(ref timings) = config.pass_timings {
+            timings.add("Wasm translation", translation_start.elapsed());
+        }
 
         if let Some(ref callbacks) = config.callbacks {
             callbacks.preopt_ir(&function, &module);
         }
 
+        let optimization_start = Instant::now();
         let pass_manager = PassManager::create(());
 
         if config.enable_verifier {
@@ -259,6 +265,9 @@ impl FuncTranslator {
         pass_manager.add_early_cse_pass();
 
         pass_manager.run_on(&module);
+        if let Some(ref timings) = config.pass_timings {
+            timings.add("IR optimization", optimization_start.elapsed());
+        }
 
         if let Some(ref callbacks) = config.callbacks {
             callbacks.postopt_ir(&function, &module);
@@ -290,9 +299,13 @@ impl FuncTranslator {
         )?;
         let function = CompiledKind::Local(*local_func_index);
         let target_machine = &self.target_machine;
+        let codegen_start = Instant::now();
         let memory_buffer = target_machine
             .write_to_memory_buffer(&module, FileType::Object)
             .unwrap();
+        if let Some(ref timings) = config.pass_timings {
+            timings.add("Code generation", codegen_start.elapsed());
+        }
 
         if let Some(ref callbacks) = config.callbacks {
             callbacks.obj_memory_buffer(&function, &memory_buffer);
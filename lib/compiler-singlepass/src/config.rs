@@ -5,7 +5,7 @@ use crate::compiler::SinglepassCompiler;
 use loupe::MemoryUsage;
 use std::sync::Arc;
 use wasmer_compiler::{
-    CallingConvention, Compiler, CompilerConfig, CpuFeature, ModuleMiddleware, Target,
+    CallingConvention, CompileError, Compiler, CompilerConfig, CpuFeature, ModuleMiddleware, Target,
 };
 use wasmer_types::Features;
 
@@ -64,6 +64,12 @@ impl CompilerConfig for Singlepass {
         // PIC code.
     }
 
+    fn disable_unwind_info(&mut self) -> Result<(), CompileError> {
+        // Do nothing, since singlepass doesn't emit unwind
+        // information.
+        Ok(())
+    }
+
     /// Transform it into the compiler
     fn compiler(self: Box<Self>) -> Box<dyn Compiler> {
         Box::new(SinglepassCompiler::new(*self))
@@ -4,7 +4,9 @@
 use crate::error::CompileError;
 use crate::function::Compilation;
 use crate::lib::std::boxed::Box;
+use crate::lib::std::fmt;
 use crate::lib::std::sync::Arc;
+use crate::lib::std::time::Duration;
 use crate::module::CompileModuleInfo;
 use crate::target::Target;
 use crate::translator::ModuleMiddleware;
@@ -28,6 +30,19 @@ pub trait CompilerConfig {
         // in case they do something special for emitting PIC code.
     }
 
+    /// Set the relocation model of the emitted code, overriding the
+    /// one implied by [`CompilerConfig::enable_pic`].
+    ///
+    /// Returns an error if the backend can't emit code with the
+    /// given relocation model.
+    fn relocation_model(&mut self, model: RelocationModel) -> Result<(), CompileError> {
+        // By default we only support the relocation model chosen with `enable_pic`.
+        Err(CompileError::UnsupportedFeature(format!(
+            "relocation model `{}`",
+            model
+        )))
+    }
+
     /// Enable compiler IR verification.
     ///
     /// For compilers capable of doing so, this enables internal consistency
@@ -56,6 +71,37 @@ pub trait CompilerConfig {
         // in case they create an IR that they can verify.
     }
 
+    /// Report the time spent in each pass of the compiler to
+    /// `timings`, summed across the compiled functions.
+    ///
+    /// Returns an error if the backend doesn't time its passes.
+    fn pass_timings(&mut self, _timings: Arc<dyn PassTimings>) -> Result<(), CompileError> {
+        Err(CompileError::UnsupportedFeature("pass timings".into()))
+    }
+
+    /// Don't generate unwind information for the compiled functions,
+    /// like the DWARF `.eh_frame` tables.
+    ///
+    /// Debuggers, profilers and backtraces can't walk through the
+    /// functions without it. Returns an error if the backend can't
+    /// leave it out.
+    fn disable_unwind_info(&mut self) -> Result<(), CompileError> {
+        Err(CompileError::UnsupportedFeature(
+            "disabling the unwind information".into(),
+        ))
+    }
+
+    /// Emit the objects of [`Compiler::experimental_native_compile_module`]
+    /// as LLVM bitcode instead of machine code, to optimize them at link
+    /// time.
+    ///
+    /// Returns an error if the backend doesn't emit bitcode.
+    fn emit_bitcode(&mut self) -> Result<(), CompileError> {
+        Err(CompileError::UnsupportedFeature(
+            "emitting LLVM bitcode".into(),
+        ))
+    }
+
     /// Gets the custom compiler config
     fn compiler(self: Box<Self>) -> Box<dyn Compiler>;
 
@@ -77,6 +123,36 @@ where
     }
 }
 
+/// Receives the time spent in each pass of a compiler, set with
+/// [`CompilerConfig::pass_timings`].
+pub trait PassTimings: fmt::Debug + Send + Sync {
+    /// Add `duration` to the time spent in `pass`, not counting the
+    /// time spent in the passes it ran itself.
+    fn add(&self, pass: &str, duration: Duration);
+}
+
+/// The relocation model of the code emitted by a compiler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, MemoryUsage)]
+pub enum RelocationModel {
+    /// Position dependent code, for a fixed load address.
+    Static,
+    /// Position independent code.
+    Pic,
+    /// Position dependent code referencing external symbols indirectly,
+    /// as used by Mach-O executables.
+    DynamicNoPic,
+}
+
+impl fmt::Display for RelocationModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Static => "static",
+            Self::Pic => "pic",
+            Self::DynamicNoPic => "dynamic-no-pic",
+        })
+    }
+}
+
 /// An implementation of a Compiler from parsed WebAssembly module to Compiled native code.
 pub trait Compiler: Send + MemoryUsage {
     /// Validates a module.
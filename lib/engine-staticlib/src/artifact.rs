@@ -2,8 +2,10 @@
 //! done as separate steps.
 
 use crate::engine::{StaticlibEngine, StaticlibEngineInner};
-use crate::serialize::{ModuleMetadata, ModuleMetadataSymbolRegistry};
+use crate::serialize::{MetadataCompression, ModuleMetadata, ModuleMetadataSymbolRegistry};
 use loupe::MemoryUsage;
+#[cfg(feature = "compiler")]
+use object::write::StandardSection;
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::mem;
@@ -18,7 +20,10 @@ use wasmer_engine::{Artifact, DeserializeError, InstantiationError, SerializeErr
 #[cfg(feature = "compiler")]
 use wasmer_engine::{Engine, Tunables};
 #[cfg(feature = "compiler")]
-use wasmer_object::{emit_compilation, emit_data, get_object_for_target};
+use wasmer_object::{
+    emit_compilation_with_relocation_table, emit_data, emit_section_bound, get_object_for_target,
+    FunctionLayout,
+};
 use wasmer_types::entity::EntityRef;
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
 #[cfg(feature = "compiler")]
@@ -55,6 +60,19 @@ fn to_compile_error(err: impl Error) -> CompileError {
 
 #[allow(dead_code)]
 const WASMER_METADATA_SYMBOL: &[u8] = b"WASMER_METADATA";
+/// The end of the metadata, the bounds of the text section holding the
+/// functions, and the table of the absolute relocations in it (offsets
+/// from `WASMER_CODE_START`), so that the linked image can check them.
+#[allow(dead_code)]
+const WASMER_METADATA_END_SYMBOL: &[u8] = b"WASMER_METADATA_END";
+#[allow(dead_code)]
+const WASMER_CODE_START_SYMBOL: &[u8] = b"WASMER_CODE_START";
+#[allow(dead_code)]
+const WASMER_CODE_END_SYMBOL: &[u8] = b"WASMER_CODE_END";
+#[allow(dead_code)]
+const WASMER_CODE_RELOCATIONS_SYMBOL: &[u8] = b"WASMER_CODE_RELOCATIONS";
+#[allow(dead_code)]
+const WASMER_CODE_RELOCATIONS_END_SYMBOL: &[u8] = b"WASMER_CODE_RELOCATIONS_END";
 
 impl StaticlibArtifact {
     // Mach-O header in Mac
@@ -201,10 +219,26 @@ impl StaticlibArtifact {
          */
 
         let serialized_data = bincode::serialize(&metadata).map_err(to_compile_error)?;
+        let metadata_compression = engine_inner.metadata_compression();
+        let function_layout = if engine_inner.single_symbol() {
+            let symbol_registry = metadata.get_symbol_registry();
+            FunctionLayout::SingleSymbol {
+                code: symbol_registry.code_symbol_name(),
+                offsets: symbol_registry.function_offsets_symbol_name(),
+            }
+        } else if engine_inner.function_sections() {
+            FunctionLayout::Sections
+        } else {
+            FunctionLayout::Symbols
+        };
+        let serialized_data = metadata_compression
+            .compress(serialized_data)
+            .map_err(to_compile_error)?;
         let mut metadata_binary = vec![0; 10];
         let mut writable = &mut metadata_binary[..];
         leb128::write::unsigned(&mut writable, serialized_data.len() as u64)
             .expect("Should write number");
+        metadata_binary[MetadataCompression::TAG_OFFSET] = metadata_compression.tag();
         metadata_binary.extend(serialized_data);
         let metadata_length = metadata_binary.len();
 
@@ -245,8 +279,31 @@ impl StaticlibArtifact {
             let mut obj = get_object_for_target(&target_triple).map_err(to_compile_error)?;
             emit_data(&mut obj, WASMER_METADATA_SYMBOL, &metadata_binary, 1)
                 .map_err(to_compile_error)?;
-            emit_compilation(&mut obj, compilation, &symbol_registry, &target_triple)
+            emit_section_bound(&mut obj, StandardSection::Data, WASMER_METADATA_END_SYMBOL)
+                .map_err(to_compile_error)?;
+            // The text section is still empty, so its offsets are the ones
+            // from the start symbol. With `FunctionLayout::Sections`, the
+            // functions are outside of these bounds, which only hold the
+            // trampolines.
+            emit_section_bound(&mut obj, StandardSection::Text, WASMER_CODE_START_SYMBOL)
                 .map_err(to_compile_error)?;
+            emit_compilation_with_relocation_table(
+                &mut obj,
+                compilation,
+                &symbol_registry,
+                &target_triple,
+                &function_layout,
+                WASMER_CODE_RELOCATIONS_SYMBOL,
+            )
+            .map_err(to_compile_error)?;
+            emit_section_bound(&mut obj, StandardSection::Text, WASMER_CODE_END_SYMBOL)
+                .map_err(to_compile_error)?;
+            emit_section_bound(
+                &mut obj,
+                StandardSection::Data,
+                WASMER_CODE_RELOCATIONS_END_SYMBOL,
+            )
+            .map_err(to_compile_error)?;
             obj.write().map_err(to_compile_error)?
         };
 
@@ -316,9 +373,22 @@ impl StaticlibArtifact {
         bytes: &[u8],
     ) -> Result<Self, DeserializeError> {
         let mut reader = bytes;
-        let data_len = leb128::read::unsigned(&mut reader).unwrap() as usize;
+        let data_len = leb128::read::unsigned(&mut reader).map_err(|e| {
+            DeserializeError::CorruptedBinary(format!("invalid metadata length: {}", e))
+        })? as usize;
+        if bytes.len() < data_len + 10 {
+            return Err(DeserializeError::CorruptedBinary(
+                "the metadata is truncated".to_string(),
+            ));
+        }
 
-        let metadata: ModuleMetadata = bincode::deserialize(&bytes[10..(data_len + 10)]).unwrap();
+        let metadata_bytes = MetadataCompression::decompress(
+            bytes[MetadataCompression::TAG_OFFSET],
+            &bytes[10..(data_len + 10)],
+        )
+        .map_err(DeserializeError::CorruptedBinary)?;
+        let metadata: ModuleMetadata = bincode::deserialize(&metadata_bytes)
+            .map_err(|e| DeserializeError::CorruptedBinary(format!("{}", e)))?;
 
         const WORD_SIZE: usize = mem::size_of::<usize>();
         let mut byte_buffer = [0u8; WORD_SIZE];
@@ -423,10 +493,37 @@ impl StaticlibArtifact {
         &self.symbol_registry
     }
 
+    /// The name of the symbol of the compiled functions, when they are
+    /// emitted in a single symbol (see
+    /// [`StaticlibEngine::set_single_symbol`](crate::StaticlibEngine::set_single_symbol)).
+    ///
+    /// The offset of each function in it is in the symbol named by
+    /// [`Self::function_offsets_symbol_name`], an array of `u64`
+    /// ordered by local function index.
+    pub fn code_symbol_name(&self) -> String {
+        self.symbol_registry.code_symbol_name()
+    }
+
+    /// The name of the symbol of the offsets of the compiled functions,
+    /// when they are emitted in a single symbol.
+    pub fn function_offsets_symbol_name(&self) -> String {
+        self.symbol_registry.function_offsets_symbol_name()
+    }
+
+    /// The prefix used in the names of the symbols of the Artifact.
+    pub fn prefix(&self) -> &str {
+        &self.metadata.prefix
+    }
+
     /// The length in bytes of the metadata in the serialized output.
     pub fn metadata_length(&self) -> usize {
         self.metadata_length
     }
+
+    /// The length in bytes of the metadata before being compressed.
+    pub fn uncompressed_metadata_length(&self) -> usize {
+        bincode::serialized_size(&self.metadata).unwrap_or_default() as usize
+    }
 }
 
 impl Artifact for StaticlibArtifact {
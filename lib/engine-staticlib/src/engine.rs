@@ -1,4 +1,4 @@
-use crate::StaticlibArtifact;
+use crate::{MetadataCompression, StaticlibArtifact};
 use loupe::MemoryUsage;
 use std::io::Read;
 use std::path::Path;
@@ -33,6 +33,9 @@ impl StaticlibEngine {
                 signatures: SignatureRegistry::new(),
                 func_data: Arc::new(FuncDataRegistry::new()),
                 prefixer: None,
+                metadata_compression: MetadataCompression::None,
+                function_sections: false,
+                single_symbol: false,
                 features,
             })),
             target: Arc::new(target),
@@ -63,6 +66,9 @@ impl StaticlibEngine {
                 signatures: SignatureRegistry::new(),
                 func_data: Arc::new(FuncDataRegistry::new()),
                 prefixer: None,
+                metadata_compression: MetadataCompression::None,
+                function_sections: false,
+                single_symbol: false,
             })),
             target: Arc::new(Target::default()),
             engine_id: EngineId::default(),
@@ -88,6 +94,36 @@ impl StaticlibEngine {
         inner.prefixer = Some(Box::new(prefixer));
     }
 
+    /// Sets the compression used for the metadata embedded in the
+    /// generated objects.
+    pub fn set_metadata_compression(&mut self, compression: MetadataCompression) {
+        let mut inner = self.inner_mut();
+        inner.metadata_compression = compression;
+    }
+
+    /// Sets whether each compiled function is emitted in its own
+    /// section of the generated objects, so a linker can discard the
+    /// unused ones with `--gc-sections`.
+    ///
+    /// This has no effect with compilers that generate the objects
+    /// themselves, like LLVM.
+    pub fn set_function_sections(&mut self, function_sections: bool) {
+        let mut inner = self.inner_mut();
+        inner.function_sections = function_sections;
+    }
+
+    /// Sets whether the compiled functions are emitted in a single
+    /// symbol of the generated objects, with a table of their offsets,
+    /// instead of a symbol each. See
+    /// [`StaticlibArtifact::code_symbol_name`](crate::StaticlibArtifact::code_symbol_name).
+    ///
+    /// This has no effect with compilers that generate the objects
+    /// themselves, like LLVM.
+    pub fn set_single_symbol(&mut self, single_symbol: bool) {
+        let mut inner = self.inner_mut();
+        inner.single_symbol = single_symbol;
+    }
+
     pub(crate) fn inner(&self) -> std::sync::MutexGuard<'_, StaticlibEngineInner> {
         self.inner.lock().unwrap()
     }
@@ -202,6 +238,18 @@ pub struct StaticlibEngineInner {
     /// `StaticlibEngine`, so we can assure no collisions.
     #[loupe(skip)]
     prefixer: Option<Box<dyn Fn(&[u8]) -> String + Send>>,
+
+    /// The compression applied to the metadata embedded in the
+    /// generated objects.
+    metadata_compression: MetadataCompression,
+
+    /// Whether each function is emitted in its own section of the
+    /// generated objects.
+    function_sections: bool,
+
+    /// Whether the functions are emitted in a single symbol of the
+    /// generated objects.
+    single_symbol: bool,
 }
 
 impl StaticlibEngineInner {
@@ -226,6 +274,21 @@ impl StaticlibEngineInner {
         }
     }
 
+    #[cfg(feature = "compiler")]
+    pub(crate) fn metadata_compression(&self) -> MetadataCompression {
+        self.metadata_compression
+    }
+
+    #[cfg(feature = "compiler")]
+    pub(crate) fn function_sections(&self) -> bool {
+        self.function_sections
+    }
+
+    #[cfg(feature = "compiler")]
+    pub(crate) fn single_symbol(&self) -> bool {
+        self.single_symbol
+    }
+
     #[cfg(feature = "compiler")]
     pub(crate) fn features(&self) -> &Features {
         &self.features
@@ -29,6 +29,7 @@ mod serialize;
 pub use crate::artifact::StaticlibArtifact;
 pub use crate::builder::Staticlib;
 pub use crate::engine::StaticlibEngine;
+pub use crate::serialize::MetadataCompression;
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
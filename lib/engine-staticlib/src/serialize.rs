@@ -1,5 +1,7 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use loupe::MemoryUsage;
 use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
 use wasmer_compiler::{CompileModuleInfo, SectionIndex, Symbol, SymbolRegistry};
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{FunctionIndex, LocalFunctionIndex, OwnedDataInitializer, SignatureIndex};
@@ -14,6 +16,72 @@ pub struct ModuleMetadata {
     pub function_body_lengths: PrimaryMap<LocalFunctionIndex, u64>,
 }
 
+/// The compression applied to the serialized metadata embedded in the
+/// generated object.
+///
+/// The algorithm is recorded in the header of the metadata, so the
+/// right decompressor is picked when the artifact is deserialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, MemoryUsage)]
+pub enum MetadataCompression {
+    /// The metadata is stored as is.
+    None,
+    /// The metadata is compressed with gzip, with the given level (0-9).
+    Gzip(u32),
+    /// The metadata is compressed with zstd, with the given level (1-22).
+    Zstd(i32),
+}
+
+impl Default for MetadataCompression {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl MetadataCompression {
+    /// The offset of the compression tag in the metadata header.
+    ///
+    /// The header is the LEB128-encoded length of the metadata padded to
+    /// 10 bytes, so the last byte is always zero for uncompressed metadata.
+    pub(crate) const TAG_OFFSET: usize = 9;
+
+    pub(crate) fn tag(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Gzip(_) => 1,
+            Self::Zstd(_) => 2,
+        }
+    }
+
+    pub(crate) fn compress(&self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data),
+            Self::Gzip(level) => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::new(*level));
+                encoder.write_all(&data)?;
+                encoder.finish()
+            }
+            Self::Zstd(level) => zstd::encode_all(&data[..], *level),
+        }
+    }
+
+    /// Decompresses the metadata, given the tag found in its header.
+    pub(crate) fn decompress(tag: u8, data: &[u8]) -> Result<Vec<u8>, String> {
+        match tag {
+            0 => Ok(data.to_vec()),
+            1 => {
+                let mut decompressed = Vec::new();
+                GzDecoder::new(data)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|e| format!("failed to decompress the gzip metadata: {}", e))?;
+                Ok(decompressed)
+            }
+            2 => zstd::decode_all(data)
+                .map_err(|e| format!("failed to decompress the zstd metadata: {}", e)),
+            tag => Err(format!("unknown metadata compression (tag `{}`)", tag)),
+        }
+    }
+}
+
 #[derive(MemoryUsage)]
 pub struct ModuleMetadataSymbolRegistry {
     pub prefix: String,
@@ -35,6 +103,20 @@ impl ModuleMetadata {
     }
 }
 
+impl ModuleMetadataSymbolRegistry {
+    /// The name of the symbol of the functions, when they are emitted
+    /// in a single symbol.
+    pub fn code_symbol_name(&self) -> String {
+        format!("wasmer_code_{}", self.prefix)
+    }
+
+    /// The name of the symbol of the offsets of the functions, when
+    /// they are emitted in a single symbol.
+    pub fn function_offsets_symbol_name(&self) -> String {
+        format!("wasmer_function_offsets_{}", self.prefix)
+    }
+}
+
 impl SymbolRegistry for ModuleMetadataSymbolRegistry {
     fn symbol_to_name(&self, symbol: Symbol) -> String {
         match symbol {
@@ -90,3 +172,41 @@ impl SymbolRegistry for ModuleMetadataSymbolRegistry {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_metadata_round_trips() {
+        let data = b"wasmer metadata ".repeat(64);
+        for compression in &[
+            MetadataCompression::None,
+            MetadataCompression::Gzip(9),
+            MetadataCompression::Zstd(1),
+            MetadataCompression::Zstd(22),
+        ] {
+            let compressed = compression.compress(data.clone()).unwrap();
+            let decompressed = MetadataCompression::decompress(compression.tag(), &compressed);
+            assert_eq!(decompressed.unwrap(), data, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn corrupted_zstd_metadata_is_reported() {
+        let mut compressed = MetadataCompression::Zstd(3)
+            .compress(b"wasmer metadata".to_vec())
+            .unwrap();
+        let last = compressed.len() - 1;
+        compressed.truncate(last);
+        compressed[0] ^= 0xff;
+        let error =
+            MetadataCompression::decompress(MetadataCompression::Zstd(3).tag(), &compressed)
+                .unwrap_err();
+        assert!(
+            error.starts_with("failed to decompress the zstd metadata: "),
+            "{}",
+            error
+        );
+    }
+}
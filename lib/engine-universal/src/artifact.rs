@@ -5,7 +5,7 @@ use crate::engine::{UniversalEngine, UniversalEngineInner};
 use crate::link::link_module;
 #[cfg(feature = "compiler")]
 use crate::serialize::SerializableCompilation;
-use crate::serialize::SerializableModule;
+use crate::serialize::{SerializableDebugInfo, SerializableModule};
 use loupe::MemoryUsage;
 use std::sync::{Arc, Mutex};
 use wasmer_compiler::{CompileError, Features, Triple};
@@ -29,6 +29,7 @@ use wasmer_vm::{
 
 const SERIALIZED_METADATA_LENGTH_OFFSET: usize = 22;
 const SERIALIZED_METADATA_CONTENT_OFFSET: usize = 32;
+const SERIALIZED_DEBUG_INFO_CONTENT_OFFSET: usize = 16;
 
 /// A compiled wasm module, ready to be instantiated.
 #[derive(MemoryUsage)]
@@ -46,6 +47,7 @@ pub struct UniversalArtifact {
 
 impl UniversalArtifact {
     const MAGIC_HEADER: &'static [u8; 22] = b"\0wasmer-universal\0\0\0\0\0";
+    const DEBUG_INFO_MAGIC_HEADER: &'static [u8; 16] = b"\0wasmer-debug\0\0\0";
 
     /// Check if the provided bytes look like a serialized `UniversalArtifact`.
     pub fn is_deserializable(bytes: &[u8]) -> bool {
@@ -148,6 +150,68 @@ impl UniversalArtifact {
         universal: &UniversalEngine,
         bytes: &[u8],
     ) -> Result<Self, DeserializeError> {
+        let serializable = Self::deserialize_serializable(bytes)?;
+        Self::from_parts(&mut universal.inner_mut(), serializable)
+            .map_err(DeserializeError::Compiler)
+    }
+
+    /// Split the debug information out of a serialized `UniversalArtifact`.
+    ///
+    /// Returns the serialized artifact without the function address maps
+    /// and names, and the debug information on its own. Backtraces of the
+    /// stripped artifact only report function indices, until the debug
+    /// information is added back with [`UniversalArtifact::merge_debug_info`].
+    ///
+    /// # Safety
+    /// This function is unsafe for the same reasons as
+    /// [`UniversalArtifact::deserialize`].
+    pub unsafe fn split_debug_info(bytes: &[u8]) -> Result<(Vec<u8>, Vec<u8>), DeserializeError> {
+        let mut serializable = Self::deserialize_serializable(bytes)?;
+        let debug_info = serializable.strip_debug_info();
+
+        let stripped = Self::serialize_serializable(&serializable)
+            .map_err(|e| DeserializeError::Generic(e.to_string()))?;
+        let mut debug = Self::DEBUG_INFO_MAGIC_HEADER.to_vec();
+        let offset = pad_and_extend::<SerializableDebugInfo>(
+            &mut debug,
+            &debug_info
+                .serialize()
+                .map_err(|e| DeserializeError::Generic(e.to_string()))?,
+        );
+        assert_eq!(offset, SERIALIZED_DEBUG_INFO_CONTENT_OFFSET);
+
+        Ok((stripped, debug))
+    }
+
+    /// Add the debug information produced by
+    /// [`UniversalArtifact::split_debug_info`] back into the serialized
+    /// artifact it was split from.
+    ///
+    /// # Safety
+    /// This function is unsafe for the same reasons as
+    /// [`UniversalArtifact::deserialize`].
+    pub unsafe fn merge_debug_info(
+        bytes: &[u8],
+        debug: &[u8],
+    ) -> Result<Vec<u8>, DeserializeError> {
+        if !debug.starts_with(Self::DEBUG_INFO_MAGIC_HEADER) {
+            return Err(DeserializeError::Incompatible(
+                "The provided bytes are not wasmer debug information".to_string(),
+            ));
+        }
+        let debug_info =
+            SerializableDebugInfo::deserialize(&debug[SERIALIZED_DEBUG_INFO_CONTENT_OFFSET..])?;
+        let mut serializable = Self::deserialize_serializable(bytes)?;
+        serializable.merge_debug_info(debug_info)?;
+
+        Self::serialize_serializable(&serializable)
+            .map_err(|e| DeserializeError::Generic(e.to_string()))
+    }
+
+    /// Deserialize the `SerializableModule` of a serialized `UniversalArtifact`.
+    unsafe fn deserialize_serializable(
+        bytes: &[u8],
+    ) -> Result<SerializableModule, DeserializeError> {
         if !Self::is_deserializable(bytes) {
             return Err(DeserializeError::Incompatible(
                 "The provided bytes are not wasmer-universal".to_string(),
@@ -164,9 +228,26 @@ impl UniversalArtifact {
             metadata_len as usize,
         );
 
-        let serializable = SerializableModule::deserialize(metadata_slice)?;
-        Self::from_parts(&mut universal.inner_mut(), serializable)
-            .map_err(DeserializeError::Compiler)
+        SerializableModule::deserialize(metadata_slice)
+    }
+
+    /// Serialize a `SerializableModule`, prepending the artifact header.
+    fn serialize_serializable(
+        serializable: &SerializableModule,
+    ) -> Result<Vec<u8>, SerializeError> {
+        // Prepend the header.
+        let mut serialized = Self::MAGIC_HEADER.to_vec();
+
+        serialized.resize(SERIALIZED_METADATA_CONTENT_OFFSET, 0);
+        let mut writable_leb = &mut serialized[SERIALIZED_METADATA_LENGTH_OFFSET..];
+        let serialized_data = serializable.serialize()?;
+        let length = serialized_data.len();
+        leb128::write::unsigned(&mut writable_leb, length as u64).expect("Should write number");
+
+        let offset = pad_and_extend::<SerializableModule>(&mut serialized, &serialized_data);
+        assert_eq!(offset, SERIALIZED_METADATA_CONTENT_OFFSET);
+
+        Ok(serialized)
     }
 
     /// Construct a `UniversalArtifact` from component parts.
@@ -336,19 +417,7 @@ impl Artifact for UniversalArtifact {
         &self.func_data_registry
     }
     fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
-        // Prepend the header.
-        let mut serialized = Self::MAGIC_HEADER.to_vec();
-
-        serialized.resize(SERIALIZED_METADATA_CONTENT_OFFSET, 0);
-        let mut writable_leb = &mut serialized[SERIALIZED_METADATA_LENGTH_OFFSET..];
-        let serialized_data = self.serializable.serialize()?;
-        let length = serialized_data.len();
-        leb128::write::unsigned(&mut writable_leb, length as u64).expect("Should write number");
-
-        let offset = pad_and_extend::<SerializableModule>(&mut serialized, &serialized_data);
-        assert_eq!(offset, SERIALIZED_METADATA_CONTENT_OFFSET);
-
-        Ok(serialized)
+        Self::serialize_serializable(&self.serializable)
     }
 }
 
@@ -6,9 +6,11 @@ use rkyv::{
     ser::{serializers::WriteSerializer, Serializer as RkyvSerializer},
     Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize,
 };
+use std::collections::HashMap;
+use std::sync::Arc;
 use wasmer_compiler::{
-    CompileModuleInfo, CompiledFunctionFrameInfo, CustomSection, Dwarf, FunctionBody,
-    JumpTableOffsets, Relocation, SectionIndex,
+    CompileModuleInfo, CompiledFunctionFrameInfo, CustomSection, Dwarf, FunctionAddressMap,
+    FunctionBody, JumpTableOffsets, Relocation, SectionIndex,
 };
 use wasmer_engine::{DeserializeError, SerializeError};
 use wasmer_types::entity::PrimaryMap;
@@ -38,6 +40,20 @@ pub struct SerializableModule {
     pub data_initializers: Box<[OwnedDataInitializer]>,
 }
 
+/// The debug information that can be split out of a `SerializableModule`
+/// and stored on its own.
+///
+/// It is only needed to symbolicate backtraces: without it, frames are
+/// reported with their function index and no source offsets.
+#[derive(Archive, RkyvDeserialize, RkyvSerialize)]
+pub struct SerializableDebugInfo {
+    /// The length of each function body, used to check that the debug
+    /// information belongs to the module it is merged into.
+    pub function_body_lengths: PrimaryMap<LocalFunctionIndex, u64>,
+    pub function_address_maps: PrimaryMap<LocalFunctionIndex, FunctionAddressMap>,
+    pub function_names: HashMap<FunctionIndex, String>,
+}
+
 fn to_serialize_error(err: impl std::error::Error) -> SerializeError {
     SerializeError::Generic(format!("{}", err))
 }
@@ -100,4 +116,91 @@ impl SerializableModule {
         RkyvDeserialize::deserialize(archived, &mut deserializer)
             .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))
     }
+
+    /// Remove the debug information (the function address maps and
+    /// names) from the module, returning it.
+    pub fn strip_debug_info(&mut self) -> SerializableDebugInfo {
+        let function_body_lengths = self
+            .compilation
+            .function_bodies
+            .values()
+            .map(|function_body| function_body.body.len() as u64)
+            .collect();
+        let function_address_maps = self
+            .compilation
+            .function_frame_info
+            .values_mut()
+            .map(|frame_info| std::mem::take(&mut frame_info.address_map))
+            .collect();
+        let function_names =
+            std::mem::take(&mut Arc::make_mut(&mut self.compile_info.module).function_names);
+        SerializableDebugInfo {
+            function_body_lengths,
+            function_address_maps,
+            function_names,
+        }
+    }
+
+    /// Add back debug information previously removed with
+    /// `strip_debug_info`.
+    pub fn merge_debug_info(
+        &mut self,
+        debug_info: SerializableDebugInfo,
+    ) -> Result<(), DeserializeError> {
+        let function_body_lengths = self
+            .compilation
+            .function_bodies
+            .values()
+            .map(|function_body| function_body.body.len() as u64);
+        if !function_body_lengths.eq(debug_info.function_body_lengths.values().copied())
+            || debug_info.function_address_maps.len() != self.compilation.function_frame_info.len()
+        {
+            return Err(DeserializeError::Incompatible(
+                "the debug information doesn't belong to this module".to_string(),
+            ));
+        }
+        for (index, address_map) in debug_info.function_address_maps {
+            self.compilation.function_frame_info[index].address_map = address_map;
+        }
+        Arc::make_mut(&mut self.compile_info.module).function_names = debug_info.function_names;
+        Ok(())
+    }
+}
+
+impl SerializableDebugInfo {
+    /// Serialize the debug information into bytes, with the same
+    /// format as `SerializableModule::serialize`.
+    pub fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        let mut serializer = SharedSerializerAdapter::new(WriteSerializer::new(vec![]));
+        let pos = serializer
+            .serialize_value(self)
+            .map_err(to_serialize_error)? as u64;
+        let mut serialized_data = serializer.into_inner().into_inner();
+        serialized_data.extend_from_slice(&pos.to_le_bytes());
+        Ok(serialized_data)
+    }
+
+    /// Deserialize the debug information from a slice.
+    ///
+    /// # Safety
+    ///
+    /// This method is unsafe for the same reasons as
+    /// `SerializableModule::deserialize`.
+    pub unsafe fn deserialize(debug_slice: &[u8]) -> Result<Self, DeserializeError> {
+        if debug_slice.len() < 8 {
+            return Err(DeserializeError::Incompatible(
+                "invalid serialized data".into(),
+            ));
+        }
+        let mut pos: [u8; 8] = Default::default();
+        pos.copy_from_slice(&debug_slice[debug_slice.len() - 8..debug_slice.len()]);
+        let pos: u64 = u64::from_le_bytes(pos);
+        let archived = archived_value::<Self>(
+            &debug_slice[..debug_slice.len() - 8],
+            pos as usize,
+        );
+        let mut deserializer = SharedDeserializerAdapter::new(AllocDeserializer);
+        RkyvDeserialize::deserialize(archived, &mut deserializer)
+            .map_err(|e| DeserializeError::CorruptedBinary(format!("{:?}", e)))
+    }
 }
@@ -24,4 +24,8 @@ mod error;
 mod module;
 
 pub use crate::error::ObjectError;
-pub use crate::module::{emit_compilation, emit_data, get_object_for_target};
+pub use crate::module::{
+    emit_compilation, emit_compilation_with_function_sections, emit_compilation_with_layout,
+    emit_compilation_with_relocation_table, emit_data, emit_section_bound, get_object_for_target,
+    FunctionLayout,
+};
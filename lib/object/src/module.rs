@@ -104,6 +104,50 @@ pub fn emit_data(
     Ok(())
 }
 
+/// Emit an empty symbol at the current end of `section`, delimiting what
+/// was emitted in it before from what is emitted after.
+///
+/// # Usage
+///
+/// ```rust
+/// # use wasmer_compiler::Triple;
+/// # use wasmer_object::ObjectError;
+/// use object::write::StandardSection;
+/// use wasmer_object::{get_object_for_target, emit_data, emit_section_bound};
+///
+/// # fn emit_data_into_object(triple: &Triple) -> Result<(), ObjectError> {
+/// let mut object = get_object_for_target(&triple)?;
+/// emit_data(&mut object, b"WASMER_METADATA", &b"Hello, World!"[..], 1)?;
+/// emit_section_bound(&mut object, StandardSection::Data, b"WASMER_METADATA_END")?;
+///
+/// # Ok(())
+/// # }
+/// ```
+pub fn emit_section_bound(
+    obj: &mut Object,
+    section: StandardSection,
+    name: &[u8],
+) -> Result<(), ObjectError> {
+    let section_id = obj.section_id(section);
+    let kind = match section {
+        StandardSection::Text => SymbolKind::Text,
+        _ => SymbolKind::Data,
+    };
+    let offset = obj.append_section_data(section_id, &[], 1);
+    obj.add_symbol(ObjSymbol {
+        name: name.to_vec(),
+        value: offset,
+        size: 0,
+        kind,
+        scope: SymbolScope::Dynamic,
+        weak: false,
+        section: SymbolSection::Section(section_id),
+        flags: SymbolFlags::None,
+    });
+
+    Ok(())
+}
+
 /// Emit the compilation result into an existing object.
 ///
 /// # Usage
@@ -129,6 +173,114 @@ pub fn emit_compilation(
     symbol_registry: &impl SymbolRegistry,
     triple: &Triple,
 ) -> Result<(), ObjectError> {
+    emit_compilation_with_function_sections(obj, compilation, symbol_registry, triple, false)
+}
+
+/// Emit the compilation result into an existing object, like
+/// [`emit_compilation`].
+///
+/// If `function_sections` is set, each function is emitted in its own
+/// text section (like `-ffunction-sections` does in C compilers), so
+/// the linker can discard the unreferenced ones with `--gc-sections`.
+/// On Mach-O, where sections are limited, the functions are kept in
+/// the text section and split by their symbols instead.
+pub fn emit_compilation_with_function_sections(
+    obj: &mut Object,
+    compilation: Compilation,
+    symbol_registry: &impl SymbolRegistry,
+    triple: &Triple,
+    function_sections: bool,
+) -> Result<(), ObjectError> {
+    let layout = if function_sections {
+        FunctionLayout::Sections
+    } else {
+        FunctionLayout::Symbols
+    };
+    emit_compilation_with_layout(obj, compilation, symbol_registry, triple, &layout)
+}
+
+/// How the compiled functions are laid out in an object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionLayout {
+    /// Each function has its own symbol, in the text section.
+    Symbols,
+    /// Each function has its own symbol and its own text section, see
+    /// [`emit_compilation_with_function_sections`].
+    Sections,
+    /// The functions are concatenated in a single `code` symbol, and the
+    /// offset of each function in it is written to an `offsets` data
+    /// symbol, as an array of `u64` ordered by local function index.
+    ///
+    /// The symbol table stays small, but the linker can't discard the
+    /// unused functions. The `code` symbol is only visible to the
+    /// linked image, so the calls between functions can't be preempted.
+    SingleSymbol {
+        /// The name of the symbol of the functions.
+        code: String,
+        /// The name of the symbol of the offsets table.
+        offsets: String,
+    },
+}
+
+/// Emit the compilation result into an existing object, like
+/// [`emit_compilation`], laying out the functions as `layout` says.
+pub fn emit_compilation_with_layout(
+    obj: &mut Object,
+    compilation: Compilation,
+    symbol_registry: &impl SymbolRegistry,
+    triple: &Triple,
+    layout: &FunctionLayout,
+) -> Result<(), ObjectError> {
+    emit_compilation_with_absolute_relocations(obj, compilation, symbol_registry, triple, layout)?;
+    Ok(())
+}
+
+/// Emit the compilation result into an existing object, like
+/// [`emit_compilation_with_layout`], followed by a `name` data symbol
+/// listing the offsets of the 64-bit absolute relocations of the text
+/// section, in increasing order, as an array of `u64`.
+///
+/// These are the addresses in the code which the loader may relocate, so
+/// that the code of a linked image can be compared without them.
+pub fn emit_compilation_with_relocation_table(
+    obj: &mut Object,
+    compilation: Compilation,
+    symbol_registry: &impl SymbolRegistry,
+    triple: &Triple,
+    layout: &FunctionLayout,
+    name: &[u8],
+) -> Result<(), ObjectError> {
+    let mut offsets = emit_compilation_with_absolute_relocations(
+        obj,
+        compilation,
+        symbol_registry,
+        triple,
+        layout,
+    )?;
+    offsets.sort_unstable();
+    let big_endian = triple.endianness() == Ok(Endianness::Big);
+    let table = offsets
+        .iter()
+        .flat_map(|offset| {
+            if big_endian {
+                offset.to_be_bytes()
+            } else {
+                offset.to_le_bytes()
+            }
+        })
+        .collect::<Vec<u8>>();
+    emit_data(obj, name, &table, 8)
+}
+
+/// Emit the compilation result, returning the offsets in the text section
+/// of its 64-bit absolute relocations.
+fn emit_compilation_with_absolute_relocations(
+    obj: &mut Object,
+    compilation: Compilation,
+    symbol_registry: &impl SymbolRegistry,
+    triple: &Triple,
+    layout: &FunctionLayout,
+) -> Result<Vec<u64>, ObjectError> {
     let function_bodies = compilation.get_function_bodies();
     let function_relocations = compilation.get_relocations();
     let custom_sections = compilation.get_custom_sections();
@@ -197,27 +349,82 @@ pub fn emit_compilation(
         })
         .collect::<PrimaryMap<SectionIndex, _>>();
 
-    // Add functions
-    let function_symbol_ids = function_bodies
-        .into_iter()
-        .map(|(function_local_index, function)| {
-            let function_name =
-                symbol_registry.symbol_to_name(Symbol::LocalFunction(function_local_index));
+    // Add functions, with their offset in their symbol
+    let function_symbol_ids = match layout {
+        FunctionLayout::SingleSymbol { code, offsets } => {
             let section_id = obj.section_id(StandardSection::Text);
+            let mut code_bytes = Vec::new();
+            let function_offsets = function_bodies
+                .into_iter()
+                .map(|(_, function)| {
+                    let offset = (code_bytes.len() as u64 + align - 1) / align * align;
+                    code_bytes.resize(offset as usize, 0);
+                    code_bytes.extend_from_slice(&function.body);
+                    offset
+                })
+                .collect::<PrimaryMap<LocalFunctionIndex, _>>();
             let symbol_id = obj.add_symbol(ObjSymbol {
-                name: function_name.into_bytes(),
+                name: code.as_bytes().to_vec(),
                 value: 0,
-                size: function.body.len() as _,
+                size: code_bytes.len() as _,
                 kind: SymbolKind::Text,
-                scope: SymbolScope::Dynamic,
+                scope: SymbolScope::Linkage,
                 weak: false,
                 section: SymbolSection::Section(section_id),
                 flags: SymbolFlags::None,
             });
-            obj.add_symbol_data(symbol_id, section_id, &function.body, align);
-            (section_id, symbol_id)
-        })
-        .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+            obj.add_symbol_data(symbol_id, section_id, &code_bytes, align);
+
+            let big_endian = triple.endianness() == Ok(Endianness::Big);
+            let offsets_bytes = function_offsets
+                .values()
+                .flat_map(|offset| {
+                    if big_endian {
+                        offset.to_be_bytes()
+                    } else {
+                        offset.to_le_bytes()
+                    }
+                })
+                .collect::<Vec<u8>>();
+            emit_data(obj, offsets.as_bytes(), &offsets_bytes, 8)?;
+
+            function_offsets
+                .into_iter()
+                .map(|(_, offset)| (section_id, symbol_id, offset))
+                .collect::<PrimaryMap<LocalFunctionIndex, _>>()
+        }
+        FunctionLayout::Symbols | FunctionLayout::Sections => function_bodies
+            .into_iter()
+            .map(|(function_local_index, function)| {
+                let function_name =
+                    symbol_registry.symbol_to_name(Symbol::LocalFunction(function_local_index));
+                let section_id = if *layout == FunctionLayout::Sections {
+                    // The body is added with the symbol below
+                    let (section_id, _) = obj.add_subsection(
+                        StandardSection::Text,
+                        function_name.as_bytes(),
+                        &[],
+                        align,
+                    );
+                    section_id
+                } else {
+                    obj.section_id(StandardSection::Text)
+                };
+                let symbol_id = obj.add_symbol(ObjSymbol {
+                    name: function_name.into_bytes(),
+                    value: 0,
+                    size: function.body.len() as _,
+                    kind: SymbolKind::Text,
+                    scope: SymbolScope::Dynamic,
+                    weak: false,
+                    section: SymbolSection::Section(section_id),
+                    flags: SymbolFlags::None,
+                });
+                obj.add_symbol_data(symbol_id, section_id, &function.body, align);
+                (section_id, symbol_id, 0)
+            })
+            .collect::<PrimaryMap<LocalFunctionIndex, _>>(),
+    };
 
     // Add function call trampolines
     for (signature_index, function) in function_call_trampolines.into_iter() {
@@ -258,20 +465,24 @@ pub fn emit_compilation(
     let mut all_relocations = Vec::new();
 
     for (function_local_index, relocations) in function_relocations.into_iter() {
-        let (section_id, symbol_id) = function_symbol_ids.get(function_local_index).unwrap();
-        all_relocations.push((*section_id, *symbol_id, relocations))
+        let (section_id, symbol_id, offset) =
+            function_symbol_ids.get(function_local_index).unwrap();
+        all_relocations.push((*section_id, *symbol_id, *offset, relocations))
     }
 
     for (section_index, relocations) in custom_section_relocations.into_iter() {
         if !debug_index.map(|d| d == section_index).unwrap_or(false) {
             // Skip DWARF relocations just yet
             let (section_id, symbol_id) = custom_section_ids.get(section_index).unwrap();
-            all_relocations.push((*section_id, *symbol_id, relocations));
+            all_relocations.push((*section_id, *symbol_id, 0, relocations));
         }
     }
 
-    for (section_id, symbol_id, relocations) in all_relocations.into_iter() {
-        let (_symbol_id, section_offset) = obj.symbol_section_and_offset(symbol_id).unwrap();
+    let text_section_id = obj.section_id(StandardSection::Text);
+    let mut absolute_relocations = Vec::new();
+    for (section_id, symbol_id, offset, relocations) in all_relocations.into_iter() {
+        let (_symbol_id, symbol_offset) = obj.symbol_section_and_offset(symbol_id).unwrap();
+        let section_offset = symbol_offset + offset;
 
         for r in relocations {
             let (relocation_kind, relocation_encoding, relocation_size) = match r.kind {
@@ -310,10 +521,17 @@ pub fn emit_compilation(
             };
 
             let relocation_address = section_offset + r.offset as u64;
+            if section_id == text_section_id
+                && relocation_kind == RelocationKind::Absolute
+                && relocation_size == 64
+                && !matches!(r.reloc_target, RelocationTarget::JumpTable(..))
+            {
+                absolute_relocations.push(relocation_address);
+            }
 
             match r.reloc_target {
                 RelocationTarget::LocalFunc(index) => {
-                    let (_, target_symbol) = function_symbol_ids.get(index).unwrap();
+                    let (_, target_symbol, target_offset) = function_symbol_ids.get(index).unwrap();
                     obj.add_relocation(
                         section_id,
                         Relocation {
@@ -322,7 +540,7 @@ pub fn emit_compilation(
                             kind: relocation_kind,
                             encoding: relocation_encoding,
                             symbol: *target_symbol,
-                            addend: r.addend,
+                            addend: r.addend + *target_offset as i64,
                         },
                     )
                     .map_err(ObjectError::Write)?;
@@ -377,5 +595,5 @@ pub fn emit_compilation(
         }
     }
 
-    Ok(())
+    Ok(absolute_relocations)
 }
@@ -45,6 +45,8 @@ pub struct WasiStateBuilder {
     stderr_override: Option<Box<dyn VirtualFile>>,
     stdin_override: Option<Box<dyn VirtualFile>>,
     fs_override: Option<Box<dyn wasmer_vfs::FileSystem>>,
+    open_files_limit: Option<u32>,
+    preopen_files: Vec<(String, Box<dyn VirtualFile>)>,
 }
 
 impl std::fmt::Debug for WasiStateBuilder {
@@ -58,6 +60,15 @@ impl std::fmt::Debug for WasiStateBuilder {
             .field("stdout_override exists", &self.stdout_override.is_some())
             .field("stderr_override exists", &self.stderr_override.is_some())
             .field("stdin_override exists", &self.stdin_override.is_some())
+            .field("open_files_limit", &self.open_files_limit)
+            .field(
+                "preopen_files",
+                &self
+                    .preopen_files
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
             .finish()
     }
 }
@@ -299,6 +310,49 @@ impl WasiStateBuilder {
         self
     }
 
+    /// Limit the number of file descriptors that can be open at once,
+    /// including the standard streams and the preopened directories.
+    ///
+    /// Opening more files fails with `__WASI_EMFILE`.
+    pub fn open_files_limit(&mut self, limit: u32) -> &mut Self {
+        self.open_files_limit = Some(limit);
+
+        self
+    }
+
+    /// Preopen an already open file, like a socket or a pipe, named
+    /// `name`.
+    ///
+    /// The file gets the next file descriptor after the preopened
+    /// directories. WASI can only describe preopened directories with
+    /// `fd_prestat_get`, so the file isn't one of them: its file
+    /// descriptor is passed in the `WASMER_PREOPEN_FD_{name}`
+    /// environment variable instead.
+    pub fn preopen_file<Name>(
+        &mut self,
+        name: Name,
+        file: Box<dyn VirtualFile>,
+    ) -> Result<&mut Self, WasiStateCreationError>
+    where
+        Name: Into<String>,
+    {
+        let name = name.into();
+        validate_mapped_dir_alias(&name)?;
+        if name.is_empty() || name.contains('=') {
+            return Err(WasiStateCreationError::MappedDirAliasFormattingError(
+                format!("Preopened file name \"{}\" is empty or contains `=`", name),
+            ));
+        }
+        if self.preopen_files.iter().any(|(other, _)| *other == name) {
+            return Err(WasiStateCreationError::MappedDirAliasFormattingError(
+                format!("Preopened file name \"{}\" is used twice", name),
+            ));
+        }
+        self.preopen_files.push((name, file));
+
+        Ok(self)
+    }
+
     /// Sets the FileSystem to be used with this WASI instance.
     ///
     /// This is usually used in case a custom `wasmer_vfs::FileSystem` is needed.
@@ -333,7 +387,8 @@ impl WasiStateBuilder {
     /// * [Self::set_fs],
     /// * [Self::stdin],
     /// * [Self::stdout],
-    /// * [Self::stderr].
+    /// * [Self::stderr],
+    /// * [Self::preopen_file].
     ///
     /// Ideally, the builder must be refactord to update `&mut self`
     /// to `mut self` for every _builder method_, but it will break
@@ -429,16 +484,31 @@ impl WasiStateBuilder {
                 .map_err(WasiStateCreationError::FileSystemError)?;
         }
 
+        let mut preopen_file_envs = vec![];
+        for (name, file) in self.preopen_files.drain(..) {
+            let fd = wasi_fs
+                .preopen_file(&name, file)
+                .map_err(WasiStateCreationError::WasiFsCreationError)?;
+            preopen_file_envs.push((
+                format!("WASMER_PREOPEN_FD_{}", name).into_bytes(),
+                fd.to_string().into_bytes(),
+            ));
+        }
+
         if let Some(f) = &self.setup_fs_fn {
             f(&mut wasi_fs).map_err(WasiStateCreationError::WasiFsSetupError)?;
         }
 
+        // The limit only applies to the files opened by the module
+        wasi_fs.open_files_limit = self.open_files_limit;
+
         Ok(WasiState {
             fs: wasi_fs,
             args: self.args.clone(),
             envs: self
                 .envs
                 .iter()
+                .chain(&preopen_file_envs)
                 .map(|(key, value)| {
                     let mut env = Vec::with_capacity(key.len() + value.len() + 1);
                     env.extend_from_slice(&key);
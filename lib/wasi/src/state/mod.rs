@@ -170,6 +170,9 @@ pub struct WasiFs {
     pub orphan_fds: HashMap<Inode, InodeVal>,
     #[cfg_attr(feature = "enable-serde", serde(skip, default = "default_fs_backing"))]
     fs_backing: Box<dyn FileSystem>,
+    /// The maximum number of file descriptors open at once, if any.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    pub open_files_limit: Option<u32>,
 }
 
 /// Returns the default filesystem backing
@@ -396,6 +399,42 @@ impl WasiFs {
         Ok(wasi_fs)
     }
 
+    /// Open `file` with the next file descriptor, named `name`, for
+    /// [`WasiStateBuilder::preopen_file`].
+    ///
+    /// Unlike the preopened directories, the file isn't reachable from the
+    /// root, and isn't reported by `fd_prestat_get`: it would be used as a
+    /// directory to resolve the paths otherwise.
+    pub(crate) fn preopen_file(
+        &mut self,
+        name: &str,
+        file: Box<dyn VirtualFile>,
+    ) -> Result<__wasi_fd_t, String> {
+        let kind = Kind::File {
+            handle: Some(file),
+            path: PathBuf::new(),
+            fd: None,
+        };
+        let inode = self
+            .create_inode(kind, false, name.to_string())
+            .map_err(|e| {
+                format!(
+                    "Failed to create inode for preopened file (name `{}`): WASI error code: {}",
+                    name, e
+                )
+            })?;
+        let rights = __WASI_RIGHT_FD_READ
+            | __WASI_RIGHT_FD_WRITE
+            | __WASI_RIGHT_FD_FDSTAT_SET_FLAGS
+            | __WASI_RIGHT_FD_FILESTAT_GET
+            | __WASI_RIGHT_POLL_FD_READWRITE
+            | __WASI_RIGHT_SOCK_SHUTDOWN;
+        let fd = self
+            .create_fd(rights, 0, 0, Fd::READ | Fd::WRITE, inode)
+            .map_err(|e| format!("Could not open fd for file {:?}: {}", name, e))?;
+        Ok(fd)
+    }
+
     /// Private helper function to init the filesystem, called in `new` and
     /// `new_with_preopen`
     fn new_init(fs_backing: Box<dyn FileSystem>) -> Result<(Self, Inode), String> {
@@ -410,6 +449,7 @@ impl WasiFs {
             inode_counter: Cell::new(1024),
             orphan_fds: HashMap::new(),
             fs_backing,
+            open_files_limit: None,
         };
         wasi_fs.create_stdin();
         wasi_fs.create_stdout();
@@ -1254,6 +1294,11 @@ impl WasiFs {
         open_flags: u16,
         inode: Inode,
     ) -> Result<__wasi_fd_t, __wasi_errno_t> {
+        if let Some(limit) = self.open_files_limit {
+            if self.fd_map.len() >= limit as usize {
+                return Err(__WASI_EMFILE);
+            }
+        }
         let idx = self.next_fd.get();
         self.next_fd.set(idx + 1);
         self.fd_map.insert(
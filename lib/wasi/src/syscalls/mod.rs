@@ -2554,6 +2554,9 @@ pub fn sched_yield(env: &WasiEnv) -> __wasi_errno_t {
     __WASI_ESUCCESS
 }
 
+/// ### `sock_recv()`
+/// Receive a message from a socket.
+/// Sockets are not supported yet, so this always fails with `__WASI_ENOTCAPABLE`.
 pub fn sock_recv(
     env: &WasiEnv,
     sock: __wasi_fd_t,
@@ -2564,8 +2567,14 @@ pub fn sock_recv(
     ro_flags: WasmPtr<__wasi_roflags_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::sock_recv");
-    unimplemented!("wasi::sock_recv")
+    // Networking is not a capability that can be granted to WASI modules yet,
+    // so we fail gracefully instead of aborting the host.
+    __WASI_ENOTCAPABLE
 }
+
+/// ### `sock_send()`
+/// Send a message on a socket.
+/// Sockets are not supported yet, so this always fails with `__WASI_ENOTCAPABLE`.
 pub fn sock_send(
     env: &WasiEnv,
     sock: __wasi_fd_t,
@@ -2575,9 +2584,13 @@ pub fn sock_send(
     so_datalen: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::sock_send");
-    unimplemented!("wasi::sock_send")
+    __WASI_ENOTCAPABLE
 }
+
+/// ### `sock_shutdown()`
+/// Shut down socket send and receive channels.
+/// Sockets are not supported yet, so this always fails with `__WASI_ENOTCAPABLE`.
 pub fn sock_shutdown(env: &WasiEnv, sock: __wasi_fd_t, how: __wasi_sdflags_t) -> __wasi_errno_t {
     debug!("wasi::sock_shutdown");
-    unimplemented!("wasi::sock_shutdown")
+    __WASI_ENOTCAPABLE
 }
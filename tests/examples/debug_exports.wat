@@ -0,0 +1,10 @@
+(module
+  (func $add (export "add") (param $x i64) (param $y i64) (result i64)
+    (call $debug_check (local.get $x))
+    (i64.add (local.get $x) (local.get $y)))
+  (func $debug_check (export "debug_check") (param $x i64))
+  (func $debug_dump (export "debug_dump") (result i64)
+    (call $dump_state))
+  (func $dump_state (result i64)
+    (i64.const 42))
+)
@@ -0,0 +1,20 @@
+;; Exit with the rights of the first preopened directory: 1 if it can be
+;; read, plus 2 if it can be written.
+(module
+  (import "wasi_snapshot_preview1" "fd_fdstat_get"
+    (func $fd_fdstat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (local $rights i64)
+    ;; the first preopened directory, after stdio and the root
+    (if (call $fd_fdstat_get (i32.const 4) (i32.const 0))
+      (then (call $proc_exit (i32.const 255))))
+    ;; `fs_rights_base`
+    (local.set $rights (i64.load (i32.const 8)))
+    (call $proc_exit
+      (i32.or
+        ;; `fd_read`
+        (i32.wrap_i64 (i64.shr_u (i64.and (local.get $rights) (i64.const 2)) (i64.const 1)))
+        ;; `fd_write`
+        (i32.wrap_i64 (i64.shr_u (i64.and (local.get $rights) (i64.const 64)) (i64.const 5)))))))
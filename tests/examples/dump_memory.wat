@@ -0,0 +1,6 @@
+;; A module writing a marker in its memory before trapping.
+(module
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i64.store (i32.const 16) (i64.const 0x0123456789abcdef))
+    unreachable))
@@ -0,0 +1,13 @@
+;; A WASI module exiting with its first argument, a single digit, plus 10.
+(module
+  (import "wasi_snapshot_preview1" "args_get"
+    (func $args_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    ;; The pointers to the arguments are written at 0, and the arguments at 64
+    (drop (call $args_get (i32.const 0) (i32.const 64)))
+    (call $proc_exit
+      (i32.add
+        (i32.sub (i32.load8_u (i32.load (i32.const 4))) (i32.const 48))
+        (i32.const 10)))))
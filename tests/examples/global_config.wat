@@ -0,0 +1,8 @@
+;; A WASI module exiting with the value of its `config_flags` global.
+(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (global $config_flags (export "config_flags") (mut i32) (i32.const 0))
+  (global (export "version") i32 (i32.const 1))
+  (func (export "_start")
+    (call $proc_exit (global.get $config_flags))))
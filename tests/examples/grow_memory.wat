@@ -0,0 +1,8 @@
+(module
+    (memory 1)
+    (func $main (export "_start")
+        ;; Only returns the size of the memory
+        (drop (memory.grow (i32.const 0)))
+        (drop (memory.grow (i32.const 2)))
+    )
+)
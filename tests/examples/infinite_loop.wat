@@ -0,0 +1,7 @@
+(module
+    (func $main (export "_start")
+        (loop $spin
+            (br $spin)
+        )
+    )
+)
@@ -0,0 +1,8 @@
+(module
+  (memory (export "memory") 1)
+  (global $counter (export "counter") (mut i32) (i32.const 0))
+  (func (export "_initialize")
+    (global.set $counter (i32.const 42))
+    (i32.store (i32.const 0) (i32.const 7)))
+  (func (export "get") (result i32)
+    (i32.add (global.get $counter) (i32.load (i32.const 0)))))
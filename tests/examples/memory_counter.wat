@@ -0,0 +1,9 @@
+;; A WASI module incrementing a counter at the start of its memory, and
+;; growing its memory by one page, before exiting with the counter.
+(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.add (i32.load (i32.const 0)) (i32.const 1)))
+    (drop (memory.grow (i32.const 1)))
+    (call $proc_exit (i32.load (i32.const 0)))))
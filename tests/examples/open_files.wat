@@ -0,0 +1,33 @@
+;; Open `file.txt` from the first preopened directory until it fails, and
+;; exit with the number of files opened if the failure is `EMFILE`, or
+;; 255 otherwise.
+(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "file.txt")
+  (func (export "_start")
+    (local $opened i32)
+    (local $errno i32)
+    (block $done
+      (loop $open
+        (local.set $errno
+          (call $path_open
+            ;; the first preopened directory, after stdio and the root
+            (i32.const 4)
+            (i32.const 0)
+            ;; the path
+            (i32.const 0) (i32.const 8)
+            (i32.const 0)
+            ;; `fd_read` rights
+            (i64.const 2) (i64.const 2)
+            (i32.const 0)
+            ;; where the new fd is written
+            (i32.const 16)))
+        (br_if $done (local.get $errno))
+        (local.set $opened (i32.add (local.get $opened) (i32.const 1)))
+        (br_if $open (i32.lt_u (local.get $opened) (i32.const 100)))))
+    (if (i32.eq (local.get $errno) (i32.const 33))
+      (then (call $proc_exit (local.get $opened))))
+    (call $proc_exit (i32.const 255))))
@@ -0,0 +1,94 @@
+;; Read the preopened file named `input`, whose file descriptor is in the
+;; `WASMER_PREOPEN_FD_input` environment variable, then open `file.txt` in
+;; the first preopened directory, and print what's read from both.
+;;
+;; Exits with 3 if the variable is missing, 4 if `fd_prestat_get` reports
+;; the preopened file, and 5 if `file.txt` can't be opened.
+(module
+  (import "wasi_snapshot_preview1" "environ_sizes_get"
+    (func $environ_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_get"
+    (func $environ_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_get"
+    (func $fd_prestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 4)
+  ;; the written iovecs: the data of the file at 0x400 and of `file.txt`
+  ;; at 0x500, with the lengths read
+  (data (i32.const 0) "\00\04\00\00\00\00\00\00\00\05\00\00\00\00\00\00")
+  ;; the read iovecs: up to 0x100 bytes at 0x400, and at 0x500
+  (data (i32.const 16) "\00\04\00\00\00\01\00\00\00\05\00\00\00\01\00\00")
+  (data (i32.const 0x100) "WASMER_PREOPEN_FD_input=")
+  (data (i32.const 0x120) "file.txt")
+
+  ;; The file descriptor in the environment variable, or -1.
+  (func $preopened_fd (result i32)
+    (local $index i32)
+    (local $entry i32)
+    (local $offset i32)
+    (local $fd i32)
+    (local $digit i32)
+    ;; the number of variables at 0x40, the pointers at 0x1000 and the
+    ;; variables at 0x4000
+    (drop (call $environ_sizes_get (i32.const 0x40) (i32.const 0x44)))
+    (drop (call $environ_get (i32.const 0x1000) (i32.const 0x4000)))
+    (block $not_found
+      (loop $entries
+        (br_if $not_found (i32.ge_u (local.get $index) (i32.load (i32.const 0x40))))
+        (local.set $entry
+          (i32.load (i32.add (i32.const 0x1000) (i32.shl (local.get $index) (i32.const 2)))))
+        (local.set $index (i32.add (local.get $index) (i32.const 1)))
+        ;; compare the 24 bytes of the prefix
+        (local.set $offset (i32.const 0))
+        (block $mismatch
+          (loop $prefix
+            (br_if $mismatch
+              (i32.ne
+                (i32.load8_u (i32.add (local.get $entry) (local.get $offset)))
+                (i32.load8_u (i32.add (i32.const 0x100) (local.get $offset)))))
+            (local.set $offset (i32.add (local.get $offset) (i32.const 1)))
+            (br_if $prefix (i32.lt_u (local.get $offset) (i32.const 24))))
+          ;; parse the decimal digits after the prefix
+          (loop $digits
+            (local.set $digit
+              (i32.sub
+                (i32.load8_u (i32.add (local.get $entry) (local.get $offset)))
+                (i32.const 0x30)))
+            (if (i32.lt_u (local.get $digit) (i32.const 10))
+              (then
+                (local.set $fd
+                  (i32.add (i32.mul (local.get $fd) (i32.const 10)) (local.get $digit)))
+                (local.set $offset (i32.add (local.get $offset) (i32.const 1)))
+                (br $digits))))
+          (return (local.get $fd)))
+        (br $entries)))
+    (i32.const -1))
+
+  (func (export "_start")
+    (local $fd i32)
+    (local.set $fd (call $preopened_fd))
+    (if (i32.lt_s (local.get $fd) (i32.const 0))
+      (then (call $proc_exit (i32.const 3))))
+    ;; `__WASI_EBADF`: the file isn't a preopened directory
+    (if (i32.ne (call $fd_prestat_get (local.get $fd) (i32.const 0x30)) (i32.const 8))
+      (then (call $proc_exit (i32.const 4))))
+    (drop (call $fd_read (local.get $fd) (i32.const 16) (i32.const 1) (i32.const 0x20)))
+    (i32.store (i32.const 4) (i32.load (i32.const 0x20)))
+
+    ;; `file.txt` in the preopened directory after the root, with the
+    ;; `fd_read` right
+    (if (call $path_open
+          (i32.const 4) (i32.const 0) (i32.const 0x120) (i32.const 8) (i32.const 0)
+          (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 0x28))
+      (then (call $proc_exit (i32.const 5))))
+    (drop (call $fd_read (i32.load (i32.const 0x28)) (i32.const 24) (i32.const 1) (i32.const 0x20)))
+    (i32.store (i32.const 12) (i32.load (i32.const 0x20)))
+
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 2) (i32.const 0x24))))
+)
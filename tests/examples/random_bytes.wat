@@ -0,0 +1,18 @@
+;; Write 16 bytes from `random_get` to stdout.
+(module
+  (import "wasi_snapshot_preview1" "random_get"
+    (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; The iovec of the bytes
+  (data (i32.const 0) "\10\00\00\00\10\00\00\00")
+  (func (export "_start")
+    (drop (call $random_get (i32.const 16) (i32.const 16)))
+    (drop
+      (call $fd_write
+        ;; stdout
+        (i32.const 1)
+        (i32.const 0) (i32.const 1)
+        ;; where the number of bytes written is written
+        (i32.const 32)))))
@@ -0,0 +1,9 @@
+;; A reactor whose `increment` export returns a counter starting from the
+;; value set by `_initialize`.
+(module
+  (global $counter (mut i32) (i32.const 0))
+  (func (export "_initialize")
+    (global.set $counter (i32.const 10)))
+  (func (export "increment") (result i32)
+    (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+    (global.get $counter)))
@@ -0,0 +1,3 @@
+(module
+  (func $splat (export "splat") (param i32) (result v128)
+    (i32x4.splat (local.get 0))))
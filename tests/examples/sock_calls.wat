@@ -0,0 +1,22 @@
+;; A WASI module calling `sock_recv`, `sock_send` and `sock_shutdown` on
+;; stdin, and exiting with their errno if they all return the same one,
+;; or with 1 otherwise.
+(module
+  (import "wasi_snapshot_preview1" "sock_recv"
+    (func $sock_recv (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send"
+    (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_shutdown"
+    (func $sock_shutdown (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (local $errno i32)
+    (local.set $errno
+      (call $sock_recv (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 8) (i32.const 12)))
+    (if (i32.ne (call $sock_send (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 8))
+                (local.get $errno))
+      (then (call $proc_exit (i32.const 1))))
+    (if (i32.ne (call $sock_shutdown (i32.const 0) (i32.const 3)) (local.get $errno))
+      (then (call $proc_exit (i32.const 1))))
+    (call $proc_exit (local.get $errno))))
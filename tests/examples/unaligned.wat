@@ -0,0 +1,4 @@
+(module
+  (memory 1)
+  (func $load (export "load") (param i32) (result i32)
+    (i32.load align=1 (local.get 0))))
@@ -0,0 +1,14 @@
+;; A WASI module calling `sched_yield`, never calling `clock_time_get`, and
+;; placing `fd_write` in a table without calling it.
+(module
+  (import "wasi_snapshot_preview1" "sched_yield" (func $sched_yield (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (table 1 funcref)
+  (elem (i32.const 0) $fd_write)
+  (func (export "_start")
+    (drop (call $sched_yield))
+    (drop (call $sched_yield))))
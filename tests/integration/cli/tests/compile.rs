@@ -170,3 +170,127 @@ fn staticlib_engine_works() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn compile_warns_about_slow_patterns() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let output = Command::new(get_wasmer_path())
+        .arg("compile")
+        .arg(format!("{}/{}", ASSET_PATH, "unaligned.wat"))
+        .arg(Compiler::Cranelift.to_flag())
+        .arg(Engine::Universal.to_flag())
+        .arg("--warn-slow-patterns")
+        .arg("-o")
+        .arg(temp_dir.path().join("unaligned.wasmu"))
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "wasmer compile failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("function 0 (`load`) has 1 memory accesses"));
+
+    Ok(())
+}
+
+#[test]
+fn compile_check_determinism_works() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let output = Command::new(get_wasmer_path())
+        .arg("compile")
+        .arg(format!("{}/{}", ASSET_PATH, "fib.wat"))
+        .arg(Compiler::Cranelift.to_flag())
+        .arg(Engine::Universal.to_flag())
+        .arg("--check-determinism")
+        .arg("3")
+        .arg("-o")
+        .arg(temp_dir.path().join("fib.wasmu"))
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "wasmer compile failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("3 compilations produced identical artifacts"));
+
+    Ok(())
+}
+
+#[test]
+fn compile_rejects_big_endian_targets() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let output = Command::new(get_wasmer_path())
+        .arg("compile")
+        .arg(format!("{}/{}", ASSET_PATH, "fib.wat"))
+        .arg(Compiler::Cranelift.to_flag())
+        .arg(Engine::Universal.to_flag())
+        .arg("--target")
+        .arg("s390x-unknown-linux-gnu")
+        .arg("-o")
+        .arg(temp_dir.path().join("fib.wasmu"))
+        .output()?;
+
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the target `s390x-unknown-linux-gnu` is big-endian"),
+        "unexpected stderr: {}",
+        stderr
+    );
+    assert!(!temp_dir.path().join("fib.wasmu").exists());
+
+    Ok(())
+}
+
+#[test]
+fn compile_target_abi_overrides_the_environment() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let compile_with_abi = |target_abi: &str| {
+        Command::new(get_wasmer_path())
+            .arg("compile")
+            .arg(format!("{}/{}", ASSET_PATH, "fib.wat"))
+            .arg(Compiler::Cranelift.to_flag())
+            .arg(Engine::Universal.to_flag())
+            .arg("--target")
+            .arg("x86_64-unknown-linux-gnu")
+            .arg("--target-abi")
+            .arg(target_abi)
+            .arg("-o")
+            .arg(temp_dir.path().join("fib.wasmu"))
+            .output()
+    };
+
+    let output = compile_with_abi("musl")?;
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "wasmer compile failed with: {}",
+        std::str::from_utf8(&output.stderr).unwrap()
+    );
+    assert!(stdout.contains("Target: x86_64-unknown-linux-musl"));
+
+    let output = compile_with_abi("eabihf")?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the supported ABIs are `gnu` and `musl`"),
+        "unexpected stderr: {}",
+        stderr
+    );
+
+    Ok(())
+}
@@ -3,7 +3,7 @@
 use anyhow::{bail, Context};
 use std::fs;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use wasmer_integration_tests_cli::*;
 
@@ -26,6 +26,10 @@ struct WasmerCreateExe {
     native_executable_path: PathBuf,
     /// Compiler with which to compile the Wasm.
     compiler: Compiler,
+    /// Kind of output to produce: `exe`, `pie` or `dylib`.
+    output_type: &'static str,
+    /// Extra CLI flags to pass to the `wasmer create-exe` command.
+    extra_cli_flags: Vec<String>,
 }
 
 impl Default for WasmerCreateExe {
@@ -40,6 +44,8 @@ impl Default for WasmerCreateExe {
             wasm_path: PathBuf::from(create_exe_test_wasm_path()),
             native_executable_path,
             compiler: Compiler::Cranelift,
+            output_type: "exe",
+            extra_cli_flags: vec![],
         }
     }
 }
@@ -53,6 +59,9 @@ impl WasmerCreateExe {
             .arg(&self.compiler.to_flag())
             .arg("-o")
             .arg(&self.native_executable_path)
+            .arg("--output-type")
+            .arg(self.output_type)
+            .args(&self.extra_cli_flags)
             .output()?;
 
         if !output.status.success() {
@@ -160,3 +169,1478 @@ fn create_exe_works_with_file() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn create_exe_works_with_compressed_metadata() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--compress-with".to_string(),
+            "gzip".to_string(),
+            "--compress-level".to_string(),
+            "9".to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    let result_lines = result.lines().collect::<Vec<&str>>();
+    assert_eq!(result_lines, vec!["\"Hello, World\""],);
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_works_with_zstd_metadata() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--compress-with".to_string(),
+            "zstd".to_string(),
+            "--zstd-level".to_string(),
+            "19".to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    let result_lines = result.lines().collect::<Vec<&str>>();
+    assert_eq!(result_lines, vec!["\"Hello, World\""],);
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_rejects_out_of_range_zstd_level() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = PathBuf::from(create_exe_test_wasm_path());
+    for level in &["0", "23"] {
+        let output = Command::new(get_wasmer_path())
+            .current_dir(&operating_dir)
+            .arg("create-exe")
+            .arg(&wasm_path)
+            .arg(Compiler::Cranelift.to_flag())
+            .arg("-o")
+            .arg(operating_dir.join("wasm.out"))
+            .arg("--compress-with")
+            .arg("zstd")
+            .arg("--zstd-level")
+            .arg(level)
+            .output()?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(
+            stderr.contains(&format!(
+                "the zstd compression level must be between 1 and 22, got {}",
+                level
+            )),
+            "{}",
+            stderr
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_works_with_prefix_algorithm() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--prefix-algorithm".to_string(), "crc32".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    let result_lines = result.lines().collect::<Vec<&str>>();
+    assert_eq!(result_lines, vec!["\"Hello, World\""],);
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_writes_build_manifest() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+    let manifest_path = operating_dir.join("build-manifest.json");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--output-manifest".to_string(),
+            manifest_path.display().to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let manifest = fs::read_to_string(&manifest_path)?;
+    assert!(manifest.contains("\"compiler\": \"cranelift\""));
+    assert!(manifest.contains("\"blake3\""));
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_embedded_manifest_is_printed_with_wasmer_info() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--embed-manifest".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let manifest = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--wasmer-info".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert!(manifest.contains("\"compiler\": \"cranelift\""));
+    assert!(manifest.contains("\"embed_manifest\": true"));
+    assert!(!manifest.contains("\"output\""));
+
+    // Other arguments are still passed to the module
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "print('Hello')".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert_eq!(result.lines().collect::<Vec<&str>>(), vec!["Hello"]);
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_hash_embedded_wasm_detects_tampering() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--hash-embedded-wasm".to_string(),
+            "--prefix-algorithm".to_string(),
+            "crc32".to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "print('Hello')".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert_eq!(result.lines().collect::<Vec<&str>>(), vec!["Hello"]);
+
+    // Change a byte of the metadata, then a byte of the code, each in a
+    // copy of the executable
+    let executable = fs::read(&executable_path)?;
+    for (symbol, tampered_path) in &[
+        ("WASMER_METADATA", "tampered_metadata.out"),
+        ("WASMER_CODE_START", "tampered_code.out"),
+    ] {
+        let offset = symbol_file_offset(&executable, symbol)?;
+        let mut tampered = executable.clone();
+        tampered[offset] ^= 1;
+        let tampered_path = operating_dir.join(tampered_path);
+        // Copied first to keep the permissions of the executable
+        fs::copy(&executable_path, &tampered_path)?;
+        fs::write(&tampered_path, &tampered)?;
+
+        let error = run_code(&operating_dir, &tampered_path, &[])
+            .expect_err("the tampered executable should fail");
+        assert!(error
+            .to_string()
+            .contains("The embedded Wasm module has been modified"));
+    }
+
+    Ok(())
+}
+
+/// The offset in the executable `data` of the symbol `name`.
+fn symbol_file_offset(data: &[u8], name: &str) -> anyhow::Result<usize> {
+    use object::{Object, ObjectSection, ObjectSymbol};
+
+    let file = object::File::parse(data)?;
+    // Mach-O symbols have a leading underscore
+    let symbol = file
+        .symbols()
+        .find(|symbol| symbol.name().map(|s| s.trim_start_matches('_')) == Ok(name))
+        .with_context(|| format!("the executable has no `{}` symbol", name))?;
+    let section = file.section_by_index(
+        symbol
+            .section_index()
+            .with_context(|| format!("`{}` isn't defined", name))?,
+    )?;
+    let (section_offset, _) = section
+        .file_range()
+        .with_context(|| format!("`{}` has no data", name))?;
+    Ok((section_offset + symbol.address() - section.address()) as usize)
+}
+
+/// Read the ELF type and whether a 64-bit little-endian ELF has any
+/// `DT_NEEDED` entry.
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn read_elf_type_and_needed(data: &[u8]) -> (u16, bool) {
+    let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let u64_at = |offset: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&data[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    };
+    const PT_DYNAMIC: u32 = 2;
+    const DT_NULL: u64 = 0;
+    const DT_NEEDED: u64 = 1;
+
+    let e_type = u16_at(16);
+    let e_phoff = u64_at(32) as usize;
+    let e_phentsize = u16_at(54) as usize;
+    let e_phnum = u16_at(56) as usize;
+    for index in 0..e_phnum {
+        let phdr = e_phoff + index * e_phentsize;
+        let p_type =
+            u32::from_le_bytes([data[phdr], data[phdr + 1], data[phdr + 2], data[phdr + 3]]);
+        if p_type != PT_DYNAMIC {
+            continue;
+        }
+        let p_offset = u64_at(phdr + 8) as usize;
+        let p_filesz = u64_at(phdr + 32) as usize;
+        for entry in (p_offset..p_offset + p_filesz).step_by(16) {
+            match u64_at(entry) {
+                DT_NULL => break,
+                DT_NEEDED => return (e_type, true),
+                _ => {}
+            }
+        }
+    }
+    (e_type, false)
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn create_exe_works_with_static_pie() -> anyhow::Result<()> {
+    const ET_DYN: u16 = 3;
+
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let executable_path = operating_dir.join("wasm.out");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--static-pie".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let (e_type, has_needed) = read_elf_type_and_needed(&fs::read(&executable_path)?);
+    assert_eq!(e_type, ET_DYN);
+    assert_eq!(has_needed, false);
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    let result_lines = result.lines().collect::<Vec<&str>>();
+    assert_eq!(result_lines, vec!["\"Hello, World\""],);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn create_exe_works_with_dylib_output_type() -> anyhow::Result<()> {
+    const ET_DYN: u16 = 3;
+
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let library_path = operating_dir.join("libqjs.so");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: library_path.clone(),
+        compiler: Compiler::Cranelift,
+        output_type: "dylib",
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let library = fs::read(&library_path)?;
+    let (e_type, _) = read_elf_type_and_needed(&library);
+    assert_eq!(e_type, ET_DYN);
+    // The entrypoints are in the dynamic symbol table
+    let contains = |name: &[u8]| library.windows(name.len()).any(|window| window == name);
+    assert!(contains(b"wasmer_staticlib_engine_new"));
+    assert!(contains(b"wasm_store_new"));
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_include_source_can_be_extracted() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+    let recovered_path = operating_dir.join("recovered.wasm");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--include-source".to_string(),
+            "--compress-with".to_string(),
+            "gzip".to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let output = Command::new(get_wasmer_path())
+        .current_dir(&operating_dir)
+        .arg("inspect")
+        .arg("--extract-source")
+        .arg(&executable_path)
+        .arg("-o")
+        .arg(&recovered_path)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "wasmer inspect failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    assert_eq!(fs::read(&recovered_path)?, fs::read(&wasm_path)?);
+
+    // The executable still works with the embedded source
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    let result_lines = result.lines().collect::<Vec<&str>>();
+    assert_eq!(result_lines, vec!["\"Hello, World\""],);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn create_exe_works_with_target_feature_profile() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--target-feature-profile".to_string(),
+            "baseline".to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    let result_lines = result.lines().collect::<Vec<&str>>();
+    assert_eq!(result_lines, vec!["\"Hello, World\""],);
+
+    Ok(())
+}
+
+/// The names of the symbols exported by a shared library, as listed by `nm`.
+#[cfg(target_os = "linux")]
+fn exported_symbols(library_path: &std::path::Path) -> anyhow::Result<Vec<String>> {
+    let output = Command::new("nm")
+        .arg("-D")
+        .arg("--defined-only")
+        .arg(library_path)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "nm failed with: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(str::to_string)
+        .collect())
+}
+
+#[test]
+fn create_exe_verify_run_checks_the_executable() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+    let create_exe_with_verify_args = |script: &str| {
+        WasmerCreateExe {
+            current_dir: operating_dir.clone(),
+            wasm_path: wasm_path.clone(),
+            native_executable_path: executable_path.clone(),
+            compiler: Compiler::Cranelift,
+            extra_cli_flags: vec![
+                "--verify-run".to_string(),
+                "--".to_string(),
+                "--eval".to_string(),
+                script.to_string(),
+            ],
+            ..Default::default()
+        }
+        .run()
+    };
+
+    create_exe_with_verify_args("print('Hello')")
+        .context("Failed to create-exe wasm with Wasmer")?;
+    let error = create_exe_with_verify_args("throw new Error('broken')")
+        .expect_err("the verification run should fail");
+    let message = error.to_string();
+    assert!(message.contains("the executable failed its verification run"));
+    assert!(message.contains("broken"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn create_exe_run_after_link_runs_the_command_on_the_output() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let executable_path = operating_dir.join("wasm.out");
+    let create_exe_with_hook = |command: &str| {
+        WasmerCreateExe {
+            current_dir: operating_dir.clone(),
+            wasm_path: wasm_path.clone(),
+            native_executable_path: executable_path.clone(),
+            compiler: Compiler::Cranelift,
+            extra_cli_flags: vec!["--run-after-link".to_string(), command.to_string()],
+            ..Default::default()
+        }
+        .run()
+    };
+
+    create_exe_with_hook("cp {output} {output}.signed")
+        .context("Failed to create-exe wasm with Wasmer")?;
+    assert_eq!(
+        std::fs::read(&executable_path)?,
+        std::fs::read(operating_dir.join("wasm.out.signed"))?
+    );
+
+    let error = create_exe_with_hook("false {target} {objects}")
+        .expect_err("the post-link command should fail");
+    assert!(error
+        .to_string()
+        .contains("the `--run-after-link` command `false {target} {objects}` failed"));
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_atom_entry_calls_the_export() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = PathBuf::from(format!("{}/{}", ASSET_PATH, "add.wat"));
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("add.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("add.exe");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--atom-entry".to_string(), "add=add".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["1".to_string(), "2".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert_eq!(result.lines().collect::<Vec<&str>>(), vec!["3"]);
+
+    // The export must exist at build time
+    let error = WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path,
+        native_executable_path: executable_path,
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--atom-entry".to_string(), "sub".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .expect_err("create-exe should fail for a missing export");
+    assert!(error
+        .to_string()
+        .contains("the module has no `sub` function export"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn create_exe_works_with_hidden_default_visibility() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let library_path = operating_dir.join("libqjs.so");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: library_path.clone(),
+        compiler: Compiler::Cranelift,
+        output_type: "dylib",
+        extra_cli_flags: vec!["--default-visibility".to_string(), "hidden".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let symbols = exported_symbols(&library_path)?;
+    assert!(symbols
+        .iter()
+        .any(|name| name == "wasmer_staticlib_engine_new"));
+    assert!(symbols.iter().any(|name| name == "wasm_store_new"));
+    assert!(!symbols
+        .iter()
+        .any(|name| name.starts_with("wasmer_function_") || name == "WASMER_METADATA"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn create_exe_works_with_ld_version_script() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let library_path = operating_dir.join("libqjs.so");
+    let version_script_path = operating_dir.join("exports.map");
+    fs::write(
+        &version_script_path,
+        "{\n  global:\n    wasmer_staticlib_engine_new;\n  local:\n    *;\n};\n",
+    )?;
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: library_path.clone(),
+        compiler: Compiler::Cranelift,
+        output_type: "dylib",
+        extra_cli_flags: vec!["--ld-version-script".to_string(), "exports.map".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let symbols = exported_symbols(&library_path)?;
+    assert_eq!(symbols, vec!["wasmer_staticlib_engine_new"]);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn create_exe_emit_symbol_versions_versions_the_entrypoints() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let library_path = operating_dir.join("libqjs.so");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: library_path.clone(),
+        compiler: Compiler::Cranelift,
+        output_type: "dylib",
+        extra_cli_flags: vec!["--emit-symbol-versions".to_string(), "QJS_1.0".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let output = Command::new("objdump")
+        .arg("-T")
+        .arg(&library_path)
+        .output()?;
+    assert!(output.status.success());
+    let symbols = String::from_utf8_lossy(&output.stdout);
+    let version_of = |name: &str| {
+        symbols
+            .lines()
+            .find(|line| line.split_whitespace().last() == Some(name))
+            .map(|line| line.contains("QJS_1.0"))
+    };
+    assert_eq!(version_of("wasmer_staticlib_engine_new"), Some(true));
+    assert_eq!(version_of("wasm_store_new"), Some(true));
+
+    let error = WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path,
+        native_executable_path: operating_dir.join("qjs.out"),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--emit-symbol-versions".to_string(), "QJS_1.0".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .expect_err("an executable has no symbol versions");
+    assert!(error
+        .to_string()
+        .contains("`--emit-symbol-versions` requires `--output-type dylib`"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn create_exe_finds_the_tools_in_the_toolchain_root() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let toolchain_root = operating_dir.join("toolchain");
+    let create_exe_with_toolchain_root = || {
+        WasmerCreateExe {
+            current_dir: operating_dir.clone(),
+            wasm_path: wasm_path.clone(),
+            compiler: Compiler::Cranelift,
+            extra_cli_flags: vec!["--toolchain-root".to_string(), "toolchain".to_string()],
+            ..Default::default()
+        }
+        .run()
+    };
+
+    fs::create_dir_all(toolchain_root.join("bin"))?;
+    let error = create_exe_with_toolchain_root().expect_err("the toolchain root has no C compiler");
+    assert!(error
+        .to_string()
+        .contains("`cc` was not found in the toolchain root"));
+
+    // A wrapper of the system C compiler, recording its invocations
+    let wrapper_path = toolchain_root.join("bin").join("cc");
+    fs::write(
+        &wrapper_path,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> '{}'\nexec cc \"$@\"\n",
+            toolchain_root.join("invocations.log").display()
+        ),
+    )?;
+    fs::set_permissions(&wrapper_path, fs::Permissions::from_mode(0o755))?;
+    create_exe_with_toolchain_root().context("Failed to create-exe wasm with Wasmer")?;
+
+    // The C compiler compiles the main and links the executable
+    let invocations = fs::read_to_string(toolchain_root.join("invocations.log"))?;
+    assert_eq!(invocations.lines().count(), 2);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn create_exe_self_extract_runs_the_packer() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let toolchain_root = operating_dir.join("toolchain");
+    fs::create_dir_all(toolchain_root.join("bin"))?;
+    // Forward to the system C compiler, and record the invocations of the
+    // packer without compressing anything
+    for (tool, command) in &[("cc", "exec cc \"$@\""), ("upx", "true")] {
+        let wrapper_path = toolchain_root.join("bin").join(tool);
+        fs::write(
+            &wrapper_path,
+            format!(
+                "#!/bin/sh\necho {} \"$@\" >> '{}'\n{}\n",
+                tool,
+                toolchain_root.join("invocations.log").display(),
+                command
+            ),
+        )?;
+        fs::set_permissions(&wrapper_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let executable_path = operating_dir.join("wasm.out");
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path,
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--self-extract".to_string(),
+            "--toolchain-root".to_string(),
+            "toolchain".to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    // The packer compresses the linked executable in place
+    let invocations = fs::read_to_string(toolchain_root.join("invocations.log"))?;
+    let last_invocation = invocations.lines().last().unwrap();
+    assert!(last_invocation.starts_with("upx --best"));
+    assert!(last_invocation.ends_with("wasm.out"));
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    let result_lines = result.lines().collect::<Vec<&str>>();
+    assert_eq!(result_lines, vec!["\"Hello, World\""],);
+
+    Ok(())
+}
+
+/// The linker and its arguments, printed by `--list-objects`.
+fn link_command(list_objects_output: &str) -> Vec<String> {
+    list_objects_output
+        .lines()
+        .skip_while(|line| *line != "Link command:")
+        .skip(1)
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+/// The linker and its arguments that `create-exe` uses with `flags`,
+/// printed by `--list-objects` without compiling the module.
+fn create_exe_link_command(flags: &[&str]) -> anyhow::Result<Vec<String>> {
+    let temp_dir = tempfile::tempdir()?;
+    let output = Command::new(get_wasmer_path())
+        .current_dir(temp_dir.path())
+        .arg("create-exe")
+        .arg(PathBuf::from(create_exe_test_wasm_path()).canonicalize()?)
+        .arg("-o")
+        .arg("wasm.out")
+        .arg("--list-objects")
+        .args(flags)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "wasmer create-exe failed with: {}",
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    Ok(link_command(std::str::from_utf8(&output.stdout)?))
+}
+
+#[test]
+fn create_exe_list_objects_prints_the_link_inputs() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let list_objects = |flag: &str| -> anyhow::Result<String> {
+        let output = Command::new(get_wasmer_path())
+            .current_dir(&operating_dir)
+            .arg("create-exe")
+            .arg(PathBuf::from(create_exe_test_wasm_path()).canonicalize()?)
+            .arg(Compiler::Cranelift.to_flag())
+            .arg("-o")
+            .arg("wasm.out")
+            .arg(flag)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "wasmer create-exe failed with: {}",
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    // Nothing is compiled before
+    let before = list_objects("--list-objects")?;
+    let inputs = before
+        .lines()
+        .skip_while(|line| *line != "Link inputs:")
+        .skip(1)
+        .take_while(|line| *line != "Link command:")
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    assert!(inputs[0].ends_with("wasmer_main.o") || inputs[0].ends_with("wasmer_main.obj"));
+    assert!(inputs[1].ends_with("wasm.o") || inputs[1].ends_with("wasm.obj"));
+    assert!(inputs[2].contains("libwasmer"));
+    // Followed by the linker command, writing the executable
+    let command = link_command(&before);
+    #[cfg(not(windows))]
+    assert!(command.ends_with(&["-o".to_string(), "wasm.out".to_string()]));
+    assert!(!before.contains("Compiler:"));
+
+    // The objects are kept after
+    let after = list_objects("--list-objects=after")?;
+    let wasm_object = after
+        .lines()
+        .map(str::trim)
+        .find(|line| line.ends_with("wasm.o") || line.ends_with("wasm.obj"))
+        .context("the module object isn't listed")?;
+    let wasm_object = std::path::Path::new(wasm_object);
+    assert!(wasm_object.is_file());
+    assert!(!operating_dir.join("wasm.out").exists());
+    fs::remove_dir_all(wasm_object.parent().unwrap())?;
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_objcopy_redefine_renames_the_module_symbols() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("wasm.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("wasm.exe");
+
+    // The renames can also be read from a file
+    fs::write(
+        operating_dir.join("syms.txt"),
+        "# The module metadata\nWASMER_METADATA qjs_metadata\n",
+    )?;
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: wasm_path.clone(),
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--objcopy-redefine".to_string(), "syms.txt".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "print('Hello')".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert_eq!(result.lines().collect::<Vec<&str>>(), vec!["Hello"]);
+    let executable = fs::read(&executable_path)?;
+    assert!(executable
+        .windows(b"qjs_metadata".len())
+        .any(|window| window == b"qjs_metadata"));
+
+    // Only the symbols of the module can be renamed
+    let error = WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path,
+        native_executable_path: executable_path,
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec![
+            "--objcopy-redefine".to_string(),
+            "wasmer_vm_memory32_grow=grow".to_string(),
+        ],
+        ..Default::default()
+    }
+    .run()
+    .expect_err("renaming an imported symbol should fail");
+    assert!(error
+        .to_string()
+        .contains("`wasmer_vm_memory32_grow` isn't defined by the module"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_exe_symbol_namespace_links_two_builds_together() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    // The objects of a build, kept by `--list-objects=after`
+    let build_objects = |extra_flags: &[&str]| -> anyhow::Result<Vec<PathBuf>> {
+        let output = Command::new(get_wasmer_path())
+            .current_dir(&operating_dir)
+            .arg("create-exe")
+            .arg(PathBuf::from(create_exe_test_wasm_path()).canonicalize()?)
+            .arg(Compiler::Cranelift.to_flag())
+            .arg("-o")
+            .arg("wasm.out")
+            .arg("--list-objects=after")
+            .args(extra_flags)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "wasmer create-exe failed with: {}",
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip_while(|line| *line != "Link inputs:")
+            .skip(1)
+            .take_while(|line| *line != "Link command:")
+            .map(|line| PathBuf::from(line.trim()))
+            .filter(|path| path.extension().map_or(false, |extension| extension == "o"))
+            .collect())
+    };
+    let link_together = |objects: &[&PathBuf]| {
+        Command::new("ld")
+            .arg("-r")
+            .arg("-o")
+            .arg(operating_dir.join("combined.o"))
+            .args(objects)
+            .output()
+    };
+
+    let team_a = build_objects(&["--symbol-namespace", "team_a"])?;
+    let team_b = build_objects(&["--symbol-namespace", "team_b"])?;
+    let plain = build_objects(&[])?;
+    let object_named = |objects: &[PathBuf], name: &str| {
+        objects
+            .iter()
+            .find(|path| path.ends_with(name))
+            .cloned()
+            .unwrap()
+    };
+
+    let glue = fs::read(object_named(&team_a, "wasmer_main.o"))?;
+    let name = b"team_a_wasmer_staticlib_engine_new";
+    assert!(glue.windows(name.len()).any(|window| window == name));
+
+    // The same module defines the same symbols, unless they're namespaced
+    let output = link_together(&[
+        &object_named(&team_a, "wasm.o"),
+        &object_named(&team_b, "wasm.o"),
+        &object_named(&plain, "wasm.o"),
+    ])?;
+    assert!(
+        output.status.success(),
+        "ld failed with: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let output = link_together(&[
+        &object_named(&plain, "wasm.o"),
+        &object_named(&plain, "wasm.o"),
+    ])?;
+    assert!(!output.status.success());
+
+    for objects in [team_a, team_b, plain].iter() {
+        fs::remove_dir_all(objects[0].parent().unwrap())?;
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_exe_weak_runtime_symbols_are_weak() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let executable_path = operating_dir.join("wasm.out");
+
+    WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path,
+        native_executable_path: executable_path.clone(),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--weak-runtime-symbols".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .context("Failed to create-exe wasm with Wasmer")?;
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["--eval".to_string(), "print('Hello')".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert_eq!(result.lines().collect::<Vec<&str>>(), vec!["Hello"]);
+
+    let output = Command::new("nm").arg(&executable_path).output()?;
+    let symbols = String::from_utf8(output.stdout)?;
+    assert!(symbols
+        .lines()
+        .any(|line| line.ends_with(" W wasmer_vm_raise_trap")));
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_strip_exports_removes_the_unused_exports() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = PathBuf::from(format!("{}/{}", ASSET_PATH, "debug_exports.wat"));
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("debug_exports.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("debug_exports.exe");
+
+    let output = Command::new(get_wasmer_path())
+        .current_dir(&operating_dir)
+        .arg("create-exe")
+        .arg(wasm_path.canonicalize()?)
+        .arg(Compiler::Cranelift.to_flag())
+        .arg("-o")
+        .arg(&executable_path)
+        .arg("--atom-entry")
+        .arg("add")
+        .arg("--strip-exports")
+        .arg("debug_*")
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "wasmer create-exe failed with: {}",
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    // `debug_check` is called by `add`, `debug_dump` takes `dump_state` with it
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Stripped exports: 1 (2 function bodies removed)"));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("`debug_check` matches `debug_*` but is kept"));
+
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["1".to_string(), "2".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert_eq!(result.lines().collect::<Vec<&str>>(), vec!["3"]);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn create_exe_build_id_is_embedded_in_the_executable() -> anyhow::Result<()> {
+    use object::Object;
+
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = PathBuf::from(format!("{}/{}", ASSET_PATH, "debug_exports.wat"));
+    let build_id_of = |build_id: &str| -> anyhow::Result<Option<Vec<u8>>> {
+        let executable_path = operating_dir.join(format!("{}.out", build_id));
+        WasmerCreateExe {
+            current_dir: operating_dir.clone(),
+            wasm_path: wasm_path.clone(),
+            native_executable_path: executable_path.clone(),
+            compiler: Compiler::Cranelift,
+            extra_cli_flags: vec![
+                "--atom-entry".to_string(),
+                "add".to_string(),
+                "--build-id".to_string(),
+                build_id.to_string(),
+            ],
+            ..Default::default()
+        }
+        .run()
+        .context("Failed to create-exe wasm with Wasmer")?;
+
+        let data = fs::read(&executable_path)?;
+        let file = object::File::parse(&*data)?;
+        Ok(file.build_id()?.map(|id| id.to_vec()))
+    };
+
+    assert_eq!(build_id_of("sha1")?.map(|id| id.len()), Some(20));
+    assert_eq!(build_id_of("uuid")?.map(|id| id.len()), Some(16));
+    assert_eq!(build_id_of("none")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_objects_only_are_linked_with_link_from() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = PathBuf::from(format!("{}/{}", ASSET_PATH, "debug_exports.wat"));
+    #[cfg(not(windows))]
+    let executable_path = operating_dir.join("debug_exports.out");
+    #[cfg(windows)]
+    let executable_path = operating_dir.join("debug_exports.exe");
+
+    let create_exe = |args: &[&std::ffi::OsStr]| -> anyhow::Result<()> {
+        let output = Command::new(get_wasmer_path())
+            .current_dir(&operating_dir)
+            .arg("create-exe")
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "wasmer create-exe failed with: {}",
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(())
+    };
+
+    create_exe(&[
+        wasm_path.canonicalize()?.as_os_str(),
+        Compiler::Cranelift.to_flag().as_ref(),
+        "--atom-entry".as_ref(),
+        "add".as_ref(),
+        "--objects-only".as_ref(),
+        "stage".as_ref(),
+    ])?;
+    assert!(operating_dir.join("stage/wasmer_stage.json").is_file());
+    assert!(!executable_path.exists());
+
+    create_exe(&[
+        "--link-from".as_ref(),
+        "stage".as_ref(),
+        "-o".as_ref(),
+        executable_path.as_os_str(),
+    ])?;
+    let result = run_code(
+        &operating_dir,
+        &executable_path,
+        &["1".to_string(), "2".to_string()],
+    )
+    .context("Failed to run generated executable")?;
+    assert_eq!(result.lines().collect::<Vec<&str>>(), vec!["3"]);
+
+    Ok(())
+}
+
+#[test]
+fn create_exe_thin_lto_requires_lld_and_llvm() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let wasm_path = operating_dir.join(create_exe_test_wasm_path());
+    let create_exe_with_flags = |flags: &[&str]| {
+        WasmerCreateExe {
+            current_dir: operating_dir.clone(),
+            wasm_path: wasm_path.clone(),
+            native_executable_path: operating_dir.join("wasm.out"),
+            compiler: Compiler::Cranelift,
+            extra_cli_flags: flags.iter().map(|flag| flag.to_string()).collect(),
+            ..Default::default()
+        }
+        .run()
+    };
+
+    let error = create_exe_with_flags(&["--thin-lto", "--linker-flavor", "gnu"])
+        .expect_err("the GNU linker can't link ThinLTO bitcode");
+    assert!(error
+        .to_string()
+        .contains("`--thin-lto` isn't supported by the `gnu` linker flavor"));
+
+    let error = create_exe_with_flags(&["--thin-lto", "--linker-flavor", "lld"])
+        .expect_err("Cranelift can't emit bitcode");
+    assert!(error
+        .to_string()
+        .contains("`--thin-lto` requires the LLVM compiler"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(not(target_os = "macos"))]
+fn create_exe_universal_is_only_supported_on_macos() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+
+    let error = WasmerCreateExe {
+        current_dir: operating_dir.clone(),
+        wasm_path: operating_dir.join(create_exe_test_wasm_path()),
+        native_executable_path: operating_dir.join("qjs.out"),
+        compiler: Compiler::Cranelift,
+        extra_cli_flags: vec!["--universal".to_string(), "x86_64,arm64".to_string()],
+        ..Default::default()
+    }
+    .run()
+    .expect_err("lipo is only available on macOS");
+    assert!(error
+        .to_string()
+        .contains("`--universal` is only supported on macOS"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_exe_cross_sysroot_needs_the_target_libc() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir: PathBuf = temp_dir.path().to_owned();
+    let empty_sysroot = operating_dir.join("sysroot");
+    fs::create_dir(&empty_sysroot)?;
+
+    let create_exe_with_sysroot = |sysroot: &Path| {
+        WasmerCreateExe {
+            current_dir: operating_dir.clone(),
+            wasm_path: operating_dir.join(create_exe_test_wasm_path()),
+            native_executable_path: operating_dir.join("wasm.out"),
+            compiler: Compiler::Cranelift,
+            extra_cli_flags: vec!["--cross-sysroot".to_string(), sysroot.display().to_string()],
+            ..Default::default()
+        }
+        .run()
+    };
+
+    let error = create_exe_with_sysroot(&empty_sysroot).expect_err("the empty sysroot has no libc");
+    assert!(error.to_string().contains("has no `crti.o`"), "{}", error);
+
+    // The host is its own sysroot
+    create_exe_with_sysroot(Path::new("/"))?;
+    assert!(operating_dir.join("wasm.out").exists());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(not(windows))]
+fn create_exe_rpath_is_passed_to_the_linker() -> anyhow::Result<()> {
+    let command =
+        create_exe_link_command(&["--rpath", "$ORIGIN/lib", "--rpath", "/opt/wasmer/lib"])?;
+    let rpaths = command
+        .iter()
+        .filter(|arg| arg.starts_with("-Wl,-rpath,"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rpaths,
+        ["-Wl,-rpath,$ORIGIN/lib", "-Wl,-rpath,/opt/wasmer/lib"],
+        "{:?}",
+        command
+    );
+
+    // None by default
+    let command = create_exe_link_command(&[])?;
+    assert!(!command.iter().any(|arg| arg.contains("-rpath")));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_exe_output_type_is_passed_to_the_linker() -> anyhow::Result<()> {
+    let command = create_exe_link_command(&["--output-type", "exe"])?;
+    assert!(!command.iter().any(|arg| arg == "-pie" || arg == "-shared"));
+
+    let command = create_exe_link_command(&["--output-type", "pie"])?;
+    assert!(command.iter().any(|arg| arg == "-pie"), "{:?}", command);
+
+    // The whole libwasmer is linked in the shared library
+    let command = create_exe_link_command(&["--output-type", "dylib"])?;
+    let shared = command
+        .iter()
+        .position(|arg| arg == "-shared")
+        .context("`-shared` isn't passed")?;
+    assert_eq!(command[shared + 1], "-Wl,--whole-archive");
+    assert!(command[shared + 2].contains("libwasmer"));
+    assert_eq!(command[shared + 3], "-Wl,--no-whole-archive");
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_exe_static_pie_is_passed_to_the_linker() -> anyhow::Result<()> {
+    let command = create_exe_link_command(&["--static-pie"])?;
+    assert!(
+        command.iter().any(|arg| arg == "-static-pie"),
+        "{:?}",
+        command
+    );
+    assert!(!command.iter().any(|arg| arg == "-pie"));
+
+    let command = create_exe_link_command(&[])?;
+    assert!(!command.iter().any(|arg| arg == "-static-pie"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_exe_linker_flavor_picks_the_linker_and_its_arguments() -> anyhow::Result<()> {
+    // The default flavor, `gnu`, links through `cc`
+    let command = create_exe_link_command(&[])?;
+    assert_eq!(command[0], "cc");
+    assert_eq!(command[command.len() - 2..], ["-o", "wasm.out"]);
+
+    let command = create_exe_link_command(&["--linker-flavor", "lld"])?;
+    assert_eq!(command[0], "clang");
+    assert!(
+        command.iter().any(|arg| arg == "-fuse-ld=lld"),
+        "{:?}",
+        command
+    );
+
+    let command = create_exe_link_command(&["--linker-flavor", "ld64"])?;
+    assert_eq!(command[0], "cc");
+    assert!(!command.iter().any(|arg| arg.starts_with("-fuse-ld")));
+
+    let command = create_exe_link_command(&["--linker-flavor", "msvc"])?;
+    assert_eq!(command[0], "link.exe");
+    assert_eq!(command[1], "/NOLOGO");
+    assert!(
+        command.iter().any(|arg| arg == "bcrypt.lib"),
+        "{:?}",
+        command
+    );
+    assert_eq!(command.last().unwrap(), "/OUT:wasm.out");
+
+    // `--linker` replaces the linker of the flavor
+    let command = create_exe_link_command(&["--linker-flavor", "lld", "--linker", "my-clang"])?;
+    assert_eq!(command[0], "my-clang");
+    assert!(command.iter().any(|arg| arg == "-fuse-ld=lld"));
+
+    Ok(())
+}
@@ -0,0 +1,685 @@
+//! Tests of the `wasmer create-obj` command.
+
+use anyhow::bail;
+use std::path::Path;
+use std::process::Command;
+use wasmer_integration_tests_cli::*;
+
+fn create_obj_test_wasm_path() -> String {
+    format!("{}/{}", C_ASSET_PATH, "qjs.wasm")
+}
+
+fn run_create_obj(current_dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new(get_wasmer_path())
+        .current_dir(current_dir)
+        .arg("create-obj")
+        .arg(create_obj_test_wasm_path())
+        .arg(Compiler::Cranelift.to_flag())
+        .args(args)
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "wasmer create-obj failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    Ok(std::str::from_utf8(&output.stdout)
+        .expect("stdout is not utf8! need to handle arbitrary bytes")
+        .to_string())
+}
+
+#[test]
+fn create_obj_incremental_cache_reuses_functions() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let first_run = run_create_obj(
+        operating_dir,
+        &[
+            "-o",
+            "wasm.o",
+            "--incremental-cache",
+            "cache",
+            "--unwind-info",
+            "none",
+        ],
+    )?;
+    assert!(first_run.contains("Incremental cache: reused 0 of"));
+
+    let second_run = run_create_obj(
+        operating_dir,
+        &[
+            "-o",
+            "wasm.o",
+            "--incremental-cache",
+            "cache",
+            "--unwind-info",
+            "none",
+        ],
+    )?;
+    let stats_line = second_run
+        .lines()
+        .find(|line| line.starts_with("Incremental cache:"))
+        .expect("the incremental cache stats are printed");
+    let counts = stats_line
+        .trim_start_matches("Incremental cache: reused ")
+        .trim_end_matches(" functions")
+        .split(" of ")
+        .collect::<Vec<_>>();
+    assert_eq!(counts[0], counts[1]);
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_incremental_cache_reuses_the_unchanged_functions() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let compile = |body: &str| -> anyhow::Result<String> {
+        std::fs::write(
+            operating_dir.join("module.wat"),
+            format!(
+                r#"(module
+                    (func (export "unchanged") (result i32) i32.const 1)
+                    (func (export "changed") (result i32) {}))"#,
+                body
+            ),
+        )?;
+        let output = Command::new(get_wasmer_path())
+            .current_dir(operating_dir)
+            .arg("create-obj")
+            .arg("module.wat")
+            .arg(Compiler::Singlepass.to_flag())
+            .args(&["-o", "module.o", "--incremental-cache", "cache"])
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "wasmer create-obj failed with: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    compile("i32.const 2")?;
+    let output = compile("i32.const 3")?;
+    assert!(
+        output.contains("Incremental cache: reused 1 of 2 functions"),
+        "{}",
+        output
+    );
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_incremental_cache_needs_per_function_unwind_info() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let error = run_create_obj(
+        operating_dir,
+        &["-o", "wasm.o", "--incremental-cache", "cache"],
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(error.contains("`--incremental-cache` requires `--unwind-info none` with Cranelift"));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_cache_stats_report_the_cache_entries() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let args = [
+        "-o",
+        "wasm.o",
+        "--incremental-cache",
+        "cache",
+        "--unwind-info",
+        "none",
+        "--cache-stats",
+        "--cache-stats-json",
+        "stats.json",
+    ];
+    let first_run = run_create_obj(operating_dir, &args)?;
+    assert!(first_run.contains("Cache stats: 0 hits, "));
+    let stats = std::fs::read_to_string(operating_dir.join("stats.json"))?;
+    assert!(stats.contains("\"bytes_read\": 0"));
+    assert!(!stats.contains("\"bytes_written\": 0"));
+
+    // The whole module is found in the cache
+    let second_run = run_create_obj(operating_dir, &args)?;
+    assert!(second_run.contains("Cache stats: 1 hits, 0 misses, "));
+    let stats = std::fs::read_to_string(operating_dir.join("stats.json"))?;
+    assert!(stats.contains("\"bytes_written\": 0"));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_compiler_pass_timing_reports_the_passes() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let output = run_create_obj(
+        operating_dir,
+        &[
+            "-o",
+            "wasm.o",
+            "--compiler-pass-timing",
+            "--compiler-pass-timing-json",
+            "timing.json",
+        ],
+    )?;
+    assert!(output.contains("Compiler pass timing (cranelift):"));
+    assert!(output.contains("Translate WASM function"));
+    let timing = std::fs::read_to_string(operating_dir.join("timing.json"))?;
+    assert!(timing.contains("\"compiler\": \"cranelift\""));
+    assert!(timing.contains("\"pass\": \"Translate WASM function\""));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_obj_merge_reports_symbol_collisions() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(operating_dir, &["-o", "wasm.o"])?;
+
+    // Merging a single object keeps all its symbols
+    let output = Command::new(get_wasmer_path())
+        .current_dir(operating_dir)
+        .arg("create-obj")
+        .arg("--merge")
+        .arg("wasm.o")
+        .arg("-o")
+        .arg("merged.o")
+        .output()?;
+    assert_eq!(output.status.success(), true);
+    assert_eq!(operating_dir.join("merged.o").exists(), true);
+
+    // The same object defines the same symbols twice
+    let output = Command::new(get_wasmer_path())
+        .current_dir(operating_dir)
+        .arg("create-obj")
+        .arg("--merge")
+        .arg("wasm.o")
+        .arg("--merge")
+        .arg("merged.o")
+        .arg("-o")
+        .arg("combined.o")
+        .output()?;
+    assert_eq!(output.status.success(), false);
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert_eq!(stderr.contains("is defined in both"), true);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn create_obj_function_sections_shrink_linked_output() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(operating_dir, &["-o", "plain.o"])?;
+    run_create_obj(operating_dir, &["-o", "sections.o", "--function-sections"])?;
+
+    let sections_object = std::fs::read(operating_dir.join("sections.o"))?;
+    let contains = |name: &[u8]| {
+        sections_object
+            .windows(name.len())
+            .any(|window| window == name)
+    };
+    assert!(contains(b".text.wasmer_function__0"));
+
+    // Keep a single function, and let the linker collect the others
+    let link = |object: &str, output: &str| -> anyhow::Result<u64> {
+        let output_status = Command::new("ld")
+            .current_dir(operating_dir)
+            .arg("--gc-sections")
+            .arg("--unresolved-symbols=ignore-all")
+            .arg("-e")
+            .arg("wasmer_function__0")
+            .arg("-o")
+            .arg(output)
+            .arg(object)
+            .output()?;
+        if !output_status.status.success() {
+            bail!(
+                "ld failed with: {}",
+                std::str::from_utf8(&output_status.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(std::fs::metadata(operating_dir.join(output))?.len())
+    };
+    let plain_size = link("plain.o", "plain.out")?;
+    let sections_size = link("sections.o", "sections.out")?;
+    assert!(
+        sections_size < plain_size,
+        "{} bytes with function sections, {} bytes without",
+        sections_size,
+        plain_size
+    );
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_single_symbol_emits_an_offsets_table() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(operating_dir, &["-o", "wasm.o", "--single-symbol"])?;
+
+    let object = std::fs::read(operating_dir.join("wasm.o"))?;
+    let contains = |name: &[u8]| object.windows(name.len()).any(|window| window == name);
+    assert!(contains(b"wasmer_code_"));
+    assert!(contains(b"wasmer_function_offsets_"));
+    assert!(!contains(b"wasmer_function__0"));
+
+    let header = std::fs::read_to_string(operating_dir.join("wasm.h"))?;
+    assert!(header.contains("extern const unsigned long long wasmer_function_offsets_[];"));
+    assert!(header.contains("resolve_function_pointers();"));
+    assert!(!header.contains("wasmer_function__0"));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_prefix_from_package_names_the_symbols() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let stdout = run_create_obj(
+        operating_dir,
+        &[
+            "-o",
+            "wasm.o",
+            "--prefix-from-package",
+            "quickjs@2021.03.27",
+        ],
+    )?;
+    assert!(stdout.contains("Symbol prefix: quickjs_2021_03_27_qjs"));
+
+    let object = std::fs::read(operating_dir.join("wasm.o"))?;
+    let name = b"wasmer_function_quickjs_2021_03_27_qjs_0";
+    assert!(object.windows(name.len()).any(|window| window == name));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_dumps_relocations() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(
+        operating_dir,
+        &[
+            "-o",
+            "wasm.o",
+            "--dump-relocations",
+            "--dump-relocations-file",
+            "relocations.txt",
+        ],
+    )?;
+
+    let dump = std::fs::read_to_string(operating_dir.join("relocations.txt"))?;
+    // Only the functions with relocations are listed
+    assert!(dump.contains("wasmer_function__"));
+    assert!(dump.contains(" (section "));
+    assert!(dump.trim_end().ends_with("relocations"));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_emits_metadata_json() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(
+        operating_dir,
+        &["-o", "wasm.o", "--emit-metadata-json", "meta.json"],
+    )?;
+
+    let metadata = std::fs::read_to_string(operating_dir.join("meta.json"))?;
+    assert!(metadata.contains("\"compiler\": \"cranelift\""));
+    assert!(metadata.contains("\"metadata_symbol\""));
+    assert!(
+        metadata.contains("\"symbol\": \"wasmer_function__0\"")
+            || metadata.contains("\"symbol\": \"_wasmer_function__0\"")
+    );
+    // `_start` is exported
+    assert!(metadata.contains("\"_start\""));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_wasm_features_from_reference_module() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let stdout = run_create_obj(
+        operating_dir,
+        &[
+            "-o",
+            "wasm.o",
+            "--wasm-features-from",
+            &create_obj_test_wasm_path(),
+        ],
+    )?;
+    assert!(stdout.contains(&format!(
+        "Wasm features (from `{}`):",
+        create_obj_test_wasm_path()
+    )));
+
+    // The SIMD module needs a feature the reference module doesn't use
+    let output = Command::new(get_wasmer_path())
+        .current_dir(operating_dir)
+        .arg("create-obj")
+        .arg(format!("{}/{}", ASSET_PATH, "simd.wat"))
+        .arg(Compiler::Cranelift.to_flag())
+        .arg("--wasm-features-from")
+        .arg(format!("{}/{}", ASSET_PATH, "fib.wat"))
+        .arg("-o")
+        .arg("simd.o")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("fib.wat` doesn't: simd"),
+        "unexpected stderr: {}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_pads_the_object() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(operating_dir, &["-o", "wasm.o"])?;
+    let length = std::fs::metadata(operating_dir.join("wasm.o"))?.len();
+    let size = (length + 4096).to_string();
+
+    // The padded object can still be parsed
+    run_create_obj(
+        operating_dir,
+        &[
+            "-o",
+            "padded.o",
+            "--pad-to",
+            &size,
+            "--pad-byte",
+            "0xFF",
+            "--emit-metadata-json",
+            "metadata.json",
+        ],
+    )?;
+    // The metadata isn't serialized deterministically, so only the sizes
+    // of the objects are the same
+    let padded = std::fs::read(operating_dir.join("padded.o"))?;
+    assert_eq!(padded.len() as u64, length + 4096);
+    assert!(padded[length as usize..].iter().all(|byte| *byte == 0xFF));
+    object::File::parse(&*padded)?;
+
+    let error = run_create_obj(
+        operating_dir,
+        &["-o", "small.o", "--pad-to", &(length - 1).to_string()],
+    )
+    .expect_err("the object doesn't fit");
+    assert!(error
+        .to_string()
+        .contains("larger than the `--pad-to` size"));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_emit_init_array_registers_a_constructor() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    let output = Command::new(get_wasmer_path())
+        .current_dir(operating_dir)
+        .arg("create-obj")
+        .arg(format!("{}/add.wat", ASSET_PATH))
+        .arg(Compiler::Cranelift.to_flag())
+        .arg("-o")
+        .arg("add.o")
+        .arg("--emit-init-array")
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "wasmer create-obj failed with: {}",
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    let header = std::fs::read_to_string(operating_dir.join("add.h"))?;
+    assert!(header.contains("static void wasmer_init_array_constructor(void)"));
+    assert!(header.contains("__attribute__((used, section(\".init_array\")))"));
+
+    // The constructor can't provide the imports of the module
+    let error = run_create_obj(operating_dir, &["-o", "qjs.o", "--emit-init-array"])
+        .expect_err("a module with imports should be rejected");
+    assert!(error
+        .to_string()
+        .contains("only supports modules without imports"));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_function_filter_compiles_the_selected_functions() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+    let create_obj_with_filter = |filter: &str| -> anyhow::Result<String> {
+        let output = Command::new(get_wasmer_path())
+            .current_dir(operating_dir)
+            .arg("create-obj")
+            .arg(format!("{}/fib.wat", ASSET_PATH))
+            .arg(Compiler::Cranelift.to_flag())
+            .arg("-o")
+            .arg("fib.o")
+            .arg("--function-filter")
+            .arg(filter)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "wasmer create-obj failed with: {}",
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(std::str::from_utf8(&output.stdout)
+            .expect("stdout is not utf8! need to handle arbitrary bytes")
+            .to_string())
+    };
+
+    // `$main` and `$fib` are the functions 0 and 1
+    let stdout = create_obj_with_filter("index:1")?;
+    assert!(stdout.contains("Function filter: compiled 1 functions (1 stubbed)"));
+    assert!(stdout.contains("  1 (`fib`)"));
+    let header = std::fs::read_to_string(operating_dir.join("fib.h"))?;
+    assert!(header.starts_with("/* Only the functions selected by `--function-filter`"));
+
+    let stdout = create_obj_with_filter("name:ma*")?;
+    assert!(stdout.contains("  0 (`main`)"));
+
+    let error = create_obj_with_filter("name:nothing")
+        .expect_err("a filter selecting no function should be rejected");
+    assert!(error
+        .to_string()
+        .contains("no function defined by the module matches `--function-filter`"));
+
+    Ok(())
+}
+
+#[test]
+fn create_obj_target_cpu_list_prints_the_cpus_of_the_target() -> anyhow::Result<()> {
+    let list_cpus = |target: &str| {
+        Command::new(get_wasmer_path())
+            .arg("create-obj")
+            .arg(Compiler::Cranelift.to_flag())
+            .arg("--target-cpu-list")
+            .arg("--target")
+            .arg(target)
+            .output()
+    };
+
+    let output = list_cpus("x86_64-unknown-linux-gnu")?;
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "wasmer create-obj failed with: {}",
+        std::str::from_utf8(&output.stderr).unwrap()
+    );
+    assert!(stdout.contains("CPUs for `x86_64-unknown-linux-gnu` with cranelift"));
+    assert!(stdout
+        .lines()
+        .any(|line| line.split_whitespace().collect::<Vec<_>>()
+            == [
+                "x86-64-v2",
+                "sse2",
+                "sse3",
+                "ssse3",
+                "sse4.1",
+                "sse4.2",
+                "popcnt"
+            ]));
+
+    let output = list_cpus("aarch64-unknown-linux-gnu")?;
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(output.status.success());
+    assert!(stdout.contains("generic          (no features)"));
+
+    let output = list_cpus("riscv64gc-unknown-linux-gnu")?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("the cranelift compiler can't compile for `riscv64gc-unknown-linux-gnu`"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_obj_unwind_info_none_leaves_out_the_unwind_tables() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(operating_dir, &["-o", "full.o"])?;
+    run_create_obj(operating_dir, &["-o", "none.o", "--unwind-info", "none"])?;
+
+    let contains_eh_frame = |object: &str| -> anyhow::Result<bool> {
+        let object = std::fs::read(operating_dir.join(object))?;
+        Ok(object
+            .windows(b".eh_frame".len())
+            .any(|window| window == b".eh_frame"))
+    };
+    assert!(contains_eh_frame("full.o")?);
+    assert!(!contains_eh_frame("none.o")?);
+    assert!(
+        std::fs::metadata(operating_dir.join("none.o"))?.len()
+            < std::fs::metadata(operating_dir.join("full.o"))?.len()
+    );
+
+    assert!(run_create_obj(
+        operating_dir,
+        &["-o", "compact.o", "--unwind-info", "compact"]
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_obj_text_noexec_and_readonly_patch_the_section_flags() -> anyhow::Result<()> {
+    use object::elf::{SHF_EXECINSTR, SHF_WRITE};
+    use object::{Object, ObjectSection, ObjectSymbol, SectionFlags};
+
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(operating_dir, &["-o", "plain.o"])?;
+    let output = run_create_obj(
+        operating_dir,
+        &["-o", "patched.o", "--text-noexec", "--text-readonly"],
+    )?;
+    assert!(output.contains("Section flags: `.text` not executable"));
+
+    let plain = std::fs::read(operating_dir.join("plain.o"))?;
+    let patched = std::fs::read(operating_dir.join("patched.o"))?;
+    assert_eq!(plain.len(), patched.len());
+    let plain = object::File::parse(&*plain)?;
+    let patched = object::File::parse(&*patched)?;
+
+    let symbols = |file: &object::File| {
+        file.symbols()
+            .map(|symbol| (symbol.name().unwrap().to_string(), symbol.address()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(symbols(&plain), symbols(&patched));
+
+    for section in patched.sections() {
+        if let SectionFlags::Elf { sh_flags } = section.flags() {
+            assert_eq!(sh_flags & u64::from(SHF_EXECINSTR | SHF_WRITE), 0);
+        }
+    }
+    let text_flags = |file: &object::File| file.section_by_name(".text").unwrap().flags();
+    assert_eq!(
+        text_flags(&plain),
+        SectionFlags::Elf {
+            sh_flags: u64::from(object::elf::SHF_ALLOC | SHF_EXECINSTR)
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn create_obj_relocation_model_is_checked() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let operating_dir = temp_dir.path();
+
+    run_create_obj(
+        operating_dir,
+        &["-o", "wasm.o", "--relocation-model", "pic"],
+    )?;
+    assert!(operating_dir.join("wasm.o").is_file());
+
+    let error = run_create_obj(
+        operating_dir,
+        &["-o", "wasm.o", "--relocation-model", "dynamic-no-pic"],
+    )
+    .expect_err("`dynamic-no-pic` is only for Apple targets");
+    assert!(error
+        .to_string()
+        .contains("the `dynamic-no-pic` relocation model is only available for Apple targets"));
+
+    let error = run_create_obj(operating_dir, &["-o", "wasm.o", "--relocation-model", "gp"])
+        .expect_err("`gp` isn't a relocation model");
+    assert!(error
+        .to_string()
+        .contains("unknown relocation model `gp`, expected `static`, `pic` or `dynamic-no-pic`"));
+
+    Ok(())
+}
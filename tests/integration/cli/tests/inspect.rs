@@ -0,0 +1,258 @@
+//! Basic tests for the `inspect` subcommand
+
+use anyhow::bail;
+use std::process::Command;
+use wasmer_integration_tests_cli::{ASSET_PATH, WASMER_PATH};
+
+fn test_memory_wat_path() -> String {
+    format!("{}/{}", ASSET_PATH, "memory.wat")
+}
+
+#[test]
+fn inspect_memory_estimate_works() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("inspect")
+        .arg(test_memory_wat_path())
+        .arg("--memory-estimate")
+        .arg("--json")
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "inspect failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    let stdout_output = std::str::from_utf8(&output.stdout).unwrap();
+    // The module declares a single page of memory without a maximum
+    assert!(stdout_output.contains("\"initial_bytes\": 65536"));
+    assert!(stdout_output.contains("\"maximum_bytes\": null"));
+    assert!(stdout_output.contains("\"total_bytes\""));
+
+    Ok(())
+}
+
+#[test]
+fn inspect_cfg_prints_the_control_flow_graph() -> anyhow::Result<()> {
+    let cfg = |format: &str| -> anyhow::Result<String> {
+        // The iterative factorial: a loop exited by a branch in an `if`
+        let output = Command::new(WASMER_PATH)
+            .arg("inspect")
+            .arg(format!("{}/{}", ASSET_PATH, "fac.wat"))
+            .arg("--cfg")
+            .arg("--function")
+            .arg("2")
+            .arg("--format")
+            .arg(format)
+            .output()?;
+
+        if !output.status.success() {
+            bail!(
+                "inspect failed with: stdout: {}\n\nstderr: {}",
+                std::str::from_utf8(&output.stdout)
+                    .expect("stdout is not utf8! need to handle arbitrary bytes"),
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let dot = cfg("dot")?;
+    assert!(dot.starts_with("digraph \"function 2\" {"));
+    assert!(dot.contains("b0 -> b3;"));
+    assert!(dot.contains("b3 -> b5 [label=\"true\"];"));
+    assert!(dot.contains("b5 -> b2;"));
+    assert!(dot.contains("b6 -> b3;"));
+    assert!(dot.contains("b2 -> b1;"));
+
+    let mermaid = cfg("mermaid")?;
+    assert!(mermaid.starts_with("flowchart TD\n"));
+    assert!(mermaid.contains("b3 -->|false| b7"));
+
+    Ok(())
+}
+
+#[test]
+fn inspect_validate_features_reports_missing_features() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let artifact_path = temp_dir.path().join("fib.wasmu");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("compile")
+        .arg(format!("{}/{}", ASSET_PATH, "fib.wat"))
+        .arg("--universal")
+        .arg("--disable-simd")
+        .arg("-o")
+        .arg(&artifact_path)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "compile failed with: {}",
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    let validate_features = |source: &str| -> anyhow::Result<std::process::Output> {
+        Ok(Command::new(WASMER_PATH)
+            .arg("inspect")
+            .arg(&artifact_path)
+            .arg("--source")
+            .arg(format!("{}/{}", ASSET_PATH, source))
+            .arg("--validate-features")
+            .output()?)
+    };
+
+    let output = validate_features("fib.wat")?;
+    assert!(output.status.success());
+    assert!(std::str::from_utf8(&output.stdout)?.contains("Features used by"));
+
+    // The artifact was compiled without SIMD
+    let output = validate_features("simd.wat")?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?
+        .contains("the artifact wasn't compiled with features used by"));
+
+    Ok(())
+}
+
+#[test]
+fn inspect_artifact_target_prints_the_target_of_the_artifact() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let artifact_path = temp_dir.path().join("add.wasmu");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("compile")
+        .arg(format!("{}/{}", ASSET_PATH, "add.wat"))
+        .arg("--universal")
+        .arg("-o")
+        .arg(&artifact_path)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "compile failed with: {}",
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    // The target is printed by `wasmer compile` too
+    let compile_stdout = std::str::from_utf8(&output.stdout)?;
+    let triple = compile_stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Target: "))
+        .expect("no target printed by `wasmer compile`");
+
+    let artifact_target = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(Command::new(WASMER_PATH)
+            .arg("inspect")
+            .arg(&artifact_path)
+            .arg("--artifact-target")
+            .args(args)
+            .output()?)
+    };
+
+    let output = artifact_target(&[])?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.starts_with(&format!("{} ", triple)), "{}", stdout);
+
+    let output = artifact_target(&["--json"])?;
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    assert!(stdout.contains(&format!("\"triple\": \"{}\"", triple)));
+    assert!(stdout.contains("\"compiler\""));
+    assert!(stdout.contains("\"cpu_features\""));
+
+    // The artifact still runs with the target recorded after it
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(&artifact_path)
+        .arg("--invoke")
+        .arg("add")
+        .arg("1")
+        .arg("2")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "3\n");
+
+    Ok(())
+}
+
+#[test]
+fn inspect_diff_source_reports_the_changes() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let old_path = temp_dir.path().join("old.wat");
+    let new_path = temp_dir.path().join("new.wat");
+    std::fs::write(
+        &old_path,
+        r#"(module
+  (import "env" "log" (func $log (param i32)))
+  (memory 1)
+  (func $add (export "add") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add)
+  (func $unused)
+  (func $same (result i32)
+    i32.const 1))"#,
+    )?;
+    std::fs::write(
+        &new_path,
+        r#"(module
+  (import "env" "log" (func $log (param i64)))
+  (memory 1 4)
+  (func $add (export "add") (param i32 i32) (result i32)
+    local.get 1
+    local.get 0
+    i32.add)
+  (func $same (result i32)
+    i32.const 1)
+  (func $sub (export "sub") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.sub))"#,
+    )?;
+
+    let diff_source = |args: &[&str]| -> anyhow::Result<String> {
+        let output = Command::new(WASMER_PATH)
+            .arg("inspect")
+            .arg("--diff-source")
+            .arg(&old_path)
+            .arg(&new_path)
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "inspect failed with: {}",
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let stdout = diff_source(&[])?;
+    assert!(stdout.contains("Identical: no"));
+    assert!(stdout.contains("Functions: 1 added, 1 removed, 1 changed, 1 unchanged"));
+    assert!(stdout.contains("  + sub: [I32, I32] -> [I32]"));
+    assert!(stdout.contains("  - unused: [] -> []"));
+    assert!(stdout.contains("  ~ add: body changed"));
+    assert!(
+        stdout.contains("  ~ \"env\".\"log\": function [I32] -> [] became function [I64] -> []")
+    );
+    assert!(stdout.contains("Exports: 1 added, 0 removed, 0 changed, 1 unchanged"));
+    assert!(stdout.contains("Memories: 0 added, 0 removed, 1 changed, 0 unchanged"));
+    assert!(stdout.contains("Tables: unchanged (0)"));
+
+    let json = diff_source(&["--json"])?;
+    assert!(json.contains("\"identical\": false"));
+    assert!(json.contains("\"body_hash\""));
+
+    Ok(())
+}
@@ -16,6 +16,14 @@ fn test_no_start_wat_path() -> String {
     format!("{}/{}", ASSET_PATH, "no_start.wat")
 }
 
+fn test_initialize_wat_path() -> String {
+    format!("{}/{}", ASSET_PATH, "initialize.wat")
+}
+
+fn test_simd_wat_path() -> String {
+    format!("{}/{}", ASSET_PATH, "simd.wat")
+}
+
 #[test]
 fn run_wasi_works() -> anyhow::Result<()> {
     let output = Command::new(WASMER_PATH)
@@ -75,3 +83,1303 @@ fn run_no_start_wasm_report_error() -> anyhow::Result<()> {
     assert_eq!(result.contains("Can not find any export functions."), true);
     Ok(())
 }
+
+#[test]
+fn run_assert_exit_works() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--assert-exit")
+        .arg("0")
+        .arg("--")
+        .arg("-e")
+        .arg("print(3 * (4 + 5))")
+        .output()?;
+
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--assert-exit")
+        .arg("1")
+        .arg("--")
+        .arg("-e")
+        .arg("print(3 * (4 + 5))")
+        .output()?;
+
+    assert_eq!(output.status.code(), Some(3));
+    let result = std::str::from_utf8(&output.stderr).unwrap().to_string();
+    assert_eq!(
+        result.contains("expected the module to exit with code 1, but it exited with code 0"),
+        true
+    );
+    Ok(())
+}
+
+#[test]
+fn run_with_split_debug_file_works() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let artifact_path = temp_dir.path().join("qjs.wasmu");
+    let debug_path = temp_dir.path().join("qjs.debug");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("compile")
+        .arg(wasi_test_wasm_path())
+        .arg("--universal")
+        .arg("-o")
+        .arg(&artifact_path)
+        .arg("--split-debug")
+        .arg(&debug_path)
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "wasmer compile failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    assert!(debug_path.exists());
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(&artifact_path)
+        .arg("--debug-file")
+        .arg(&debug_path)
+        .arg("--")
+        .arg("-e")
+        .arg("print(3 * (4 + 5))")
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "running failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    let stdout_output = std::str::from_utf8(&output.stdout).unwrap();
+    assert_eq!(stdout_output, "27\n");
+
+    Ok(())
+}
+
+#[test]
+fn run_snapshot_and_restore_works() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let snapshot_path = temp_dir.path().join("snapshot.bin");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(test_initialize_wat_path())
+        .arg("--snapshot-after-init")
+        .arg(&snapshot_path)
+        .arg("--invoke")
+        .arg("get")
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "snapshotting failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "49\n");
+
+    // `_initialize` is not called when restoring, so the state comes
+    // from the snapshot
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(test_initialize_wat_path())
+        .arg("--restore")
+        .arg(&snapshot_path)
+        .arg("--invoke")
+        .arg("get")
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "restoring failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "49\n");
+
+    // Snapshots can't be restored into a different module
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(test_no_start_wat_path())
+        .arg("--restore")
+        .arg(&snapshot_path)
+        .output()?;
+
+    assert_eq!(output.status.success(), false);
+    let result = std::str::from_utf8(&output.stderr).unwrap().to_string();
+    assert_eq!(
+        result.contains("the snapshot was taken from a different module"),
+        true
+    );
+    Ok(())
+}
+
+#[test]
+fn run_disabled_feature_report_error() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(test_simd_wat_path())
+        .arg("--invoke")
+        .arg("splat")
+        .arg("1")
+        .arg("--disable-simd")
+        .output()?;
+
+    assert_eq!(output.status.success(), false);
+    let result = std::str::from_utf8(&output.stderr).unwrap().to_lowercase();
+    assert_eq!(result.contains("simd"), true);
+    Ok(())
+}
+
+#[test]
+fn run_deterministic_wasi_works() -> anyhow::Result<()> {
+    let run = || -> anyhow::Result<String> {
+        let output = Command::new(WASMER_PATH)
+            .env("SOURCE_DATE_EPOCH", "1000")
+            .arg("run")
+            .arg(wasi_test_wasm_path())
+            .arg("--deterministic-wasi")
+            .arg("--")
+            .arg("-e")
+            .arg("print(Date.now(), Math.random())")
+            .output()?;
+
+        if !output.status.success() {
+            bail!(
+                "running failed with: stdout: {}\n\nstderr: {}",
+                std::str::from_utf8(&output.stdout)
+                    .expect("stdout is not utf8! need to handle arbitrary bytes"),
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(std::str::from_utf8(&output.stdout).unwrap().to_string())
+    };
+
+    let first_output = run()?;
+    let second_output = run()?;
+    assert_eq!(first_output, second_output);
+    // `Date.now()` is in milliseconds
+    assert_eq!(first_output.starts_with("1000000 "), true);
+
+    Ok(())
+}
+
+#[test]
+fn run_seed_makes_random_get_reproducible() -> anyhow::Result<()> {
+    let run_with_seed = |seed: &str| -> anyhow::Result<Vec<u8>> {
+        let output = Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "random_bytes.wat"))
+            .arg("--seed")
+            .arg(seed)
+            .output()?;
+
+        if !output.status.success() {
+            bail!(
+                "running failed with: {}",
+                std::str::from_utf8(&output.stderr)
+                    .expect("stderr is not utf8! need to handle arbitrary bytes")
+            );
+        }
+        Ok(output.stdout)
+    };
+
+    let first_output = run_with_seed("1234")?;
+    assert_eq!(first_output.len(), 16);
+    assert_eq!(first_output, run_with_seed("1234")?);
+    assert_ne!(first_output, run_with_seed("1235")?);
+
+    Ok(())
+}
+
+#[test]
+fn run_wasi_redirects_stdout_and_stderr_to_files() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let stdout_path = temp_dir.path().join("out.txt");
+    let stderr_path = temp_dir.path().join("err.txt");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--stdout-file")
+        .arg(&stdout_path)
+        .arg("--stderr-file")
+        .arg(&stderr_path)
+        .arg("--")
+        .arg("--eval")
+        .arg("function greet(name) { return JSON.stringify('Hello, ' + name); }; print(greet('World'));")
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "running failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    assert_eq!(output.stdout.is_empty(), true);
+    assert_eq!(std::fs::read(&stdout_path)?, b"\"Hello, World\"\n");
+    assert_eq!(std::fs::read(&stderr_path)?.is_empty(), true);
+
+    Ok(())
+}
+
+#[test]
+fn run_wasi_preopen_stdin_as_file() -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--preopen-stdin-as")
+        .arg("/input/script.js")
+        .arg("--")
+        .arg("/input/script.js")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"print(3 * (4 + 5))\n")?;
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        bail!(
+            "running failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+
+    let stdout_output = std::str::from_utf8(&output.stdout).unwrap();
+    assert_eq!(stdout_output, "27\n");
+
+    Ok(())
+}
+
+#[test]
+fn run_wasi_socket_calls_are_denied_gracefully() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "sock_calls.wat"))
+        .output()?;
+
+    // The module exits with the errno of the socket calls: `ENOTCAPABLE`,
+    // instead of `wasmer` aborting
+    assert_eq!(
+        output.status.code(),
+        Some(76),
+        "{}",
+        std::str::from_utf8(&output.stderr).unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_wasi_limit_open_files_returns_emfile() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    std::fs::write(temp_dir.path().join("file.txt"), b"contents")?;
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "open_files.wat"))
+        .arg("--mapdir")
+        .arg(format!("/sandbox:{}", temp_dir.path().display()))
+        .arg("--limit-open-files")
+        .arg("16")
+        .output()?;
+
+    // The module exits with the number of files it opened before `EMFILE`
+    let opened = output.status.code().unwrap();
+    assert!(
+        opened > 0 && opened < 16,
+        "unexpected exit code {}: {}",
+        opened,
+        std::str::from_utf8(&output.stderr).unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_wasi_working_dir_resolves_relative_paths() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    std::fs::create_dir(temp_dir.path().join("sub"))?;
+    std::fs::write(temp_dir.path().join("sub").join("hello.txt"), b"hello")?;
+
+    let run_in = |working_dir: &str| -> anyhow::Result<std::process::Output> {
+        Ok(Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(wasi_test_wasm_path())
+            .arg("--mapdir")
+            .arg(format!("/data:{}", temp_dir.path().display()))
+            .arg("--working-dir")
+            .arg(working_dir)
+            .arg("--")
+            .arg("--std")
+            .arg("-e")
+            .arg("print(std.open(\"hello.txt\", \"r\").getline())")
+            .output()?)
+    };
+
+    let output = run_in("/data/sub")?;
+    if !output.status.success() {
+        bail!(
+            "wasmer run failed with: {}",
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "hello\n");
+
+    let output = run_in("/elsewhere")?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?
+        .contains("isn't in a directory of `--dir` or `--mapdir`"));
+
+    Ok(())
+}
+
+#[test]
+fn run_wasi_dir_access_mode_restricts_preopened_dirs() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let run_with_mode = |mode: Option<&str>| -> anyhow::Result<i32> {
+        let mut command = Command::new(WASMER_PATH);
+        command
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "dir_rights.wat"))
+            .arg("--mapdir")
+            .arg(format!("/sandbox:{}", temp_dir.path().display()));
+        if let Some(mode) = mode {
+            command.arg("--dir-access-mode").arg(mode);
+        }
+        Ok(command.output()?.status.code().unwrap())
+    };
+
+    // The module exits with 1 if the directory can be read, plus 2 if it
+    // can be written
+    assert_eq!(run_with_mode(None)?, 3);
+    assert_eq!(run_with_mode(Some("0444"))?, 1);
+    assert_eq!(run_with_mode(Some("0644"))?, 3);
+    assert_eq!(run_with_mode(Some("0222"))?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn run_instances_runs_the_module_concurrently() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "add.wat"))
+        .arg("--instances")
+        .arg("4")
+        .arg("--invoke")
+        .arg("add")
+        .arg("1")
+        .arg("2")
+        .output()?;
+
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "wasmer run failed: {}", stderr);
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), vec!["3"; 4]);
+    assert!(stderr.contains("Ran 4 instances in"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn run_cpu_affinity_pins_the_instances() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "add.wat"))
+        .arg("--cpu-affinity")
+        .arg("0")
+        .arg("--instances")
+        .arg("2")
+        .arg("--invoke")
+        .arg("add")
+        .arg("1")
+        .arg("2")
+        .output()?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "wasmer run failed: {}", stderr);
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "3\n3\n");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "add.wat"))
+        .arg("--cpu-affinity")
+        .arg("3-1")
+        .arg("--invoke")
+        .arg("add")
+        .arg("1")
+        .arg("2")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?.contains("invalid CPU range `3-1`"));
+
+    Ok(())
+}
+
+#[test]
+fn run_exit_after_compile_writes_timings() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let timings_path = temp_dir.path().join("timings.json");
+
+    // The module traps when it runs, so it must not be run
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "trap.wat"))
+        .arg("--exit-after")
+        .arg("compile")
+        .arg("--timings-json")
+        .arg(&timings_path)
+        .output()?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "wasmer run failed: {}", stderr);
+    assert!(stderr.contains("Compiled `"));
+    let timings = std::fs::read_to_string(&timings_path)?;
+    assert!(timings.contains("\"compile_seconds\""));
+    assert!(timings.contains("\"run_seconds\": null"));
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "add.wat"))
+        .arg("--timings-json")
+        .arg(&timings_path)
+        .arg("--invoke")
+        .arg("add")
+        .arg("1")
+        .arg("2")
+        .output()?;
+    assert!(output.status.success());
+    let timings = std::fs::read_to_string(&timings_path)?;
+    assert!(timings.contains("\"run_seconds\""));
+    assert!(!timings.contains("null"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn run_memory_file_persists_memory() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let memory_path = temp_dir.path().join("memory.bin");
+    let run_with_memory_file = || -> anyhow::Result<Option<i32>> {
+        let output = Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "memory_counter.wat"))
+            .arg("--memory-file")
+            .arg(&memory_path)
+            .output()?;
+        Ok(output.status.code())
+    };
+
+    // The module exits with the counter stored in its memory
+    assert_eq!(run_with_memory_file()?, Some(1));
+    assert_eq!(run_with_memory_file()?, Some(2));
+    // The file grew with the memory: one page, plus one per run
+    assert_eq!(std::fs::metadata(&memory_path)?.len(), 3 * 0x10000);
+
+    Ok(())
+}
+
+#[test]
+fn run_dump_memory_on_exit_writes_the_memory_after_a_trap() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dump_path = temp_dir.path().join("memory.bin");
+    let run_with_range = |range: Option<&str>| -> anyhow::Result<std::process::Output> {
+        let mut command = Command::new(WASMER_PATH);
+        command
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "dump_memory.wat"))
+            .arg("--dump-memory-on-exit")
+            .arg(&dump_path);
+        if let Some(range) = range {
+            command.arg("--dump-memory-range").arg(range);
+        }
+        Ok(command.output()?)
+    };
+    let marker = 0x0123456789abcdef_u64.to_le_bytes();
+
+    // The module traps after writing the marker
+    let output = run_with_range(None)?;
+    assert!(!output.status.success());
+    let memory = std::fs::read(&dump_path)?;
+    assert_eq!(memory.len(), 0x10000);
+    assert_eq!(&memory[16..24], &marker);
+
+    run_with_range(Some("0x10:8"))?;
+    assert_eq!(std::fs::read(&dump_path)?, marker);
+
+    let output = run_with_range(Some("0xfff0:32"))?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("failed to dump the memory: the range 0xfff0:32 is out of the memory"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_exec_model_reactor_keeps_the_instance_between_invocations() -> anyhow::Result<()> {
+    let run_with = |module: &str, args: &[&str]| {
+        Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, module))
+            .args(args)
+            .output()
+    };
+    let reactor_args = [
+        "--exec-model",
+        "reactor",
+        "--invoke",
+        "increment",
+        "--invoke",
+        "increment",
+    ];
+
+    // `_initialize` sets the counter to 10
+    let output = run_with("reactor_counter.wat", &reactor_args)?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "wasmer run failed: {}", stderr);
+    assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "11\n12\n");
+
+    let output = run_with("reactor_counter.wat", &reactor_args[2..])?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("calling several `--invoke` functions requires `--exec-model reactor`"));
+
+    let output = run_with("fib.wat", &reactor_args[..2])?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("the module has no `_initialize` export, required by `--exec-model reactor`"));
+
+    Ok(())
+}
+
+#[test]
+fn run_time_limit_cpu_interrupts_an_infinite_loop() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "infinite_loop.wat"))
+        .arg("--time-limit-cpu")
+        .arg("200ms")
+        .output()?;
+
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(5), "{}", stderr);
+    assert!(
+        stderr.contains("the module exceeded its CPU time limit of 200ms"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_interrupt_on_signal_exits_cleanly_on_sigint() -> anyhow::Result<()> {
+    let child = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "infinite_loop.wat"))
+        .arg("--interrupt-on-signal")
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    // Leave the time to compile the module and install the handlers
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(child.id().to_string())
+        .status()?;
+    assert!(status.success());
+
+    let output = child.wait_with_output()?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(130), "{}", stderr);
+    assert!(
+        stderr.contains("the module was interrupted by SIGINT"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_print_metering_remaining_on_trap_tells_out_of_fuel_traps() -> anyhow::Result<()> {
+    let run_with = |module: &str| {
+        Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, module))
+            .arg("--metering-limit")
+            .arg("1000")
+            .arg("--print-metering-remaining-on-trap")
+            .output()
+    };
+
+    let output = run_with("infinite_loop.wat")?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("Metering points remaining: 0 (exhausted)"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("the module ran out of its 1000 metering points"),
+        "{}",
+        stderr
+    );
+
+    let output = run_with("trap.wat")?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("Metering points remaining: "), "{}", stderr);
+    assert!(!stderr.contains("exhausted"), "{}", stderr);
+    assert!(!stderr.contains("ran out of"), "{}", stderr);
+
+    Ok(())
+}
+
+#[test]
+fn run_trap_on_grow_traps_when_the_memory_grows() -> anyhow::Result<()> {
+    let run_with = |args: &[&str]| {
+        Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "grow_memory.wat"))
+            .args(args)
+            .output()
+    };
+
+    let output = run_with(&[])?;
+    assert!(
+        output.status.success(),
+        "{}",
+        std::str::from_utf8(&output.stderr).unwrap()
+    );
+
+    let output = run_with(&["--trap-on-grow"])?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the module tried to grow its memory by 2 pages"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_deny_syscall_returns_the_configured_error() -> anyhow::Result<()> {
+    let run_with = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "clock_time_get.wat"))
+            .args(args)
+            .output()?)
+    };
+
+    // The module exits with the errno of `clock_time_get`
+    assert_eq!(run_with(&[])?.status.code(), Some(0));
+    // `ENOSYS`
+    let output = run_with(&["--deny-syscall", "clock_time_get"])?;
+    assert_eq!(output.status.code(), Some(52));
+    // `EPERM`
+    let output = run_with(&[
+        "--deny-syscall",
+        "fd_write,clock_time_get",
+        "--deny-mode",
+        "eperm",
+    ])?;
+    assert_eq!(output.status.code(), Some(63));
+
+    let output = run_with(&["--deny-syscall", "clock_time_get", "--deny-mode", "trap"])?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the WASI syscall `clock_time_get`, denied by `--deny-syscall`"),
+        "{}",
+        stderr
+    );
+
+    let output = run_with(&["--deny-syscall", "clock_get_time"])?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("unknown WASI syscall `clock_get_time`"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_report_unused_imports_lists_the_imports_never_called() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let json_path = temp_dir.path().join("imports.json");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "unused_imports.wat"))
+        .arg("--report-unused-imports")
+        .arg("--unused-imports-json")
+        .arg(&json_path)
+        .output()?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("2 of the 3 imported functions were never called"),
+        "{}",
+        stderr
+    );
+    assert!(stderr
+        .contains("\"wasi_snapshot_preview1\".\"clock_time_get\": [I32, I64, I32] -> [I32]\n"));
+    assert!(stderr.contains("\"wasi_snapshot_preview1\".\"fd_write\": [I32, I32, I32, I32] -> [I32] (may have been called indirectly)"));
+    assert!(!stderr.contains("sched_yield"));
+
+    let report = std::fs::read_to_string(&json_path)?;
+    assert!(report.contains("\"name\": \"sched_yield\""));
+    assert!(report.contains("\"calls\": 2"));
+
+    Ok(())
+}
+
+#[test]
+fn run_set_global_writes_exported_global() -> anyhow::Result<()> {
+    let run_with_global = |assignment: &str| -> anyhow::Result<std::process::Output> {
+        Ok(Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "global_config.wat"))
+            .arg("--set-global")
+            .arg(assignment)
+            .output()?)
+    };
+
+    // The module exits with the value of `config_flags`
+    assert_eq!(run_with_global("config_flags=7")?.status.code(), Some(7));
+
+    let output = run_with_global("version=2")?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?.contains("isn't mutable"));
+
+    let output = run_with_global("config_flags=seven")?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)?.contains("into a i32"));
+
+    Ok(())
+}
+
+#[test]
+fn run_on_exit_hook_receives_exit_code() -> anyhow::Result<()> {
+    let run_with_hook = |extra_args: &[&str]| -> anyhow::Result<Option<i32>> {
+        let output = Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "exit_code.wat"))
+            .arg("--on-exit-hook")
+            .arg(format!("{}/{}", ASSET_PATH, "exit_hook.wat"))
+            .args(extra_args)
+            .output()?;
+        Ok(output.status.code())
+    };
+
+    // The hook exits with 17, but the main module's exit code is kept
+    assert_eq!(run_with_hook(&[])?, Some(7));
+    // The hook received `7` as its argument
+    assert_eq!(run_with_hook(&["--hook-overrides-exit"])?, Some(17));
+
+    Ok(())
+}
+
+#[test]
+fn run_capture_trap_json_works() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let trap_path = temp_dir.path().join("trap.json");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "trap.wat"))
+        .arg("--capture-trap-json")
+        .arg(&trap_path)
+        .output()?;
+
+    assert_eq!(output.status.code(), Some(4));
+    let report = std::fs::read_to_string(&trap_path)?;
+    assert!(report.contains("\"trap_code\": \"IntegerDivisionByZero\""));
+    assert!(report.contains("\"function_index\": 0"));
+    assert!(report.contains("\"frames\""));
+
+    Ok(())
+}
+
+#[test]
+fn run_print_wat_on_trap_marks_the_trapping_instruction() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "trap.wat"))
+        .arg("--print-wat-on-trap")
+        .output()?;
+
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("Trapping function (index 0"));
+    let trapping_line = stderr
+        .lines()
+        .find(|line| line.starts_with("-->"))
+        .expect("the trapping instruction isn't marked");
+    assert!(trapping_line.contains("i32.div_s"));
+
+    Ok(())
+}
+
+#[test]
+fn run_allow_imports_rejects_other_namespaces() -> anyhow::Result<()> {
+    let run_allowing = |namespace: &str| {
+        Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(wasi_test_wasm_path())
+            .arg("--allow-imports")
+            .arg(namespace)
+            .arg("--")
+            .arg("-e")
+            .arg("print(3 * (4 + 5))")
+            .output()
+    };
+
+    let output = run_allowing("env")?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("the module imports from namespaces not allowed by `--allow-imports`"));
+    assert!(stderr.contains("\"wasi_unstable\".\"fd_write\""));
+
+    let output = run_allowing("wasi_unstable")?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "wasmer run failed: {}", stderr);
+    assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "27\n");
+
+    Ok(())
+}
+
+#[test]
+fn run_sandbox_profile_merges_with_the_flags() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let profile_path = temp_dir.path().join("sandbox.toml");
+    std::fs::write(
+        &profile_path,
+        r#"
+dir-access-mode = "0444"
+limit-open-files = 16
+allow-imports = ["env"]
+
+[env]
+GREETING = "hello"
+LEVEL = "info"
+"#,
+    )?;
+    let run_with = |args: &[&str]| {
+        Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(wasi_test_wasm_path())
+            .arg("--sandbox-profile")
+            .arg(&profile_path)
+            .args(args)
+            .output()
+    };
+
+    let output = run_with(&[
+        "--print-sandbox",
+        "--limit-open-files",
+        "8",
+        "--env",
+        "LEVEL=debug",
+    ])?;
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "wasmer run failed: {}", stderr);
+    assert_eq!(
+        std::str::from_utf8(&output.stdout).unwrap(),
+        r#"dir-access-mode = "0444"
+limit-open-files = 8
+deterministic-wasi = false
+allow-imports = ["env"]
+
+[env]
+GREETING = "hello"
+LEVEL = "debug"
+"#
+    );
+
+    // The profile only allows the `env` imports
+    let output = run_with(&["--", "-e", "print(1)"])?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("the module imports from namespaces not allowed by `--allow-imports`"));
+
+    std::fs::write(&profile_path, "limit-open-file = 16\n")?;
+    let output = run_with(&["--print-sandbox"])?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("unknown field `limit-open-file`"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_env_file_passes_the_variables_to_the_module() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let env_file_path = temp_dir.path().join(".env");
+    std::fs::write(
+        &env_file_path,
+        "# The greeting\nGREETING=\"hello world\"\nLEVEL=info # overridden\n",
+    )?;
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--env-file")
+        .arg(&env_file_path)
+        .arg("--env")
+        .arg("LEVEL=debug")
+        .arg("--")
+        .arg("--std")
+        .arg("-e")
+        .arg("print(std.getenv(\"GREETING\"), std.getenv(\"LEVEL\"))")
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "wasmer run failed with: {}",
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "hello world debug\n");
+
+    Ok(())
+}
+
+#[test]
+fn run_capture_output_writes_the_streams_and_the_exit_code() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let capture_path = temp_dir.path().join("result.json");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--capture-output")
+        .arg(&capture_path)
+        .arg("--")
+        .arg("--std")
+        .arg("-e")
+        .arg("print(\"hello\"); std.err.putByte(255); std.exit(3)")
+        .output()?;
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+
+    let capture = std::fs::read_to_string(&capture_path)?;
+    assert!(capture.contains(r#""exit_code": 3"#), "{}", capture);
+    assert!(capture.contains(r#""utf8": "hello\n""#), "{}", capture);
+    assert!(capture.contains(r#""base64": "/w==""#), "{}", capture);
+
+    Ok(())
+}
+
+#[test]
+fn run_alloc_profile_records_the_memory_growth() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let profile_path = temp_dir.path().join("alloc.json");
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--alloc-profile")
+        .arg(&profile_path)
+        .arg("--")
+        .arg("-e")
+        .arg("print(new Array(1000000).fill(1).length)")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "wasmer run --alloc-profile failed: {}",
+        std::str::from_utf8(&output.stderr).unwrap()
+    );
+    assert_eq!(std::str::from_utf8(&output.stdout)?, "1000000\n");
+
+    // The array doesn't fit in the initial memory of QuickJS
+    let profile = std::fs::read_to_string(&profile_path)?;
+    assert!(profile.contains(r#""initial_pages""#), "{}", profile);
+    assert!(profile.contains(r#""delta_pages""#), "{}", profile);
+    assert!(profile.contains(r#""failed": false"#), "{}", profile);
+
+    Ok(())
+}
+
+#[test]
+fn run_record_and_replay_wasi_syscalls() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let trace_path = temp_dir.path().join("trace.bin");
+    let run_with = |flag: &str, module_path: &str| {
+        Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(module_path)
+            .arg(flag)
+            .arg(&trace_path)
+            .arg("--")
+            .arg("-e")
+            .arg("print(Date.now())")
+            .output()
+    };
+
+    let recorded = run_with("--record", &wasi_test_wasm_path())?;
+    assert!(
+        recorded.status.success(),
+        "wasmer run --record failed: {}",
+        std::str::from_utf8(&recorded.stderr).unwrap()
+    );
+    std::thread::sleep(std::time::Duration::from_millis(10));
+
+    // The clock returns the recorded time
+    let replayed = run_with("--replay", &wasi_test_wasm_path())?;
+    assert!(
+        replayed.status.success(),
+        "wasmer run --replay failed: {}",
+        std::str::from_utf8(&replayed.stderr).unwrap()
+    );
+    assert_eq!(replayed.stdout, recorded.stdout);
+
+    let output = run_with(
+        "--replay",
+        &format!("{}/{}", ASSET_PATH, "clock_time_get.wat"),
+    )?;
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("was recorded with a different module"));
+
+    Ok(())
+}
+
+#[test]
+fn run_trace_calls_logs_the_matching_functions() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--trace-calls")
+        .arg("js_std_*")
+        .arg("--")
+        .arg("-e")
+        .arg("print(3 * (4 + 5))")
+        .output()?;
+
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    if !output.status.success() {
+        bail!("wasmer run --trace-calls failed with: {}", stderr);
+    }
+    assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "27\n");
+    assert!(stderr.contains("-> js_std_add_helpers("));
+    assert!(stderr.contains("<- js_std_add_helpers"));
+    assert!(stderr.lines().all(|line| line.contains("js_std_")));
+
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(wasi_test_wasm_path())
+        .arg("--trace-calls")
+        .arg("no_such_function")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("no function matches the `--trace-calls` pattern"));
+
+    Ok(())
+}
+
+#[test]
+fn run_trace_calls_needs_reference_types() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(test_no_imports_wat_path())
+        .arg("--trace-calls")
+        .arg("*")
+        .arg("--disable-reference-types")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("'--disable-reference-types' cannot be used with '--trace-calls <PATTERN>'"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn run_wasi_preopen_fd_reads_from_an_inherited_pipe() -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let temp_dir = tempfile::tempdir()?;
+    std::fs::write(temp_dir.path().join("file.txt"), " and from the file")?;
+
+    // The stdin pipe of the shell is inherited as the file descriptor 3, and
+    // paths still resolve in the mapped directory
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("exec \"$0\" run \"$1\" --mapdir \"data:$2\" --preopen-fd 3:input 3<&0 </dev/null")
+        .arg(WASMER_PATH)
+        .arg(format!("{}/{}", ASSET_PATH, "preopen_fd.wat"))
+        .arg(temp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"hello from the pipe")?;
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        bail!(
+            "running failed with: stdout: {}\n\nstderr: {}",
+            std::str::from_utf8(&output.stdout)
+                .expect("stdout is not utf8! need to handle arbitrary bytes"),
+            std::str::from_utf8(&output.stderr)
+                .expect("stderr is not utf8! need to handle arbitrary bytes")
+        );
+    }
+    assert_eq!(
+        std::str::from_utf8(&output.stdout).unwrap(),
+        "hello from the pipe and from the file"
+    );
+
+    // The descriptor must be open
+    let output = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(format!("{}/{}", ASSET_PATH, "preopen_fd.wat"))
+        .arg("--preopen-fd")
+        .arg("100:input")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("the file descriptor 100 isn't open"));
+
+    Ok(())
+}
+
+/// A WASI module printing `message` and a newline.
+fn wasi_module_printing(message: &str) -> String {
+    format!(
+        r#"(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\10\00\00\00\{len:02x}\00\00\00")
+  (data (i32.const 16) "{message}\n")
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))"#,
+        len = message.len() + 1,
+        message = message
+    )
+}
+
+#[test]
+fn run_watch_reruns_the_changed_module() -> anyhow::Result<()> {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let temp_dir = tempfile::tempdir()?;
+    let module = temp_dir.path().join("module.wat");
+    std::fs::write(&module, wasi_module_printing("first run"))?;
+
+    let mut child = Command::new(WASMER_PATH)
+        .arg("run")
+        .arg(&module)
+        .arg("--watch")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+
+    let result = (|| -> anyhow::Result<()> {
+        let mut line = String::new();
+        stdout.read_line(&mut line)?;
+        assert_eq!(line, "first run\n");
+        line.clear();
+        stderr.read_line(&mut line)?;
+        assert!(line.starts_with("--- waiting for"), "{}", line);
+
+        std::fs::write(&module, wasi_module_printing("second run"))?;
+        line.clear();
+        stdout.read_line(&mut line)?;
+        assert_eq!(line, "second run\n");
+        Ok(())
+    })();
+    child.kill()?;
+    child.wait()?;
+    result
+}
+
+#[test]
+fn run_watch_conflicts_with_inputs_read_once() -> anyhow::Result<()> {
+    for args in &[
+        &["--preopen-stdin-as", "/input/stdin.txt"][..],
+        &["--preopen-fd", "0:input"][..],
+    ] {
+        let output = Command::new(WASMER_PATH)
+            .arg("run")
+            .arg(format!("{}/{}", ASSET_PATH, "exit_code.wat"))
+            .arg("--watch")
+            .args(*args)
+            .output()?;
+        assert!(!output.status.success());
+        assert!(std::str::from_utf8(&output.stderr)
+            .unwrap()
+            .contains("cannot be used with"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! Basic tests for the `targets` subcommand
+
+use std::process::Command;
+use wasmer_integration_tests_cli::WASMER_PATH;
+
+#[test]
+fn targets_lists_the_host() -> anyhow::Result<()> {
+    let output = Command::new(WASMER_PATH)
+        .arg("targets")
+        .arg("--json")
+        .output()?;
+
+    let stdout_output = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "wasmer targets failed: {}",
+        std::str::from_utf8(&output.stderr).unwrap()
+    );
+    assert!(stdout_output.contains("\"host\": true"));
+    assert!(stdout_output.contains("\"triple\": \"x86_64-unknown-linux-gnu\""));
+    assert!(stdout_output.contains("\"aarch64-apple-darwin\""));
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! Basic tests for the `validate` subcommand
+
+use std::fs;
+use std::process::Command;
+use wasmer_integration_tests_cli::WASMER_PATH;
+
+/// The smallest valid Wasm module.
+const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+#[test]
+fn validate_batch_reports_failures() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    fs::create_dir(temp_dir.path().join("nested"))?;
+    fs::write(temp_dir.path().join("valid.wasm"), EMPTY_MODULE)?;
+    fs::write(temp_dir.path().join("nested/valid.wasm"), EMPTY_MODULE)?;
+    // An unknown section id
+    fs::write(
+        temp_dir.path().join("invalid.wasm"),
+        b"\0asm\x01\0\0\0\xff\0",
+    )?;
+    fs::write(temp_dir.path().join("ignored.txt"), b"not a module")?;
+
+    let output = Command::new(WASMER_PATH)
+        .arg("validate")
+        .arg("--batch")
+        .arg(temp_dir.path())
+        .arg("--jobs")
+        .arg("2")
+        .arg("--json")
+        .output()?;
+
+    let stdout_output = std::str::from_utf8(&output.stdout).unwrap();
+    let stderr_output = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        !output.status.success(),
+        "validation should have failed: {}",
+        stdout_output
+    );
+    assert!(stdout_output.contains("\"passed\": 2"));
+    assert!(stdout_output.contains("\"failed\": 1"));
+    assert!(stdout_output.contains("invalid.wasm"));
+    assert!(!stdout_output.contains("ignored.txt"));
+    assert!(stderr_output.contains("1 of 3 modules failed validation"));
+
+    Ok(())
+}
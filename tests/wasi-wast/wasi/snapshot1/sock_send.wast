@@ -0,0 +1,7 @@
+;; This file was generated by https://github.com/wasmerio/wasi-tests
+
+(wasi_test "sock_send.wasm"
+  (map_dirs ".:test_fs/hamlet")
+  (assert_return (i64.const 0))
+  (assert_stdout "sock_send: 76\n")
+)
@@ -0,0 +1,41 @@
+// WASI:
+// mapdir: .:test_fs/hamlet
+
+// `sock_send` on a file descriptor that isn't a socket returns an errno
+// instead of aborting
+
+use std::fs;
+#[cfg(target_os = "wasi")]
+use std::os::wasi::prelude::AsRawFd;
+
+#[cfg(target_os = "wasi")]
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn sock_send(fd: u32, si_data: u32, si_data_len: u32, si_flags: u16, so_datalen: u32)
+        -> u16;
+}
+
+fn main() {
+    let file = fs::File::open("README.md").expect("could not open README.md");
+
+    #[cfg(target_os = "wasi")]
+    let errno = {
+        let mut sent: u32 = 0;
+        unsafe {
+            sock_send(
+                file.as_raw_fd(),
+                0,
+                0,
+                0,
+                &mut sent as *mut u32 as usize as u32,
+            )
+        }
+    };
+    #[cfg(not(target_os = "wasi"))]
+    let errno = {
+        drop(file);
+        76
+    };
+
+    println!("sock_send: {}", errno);
+}